@@ -0,0 +1,655 @@
+//! The session interface for frontends other than the web interface.
+//!
+//! Everything in here only takes and returns plain data, which can be serialized with serde. The
+//! types and functions of this module only change when the debugger gains functionality, unlike
+//! the rest of the crate, which has to follow every rustc and miri update.
+
+use rustc::mir::interpret::{Allocation, GlobalAlloc, GlobalId};
+use rustc::ty::layout::Size;
+use rustc::ty::{Instance, ParamEnv};
+use rustc_data_structures::indexed_vec::Idx;
+use rustc_mir::interpret::MemoryKind;
+
+use crate::step::{Breakpoint, StopReason};
+use crate::*;
+
+/// A position in the interpreted program.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub function: String,
+    pub def_id: String,
+    pub block: usize,
+    pub stmt: usize,
+    pub span: String,
+}
+
+/// The outcome of a stepping command.
+#[derive(Serialize, Debug, Clone)]
+pub struct StepResult {
+    pub message: String,
+    /// The kind of `step::StopReason` the command stopped with, if it executed anything
+    pub reason: Option<&'static str>,
+    pub step_count: u128,
+    pub position: Option<Position>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Local {
+    pub id: usize,
+    pub name: String,
+    pub ty: String,
+    pub alloc: Option<u64>,
+    pub value: String,
+    /// The pretty printed value, `None` if there is none
+    pub pretty: Option<String>,
+    /// The bytes or scalars backing the value, to assert on exact values
+    pub raw: String,
+    /// One of `return`, `argument`, `variable` and `temporary`
+    pub kind: &'static str,
+}
+
+impl From<crate::render::locals::LocalInfo> for Local {
+    fn from(info: crate::render::locals::LocalInfo) -> Local {
+        Local {
+            id: info.id,
+            name: info.name,
+            ty: info.ty,
+            alloc: info.alloc,
+            value: html_to_text(&info.value),
+            pretty: info.pretty.map(|pretty| html_to_text(&pretty)),
+            raw: html_to_text(&info.raw),
+            kind: info.kind,
+        }
+    }
+}
+
+/// The contents of an allocation. Undefined bytes are `None`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Memory {
+    pub alloc_id: u64,
+    pub bytes: Vec<Option<u8>>,
+    /// The offsets of pointers in this allocation and the allocations they point to.
+    pub relocations: Vec<(u64, u64)>,
+}
+
+/// Execute a stepping command, see `step::step_command` for the list of commands.
+pub fn command(pcx: &mut PrirodaContext, cmd: &str) -> StepResult {
+    let message = crate::step::step_command(pcx, cmd);
+    StepResult {
+        message,
+        reason: pcx.last_stop.as_ref().map(crate::step::StopReason::kind),
+        step_count: *pcx.step_count,
+        position: position(pcx),
+    }
+}
+
+/// Execute the command script at `path`, see the `script` module. The error names the line of the
+/// failing command and why it failed.
+pub fn run_script(pcx: &mut PrirodaContext, path: &std::path::Path) -> Result<(), String> {
+    crate::script::run_script(pcx, path)
+}
+
+pub fn step_count(pcx: &PrirodaContext) -> u128 {
+    *pcx.step_count
+}
+
+/// The current position, or `None` if the stack is empty.
+pub fn position(pcx: &PrirodaContext) -> Option<Position> {
+    stack(pcx).pop()
+}
+
+/// The positions of all stack frames, the innermost frame is the last one.
+pub fn stack(pcx: &PrirodaContext) -> Vec<Position> {
+    pcx.ecx
+        .stack()
+        .iter()
+        .map(|frame| Position {
+            function: frame.instance.to_string(),
+            def_id: format!("{:?}", frame.instance.def_id()),
+            block: frame.block.index(),
+            stmt: frame.stmt,
+            span: crate::render::source::pretty_src_path(frame.span),
+        })
+        .collect()
+}
+
+/// An entry of the condensed stack, see `condensed_stack`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind")]
+pub enum StackEntry {
+    Frame {
+        depth: usize,
+        position: Position,
+    },
+    /// Consecutive frames of the same function, e.g. from recursion
+    Run {
+        first_depth: usize,
+        last_depth: usize,
+        first: Position,
+        last: Position,
+    },
+}
+
+/// The stack with runs of at least `threshold` frames of the same function condensed into a
+/// single entry. The outermost frame is the first entry.
+pub fn condensed_stack(pcx: &PrirodaContext, threshold: usize) -> Vec<StackEntry> {
+    let stack = stack(pcx);
+    let runs = crate::render::recursion_runs(
+        &stack.iter().map(|pos| &pos.def_id).collect::<Vec<_>>(),
+        threshold,
+    );
+    let mut entries = Vec::new();
+    let mut depth = 0;
+    while depth < stack.len() {
+        if let Some(&(start, end)) = runs.iter().find(|&&(start, _)| start == depth) {
+            entries.push(StackEntry::Run {
+                first_depth: start,
+                last_depth: end,
+                first: stack[start].clone(),
+                last: stack[end].clone(),
+            });
+            depth = end + 1;
+        } else {
+            entries.push(StackEntry::Frame {
+                depth,
+                position: stack[depth].clone(),
+            });
+            depth += 1;
+        }
+    }
+    entries
+}
+
+/// The locals of stack frame `frame`, or `None` if there is no such frame.
+pub fn locals(pcx: &PrirodaContext, frame: usize) -> Option<Vec<Local>> {
+    let frame = pcx.ecx.stack().get(frame)?;
+    Some(
+        crate::render::locals::collect_locals(&pcx.ecx, frame, pcx.config.locals_settings())
+            .into_iter()
+            .map(Local::from)
+            .collect(),
+    )
+}
+
+/// Local `local` of stack frame `frame` rendered with nested values up to `depth` levels deep,
+/// `None` meaning unlimited. With `raw` only the bytes are rendered.
+pub fn local_full(pcx: &PrirodaContext, frame: usize, local: usize, depth: Option<usize>, raw: bool) -> Result<Local, String> {
+    let stack_frame = pcx
+        .ecx
+        .stack()
+        .get(frame)
+        .ok_or_else(|| format!("No frame {}, the stack has {} frames", frame, pcx.ecx.stack().len()))?;
+    let full_url = format!("/frame/{}/local/{}/full", frame, local);
+    let limits = crate::render::locals::Limits {
+        depth,
+        raw,
+        full_url: &full_url,
+        string_bytes: None,
+        ..pcx.config.locals_settings()
+    };
+    let id = crate::render::locals::frame_local(stack_frame, local)?;
+    crate::render::locals::render_local(&pcx.ecx, stack_frame, id, limits).map(Local::from)
+}
+
+pub fn memory(pcx: &PrirodaContext, alloc_id: u64) -> Option<Memory> {
+    let alloc = pcx.ecx.memory().get(AllocId(alloc_id)).ok()?;
+    Some(alloc_memory(alloc_id, alloc))
+}
+
+fn alloc_memory<T, E>(alloc_id: u64, alloc: &Allocation<T, E>) -> Memory {
+    Memory {
+        alloc_id,
+        bytes: alloc
+            .bytes
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| {
+                if alloc.undef_mask.get(Size::from_bytes(i as u64)) {
+                    Some(byte)
+                } else {
+                    None
+                }
+            })
+            .collect(),
+        relocations: alloc
+            .relocations
+            .iter()
+            .map(|(offset, &(_tag, target))| (offset.bytes(), target.0))
+            .collect(),
+    }
+}
+
+/// A range of the bytes of an allocation, with undefined bytes and the bytes of pointers replaced
+/// by a filler. The ranges tell which bytes aren't real.
+#[derive(Serialize, Debug, Clone)]
+pub struct RawBytes {
+    pub alloc_id: u64,
+    pub offset: u64,
+    pub len: u64,
+    #[serde(skip)]
+    pub bytes: Vec<u8>,
+    /// The start and end offsets of the undefined ranges, relative to the allocation
+    pub undef: Vec<(u64, u64)>,
+    /// The start and end offsets of the pointers, relative to the allocation, and the allocations
+    /// they point to
+    pub relocations: Vec<(u64, u64, u64)>,
+}
+
+/// `len` bytes of allocation `alloc_id` starting at `offset`, up to its end if `len` is `None`.
+pub fn raw_bytes(pcx: &PrirodaContext, alloc_id: u64, offset: u64, len: Option<u64>, fill: u8) -> Result<RawBytes, String> {
+    let alloc = pcx
+        .ecx
+        .memory()
+        .get(AllocId(alloc_id))
+        .map_err(|_| format!("Allocation {} has no bytes", alloc_id))?;
+    let size = alloc.bytes.len() as u64;
+    let end = match len {
+        Some(len) => offset.checked_add(len).filter(|&end| end <= size),
+        None => Some(size).filter(|&size| offset <= size),
+    }
+    .ok_or_else(|| format!("{}..{} is out of bounds for allocation {} of {} bytes", offset, offset.saturating_add(len.unwrap_or(0)), alloc_id, size))?;
+    let mut bytes = alloc.bytes[offset as usize..end as usize].to_vec();
+    let mut undef: Vec<(u64, u64)> = Vec::new();
+    for i in offset..end {
+        if alloc.undef_mask.get(Size::from_bytes(i)) {
+            continue;
+        }
+        bytes[(i - offset) as usize] = fill;
+        match undef.last_mut() {
+            Some(last) if last.1 == i => last.1 = i + 1,
+            _ => undef.push((i, i + 1)),
+        }
+    }
+    let pointer_size = pcx.ecx.tcx.data_layout.pointer_size.bytes();
+    let relocations = alloc
+        .relocations
+        .iter()
+        .map(|(start, &(_tag, target))| (start.bytes(), start.bytes() + pointer_size, target.0))
+        .filter(|&(start, ptr_end, _)| start < end && ptr_end > offset)
+        .collect::<Vec<_>>();
+    for &(start, ptr_end, _) in &relocations {
+        for i in start.max(offset)..ptr_end.min(end) {
+            bytes[(i - offset) as usize] = fill;
+        }
+    }
+    Ok(RawBytes {
+        alloc_id,
+        offset,
+        len: end - offset,
+        bytes,
+        undef,
+        relocations,
+    })
+}
+
+/// All breakpoints in the `DefId(..)@bb:stmt` format accepted by `add_breakpoint`.
+pub fn breakpoints(pcx: &PrirodaContext) -> Vec<String> {
+    pcx.config
+        .bptree
+        .iter()
+        .map(|&Breakpoint(def_id, bb, stmt)| format!("{:?}@{}:{}", def_id, bb.index(), stmt))
+        .collect()
+}
+
+pub fn add_breakpoint(pcx: &mut PrirodaContext, bp: &str) -> Result<(), String> {
+    let bp = crate::step::parse_breakpoint_from_url(bp)?;
+    pcx.config.bptree.add_breakpoint(bp);
+    Ok(())
+}
+
+/// Returns whether the breakpoint existed.
+pub fn remove_breakpoint(pcx: &mut PrirodaContext, bp: &str) -> Result<bool, String> {
+    let bp = crate::step::parse_breakpoint_from_url(bp)?;
+    Ok(pcx.config.bptree.remove_breakpoint(bp))
+}
+
+/// Set the condition of a breakpoint, see the `condition` module for the syntax. An empty
+/// condition removes the condition.
+pub fn set_breakpoint_condition(pcx: &mut PrirodaContext, bp: &str, condition: &str) -> Result<(), String> {
+    let bp = crate::step::parse_breakpoint_from_url(bp)?;
+    let condition = if condition.trim().is_empty() {
+        None
+    } else {
+        Some(crate::condition::Condition::parse(condition)?)
+    };
+    if pcx.config.bptree.set_condition(bp, condition) {
+        Ok(())
+    } else {
+        Err(format!("No breakpoint {:?}@{}:{}", bp.0, bp.1.index(), bp.2))
+    }
+}
+
+pub fn remove_all_breakpoints(pcx: &mut PrirodaContext) {
+    pcx.config.bptree.remove_all();
+}
+
+/// The path substrings of functions `step` doesn't stop in.
+pub fn skip_patterns(pcx: &PrirodaContext) -> Vec<String> {
+    pcx.config.skip.clone()
+}
+
+/// Returns `false` if the pattern was already in the skip list.
+pub fn add_skip_pattern(pcx: &mut PrirodaContext, pattern: &str) -> bool {
+    if pattern.is_empty() || pcx.config.skip.iter().any(|p| p == pattern) {
+        return false;
+    }
+    pcx.config.skip.push(pattern.to_string());
+    true
+}
+
+/// Returns `false` if the pattern wasn't in the skip list.
+pub fn remove_skip_pattern(pcx: &mut PrirodaContext, pattern: &str) -> bool {
+    let len = pcx.config.skip.len();
+    pcx.config.skip.retain(|p| p != pattern);
+    pcx.config.skip.len() != len
+}
+
+/// An allocation of the interpreter memory.
+#[derive(Serialize, Debug, Clone)]
+pub struct AllocInfo {
+    pub alloc_id: u64,
+    /// See `ORIGINS`
+    pub origin: &'static str,
+    pub size: u64,
+    pub align: u64,
+    /// The number of pointers stored in the allocation
+    pub relocations: usize,
+}
+
+/// Where allocations come from: `stack` for locals, `heap` and `c heap` for the allocators of
+/// the program, `argv/env` for the arguments and environment set up by the interpreter,
+/// `static` for mutable statics and `vtable` for vtables.
+pub const ORIGINS: &[&str] = &["stack", "heap", "c heap", "argv/env", "static", "vtable"];
+
+/// The origins of allocations made by the interpreter rather than the program.
+pub const MACHINE_ORIGINS: &[&str] = &["argv/env", "vtable"];
+
+pub(crate) fn origin(kind: &MemoryKind<miri::MiriMemoryKind>) -> &'static str {
+    match kind {
+        MemoryKind::Stack => "stack",
+        MemoryKind::Vtable => "vtable",
+        MemoryKind::Machine(miri::MiriMemoryKind::Rust) => "heap",
+        MemoryKind::Machine(miri::MiriMemoryKind::C) => "c heap",
+        MemoryKind::Machine(miri::MiriMemoryKind::Env) => "argv/env",
+        MemoryKind::Machine(miri::MiriMemoryKind::MutStatic) => "static",
+    }
+}
+
+/// All allocations of the interpreter memory ordered by their id.
+pub fn allocations(pcx: &PrirodaContext) -> Vec<AllocInfo> {
+    let mut allocs = pcx.ecx.memory().alloc_map().iter(|values| {
+        values
+            .map(|(&id, (kind, alloc))| AllocInfo {
+                alloc_id: id.0,
+                origin: origin(kind),
+                size: alloc.bytes.len() as u64,
+                align: alloc.align.bytes(),
+                relocations: alloc.relocations.len(),
+            })
+            .collect::<Vec<_>>()
+    });
+    allocs.sort_by_key(|alloc| alloc.alloc_id);
+    allocs
+}
+
+/// A summary of the run so far, meant for when the interpretation finished.
+#[derive(Serialize, Debug, Clone)]
+pub struct PostMortem {
+    /// `finished`, the last error or `running`
+    pub result: String,
+    pub step_count: u128,
+    pub changed_statics: Vec<ChangedStatic>,
+    /// The number of heap allocations made
+    pub allocations: u64,
+    /// Heap and interpreter allocations which were never deallocated
+    pub leaked: Vec<LeakedAlloc>,
+    /// The number of leaked allocations of every origin, see `ORIGINS`
+    pub leaked_by_origin: Vec<(&'static str, usize)>,
+    /// The ids of the leaked allocations of every size, largest first
+    pub leaked_by_size: Vec<(u64, Vec<u64>)>,
+    pub leaked_bytes: u64,
+    /// The number of heap and interpreter allocations made and freed
+    pub made: u64,
+    pub freed: u64,
+    pub freed_bytes: u64,
+    /// The most bytes of heap and interpreter allocations live at once
+    pub peak_live_bytes: u64,
+    /// The 20 functions most steps were executed in, not counting the functions they called
+    pub functions: Vec<(String, u128)>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ChangedStatic {
+    pub name: String,
+    pub initial: Memory,
+    pub last: Memory,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LeakedAlloc {
+    pub alloc_id: u64,
+    pub origin: &'static str,
+    pub size: u64,
+    /// The step and function which made the allocation, `None` for allocations made before the
+    /// first step
+    pub created: Option<String>,
+}
+
+/// The heap and interpreter allocations which are still live, ordered by their id.
+pub fn leaks(pcx: &PrirodaContext) -> Vec<LeakedAlloc> {
+    let mut leaked = pcx.ecx.memory().alloc_map().iter(|values| {
+        values
+            .filter(|(_, (kind, _))| crate::watch::is_heap(kind))
+            .map(|(&id, (kind, alloc))| LeakedAlloc {
+                alloc_id: id.0,
+                origin: origin(kind),
+                size: alloc.bytes.len() as u64,
+                created: pcx.traces.heap().creation_site(id).map(str::to_string),
+            })
+            .collect::<Vec<_>>()
+    });
+    leaked.sort_by_key(|alloc| alloc.alloc_id);
+    leaked
+}
+
+pub fn postmortem(pcx: &PrirodaContext) -> PostMortem {
+    let finished = match pcx.last_stop {
+        Some(StopReason::Finished) => true,
+        // `main` returned before the last command, which then didn't execute anything
+        _ => pcx.ecx.stack().len() <= 1 && crate::step::is_ret(&pcx.ecx),
+    };
+    let result = if finished {
+        "finished".to_string()
+    } else if let Some(error) = pcx.events.errors().last() {
+        error.clone()
+    } else {
+        "running".to_string()
+    };
+
+    let statics = pcx.ecx.memory().alloc_map().iter(|values| {
+        values
+            .filter(|(_, (kind, _))| *kind == MemoryKind::Machine(miri::MiriMemoryKind::MutStatic))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>()
+    });
+    let mut changed_statics = statics
+        .into_iter()
+        .filter_map(|id| {
+            let (name, initial) = initial_static(pcx, id)?;
+            let last = memory(pcx, id.0)?;
+            if initial == last {
+                return None;
+            }
+            Some(ChangedStatic { name, initial, last })
+        })
+        .collect::<Vec<_>>();
+    changed_statics.sort_by(|a, b| a.name.cmp(&b.name));
+    let leaked = leaks(pcx);
+    let leaked_by_origin = ORIGINS
+        .iter()
+        .map(|&origin| (origin, leaked.iter().filter(|alloc| alloc.origin == origin).count()))
+        .filter(|&(_, count)| count != 0)
+        .collect();
+    let mut leaked_by_size = std::collections::BTreeMap::<u64, Vec<u64>>::new();
+    for alloc in &leaked {
+        leaked_by_size.entry(alloc.size).or_default().push(alloc.alloc_id);
+    }
+    let leaked_by_size = leaked_by_size.into_iter().rev().collect();
+    let heap = pcx.traces.heap();
+
+    let mut functions = pcx
+        .traces
+        .steps_per_function()
+        .into_iter()
+        .map(|(instance, steps)| (pcx.ecx.tcx.def_path_str(instance.def_id()), steps))
+        .collect::<Vec<_>>();
+    functions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    functions.truncate(20);
+
+    let mut errors = pcx.events.errors().to_vec();
+    errors.extend(pcx.events.error().map(str::to_string));
+
+    PostMortem {
+        result,
+        step_count: *pcx.step_count,
+        changed_statics,
+        allocations: pcx.traces.heap_allocations(),
+        leaked_bytes: leaked.iter().map(|alloc| alloc.size).sum(),
+        leaked,
+        leaked_by_origin,
+        leaked_by_size,
+        made: heap.made,
+        freed: heap.freed,
+        freed_bytes: heap.freed_bytes,
+        peak_live_bytes: heap.peak_live_bytes,
+        functions,
+        errors,
+    }
+}
+
+/// The path of the mutable static `alloc_id` and its contents before the program wrote to it,
+/// which is the value it was initialized with. Mutable statics are only copied into the
+/// interpreter memory on their first write.
+fn initial_static(pcx: &PrirodaContext, alloc_id: AllocId) -> Option<(String, Memory)> {
+    let def_id = match pcx.ecx.tcx.alloc_map.lock().get(alloc_id) {
+        Some(GlobalAlloc::Static(def_id)) => def_id,
+        _ => return None,
+    };
+    let cid = GlobalId {
+        instance: Instance::mono(pcx.ecx.tcx.tcx, def_id),
+        promoted: None,
+    };
+    let raw = pcx.ecx.tcx.const_eval_raw(ParamEnv::reveal_all().and(cid)).ok()?;
+    let alloc = pcx.ecx.tcx.alloc_map.lock().unwrap_memory(raw.alloc_id);
+    Some((pcx.ecx.tcx.def_path_str(def_id), alloc_memory(alloc_id.0, alloc)))
+}
+
+pub(crate) fn html_to_text(html: &str) -> String {
+    lazy_static::lazy_static! {
+        static ref TAG: regex::Regex = regex::Regex::new("<[^>]*>").unwrap();
+    }
+    TAG.replace_all(html, "")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#x2F;", "/")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{command, current_fn, with_program};
+
+    const NESTED: &str = r#"
+struct Leaf { value: u32 }
+struct Inner { leaf: Leaf }
+struct Outer { inner: Inner }
+
+fn inspect(outer: Outer) -> u32 {
+    outer.inner.leaf.value
+}
+
+fn main() {
+    inspect(Outer { inner: Inner { leaf: Leaf { value: 42 } } });
+}
+"#;
+
+    #[test]
+    fn local_full_depth() {
+        let (shallow, deep) = with_program(NESTED, |pcx| {
+            while current_fn(pcx) != "inspect" {
+                command(pcx, "step");
+            }
+            let frame = pcx.ecx.stack().len() - 1;
+            let shallow = super::local_full(pcx, frame, 1, Some(1), false).unwrap();
+            let deep = super::local_full(pcx, frame, 1, Some(10), false).unwrap();
+            (shallow.value, deep.value)
+        });
+        assert!(shallow.contains('…'), "{}", shallow);
+        assert!(!shallow.contains("42"), "{}", shallow);
+        assert!(deep.contains("42"), "{}", deep);
+        assert!(!deep.contains('…'), "{}", deep);
+    }
+
+    #[test]
+    fn local_full_missing_local() {
+        let res = with_program(NESTED, |pcx| {
+            let frame = pcx.ecx.stack().len() - 1;
+            super::local_full(pcx, frame, 1000, None, false).map(|local| local.value)
+        });
+        match res {
+            Err(err) => assert!(err.starts_with("No local _1000 "), "{}", err),
+            Ok(value) => panic!("rendered a missing local as {}", value),
+        }
+    }
+
+    const STATIC_WRITE: &str = r#"
+static mut COUNTER: u32 = 1;
+
+fn main() {
+    unsafe {
+        COUNTER += 1;
+    }
+}
+"#;
+
+    #[test]
+    fn postmortem_changed_statics() {
+        let postmortem = with_program(STATIC_WRITE, |pcx| {
+            command(pcx, "continue");
+            super::postmortem(pcx)
+        });
+        assert_eq!(postmortem.result, "finished");
+        assert_eq!(postmortem.changed_statics.len(), 1, "{:?}", postmortem.changed_statics);
+        let changed = &postmortem.changed_statics[0];
+        assert_eq!(changed.name, "COUNTER");
+        assert_eq!(changed.initial.bytes, [1, 0, 0, 0].iter().map(|&byte| Some(byte)).collect::<Vec<_>>());
+        assert_eq!(changed.last.bytes, [2, 0, 0, 0].iter().map(|&byte| Some(byte)).collect::<Vec<_>>());
+    }
+
+    const LEAK: &str = r#"
+fn main() {
+    let freed = Box::new(1u64);
+    drop(freed);
+    let v = vec![1u32, 2, 3];
+    std::mem::forget(v);
+}
+"#;
+
+    #[test]
+    fn postmortem_leaks() {
+        let (environment, postmortem) = with_program(LEAK, |pcx| {
+            let environment = super::leaks(pcx).into_iter().map(|alloc| alloc.alloc_id).collect::<Vec<_>>();
+            command(pcx, "continue");
+            (environment, super::postmortem(pcx))
+        });
+        assert_eq!(postmortem.made, environment.len() as u64 + 2);
+        assert_eq!((postmortem.freed, postmortem.freed_bytes), (1, 8));
+        let leaked = postmortem.leaked.iter().filter(|alloc| !environment.contains(&alloc.alloc_id)).collect::<Vec<_>>();
+        assert_eq!(leaked.len(), 1, "{:?}", postmortem.leaked);
+        assert_eq!(leaked[0].size, 12);
+        assert!(leaked[0].created.as_ref().unwrap().contains(" in main "), "{:?}", leaked[0]);
+    }
+}
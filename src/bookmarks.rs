@@ -0,0 +1,146 @@
+//! Named positions of the execution the user can return to.
+//!
+//! The interpreter state can't be snapshotted, so a bookmark only stores the step count and is
+//! restored by replaying. That reproduces the state as long as no mutation changed the
+//! execution before the bookmark, otherwise the bookmark is marked as stale.
+
+use std::collections::VecDeque;
+
+use horrorshow::prelude::*;
+
+use crate::*;
+
+pub struct Bookmark {
+    name: String,
+    step: u128,
+    position: Option<api::Position>,
+    /// Why replaying doesn't reproduce the bookmarked state
+    stale: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Bookmarks(Vec<Bookmark>);
+
+impl Bookmarks {
+    /// Mark the bookmarks after `step` as stale, because the execution was changed at `step`.
+    pub fn invalidate_after(&mut self, step: u128, reason: &str) {
+        for bookmark in &mut self.0 {
+            if bookmark.step > step && bookmark.stale.is_none() {
+                bookmark.stale = Some(reason.to_string());
+            }
+        }
+    }
+}
+
+/// The step counts before the last few commands that moved the execution, most recent last.
+/// Undoing a command replays to the step count before it, like restoring a bookmark.
+#[derive(Default)]
+pub struct UndoHistory(VecDeque<(u128, String)>);
+
+impl UndoHistory {
+    /// Remember that `command` was executed at step `step`, forgetting the oldest entries beyond
+    /// `depth`.
+    pub fn push(&mut self, step: u128, command: &str, depth: usize) {
+        self.0.push_back((step, command.to_string()));
+        while self.0.len() > depth {
+            self.0.pop_front();
+        }
+    }
+}
+
+/// Restore the state before the most recent command that moved the execution.
+pub fn undo(pcx: &mut PrirodaContext) -> Result<String, String> {
+    let (step, command) = match pcx.undo_history.0.pop_back() {
+        Some(entry) => entry,
+        None => return Ok("Nothing to undo, no command moved the execution since the session started".to_string()),
+    };
+    // Replaying only reproduces mutations made after the target step by dropping them
+    let lost_mutations = pcx.mutations.has_active(step);
+    crate::step::replay_to(pcx, step)?;
+    Ok(if lost_mutations {
+        format!("Undid {:?} and replayed to step {}, discarding the mutations made before it", command, step)
+    } else {
+        format!("Undid {:?}, back at step {}", command, step)
+    })
+}
+
+/// Bookmark the current position as `name`, replacing an existing bookmark of that name.
+pub fn add(pcx: &mut PrirodaContext, name: &str) -> String {
+    let step = *pcx.step_count;
+    let stale = if pcx.mutations.has_active(step) {
+        Some("taken after a mutation, which isn't replayed".to_string())
+    } else {
+        None
+    };
+    let bookmark = Bookmark {
+        name: name.to_string(),
+        step,
+        position: api::position(pcx),
+        stale,
+    };
+    pcx.bookmarks.0.retain(|b| b.name != name);
+    pcx.bookmarks.0.push(bookmark);
+    format!("Bookmarked step {} as {:?}", step, name)
+}
+
+pub fn list(pcx: &PrirodaContext) -> String {
+    if pcx.bookmarks.0.is_empty() {
+        return "No bookmarks".to_string();
+    }
+    pcx.bookmarks
+        .0
+        .iter()
+        .map(|b| {
+            let mut s = format!("{} at step {}", b.name, b.step);
+            if let Some(pos) = &b.position {
+                s.push_str(&format!(" in {} {}:{}", pos.function, pos.block, pos.stmt));
+            }
+            if let Some(reason) = &b.stale {
+                s.push_str(&format!(" (stale: {})", reason));
+            }
+            s
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn goto(pcx: &mut PrirodaContext, name: &str) -> Result<String, String> {
+    let (step, stale) = match pcx.bookmarks.0.iter().find(|b| b.name == name) {
+        Some(b) => (b.step, b.stale.clone()),
+        None => return Err(format!("No bookmark {:?}", name)),
+    };
+    crate::step::replay_to(pcx, step)?;
+    Ok(match stale {
+        Some(reason) => format!("Replayed to bookmark {:?} at step {}, but it is stale: {}", name, step, reason),
+        None => format!("Restored bookmark {:?} at step {}", name, step),
+    })
+}
+
+pub fn render_bookmarks(pcx: &PrirodaContext) -> impl RenderOnce + '_ {
+    owned_html! {
+        table(border="1") {
+            @ for b in &pcx.bookmarks.0 {
+                tr(style=if b.stale.is_some() { "color: gray;" } else { "" }, title=b.stale.clone().unwrap_or_else(String::new)) {
+                    td { a(href=format!("/bookmarks/goto?name={}", rocket::http::uri::Uri::percent_encode(&b.name))) { : &b.name } }
+                    td { : b.step.to_string() }
+                    td { : b.position.as_ref().map(|pos| format!("{} {}:{}", pos.function, pos.block, pos.stmt)).unwrap_or_else(String::new) }
+                }
+            }
+        }
+        form(action="/bookmarks/add") {
+            input(name="name", placeholder="bookmark name");
+        }
+    }
+}
+
+pub fn routes() -> Vec<::rocket::Route> {
+    routes![bookmarks::add_route, bookmarks::goto_route]
+}
+
+action_route!(add_route: "/add?<name>", |pcx, name: String| {
+    crate::step::step_command(pcx, &format!("bookmark {}", name))
+});
+
+action_route!(goto_route: "/goto?<name>", |pcx, name: String| {
+    crate::step::step_command(pcx, &format!("goto-bookmark {}", name))
+});
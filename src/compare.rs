@@ -0,0 +1,311 @@
+//! Comparing two allocations byte by byte, e.g. to check that one is a copy of the other.
+//!
+//! Pointers are compared by the allocation they point to, as their bytes only hold the offset
+//! into it. Bytes past the end of the shorter allocation always differ.
+
+use rustc::ty::layout::Size;
+
+use horrorshow::prelude::*;
+use horrorshow::Raw;
+
+use miri::{AllocId, Allocation, Tag};
+
+use crate::render::locals;
+use crate::*;
+
+/// The summary lists at most this many differing ranges.
+const SHOWN_RANGES: usize = 20;
+
+/// What a byte of an allocation holds, as far as the comparison is concerned.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Cell {
+    Byte(u8),
+    Undef,
+    /// A byte of a pointer to the allocation
+    Pointer(u64, u8),
+}
+
+/// The cells of an allocation in increasing offset order.
+struct Cells<'a, Extra> {
+    alloc: &'a Allocation<Tag, Extra>,
+    /// The offsets and targets of the pointers
+    relocations: Vec<(u64, u64)>,
+    /// The first pointer which doesn't end before the current offset
+    next: usize,
+    pointer_size: u64,
+}
+
+impl<'a, Extra> Cells<'a, Extra> {
+    fn new(alloc: &'a Allocation<Tag, Extra>, pointer_size: u64) -> Self {
+        Cells {
+            alloc,
+            relocations: alloc.relocations.iter().map(|(offset, &(_tag, target))| (offset.bytes(), target.0)).collect(),
+            next: 0,
+            pointer_size,
+        }
+    }
+
+    /// The cell at `offset`, which has to be at least the offset of the last call.
+    fn cell(&mut self, offset: u64) -> Option<Cell> {
+        if offset >= self.alloc.bytes.len() as u64 {
+            return None;
+        }
+        while self.next < self.relocations.len() && self.relocations[self.next].0 + self.pointer_size <= offset {
+            self.next += 1;
+        }
+        let byte = self.alloc.bytes[offset as usize];
+        Some(match self.relocations.get(self.next) {
+            Some(&(start, target)) if start <= offset => Cell::Pointer(target, byte),
+            _ if !self.alloc.undef_mask.get(Size::from_bytes(offset)) => Cell::Undef,
+            _ => Cell::Byte(byte),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Comparison {
+    pub len_a: u64,
+    pub len_b: u64,
+    /// The ranges of offsets whose cells differ, including the overhang of the longer allocation
+    pub ranges: Vec<(u64, u64)>,
+    /// The number of defined bytes with different values
+    pub bytes: u64,
+    /// The number of bytes defined in only one of the allocations
+    pub undef: u64,
+    /// The number of pointer bytes which differ in their target or aren't a pointer in the other
+    /// allocation
+    pub pointers: u64,
+}
+
+/// Compare the allocations `a` and `b` byte by byte.
+pub fn compare<Extra>(a: &Allocation<Tag, Extra>, b: &Allocation<Tag, Extra>, pointer_size: u64) -> Comparison {
+    let mut comparison = Comparison {
+        len_a: a.bytes.len() as u64,
+        len_b: b.bytes.len() as u64,
+        ..Comparison::default()
+    };
+    let common = comparison.len_a.min(comparison.len_b);
+    let (mut cells_a, mut cells_b) = (Cells::new(a, pointer_size), Cells::new(b, pointer_size));
+    let mut start = None;
+    for offset in 0..=common {
+        let cells = if offset < common { Some((cells_a.cell(offset), cells_b.cell(offset))) } else { None };
+        let differs = match cells {
+            Some((a, b)) if a != b => {
+                match (a, b) {
+                    (Some(Cell::Pointer(..)), _) | (_, Some(Cell::Pointer(..))) => comparison.pointers += 1,
+                    (Some(Cell::Undef), _) | (_, Some(Cell::Undef)) => comparison.undef += 1,
+                    _ => comparison.bytes += 1,
+                }
+                true
+            }
+            _ => false,
+        };
+        match (start, differs) {
+            (None, true) => start = Some(offset),
+            (Some(s), false) => {
+                comparison.ranges.push((s, offset));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if comparison.len_a != comparison.len_b {
+        let end = comparison.len_a.max(comparison.len_b);
+        // Extend a difference reaching the end of the shorter allocation
+        match comparison.ranges.last_mut() {
+            Some(last) if last.1 == common => last.1 = end,
+            _ => comparison.ranges.push((common, end)),
+        }
+    }
+    comparison
+}
+
+fn summary(comparison: &Comparison) -> String {
+    if comparison.ranges.is_empty() {
+        return "The allocations are identical".to_string();
+    }
+    let mut ranges = comparison
+        .ranges
+        .iter()
+        .take(SHOWN_RANGES)
+        .map(|(start, end)| format!("0x{:x}..0x{:x}", start, end))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if comparison.ranges.len() > SHOWN_RANGES {
+        ranges.push_str(", …");
+    }
+    format!(
+        "Differs at {} range{}: {} ({} bytes, {} undefined in one of them, {} pointer bytes)",
+        comparison.ranges.len(),
+        if comparison.ranges.len() == 1 { "" } else { "s" },
+        ranges,
+        comparison.bytes,
+        comparison.undef,
+        comparison.pointers,
+    )
+}
+
+/// The differing ranges overlapping `start..end`.
+fn ranges_in(ranges: &[(u64, u64)], start: u64, end: u64) -> Vec<(u64, u64)> {
+    ranges.iter().cloned().filter(|&(s, e)| s < end && e > start).collect()
+}
+
+pub fn render_compare(pcx: &PrirodaContext, a: u64, b: u64, offset: u64, len: Option<u64>) -> Html<String> {
+    let memory = pcx.ecx.memory();
+    let (alloc_a, alloc_b) = match (memory.get(AllocId(a)), memory.get(AllocId(b))) {
+        (Ok(alloc_a), Ok(alloc_b)) => (alloc_a, alloc_b),
+        (Err(_), _) => return crate::render::template(pcx, "Compare allocations".to_string(), html! {
+            p { : format!("Allocation {} has no bytes", a) }
+        }),
+        (_, Err(_)) => return crate::render::template(pcx, "Compare allocations".to_string(), html! {
+            p { : format!("Allocation {} has no bytes", b) }
+        }),
+    };
+    let comparison = compare(alloc_a, alloc_b, pcx.ecx.tcx.data_layout.pointer_size.bytes());
+    let len = len.unwrap_or(crate::render::ALLOC_WINDOW).max(locals::HEXDUMP_ROW);
+    let start = offset - offset % locals::HEXDUMP_ROW;
+    let end = start.saturating_add(len);
+    let highlight = ranges_in(&comparison.ranges, start, end);
+    let annotations = locals::Annotations { highlight: &highlight, ..locals::Annotations::default() };
+    let dump_a = locals::print_alloc(&pcx.ecx, AllocId(a), alloc_a, start, end, &annotations);
+    let dump_b = locals::print_alloc(&pcx.ecx, AllocId(b), alloc_b, start, end, &annotations);
+    let longest = comparison.len_a.max(comparison.len_b);
+    let overhang = |own: u64, other: u64, other_id: u64| {
+        if own < other && own < end {
+            Some(format!("ends at 0x{:x}, the {} bytes after it only exist in allocation {}", own, other - own, other_id))
+        } else {
+            None
+        }
+    };
+    let (overhang_a, overhang_b) = (overhang(comparison.len_a, comparison.len_b, b), overhang(comparison.len_b, comparison.len_a, a));
+    crate::render::template(
+        pcx,
+        format!("Allocation {} and {}", a, b),
+        html! {
+            a(href="/") { : "Back" }
+            form(action="/compare") {
+                : "Compare allocation ";
+                input(name="a", size="6", value=a.to_string());
+                : " with ";
+                input(name="b", size="6", value=b.to_string());
+                : " from offset ";
+                input(name="offset", size="8", value=start.to_string());
+                : " ";
+                input(type="submit", value="Compare");
+            }
+            p { : summary(&comparison) }
+            p {
+                : format!("Bytes {}..{} of {} ", start, end.min(longest), longest);
+                @ if start > 0 {
+                    a(href=format!("/compare/{}/{}?offset={}&len={}", a, b, start.saturating_sub(len), len)) { : "previous" }
+                    : " ";
+                }
+                @ if end < longest {
+                    a(href=format!("/compare/{}/{}?offset={}&len={}", a, b, end, len)) { : "next" }
+                }
+            }
+            table {
+                tr {
+                    th { a(href=format!("/ptr/{}/{}", a, start)) { : format!("Allocation {} ({} bytes)", a, comparison.len_a) } }
+                    th { a(href=format!("/ptr/{}/{}", b, start)) { : format!("Allocation {} ({} bytes)", b, comparison.len_b) } }
+                }
+                tr(style="vertical-align: top;") {
+                    td {
+                        : Raw(dump_a);
+                        @ if let Some(overhang) = overhang_a {
+                            p(style="color: white; background: red;") { : overhang }
+                        }
+                    }
+                    td {
+                        : Raw(dump_b);
+                        @ if let Some(overhang) = overhang_b {
+                            p(style="color: white; background: red;") { : overhang }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+pub fn routes() -> Vec<::rocket::Route> {
+    routes![compare::show, compare::form]
+}
+
+view_route!(show: "/<a>/<b>?<offset>&<len>", |pcx, a: u64, b: u64, offset: Option<u64>, len: Option<u64>| {
+    render_compare(pcx, a, b, offset.unwrap_or(0), len)
+});
+
+view_route!(form: "/?<a>&<b>&<offset>&<len>", |pcx, a: u64, b: u64, offset: Option<u64>, len: Option<u64>| {
+    render_compare(pcx, a, b, offset.unwrap_or(0), len)
+});
+
+#[cfg(test)]
+mod tests {
+    use rustc::ty::layout::Align;
+
+    use super::*;
+
+    fn alloc(bytes: &[u8]) -> Allocation<Tag> {
+        Allocation::from_bytes(bytes, Align::from_bytes(1).unwrap())
+    }
+
+    #[test]
+    fn identical() {
+        let comparison = compare(&alloc(&[1, 2, 3, 4]), &alloc(&[1, 2, 3, 4]), 8);
+        assert!(comparison.ranges.is_empty());
+        assert_eq!((comparison.bytes, comparison.undef, comparison.pointers), (0, 0, 0));
+        assert_eq!(summary(&comparison), "The allocations are identical");
+    }
+
+    #[test]
+    fn differing_bytes() {
+        let comparison = compare(&alloc(&[1, 2, 3, 4, 5, 6]), &alloc(&[1, 0, 0, 4, 5, 0]), 8);
+        assert_eq!(comparison.ranges, vec![(1, 3), (5, 6)]);
+        assert_eq!((comparison.bytes, comparison.undef, comparison.pointers), (3, 0, 0));
+    }
+
+    #[test]
+    fn undefined_bytes() {
+        let a = alloc(&[1, 2, 3, 4]);
+        let mut b = alloc(&[1, 2, 3, 4]);
+        b.undef_mask.set_range(Size::from_bytes(2), Size::from_bytes(4), false);
+        let comparison = compare(&a, &b, 8);
+        assert_eq!(comparison.ranges, vec![(2, 4)]);
+        assert_eq!((comparison.bytes, comparison.undef, comparison.pointers), (0, 2, 0));
+        // Undefined bytes compare equal whatever their value
+        let mut c = alloc(&[1, 2, 0, 0]);
+        c.undef_mask.set_range(Size::from_bytes(2), Size::from_bytes(4), false);
+        assert!(compare(&b, &c, 8).ranges.is_empty());
+    }
+
+    #[test]
+    fn relocations() {
+        let mut a = alloc(&[0; 12]);
+        let mut b = alloc(&[0; 12]);
+        a.relocations.insert(Size::from_bytes(0), (Tag::Untagged, AllocId(1)));
+        b.relocations.insert(Size::from_bytes(0), (Tag::Untagged, AllocId(1)));
+        assert!(compare(&a, &b, 4).ranges.is_empty());
+
+        // The same offset into another allocation
+        b.relocations.insert(Size::from_bytes(0), (Tag::Untagged, AllocId(2)));
+        // A pointer in `a` where `b` holds the same bytes
+        a.relocations.insert(Size::from_bytes(8), (Tag::Untagged, AllocId(1)));
+        let comparison = compare(&a, &b, 4);
+        assert_eq!(comparison.ranges, vec![(0, 4), (8, 12)]);
+        assert_eq!((comparison.bytes, comparison.undef, comparison.pointers), (0, 0, 8));
+    }
+
+    #[test]
+    fn overhang() {
+        let comparison = compare(&alloc(&[1, 2, 3]), &alloc(&[1, 2, 3, 4, 5]), 8);
+        assert_eq!((comparison.len_a, comparison.len_b), (3, 5));
+        assert_eq!(comparison.ranges, vec![(3, 5)]);
+        assert_eq!(comparison.bytes, 0);
+
+        // A difference reaching the end of the shorter allocation is extended
+        let comparison = compare(&alloc(&[1, 2, 0]), &alloc(&[1, 2, 3, 4, 5]), 8);
+        assert_eq!(comparison.ranges, vec![(2, 5)]);
+        assert_eq!(comparison.bytes, 1);
+    }
+}
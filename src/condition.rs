@@ -0,0 +1,350 @@
+//! A small expression language for breakpoint conditions.
+//!
+//! Conditions compare integers and strings and combine the comparisons with `&&`, `||` and
+//! parentheses, e.g. `$step > 1000000 && ($hits(2) > 0 || $depth == 3)`. The `$` variables are
+//! read from the debugger session, not from the interpreted program:
+//!
+//! * `$step`: the global step count
+//! * `$depth`: the stack depth
+//! * `$frame_fn`: the path of the current function, can only be compared with `==` and `!=`
+//! * `$hits(<id>)`: how often the breakpoint with id `<id>` has been hit
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Int(u128),
+    Str(String),
+    Step,
+    Depth,
+    FrameFn,
+    Hits(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Cmp(Term, CmpOp, Term),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Term(Term),
+    Op(CmpOp),
+    And,
+    Or,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Int(u128),
+    Str(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Str(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// The session state conditions are evaluated against.
+pub struct Session<'a> {
+    pub step: u128,
+    pub depth: usize,
+    pub frame_fn: String,
+    /// The hit count of the breakpoint with the given id, `None` if there is no such breakpoint.
+    pub hits: &'a dyn Fn(usize) -> Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut pos = 0;
+        let expr = parse_or(&tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(format!("Unexpected {:?} in condition", tokens[pos]));
+        }
+        Ok(Condition {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn eval(&self, session: &Session) -> Result<bool, String> {
+        eval_expr(&self.expr, session)
+    }
+
+    /// The current values of all variables used in the condition, e.g. `$step = 42`.
+    pub fn preview(&self, session: &Session) -> String {
+        let mut terms = Vec::new();
+        collect_vars(&self.expr, &mut terms);
+        terms
+            .into_iter()
+            .map(|term| match eval_term(&term, session) {
+                Ok(val) => format!("{} = {}", term_name(&term), val),
+                Err(e) => format!("{}: {}", term_name(&term), e),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            '0'..='9' => {
+                let mut num = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() || c == '_' {
+                        num.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let num = num.replace("_", "");
+                tokens.push(Token::Term(Term::Int(
+                    num.parse().map_err(|_| format!("Invalid number {}", num))?,
+                )));
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err("Unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Term(Term::Str(string)));
+            }
+            '$' => {
+                chars.next();
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        name.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let term = match &name[..] {
+                    "step" => Term::Step,
+                    "depth" => Term::Depth,
+                    "frame_fn" => Term::FrameFn,
+                    "hits" => {
+                        let mut arg = String::new();
+                        if chars.next() != Some('(') {
+                            return Err("Expected `(` after `$hits`".to_string());
+                        }
+                        loop {
+                            match chars.next() {
+                                Some(')') => break,
+                                Some(c) => arg.push(c),
+                                None => return Err("Unterminated `$hits(`".to_string()),
+                            }
+                        }
+                        Term::Hits(
+                            arg.trim()
+                                .parse()
+                                .map_err(|_| format!("Invalid breakpoint id {:?}", arg))?,
+                        )
+                    }
+                    _ => return Err(format!("Unknown variable ${}", name)),
+                };
+                tokens.push(Token::Term(term));
+            }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                chars.next();
+                let next_eq = chars.peek() == Some(&'=');
+                let token = match (c, chars.peek()) {
+                    ('=', Some('=')) => Token::Op(CmpOp::Eq),
+                    ('!', Some('=')) => Token::Op(CmpOp::Ne),
+                    ('<', Some('=')) => Token::Op(CmpOp::Le),
+                    ('>', Some('=')) => Token::Op(CmpOp::Ge),
+                    ('&', Some('&')) => Token::And,
+                    ('|', Some('|')) => Token::Or,
+                    ('<', _) => Token::Op(CmpOp::Lt),
+                    ('>', _) => Token::Op(CmpOp::Gt),
+                    _ => return Err(format!("Unexpected `{}`", c)),
+                };
+                if next_eq || token == Token::And || token == Token::Or {
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+            _ => return Err(format!("Unexpected `{}`", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_or(tokens: &[Token], pos: &mut usize) -> Result<Expr, String> {
+    let mut lhs = parse_and(tokens, pos)?;
+    while tokens.get(*pos) == Some(&Token::Or) {
+        *pos += 1;
+        lhs = Expr::Or(Box::new(lhs), Box::new(parse_and(tokens, pos)?));
+    }
+    Ok(lhs)
+}
+
+fn parse_and(tokens: &[Token], pos: &mut usize) -> Result<Expr, String> {
+    let mut lhs = parse_atom(tokens, pos)?;
+    while tokens.get(*pos) == Some(&Token::And) {
+        *pos += 1;
+        lhs = Expr::And(Box::new(lhs), Box::new(parse_atom(tokens, pos)?));
+    }
+    Ok(lhs)
+}
+
+fn parse_atom(tokens: &[Token], pos: &mut usize) -> Result<Expr, String> {
+    if tokens.get(*pos) == Some(&Token::LParen) {
+        *pos += 1;
+        let expr = parse_or(tokens, pos)?;
+        if tokens.get(*pos) != Some(&Token::RParen) {
+            return Err("Expected `)`".to_string());
+        }
+        *pos += 1;
+        return Ok(expr);
+    }
+    let lhs = parse_term(tokens, pos)?;
+    let op = match tokens.get(*pos) {
+        Some(Token::Op(op)) => *op,
+        other => return Err(format!("Expected a comparison, found {:?}", other)),
+    };
+    *pos += 1;
+    let rhs = parse_term(tokens, pos)?;
+    if (is_str(&lhs) || is_str(&rhs)) && op != CmpOp::Eq && op != CmpOp::Ne {
+        return Err("Strings can only be compared with `==` and `!=`".to_string());
+    }
+    Ok(Expr::Cmp(lhs, op, rhs))
+}
+
+fn parse_term(tokens: &[Token], pos: &mut usize) -> Result<Term, String> {
+    match tokens.get(*pos) {
+        Some(Token::Term(term)) => {
+            *pos += 1;
+            Ok(term.clone())
+        }
+        other => Err(format!("Expected a value, found {:?}", other)),
+    }
+}
+
+fn is_str(term: &Term) -> bool {
+    match term {
+        Term::Str(_) | Term::FrameFn => true,
+        _ => false,
+    }
+}
+
+fn eval_term(term: &Term, session: &Session) -> Result<Value, String> {
+    Ok(match term {
+        Term::Int(i) => Value::Int(*i),
+        Term::Str(s) => Value::Str(s.clone()),
+        Term::Step => Value::Int(session.step),
+        Term::Depth => Value::Int(session.depth as u128),
+        Term::FrameFn => Value::Str(session.frame_fn.clone()),
+        Term::Hits(id) => Value::Int(
+            (session.hits)(*id).ok_or_else(|| format!("No breakpoint with id {}", id))? as u128,
+        ),
+    })
+}
+
+fn eval_expr(expr: &Expr, session: &Session) -> Result<bool, String> {
+    match expr {
+        Expr::And(lhs, rhs) => Ok(eval_expr(lhs, session)? && eval_expr(rhs, session)?),
+        Expr::Or(lhs, rhs) => Ok(eval_expr(lhs, session)? || eval_expr(rhs, session)?),
+        Expr::Cmp(lhs, op, rhs) => {
+            let (lhs, rhs) = (eval_term(lhs, session)?, eval_term(rhs, session)?);
+            match (lhs, rhs) {
+                (Value::Int(lhs), Value::Int(rhs)) => Ok(match op {
+                    CmpOp::Eq => lhs == rhs,
+                    CmpOp::Ne => lhs != rhs,
+                    CmpOp::Lt => lhs < rhs,
+                    CmpOp::Le => lhs <= rhs,
+                    CmpOp::Gt => lhs > rhs,
+                    CmpOp::Ge => lhs >= rhs,
+                }),
+                (Value::Str(lhs), Value::Str(rhs)) => match op {
+                    CmpOp::Eq => Ok(lhs == rhs),
+                    CmpOp::Ne => Ok(lhs != rhs),
+                    _ => Err("Strings can only be compared with `==` and `!=`".to_string()),
+                },
+                (lhs, rhs) => Err(format!("Can't compare {} with {}", lhs, rhs)),
+            }
+        }
+    }
+}
+
+fn collect_vars(expr: &Expr, vars: &mut Vec<Term>) {
+    match expr {
+        Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) => {
+            collect_vars(lhs, vars);
+            collect_vars(rhs, vars);
+        }
+        Expr::Cmp(lhs, _, rhs) => {
+            for &term in &[lhs, rhs] {
+                match term {
+                    Term::Int(_) | Term::Str(_) => {}
+                    _ => {
+                        if !vars.contains(term) {
+                            vars.push(term.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn term_name(term: &Term) -> String {
+    match term {
+        Term::Int(i) => i.to_string(),
+        Term::Str(s) => format!("{:?}", s),
+        Term::Step => "$step".to_string(),
+        Term::Depth => "$depth".to_string(),
+        Term::FrameFn => "$frame_fn".to_string(),
+        Term::Hits(id) => format!("$hits({})", id),
+    }
+}
@@ -0,0 +1,110 @@
+//! Evaluation of `const` items and associated consts on demand.
+
+use rustc::hir;
+use rustc::hir::def_id::DefId;
+use rustc::mir::interpret::{ErrorHandled, GlobalId};
+use rustc::ty::{Instance, InternalSubsts, ParamEnv};
+
+use crate::*;
+
+/// All const items and associated consts with a value in the local crate.
+fn local_consts(tcx: TyCtxt) -> Vec<DefId> {
+    let krate = tcx.hir().krate();
+    let items = krate.items.values().filter_map(|item| match item.node {
+        hir::ItemKind::Const(..) => Some(item.hir_id),
+        _ => None,
+    });
+    let impl_items = krate.impl_items.values().filter_map(|item| match item.node {
+        hir::ImplItemKind::Const(..) => Some(item.hir_id),
+        _ => None,
+    });
+    let trait_items = krate.trait_items.values().filter_map(|item| match item.node {
+        hir::TraitItemKind::Const(_, Some(_)) => Some(item.hir_id),
+        _ => None,
+    });
+    items
+        .chain(impl_items)
+        .chain(trait_items)
+        .map(|hir_id| tcx.hir().local_def_id_from_hir_id(hir_id))
+        .collect()
+}
+
+/// Whether `path` names the item with the full path `full_path`. Leading path segments may be
+/// left out and the brackets around impl self types are ignored.
+pub fn path_matches(full_path: &str, path: &str) -> bool {
+    let full_path = full_path.replace("<", "").replace(">", "");
+    let path = path.trim().trim_start_matches("::");
+    full_path == path || full_path.ends_with(&format!("::{}", path))
+}
+
+/// Evaluate the const item `path` and render its value and type.
+pub fn eval_const(pcx: &PrirodaContext, path: &str) -> Result<String, String> {
+    let tcx = pcx.ecx.tcx.tcx;
+    let candidates = local_consts(tcx)
+        .into_iter()
+        .filter(|&def_id| path_matches(&tcx.def_path_str(def_id), path))
+        .collect::<Vec<_>>();
+    let def_id = match &candidates[..] {
+        [] => return Err(format!("No const {} in the local crate", path)),
+        [def_id] => *def_id,
+        _ => {
+            return Err(format!(
+                "{} is ambiguous: {}",
+                path,
+                candidates.iter().map(|&def_id| tcx.def_path_str(def_id)).collect::<Vec<_>>().join(", ")
+            ))
+        }
+    };
+    let name = tcx.def_path_str(def_id);
+
+    // Generic consts can only be evaluated with the generic arguments of the viewed frame
+    let count = tcx.generics_of(def_id).count();
+    let substs = if count == 0 {
+        InternalSubsts::empty()
+    } else {
+        match pcx.view_frame().and_then(|frame| pcx.ecx.stack().get(frame)) {
+            Some(frame) if tcx.parent(frame.instance.def_id()) == tcx.parent(def_id)
+                && frame.instance.substs.len() >= count =>
+            {
+                tcx.mk_substs(frame.instance.substs.iter().take(count).cloned())
+            }
+            _ => {
+                return Err(format!(
+                    "{} is generic, its generic arguments are only known inside of the impl or trait it belongs to",
+                    name
+                ))
+            }
+        }
+    };
+    let instance = match Instance::resolve(tcx, ParamEnv::reveal_all(), def_id, substs) {
+        Some(instance) => instance,
+        None => return Err(format!("Couldn't resolve {}", name)),
+    };
+    let cid = GlobalId {
+        instance,
+        promoted: None,
+    };
+    let value = match tcx.const_eval(ParamEnv::reveal_all().and(cid)) {
+        Ok(value) => value,
+        Err(ErrorHandled::Reported) => {
+            return Err(format!("Evaluating {} failed, the error was reported to the compiler output", name))
+        }
+        Err(ErrorHandled::TooGeneric) => return Err(format!("{} is too generic to be evaluated", name)),
+    };
+    let rendered = pcx
+        .ecx
+        .eval_const_to_op(value, None)
+        .ok()
+        .and_then(|op_ty| crate::render::locals::print_operand(&pcx.ecx, op_ty).ok())
+        .map(|(_alloc, text, _ranges)| crate::api::html_to_text(&text))
+        .unwrap_or_else(|| format!("{:?}", value.val));
+    Ok(format!("{}: {} = {}", name, value.ty, rendered))
+}
+
+pub fn routes() -> Vec<::rocket::Route> {
+    routes![consts::eval]
+}
+
+action_route!(eval: "/?<path>", |pcx, path: String| {
+    crate::step::step_command(pcx, &format!("const {}", path))
+});
@@ -0,0 +1,80 @@
+//! Which allocations were written during the last command, without diffing any bytes.
+//!
+//! With `track-writes on`, before every step the place assigned by the next statement or call is
+//! resolved and its allocation remembers the step of this last write, as do the allocations the
+//! user writes to or restores from the mutations page. Writes through pointers
+//! passed to intrinsics and foreign functions emulated by miri are missed. Steps serve as
+//! generations: they are deterministic, so replaying to an earlier step rebuilds exactly the
+//! writes made until then.
+
+use std::collections::HashMap;
+
+use miri::AllocId;
+
+use crate::*;
+
+#[derive(Default)]
+pub struct DirtyAllocs {
+    /// The step of the last write to every allocation written so far
+    last_write: HashMap<u64, u128>,
+    /// The step the last command started at
+    command_start: u128,
+}
+
+impl DirtyAllocs {
+    pub fn clear(&mut self) {
+        self.last_write.clear();
+    }
+
+    /// The step the last write to `alloc_id` was made at.
+    pub fn last_write(&self, alloc_id: AllocId) -> Option<u128> {
+        self.last_write.get(&alloc_id.0).cloned()
+    }
+
+    /// Whether the last command wrote to `alloc_id`. After going back in time nothing is, as the
+    /// writes replayed up to the target step happened before the command started.
+    pub fn modified_by_last_command(&self, alloc_id: AllocId) -> bool {
+        self.last_write(alloc_id).map_or(false, |step| step >= self.command_start)
+    }
+}
+
+/// Start a new generation for the command about to be executed.
+pub fn start_command(pcx: &mut PrirodaContext) {
+    pcx.dirty.command_start = *pcx.step_count;
+}
+
+/// Remember the allocation the statement or call about to be executed as step
+/// `*pcx.step_count` writes.
+pub fn record(pcx: &mut PrirodaContext) {
+    if !pcx.config.track_writes {
+        return;
+    }
+    let alloc_id = crate::step::assigned_alloc(&mut pcx.ecx).or_else(|| call_destination(&mut pcx.ecx));
+    if let Some(alloc_id) = alloc_id {
+        pcx.dirty.last_write.insert(alloc_id.0, *pcx.step_count);
+    }
+}
+
+/// Remember that the user just wrote to `alloc_id`, see `mutations`.
+pub fn record_user_write(pcx: &mut PrirodaContext, alloc_id: AllocId) {
+    if pcx.config.track_writes {
+        pcx.dirty.last_write.insert(alloc_id.0, *pcx.step_count);
+    }
+}
+
+/// The allocation the call about to be executed writes its return value to. Functions emulated
+/// by miri write it right away, others when they assign their return place.
+fn call_destination(ecx: &mut InterpretCx) -> Option<AllocId> {
+    let place = {
+        let frame = ecx.stack().last()?;
+        let block = &frame.mir.basic_blocks()[frame.block];
+        if frame.stmt != block.statements.len() {
+            return None;
+        }
+        match block.terminator().kind {
+            mir::TerminatorKind::Call { destination: Some((ref place, _)), .. } => place.clone(),
+            _ => return None,
+        }
+    };
+    crate::step::place_alloc(ecx, &place)
+}
@@ -0,0 +1,81 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+
+use crate::PrirodaContext;
+
+/// Appends every notable event of the session as a JSON line to the file given by
+/// `--event-log <path>`.
+pub struct EventLog {
+    file: Option<File>,
+    /// Set when writing failed, the log is disabled afterwards
+    error: Option<String>,
+    /// The payloads of all `"error"` events since the last restart, also kept without a log file
+    errors: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Event<'a> {
+    kind: &'a str,
+    step: u128,
+    time: f64,
+    location: Option<crate::api::Position>,
+    payload: Value,
+}
+
+impl EventLog {
+    pub fn open(path: Option<&PathBuf>) -> Self {
+        match path.map(|path| OpenOptions::new().create(true).append(true).open(path)) {
+            None => EventLog { file: None, error: None, errors: Vec::new() },
+            Some(Ok(file)) => EventLog { file: Some(file), error: None, errors: Vec::new() },
+            Some(Err(err)) => EventLog {
+                file: None,
+                error: Some(format!("Couldn't open the event log: {}", err)),
+                errors: Vec::new(),
+            },
+        }
+    }
+
+    /// A description of why logging was disabled, if it was.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_ref().map(|s| &s[..])
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn clear_errors(&mut self) {
+        self.errors.clear();
+    }
+}
+
+/// Append an event of kind `kind` to the event log. The line is written immediately, so a
+/// crashing session still leaves a complete log.
+pub fn log(pcx: &mut PrirodaContext, kind: &str, payload: Value) {
+    if kind == "error" {
+        pcx.events.errors.push(payload["error"].as_str().map(str::to_string).unwrap_or_else(|| payload.to_string()));
+    }
+    if pcx.events.file.is_none() {
+        return;
+    }
+    let event = Event {
+        kind,
+        step: *pcx.step_count,
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs() as f64 + f64::from(time.subsec_micros()) / 1e6)
+            .unwrap_or(0.0),
+        location: crate::api::position(pcx),
+        payload,
+    };
+    let line = serde_json::to_string(&event).unwrap();
+    let file = pcx.events.file.as_mut().unwrap();
+    if let Err(err) = writeln!(file, "{}", line).and_then(|()| file.flush()) {
+        pcx.events.file = None;
+        pcx.events.error = Some(format!("Writing to the event log failed, it is disabled now: {}", err));
+    }
+}
@@ -0,0 +1,142 @@
+//! Opt-in counts of how often every statement and terminator was executed, shown as a heatmap in
+//! the MIR listing and as a list of the hottest statements of all functions.
+//!
+//! Counting is a single `HashMap` bump per step and skipped entirely while it is off.
+
+use std::collections::HashMap;
+
+use rustc::hir::def_id::DefId;
+use rustc::ty::InstanceDef;
+use rustc_data_structures::indexed_vec::Idx;
+
+use horrorshow::prelude::*;
+
+use crate::*;
+
+#[derive(Default)]
+pub struct ExecutionCounts {
+    enabled: bool,
+    counts: HashMap<(DefId, mir::BasicBlock, usize), u64>,
+}
+
+impl ExecutionCounts {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.clear();
+    }
+
+    /// The counts of the statements of `def_id` which were executed at least once.
+    pub fn for_function(&self, def_id: DefId) -> HashMap<(mir::BasicBlock, usize), u64> {
+        self.counts
+            .iter()
+            .filter(|(&(function, _, _), _)| function == def_id)
+            .map(|(&(_, bb, stmt), &count)| ((bb, stmt), count))
+            .collect()
+    }
+}
+
+/// Count the position which is about to be executed.
+pub fn record(pcx: &mut PrirodaContext) {
+    if !pcx.heatmap.enabled {
+        return;
+    }
+    if let Some(frame) = pcx.ecx.stack().last() {
+        *pcx.heatmap.counts.entry((frame.instance.def_id(), frame.block, frame.stmt)).or_insert(0) += 1;
+    }
+}
+
+/// The background of a statement executed `count` times in a function whose hottest statement
+/// was executed `max` times. The scale is logarithmic so loops don't wash out everything else.
+pub fn heat_style(count: u64, max: u64) -> String {
+    let heat = if max <= 1 { 1.0 } else { (count as f64).ln_1p() / (max as f64).ln_1p() };
+    format!("background-color: rgba(255, 140, 0, {:.2});", 0.1 + 0.5 * heat)
+}
+
+/// Execute a `counts` command: `counts on`, `counts off` or `counts reset`.
+pub fn counts_command(pcx: &mut PrirodaContext, args: &[&str]) -> Result<String, String> {
+    Ok(match args {
+        ["on"] => {
+            pcx.heatmap.enabled = true;
+            "Counting executed statements".to_string()
+        }
+        ["off"] => {
+            pcx.heatmap.enabled = false;
+            "Stopped counting executed statements".to_string()
+        }
+        ["reset"] => {
+            pcx.heatmap.clear();
+            "Reset the execution counts".to_string()
+        }
+        _ => return Err(format!("Unknown counts command {:?}", args.join(" "))),
+    })
+}
+
+/// The MIR of the statement or terminator at `bb:stmt` of `def_id`, if the function has MIR.
+fn statement_text(pcx: &PrirodaContext, def_id: DefId, bb: mir::BasicBlock, stmt: usize) -> String {
+    let mir = match pcx.ecx.load_mir(InstanceDef::Item(def_id)) {
+        Ok(mir) => mir,
+        Err(_) => return String::new(),
+    };
+    match mir.basic_blocks().get(bb) {
+        Some(block) if stmt < block.statements.len() => format!("{:?}", block.statements[stmt]),
+        Some(block) if stmt == block.statements.len() => format!("{:?}", block.terminator().kind),
+        _ => String::new(),
+    }
+}
+
+pub fn routes() -> Vec<::rocket::Route> {
+    routes![heatmap::show, heatmap::command]
+}
+
+view_route!(show: "/?<n>", |pcx, n: Option<usize>| {
+    let n = n.unwrap_or(50);
+    let heatmap = &pcx.heatmap;
+    let total = heatmap.counts.values().sum::<u64>();
+    let mut hottest = heatmap.counts.iter().map(|(&position, &count)| (position, count)).collect::<Vec<_>>();
+    hottest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    hottest.truncate(n);
+    crate::render::template(pcx, "Execution counts".to_string(), html! {
+        a(href="/") { : "Back" }
+        p {
+            : format!(
+                "Counting is {}, {} executions of {} statements counted. ",
+                if heatmap.enabled { "on" } else { "off" },
+                total,
+                heatmap.counts.len(),
+            );
+            a(href=if heatmap.enabled { "/heatmap/command?cmd=off" } else { "/heatmap/command?cmd=on" }) {
+                : if heatmap.enabled { "Stop counting" } else { "Start counting" }
+            }
+            : " ";
+            a(href="/heatmap/command?cmd=reset") { : "Reset" }
+        }
+        form(action="/heatmap") {
+            : "Show the ";
+            input(name="n", size="6", value=n.to_string());
+            : " hottest statements";
+        }
+        table(border="1") {
+            tr { th { : "count" } th { : "share" } th { : "function" } th { : "position" } th { : "statement" } }
+            @ for ((def_id, bb, stmt), count) in hottest {
+                tr {
+                    td { : count.to_string() }
+                    td { : format!("{:.1}%", 100.0 * count as f64 / total as f64) }
+                    td { : pcx.ecx.tcx.def_path_str(def_id) }
+                    td {
+                        a(href=format!("{}#bb{}_{}", crate::listing::url(def_id), bb.index(), stmt)) {
+                            : format!("{}:{}", bb.index(), stmt)
+                        }
+                    }
+                    td(style="font-family: monospace;") { : statement_text(pcx, def_id, bb, stmt) }
+                }
+            }
+        }
+    })
+});
+
+action_route!(command: "/command?<cmd>", |pcx, cmd: String| {
+    crate::step::step_command(pcx, &format!("counts {}", cmd))
+});
@@ -0,0 +1,556 @@
+//! The debugger core of priroda.
+//!
+//! Frontends should only use the [`api`](api/index.html) module, [`spawn_interpreter`] and
+//! [`PrirodaSender`]. The other public items exist for the web frontend and may change with
+//! every miri update.
+
+#![feature(rustc_private, decl_macro, plugin, fnbox, try_blocks, proc_macro_hygiene)]
+#![feature(never_type, slice_patterns)]
+#![allow(unused_attributes)]
+#![recursion_limit = "5000"]
+
+extern crate syntax;
+#[macro_use(err)]
+extern crate rustc;
+extern crate rustc_data_structures;
+extern crate rustc_driver;
+extern crate rustc_interface;
+extern crate rustc_mir;
+
+extern crate regex;
+extern crate lazy_static;
+#[macro_use]
+extern crate rental;
+extern crate miri;
+#[macro_use]
+extern crate rocket;
+
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+
+extern crate promising_future;
+extern crate syntect;
+#[macro_use]
+extern crate horrorshow;
+extern crate cgraph;
+
+pub mod api;
+mod bookmarks;
+mod compare;
+mod condition;
+mod consts;
+mod dirty;
+mod events;
+mod heatmap;
+mod listing;
+mod memdiff;
+mod mutations;
+mod render;
+mod script;
+mod search;
+mod step;
+#[cfg(test)]
+mod testing;
+mod timeline;
+mod trace;
+mod watch;
+
+use std::collections::BTreeSet;
+use std::ops::FnOnce;
+use std::sync::{Arc, Mutex};
+
+use rustc::mir;
+use rustc::ty::TyCtxt;
+use rustc::hir::def_id::LOCAL_CRATE;
+use rustc_interface::interface;
+
+use promising_future::future_promise;
+use rocket::response::content::*;
+use rocket::response::status::BadRequest;
+use rocket::State;
+
+use miri::AllocId;
+
+use crate::step::BreakpointTree;
+
+fn should_hide_stmt(stmt: &mir::Statement) -> bool {
+    use rustc::mir::StatementKind::*;
+    match stmt.kind {
+        StorageLive(_) | StorageDead(_) | Nop => true,
+        _ => false,
+    }
+}
+
+type InterpretCx<'a, 'tcx> = miri::InterpretCx<'a, 'tcx, 'tcx, miri::Evaluator<'tcx>>;
+
+pub struct PrirodaContext<'a, 'tcx: 'a> {
+    ecx: InterpretCx<'a, 'tcx>,
+    step_count: &'a mut u128,
+    traces: watch::Traces<'tcx>,
+    mutations: &'a mut mutations::MutationLog,
+    events: events::EventLog,
+    run: Arc<step::RunState>,
+    exec_trace: trace::ExecutionTrace,
+    heatmap: heatmap::ExecutionCounts,
+    dirty: dirty::DirtyAllocs,
+    locals_cache: render::cache::LocalsCache<'tcx>,
+    bookmarks: bookmarks::Bookmarks,
+    undo_history: bookmarks::UndoHistory,
+    script: script::ScriptRecorder,
+    /// Why the last stepping command stopped, `None` if it didn't execute anything
+    last_stop: Option<step::StopReason>,
+    locals_history: render::locals::LocalsHistory<'tcx>,
+    /// The memory `/memdiff` compares against, set by `mark-memory`
+    memory_mark: Option<memdiff::MemoryMark>,
+    timeline: timeline::Timeline,
+    /// The frame the views show instead of the innermost one, chosen in the stack view. Stepping
+    /// resets it.
+    selected_frame: Option<usize>,
+    config: &'a mut Config,
+}
+
+impl<'a, 'tcx: 'a> PrirodaContext<'a, 'tcx> {
+    fn restart(&mut self) {
+        self.ecx = create_ecx(self.ecx.tcx.tcx);
+        *self.step_count = 0;
+        self.traces.clear(); // Cleanup all traces
+        self.mutations.freeze();
+        self.events.clear_errors();
+        self.exec_trace.clear();
+        self.heatmap.clear();
+        self.dirty.clear();
+        self.locals_cache.clear();
+        self.last_stop = None;
+        self.config.bptree.reset_hits();
+        self.locals_history = Default::default();
+        self.memory_mark = None;
+        self.timeline.clear();
+        self.selected_frame = None;
+    }
+
+    /// The selected frame if it is still on the stack.
+    fn selected_frame(&self) -> Option<usize> {
+        self.selected_frame.filter(|&frame| frame < self.ecx.stack().len())
+    }
+
+    /// The frame the views show: the selected one or else the innermost one.
+    fn view_frame(&self) -> Option<usize> {
+        self.selected_frame().or_else(|| self.ecx.stack().len().checked_sub(1))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Config {
+    #[serde(default = "true_bool")]
+    auto_refresh: bool,
+    #[serde(default = "default_theme")]
+    theme: String,
+    #[serde(default)]
+    bptree: BreakpointTree,
+    /// Runs of at least this many recursive frames are condensed in the stack view, 0 disables
+    #[serde(default = "default_condense_recursion")]
+    condense_recursion: usize,
+    /// `step` doesn't stop in functions whose path contains one of these
+    #[serde(default = "default_skip")]
+    skip: Vec<String>,
+    /// A single command stops after this many steps, `None` means unlimited
+    #[serde(default = "default_step_limit")]
+    step_limit: Option<u128>,
+    /// `step` executes `Drop` terminators completely instead of stepping into the drop glue
+    #[serde(default)]
+    step_over_drops: bool,
+    /// Stepping stops before statements assigning to immutable allocations
+    #[serde(default)]
+    strict_writes: bool,
+    /// Stepping remembers which allocations every step writes to, see `dirty`
+    #[serde(default)]
+    track_writes: bool,
+    /// How many levels of pointers and boxes the locals table renders as their pointee, 0 to 2
+    #[serde(default = "default_auto_deref")]
+    auto_deref: usize,
+    /// How the locals table prints integers
+    #[serde(default = "default_int_format")]
+    int_format: render::locals::IntFormat,
+    /// The locals table shows the size and alignment of locals and the offsets of fields
+    #[serde(default)]
+    show_layout: bool,
+    /// Hexdumps show which bytes are defined in a row below every row of bytes
+    #[serde(default)]
+    show_undef_mask: bool,
+    /// The step loop samples the live heap allocations and bytes for `/timeline`
+    #[serde(default)]
+    memory_timeline: bool,
+    /// Strings in the locals table show at most this many bytes
+    #[serde(default = "default_string_bytes")]
+    string_bytes: u64,
+    /// Which locals the locals table shows
+    #[serde(default)]
+    locals_filter: render::locals::LocalsFilter,
+    /// How many commands `undo` can revert
+    #[serde(default = "default_undo_depth")]
+    undo_depth: usize,
+    /// The collapsed panels of the main window, see `render::PANELS`
+    #[serde(default = "default_hidden_panels")]
+    hidden_panels: BTreeSet<String>,
+}
+
+fn true_bool() -> bool {
+    true
+}
+fn default_theme() -> String {
+    "default".to_string()
+}
+fn default_condense_recursion() -> usize {
+    5
+}
+fn default_skip() -> Vec<String> {
+    vec!["core::".to_string(), "alloc::".to_string()]
+}
+fn default_step_limit() -> Option<u128> {
+    Some(1_000_000)
+}
+fn default_auto_deref() -> usize {
+    1
+}
+fn default_int_format() -> render::locals::IntFormat {
+    render::locals::IntFormat::Dec
+}
+fn default_string_bytes() -> u64 {
+    256
+}
+fn default_undo_depth() -> usize {
+    5
+}
+fn default_hidden_panels() -> BTreeSet<String> {
+    ["skip".to_string()].iter().cloned().collect()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        ::std::fs::File::open("config.json")
+            .map(|f| serde_json::from_reader(f).unwrap())
+            .unwrap_or(Config {
+                auto_refresh: true,
+                theme: "default".to_string(),
+                bptree: step::BreakpointTree::default(),
+                condense_recursion: default_condense_recursion(),
+                skip: default_skip(),
+                step_limit: default_step_limit(),
+                step_over_drops: false,
+                strict_writes: false,
+                track_writes: false,
+                auto_deref: default_auto_deref(),
+                int_format: default_int_format(),
+                show_layout: false,
+                show_undef_mask: false,
+                memory_timeline: false,
+                string_bytes: default_string_bytes(),
+                locals_filter: Default::default(),
+                undo_depth: default_undo_depth(),
+                hidden_panels: default_hidden_panels(),
+            })
+    }
+}
+
+impl Config {
+    /// The rendering settings of locals which don't depend on the local or the view.
+    fn locals_settings(&self) -> render::locals::Limits<'static> {
+        render::locals::Limits {
+            auto_deref: self.auto_deref,
+            int_format: self.int_format,
+            layout: self.show_layout,
+            string_bytes: Some(self.string_bytes),
+            ..render::locals::Limits::UNLIMITED
+        }
+    }
+}
+
+pub type RResult<T> = Result<T, Html<String>>;
+
+fn create_ecx<'a, 'tcx: 'a>(tcx: TyCtxt<'a, 'tcx, 'tcx>) -> InterpretCx<'a, 'tcx> {
+    let (main_id, _) = tcx
+        .entry_fn(LOCAL_CRATE)
+        .expect("no main or start function found");
+
+    miri::create_ecx(tcx, main_id, miri::MiriConfig {
+        validate: true,
+        args: vec![],
+        seed: None,
+    }).unwrap()
+}
+
+type Command = Box<dyn FnOnce(&mut PrirodaContext) + Send>;
+
+pub struct PrirodaSender(Mutex<::std::sync::mpsc::Sender<Command>>, Arc<step::RunState>);
+
+impl Clone for PrirodaSender {
+    fn clone(&self) -> Self {
+        let sender = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        PrirodaSender(Mutex::new(sender.clone()), self.1.clone())
+    }
+}
+
+impl PrirodaSender {
+    /// Run `f` on the interpreter thread and wait for its result.
+    pub fn do_work<T, F>(&self, f: F) -> Result<T, Html<String>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut PrirodaContext) -> T + Send + 'static,
+    {
+        let (future, promise) = future_promise();
+        let sender = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        match sender.send(Box::new(move |pcx: &mut PrirodaContext| {
+            promise.set(f(pcx));
+        })) {
+            Ok(()) => match future.value() {
+                Some(val) => Ok(val),
+                None => Err(Html(
+                    "<center><h1>Miri crashed please go to <a href='/'>index</a></h1></center>"
+                        .to_string(),
+                )),
+            },
+            Err(_) => Err(Html(
+                "<center><h1>Miri crashed too often. Please restart priroda.</h1></center>"
+                    .to_string(),
+            )),
+        }
+    }
+
+    /// Run the command `f` on the interpreter thread without waiting for it. Its progress is
+    /// available through `run_state`. Nothing happens if a command is already running.
+    pub fn spawn_run<F>(&self, f: F) -> Result<(), Html<String>>
+    where
+        F: FnOnce(&mut PrirodaContext) -> String + Send + 'static,
+    {
+        if !self.1.start() {
+            return Ok(());
+        }
+        let guard = self.1.guard();
+        let sender = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        sender
+            .send(Box::new(move |pcx: &mut PrirodaContext| {
+                guard.finish(f(pcx));
+            }))
+            .map_err(|_| {
+                Html(
+                    "<center><h1>Miri crashed too often. Please restart priroda.</h1></center>"
+                        .to_string(),
+                )
+            })
+    }
+
+    pub fn run_state(&self) -> &step::RunState {
+        &self.1
+    }
+}
+
+macro action_route($name:ident : $route:expr, |$pcx:ident $(,$arg:ident : $arg_ty:ty)*| $body:block) {
+    #[get($route)]
+    pub fn $name(
+        sender: rocket::State<crate::PrirodaSender>
+        $(,$arg:$arg_ty)*
+    ) -> crate::RResult<rocket::response::Flash<rocket::response::Redirect>> {
+        sender.do_work(move |$pcx| {
+            rocket::response::Flash::success(rocket::response::Redirect::to("/"), (||$body)())
+        })
+    }
+}
+
+macro view_route($name:ident : $route:expr, |$pcx:ident $(,$arg:ident : $arg_ty:ty)*| $body:block) {
+    #[get($route)]
+    pub fn $name(
+        sender: rocket::State<crate::PrirodaSender>
+        $(,$arg:$arg_ty)*
+    ) -> crate::RResult<Html<String>> {
+        sender.do_work(move |pcx| {
+            let $pcx = &*pcx;
+            (||$body)()
+        })
+    }
+}
+
+/// The routes of the web frontend and the paths they should be mounted at.
+pub fn web_routes() -> Vec<(&'static str, Vec<rocket::Route>)> {
+    vec![
+        ("/", render::routes::routes()),
+        ("/breakpoints", step::bp_routes::routes()),
+        ("/step", step::step_routes::routes()),
+        ("/skip", step::skip_routes::routes()),
+        ("/watch", watch::routes()),
+        ("/mutations", mutations::routes()),
+        ("/memdiff", memdiff::routes()),
+        ("/compare", compare::routes()),
+        ("/mir", listing::routes()),
+        ("/timeline", timeline::routes()),
+        ("/search", search::routes()),
+        ("/trace", trace::routes()),
+        ("/heatmap", heatmap::routes()),
+        ("/bookmarks", bookmarks::routes()),
+        ("/const", consts::routes()),
+        ("/layout", render::layout_routes::routes()),
+    ]
+}
+
+// Copied from miri/bin/miri.rs
+pub fn find_sysroot() -> String {
+    if let Ok(sysroot) = std::env::var("MIRI_SYSROOT") {
+        return sysroot;
+    }
+
+    // Taken from https://github.com/Manishearth/rust-clippy/pull/911.
+    let home = option_env!("RUSTUP_HOME").or(option_env!("MULTIRUST_HOME"));
+    let toolchain = option_env!("RUSTUP_TOOLCHAIN").or(option_env!("MULTIRUST_TOOLCHAIN"));
+    match (home, toolchain) {
+        (Some(home), Some(toolchain)) => format!("{}/toolchains/{}", home, toolchain),
+        _ => option_env!("RUST_SYSROOT")
+            .expect("need to specify RUST_SYSROOT env var or use rustup or multirust")
+            .to_owned(),
+    }
+}
+
+/// Start interpreting the program described by the rustc command line `args` on a new thread.
+///
+/// The interpreter is restarted a few times if it crashes. The returned sender is used to run
+/// commands on it. `--event-log <path>` is removed from the arguments and enables the event log.
+pub fn spawn_interpreter(mut args: Vec<String>) -> (PrirodaSender, std::thread::JoinHandle<()>) {
+    let event_log = args.iter().position(|arg| arg == "--event-log").map(|i| {
+        let path = args.get(i + 1).expect("--event-log needs a path").into();
+        args.drain(i..i + 2);
+        path
+    });
+
+    let sysroot_flag = String::from("--sysroot");
+    if !args.contains(&sysroot_flag) {
+        args.push(sysroot_flag);
+        args.push(find_sysroot());
+    }
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let run = Arc::new(step::RunState::default());
+    let sender = PrirodaSender(Mutex::new(sender), run.clone());
+    let step_count = Arc::new(Mutex::new(0));
+    let config = Arc::new(Mutex::new(Config::default()));
+    let mutations = Arc::new(Mutex::new(mutations::MutationLog::default()));
+
+    let handle = std::thread::spawn(move || {
+        let args = Arc::new(args);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..5 {
+            if i != 0 {
+                println!(
+                    "\n============== Miri crashed - restart try {} ==============\n",
+                    i
+                );
+            }
+            let step_count = step_count.clone();
+            let config = config.clone();
+            let mutations = mutations.clone();
+            let receiver = receiver.clone();
+            let args = args.clone();
+            let event_log = event_log.clone();
+            let run = run.clone();
+            // Ignore result to restart in case of a crash
+            let _ = std::thread::spawn(move || {
+                let _ = rustc_driver::report_ices_to_stderr_if_any(move || {
+                    struct PrirodaCompilerCalls {
+                        step_count: Arc<Mutex<u128>>,
+                        config: Arc<Mutex<Config>>,
+                        mutations: Arc<Mutex<mutations::MutationLog>>,
+                        event_log: Option<std::path::PathBuf>,
+                        run: Arc<step::RunState>,
+                        receiver: Arc<Mutex<std::sync::mpsc::Receiver<Command>>>,
+                    }
+
+                    impl rustc_driver::Callbacks for PrirodaCompilerCalls {
+                        fn after_parsing(&mut self, compiler: &interface::Compiler) -> bool {
+                            let attr = (
+                                syntax::symbol::Symbol::intern("miri"),
+                                syntax::feature_gate::AttributeType::Whitelisted,
+                            );
+                            compiler.session().plugin_attributes.borrow_mut().push(attr);
+
+                            // Continue execution
+                            true
+                        }
+
+                        fn after_analysis(&mut self, compiler: &interface::Compiler) -> bool {
+                            compiler.session().abort_if_errors();
+
+                            compiler.global_ctxt().unwrap().peek_mut().enter(|tcx| {
+
+                                let mut step_count =
+                                    self.step_count.lock().unwrap_or_else(|err| err.into_inner());
+                                let mut config =
+                                    self.config.lock().unwrap_or_else(|err| err.into_inner());
+                                let mut mutations =
+                                    self.mutations.lock().unwrap_or_else(|err| err.into_inner());
+                                // A crash discards the interpreter state the mutations were made to
+                                mutations.freeze();
+
+                                let mut pcx = PrirodaContext {
+                                    ecx: create_ecx(tcx),
+                                    step_count: &mut *step_count,
+                                    traces: watch::Traces::new(),
+                                    mutations: &mut *mutations,
+                                    events: events::EventLog::open(self.event_log.as_ref()),
+                                    run: self.run.clone(),
+                                    exec_trace: trace::ExecutionTrace::default(),
+                                    heatmap: heatmap::ExecutionCounts::default(),
+                                    dirty: dirty::DirtyAllocs::default(),
+                                    locals_cache: render::cache::LocalsCache::default(),
+                                    bookmarks: bookmarks::Bookmarks::default(),
+                                    undo_history: bookmarks::UndoHistory::default(),
+                                    script: script::ScriptRecorder::default(),
+                                    last_stop: None,
+                                    locals_history: Default::default(),
+                                    memory_mark: None,
+                                    timeline: Default::default(),
+                                    selected_frame: None,
+                                    config: &mut *config,
+                                };
+
+                                // Step to the position where miri crashed if it crashed
+                                for _ in 0..*pcx.step_count {
+                                    match pcx.ecx.step() {
+                                        Ok(true) => {}
+                                        res => panic!("Miri is not deterministic causing error {:?}", res),
+                                    }
+                                }
+
+                                // Just ignore poisoning by panicking
+                                let receiver =
+                                    self.receiver.lock().unwrap_or_else(|err| err.into_inner());
+
+                                // process commands
+                                for command in receiver.iter() {
+                                    command(&mut pcx);
+                                }
+                            });
+
+                            compiler.session().abort_if_errors();
+
+                            // Don't continue execution
+                            false
+                        }
+                    }
+
+                    rustc_driver::run_compiler(&*args, &mut PrirodaCompilerCalls {
+                        step_count,
+                        config,
+                        mutations,
+                        event_log,
+                        run,
+                        receiver,
+                    }, None, None)
+                });
+            })
+            .join();
+            std::thread::sleep(std::time::Duration::from_millis(200));
+        }
+        println!("\n============== Miri crashed too often. Aborting ==============\n");
+    });
+    (sender, handle)
+}
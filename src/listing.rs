@@ -0,0 +1,421 @@
+//! The MIR of a function as text, with the statement a frame is about to execute highlighted and
+//! a breakpoint toggle for every statement and terminator.
+//!
+//! `/mir` lists the function of the viewed frame, `/mir/<crate>/<index>` the function with that
+//! `DefId`. The page scrolls to the highlighted statement when it is loaded, so it follows the
+//! execution when it is refreshed after a step. `/mir/cfg` shows the control flow graph of the
+//! same functions.
+//!
+//! The values of constant operands are shown after the statements using them and references to
+//! promoted constants expand to their MIR. While execution counting is on, every statement shows
+//! how often it was executed, see the `heatmap` module.
+
+use rustc::hir::def_id::{CrateNum, DefId, DefIndex};
+use rustc::mir;
+use rustc::mir::interpret::GlobalId;
+use rustc::mir::visit::Visitor;
+use rustc::ty::fold::TypeFoldable;
+use rustc::ty::{Instance, InstanceDef, ParamEnv};
+use rustc_data_structures::indexed_vec::Idx;
+
+use horrorshow::prelude::*;
+use horrorshow::Raw;
+use rocket::response::{Flash, Redirect};
+use rocket::State;
+
+use crate::render::{escape_html, locals, FlashString};
+use crate::*;
+
+lazy_static::lazy_static! {
+    static ref PROMOTED: regex::Regex = regex::Regex::new(r"promoted\[(\d+)\]").unwrap();
+}
+
+/// Scroll to the statement named by the fragment once, e.g. after toggling its breakpoint, and
+/// otherwise to the current statement. The fragment is dropped so refreshes after a step scroll
+/// to the current statement again.
+const SCROLL_SCRIPT: &str = r#"<script>
+    window.addEventListener("load", () => {
+        let target = (location.hash && document.getElementById(location.hash.substr(1))) || document.querySelector(".mir_line.current");
+        if(target) {
+            target.scrollIntoView({ block: "center" });
+        }
+        history.replaceState(null, "", location.pathname);
+    });
+</script>"#;
+
+/// The path of the listing of `def_id`.
+pub fn url(def_id: DefId) -> String {
+    format!("/mir/{}/{}", def_id.krate.as_usize(), def_id.index.index())
+}
+
+struct Line {
+    /// `None` for local declarations
+    position: Option<(mir::BasicBlock, usize)>,
+    text: String,
+    /// Storage markers and nops, which stepping skips
+    hidden: bool,
+    /// The rendered values of the constant operands
+    constants: Vec<String>,
+    /// The promoted constants the line refers to
+    promoted: Vec<mir::Promoted>,
+}
+
+impl Line {
+    fn text(text: String) -> Self {
+        Line {
+            position: None,
+            text,
+            hidden: false,
+            constants: Vec::new(),
+            promoted: Vec::new(),
+        }
+    }
+}
+
+/// The constant operands of a statement or terminator.
+#[derive(Default)]
+struct Constants<'tcx>(Vec<mir::Constant<'tcx>>);
+
+impl<'tcx> Visitor<'tcx> for Constants<'tcx> {
+    fn visit_constant(&mut self, constant: &mir::Constant<'tcx>, _location: mir::Location) {
+        self.0.push(constant.clone());
+    }
+}
+
+/// The value of `constant` as HTML, `None` for zero sized constants like functions. Generic
+/// constants are evaluated with the generic arguments of `instance`.
+fn render_constant<'a, 'tcx>(
+    pcx: &PrirodaContext<'a, 'tcx>,
+    constant: &mir::Constant<'tcx>,
+    instance: Option<Instance<'tcx>>,
+) -> Option<String> {
+    let literal = match instance {
+        Some(instance) => pcx.ecx.tcx.subst_and_normalize_erasing_regions(
+            instance.substs,
+            ParamEnv::reveal_all(),
+            &constant.literal,
+        ),
+        None if constant.literal.needs_subst() => return Some("generic".to_string()),
+        None => constant.literal,
+    };
+    let op = match pcx.ecx.eval_const_to_op(literal, None) {
+        Ok(op) => op,
+        Err(err) => return Some(escape_html(&format!("{:?}", err)).into_owned()),
+    };
+    if op.layout.is_zst() {
+        return None;
+    }
+    let (alloc, value, _ranges) = locals::print_operand_with(&pcx.ecx, op, pcx.config.locals_settings()).ok()?;
+    Some(match alloc {
+        Some(alloc) => format!("{} <a href=\"/ptr/{}/0\">alloc {}</a>", value, alloc, alloc),
+        None => value,
+    })
+}
+
+/// The value of the promoted constant `promoted` of `instance` as HTML.
+fn render_promoted(pcx: &PrirodaContext, instance: Option<Instance>, promoted: mir::Promoted) -> Option<String> {
+    let cid = GlobalId {
+        instance: instance?,
+        promoted: Some(promoted),
+    };
+    let value = pcx.ecx.tcx.const_eval(ParamEnv::reveal_all().and(cid)).ok()?;
+    let op = pcx.ecx.eval_const_to_op(value, None).ok()?;
+    locals::print_operand_with(&pcx.ecx, op, pcx.config.locals_settings())
+        .ok()
+        .map(|(_alloc, value, _ranges)| value)
+}
+
+fn lines<'a, 'tcx>(pcx: &PrirodaContext<'a, 'tcx>, mir: &mir::Body<'tcx>, instance: Option<Instance<'tcx>>) -> Vec<Line> {
+    let operands = |text: String, position: (mir::BasicBlock, usize), constants: Constants<'tcx>| Line {
+        position: Some(position),
+        constants: constants.0.iter().filter_map(|constant| render_constant(pcx, constant, instance)).collect(),
+        promoted: PROMOTED
+            .captures_iter(&text)
+            .filter_map(|caps| caps[1].parse::<usize>().ok())
+            .filter(|&promoted| promoted < mir.promoted.len())
+            .map(mir::Promoted::new)
+            .collect(),
+        text,
+        hidden: false,
+    };
+    let mut lines = mir
+        .local_decls
+        .iter_enumerated()
+        .map(|(local, decl)| Line::text(format!(
+            "let {}{:?}: {};{}",
+            if decl.mutability == mir::Mutability::Mut { "mut " } else { "" },
+            local,
+            decl.ty,
+            decl.name.map(|name| format!(" // {}", name)).unwrap_or_default(),
+        )))
+        .collect::<Vec<_>>();
+    for (bb, block) in mir.basic_blocks().iter_enumerated() {
+        lines.push(Line::text(format!("{:?}{}:", bb, if block.is_cleanup { " (cleanup)" } else { "" })));
+        for (stmt, statement) in block.statements.iter().enumerate() {
+            let mut constants = Constants::default();
+            constants.visit_statement(statement, mir::Location { block: bb, statement_index: stmt });
+            lines.push(Line {
+                hidden: crate::should_hide_stmt(statement),
+                ..operands(format!("    {:?};", statement), (bb, stmt), constants)
+            });
+        }
+        let stmt = block.statements.len();
+        let mut constants = Constants::default();
+        constants.visit_terminator(block.terminator(), mir::Location { block: bb, statement_index: stmt });
+        lines.push(operands(format!("    {:?};", block.terminator().kind), (bb, stmt), constants));
+    }
+    lines
+}
+
+/// The function `def_id`, or the function of the viewed frame if it is `None`, its MIR and the
+/// frame whose position in it is shown: the viewed frame if it is in this function, otherwise the
+/// innermost frame which is.
+pub fn find_function<'a, 'tcx>(
+    pcx: &PrirodaContext<'a, 'tcx>,
+    def_id: Option<DefId>,
+) -> Result<(DefId, &'a mir::Body<'tcx>, Option<usize>), String> {
+    let stack = pcx.ecx.stack();
+    let viewed = pcx.view_frame();
+    let frame = match def_id {
+        None => viewed,
+        Some(def_id) => viewed
+            .filter(|&frame| stack[frame].instance.def_id() == def_id)
+            .or_else(|| stack.iter().rposition(|frame| frame.instance.def_id() == def_id)),
+    };
+    let def_id = def_id
+        .or_else(|| frame.map(|frame| stack[frame].instance.def_id()))
+        .ok_or_else(|| "The stack is empty".to_string())?;
+    // Shims like drop glue only exist as the MIR of a frame
+    let mir = match frame {
+        Some(frame) => stack[frame].mir,
+        None => pcx.ecx.load_mir(InstanceDef::Item(def_id)).map_err(|_| {
+            format!("{} has no MIR, it is a foreign function or emulated by miri", pcx.ecx.tcx.def_path_str(def_id))
+        })?,
+    };
+    Ok((def_id, mir, frame))
+}
+
+/// The listing of `def_id`, or of the function of the viewed frame if it is `None`, with the
+/// position of the frame chosen by `find_function` highlighted.
+pub fn render_listing(pcx: &PrirodaContext, def_id: Option<DefId>, message: String) -> Html<String> {
+    let (def_id, mir, frame) = match find_function(pcx, def_id) {
+        Ok(function) => function,
+        Err(err) => return crate::render::template(pcx, "MIR".to_string(), html! {
+            a(href="/") { : "Back" }
+            p { : err }
+        }),
+    };
+    let stack = pcx.ecx.stack();
+    let viewed = pcx.view_frame();
+    let name = pcx.ecx.tcx.def_path_str(def_id);
+    let current = frame.map(|frame| (stack[frame].block, stack[frame].stmt));
+    let breakpoints = pcx.config.bptree.for_def_id(def_id);
+    let viewed_query = if frame.is_some() && frame == viewed { "?viewed=true" } else { "" };
+    let stack_instance = frame.map(|frame| stack[frame].instance);
+    // Without a frame only functions without generic parameters have known constants
+    let instance = stack_instance.or_else(|| {
+        if pcx.ecx.tcx.generics_of(def_id).count() == 0 {
+            Some(Instance::mono(pcx.ecx.tcx.tcx, def_id))
+        } else {
+            None
+        }
+    });
+    let promoted = mir
+        .promoted
+        .iter_enumerated()
+        .map(|(promoted, body)| (render_promoted(pcx, instance, promoted), lines(pcx, body, instance)))
+        .collect::<Vec<_>>();
+    let lines = lines(pcx, mir, instance);
+    let counts = if pcx.heatmap.enabled() { Some(pcx.heatmap.for_function(def_id)) } else { None };
+    let max_count = counts.iter().flat_map(|counts| counts.values()).cloned().max().unwrap_or(0);
+    let count = |bb: mir::BasicBlock, stmt: usize| counts.as_ref().map(|counts| counts.get(&(bb, stmt)).cloned().unwrap_or(0));
+    let executed = |bb: mir::BasicBlock| {
+        counts.as_ref().map_or(true, |counts| counts.keys().any(|&(block, _)| block == bb))
+    };
+    crate::render::template(
+        pcx,
+        format!("MIR of {}", name),
+        html! {
+            a(href="/") { : "Back" }
+            : " ";
+            a(href=format!("/mir/cfg?krate={}&index={}", def_id.krate.as_usize(), def_id.index.index())) { : "Control flow graph" }
+            h2 { : &name }
+            p {
+                @ if let Some(frame) = frame {
+                    : format!("Frame {} of {}", frame, stack.len());
+                    @ if Some(frame) != stack.len().checked_sub(1) {
+                        : ", not the innermost one";
+                    }
+                    : ". ";
+                } else {
+                    : "Not on the stack. ";
+                }
+                : "Click a statement or terminator to toggle its breakpoint.";
+            }
+            @ if pcx.heatmap.enabled() {
+                p {
+                    : "Statements are shaded by how often they were executed, blocks which never ran are grey. ";
+                    a(href="/heatmap") { : "Hottest statements" }
+                }
+            }
+            @ if !message.is_empty() {
+                p { : &message }
+            }
+            div(class="mir_listing") {
+                @ for line in &lines {
+                    @ if let Some((bb, stmt)) = line.position {
+                        div(
+                            id=format!("bb{}_{}", bb.index(), stmt),
+                            class=if Some((bb, stmt)) == current {
+                                "mir_line current"
+                            } else if !executed(bb) {
+                                "mir_line never_executed"
+                            } else {
+                                "mir_line"
+                            },
+                            style=format!(
+                                "{}{}",
+                                if line.hidden { "color: gray;" } else { "" },
+                                match count(bb, stmt) {
+                                    Some(count) if count > 0 && Some((bb, stmt)) != current => crate::heatmap::heat_style(count, max_count),
+                                    _ => String::new(),
+                                },
+                            )
+                        ) {
+                            @ if let Some(count) = count(bb, stmt) {
+                                span(class="exec_count") { : format!("{:>8} ", count) }
+                            }
+                            a(
+                                href=format!("/mir/toggle/{}/{}/{}/{}{}", def_id.krate.as_usize(), def_id.index.index(), bb.index(), stmt, viewed_query),
+                                title=format!("Toggle the breakpoint at {}:{}", bb.index(), stmt)
+                            ) {
+                                : if breakpoints.breakpoint_exists(bb, stmt) { Raw("&#9679; ") } else { Raw("&#9675; ") };
+                                : &line.text
+                            }
+                            @ for value in &line.constants {
+                                span(class="const_value") { : " = "; : Raw(value) }
+                            }
+                        }
+                        @ for &index in &line.promoted {
+                            details(class="promoted") {
+                                summary {
+                                    : format!("promoted[{}]", index.index());
+                                    @ if let Some(value) = &promoted[index.index()].0 {
+                                        : " = ";
+                                        : Raw(value)
+                                    }
+                                }
+                                @ for promoted_line in &promoted[index.index()].1 {
+                                    div(class="mir_line") {
+                                        : &promoted_line.text;
+                                        @ for value in &promoted_line.constants {
+                                            span(class="const_value") { : " = "; : Raw(value) }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    } else {
+                        div(class="mir_line") { : &line.text }
+                    }
+                }
+            }
+            : Raw(SCROLL_SCRIPT)
+        },
+    )
+}
+
+/// The control flow graph of `def_id`, or of the function of the viewed frame if it is `None`,
+/// as DOT source.
+pub fn cfg_source(pcx: &PrirodaContext, def_id: Option<DefId>) -> Result<(DefId, String), String> {
+    let (def_id, mir, frame) = find_function(pcx, def_id)?;
+    let current = frame.map(|frame| pcx.ecx.stack()[frame].block);
+    Ok((def_id, crate::render::cfg::cfg_dot(def_id, mir, current, pcx.config.bptree.for_def_id(def_id))))
+}
+
+pub fn render_cfg(pcx: &PrirodaContext, def_id: Option<DefId>) -> Html<String> {
+    let (def_id, dot) = match cfg_source(pcx, def_id) {
+        Ok(cfg) => cfg,
+        Err(err) => return crate::render::template(pcx, "Control flow graph".to_string(), html! {
+            a(href="/") { : "Back" }
+            p { : err }
+        }),
+    };
+    let graph = match crate::render::alloc_graph::render_svg(dot) {
+        Ok(svg) => svg,
+        Err(err) => format!("<p>Could not render the graph: {}</p>", crate::render::escape_html(&err)),
+    };
+    let query = format!("krate={}&index={}", def_id.krate.as_usize(), def_id.index.index());
+    let name = pcx.ecx.tcx.def_path_str(def_id);
+    crate::render::template(
+        pcx,
+        format!("Control flow graph of {}", name),
+        html! {
+            a(href="/") { : "Back" }
+            : " ";
+            a(href=url(def_id)) { : "MIR listing" }
+            : " ";
+            a(href=format!("/mir/cfg.dot?{}", query)) { : "DOT source" }
+            h2 { : &name }
+            p { : "The current block is filled, blocks with breakpoints are outlined in red and unwind edges are dashed." }
+            div(id="cfg") { : Raw(graph) }
+        },
+    )
+}
+
+pub fn routes() -> Vec<::rocket::Route> {
+    routes![listing::viewed, listing::function, listing::toggle, listing::cfg_svg, listing::cfg_dot]
+}
+
+fn def_id(krate: usize, index: usize) -> DefId {
+    DefId {
+        krate: CrateNum::new(krate),
+        index: DefIndex::from_usize(index),
+    }
+}
+
+view_route!(viewed: "/", |pcx, flash: FlashString| {
+    render_listing(pcx, None, flash.0)
+});
+
+/// `krate` and `index` of a `DefId` if both are given.
+fn optional_def_id(krate: Option<usize>, index: Option<usize>) -> Option<DefId> {
+    Some(def_id(krate?, index?))
+}
+
+view_route!(cfg_svg: "/cfg?<krate>&<index>", |pcx, krate: Option<usize>, index: Option<usize>| {
+    render_cfg(pcx, optional_def_id(krate, index))
+});
+
+/// The graph of `cfg_svg` as DOT source, for rendering it with other tools.
+#[get("/cfg.dot?<krate>&<index>")]
+pub fn cfg_dot(sender: State<PrirodaSender>, krate: Option<usize>, index: Option<usize>) -> RResult<String> {
+    sender.do_work(move |pcx| match cfg_source(pcx, optional_def_id(krate, index)) {
+        Ok((_, dot)) => dot,
+        Err(err) => err,
+    })
+}
+
+view_route!(function: "/<krate>/<index>", |pcx, flash: FlashString, krate: usize, index: usize| {
+    render_listing(pcx, Some(def_id(krate, index)), flash.0)
+});
+
+/// Like an action route, but returns to the toggled statement of the listing it was clicked on.
+#[get("/toggle/<krate>/<index>/<bb>/<stmt>?<viewed>")]
+pub fn toggle(
+    sender: State<PrirodaSender>,
+    krate: usize,
+    index: usize,
+    bb: usize,
+    stmt: usize,
+    viewed: Option<bool>,
+) -> RResult<Flash<Redirect>> {
+    sender.do_work(move |pcx| {
+        let def_id = def_id(krate, index);
+        let exists = pcx.config.bptree.for_def_id(def_id).breakpoint_exists(mir::BasicBlock::new(bb), stmt);
+        let command = if exists { "delete" } else { "break" };
+        let msg = crate::step::step_command(pcx, &format!("{} {:?}@{}:{}", command, def_id, bb, stmt));
+        let back = if viewed == Some(true) { "/mir".to_string() } else { url(def_id) };
+        Flash::success(Redirect::to(format!("{}#bb{}_{}", back, bb, stmt)), msg)
+    })
+}
@@ -1,183 +1,26 @@
-#![feature(rustc_private, decl_macro, plugin, fnbox, try_blocks, proc_macro_hygiene)]
-#![feature(never_type)]
-#![allow(unused_attributes)]
-#![recursion_limit = "5000"]
+#![feature(rustc_private, decl_macro, proc_macro_hygiene)]
 
-extern crate syntax;
-#[macro_use(err)]
-extern crate rustc;
-extern crate rustc_data_structures;
-extern crate rustc_driver;
-extern crate rustc_interface;
-extern crate rustc_mir;
-
-extern crate regex;
-extern crate lazy_static;
-#[macro_use]
-extern crate rental;
-extern crate miri;
 #[macro_use]
 extern crate rocket;
 
 extern crate env_logger;
+#[cfg(unix)]
+extern crate libc;
 extern crate log;
 extern crate log_settings;
 
-extern crate serde;
-#[macro_use]
-extern crate serde_derive;
-extern crate serde_json;
-
 extern crate open;
-extern crate promising_future;
-extern crate syntect;
-#[macro_use]
-extern crate horrorshow;
-extern crate cgraph;
-
-mod render;
-mod step;
-mod watch;
+extern crate priroda;
+extern crate serde_json;
 
-use std::ops::FnOnce;
+use std::io::Write;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
 
-use rustc::mir;
-use rustc::ty::TyCtxt;
-use rustc::hir::def_id::LOCAL_CRATE;
-use rustc_interface::interface;
-
-use promising_future::future_promise;
 use rocket::response::content::*;
-use rocket::response::status::BadRequest;
 use rocket::response::NamedFile;
 use rocket::State;
 
-use miri::AllocId;
-
-use crate::step::BreakpointTree;
-
-fn should_hide_stmt(stmt: &mir::Statement) -> bool {
-    use rustc::mir::StatementKind::*;
-    match stmt.kind {
-        StorageLive(_) | StorageDead(_) | Nop => true,
-        _ => false,
-    }
-}
-
-type InterpretCx<'a, 'tcx> = miri::InterpretCx<'a, 'tcx, 'tcx, miri::Evaluator<'tcx>>;
-
-pub struct PrirodaContext<'a, 'tcx: 'a> {
-    ecx: InterpretCx<'a, 'tcx>,
-    step_count: &'a mut u128,
-    traces: watch::Traces<'tcx>,
-    config: &'a mut Config,
-}
-
-impl<'a, 'tcx: 'a> PrirodaContext<'a, 'tcx> {
-    fn restart(&mut self) {
-        self.ecx = create_ecx(self.ecx.tcx.tcx);
-        *self.step_count = 0;
-        self.traces.clear(); // Cleanup all traces
-    }
-}
-
-#[derive(Deserialize)]
-pub struct Config {
-    #[serde(default = "true_bool")]
-    auto_refresh: bool,
-    #[serde(default = "default_theme")]
-    theme: String,
-    #[serde(default)]
-    bptree: BreakpointTree,
-}
-
-fn true_bool() -> bool {
-    true
-}
-fn default_theme() -> String {
-    "default".to_string()
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        ::std::fs::File::open("config.json")
-            .map(|f| serde_json::from_reader(f).unwrap())
-            .unwrap_or(Config {
-                auto_refresh: true,
-                theme: "default".to_string(),
-                bptree: step::BreakpointTree::default(),
-            })
-    }
-}
-
-type RResult<T> = Result<T, Html<String>>;
-
-fn create_ecx<'a, 'tcx: 'a>(tcx: TyCtxt<'a, 'tcx, 'tcx>) -> InterpretCx<'a, 'tcx> {
-    let (main_id, _) = tcx
-        .entry_fn(LOCAL_CRATE)
-        .expect("no main or start function found");
-
-    miri::create_ecx(tcx, main_id, miri::MiriConfig {
-        validate: true,
-        args: vec![],
-        seed: None,
-    }).unwrap()
-}
-
-pub struct PrirodaSender(Mutex<::std::sync::mpsc::Sender<Box<dyn FnOnce(&mut PrirodaContext) + Send>>>);
-
-impl PrirodaSender {
-    fn do_work<'r, T, F>(&self, f: F) -> Result<T, Html<String>>
-    where
-        T: rocket::response::Responder<'r> + Send + 'static,
-        F: FnOnce(&mut PrirodaContext) -> T + Send + 'static,
-    {
-        let (future, promise) = future_promise();
-        let sender = self.0.lock().unwrap_or_else(|err| err.into_inner());
-        match sender.send(Box::new(move |pcx: &mut PrirodaContext| {
-            promise.set(f(pcx));
-        })) {
-            Ok(()) => match future.value() {
-                Some(val) => Ok(val),
-                None => Err(Html(
-                    "<center><h1>Miri crashed please go to <a href='/'>index</a></h1></center>"
-                        .to_string(),
-                )),
-            },
-            Err(_) => Err(Html(
-                "<center><h1>Miri crashed too often. Please restart priroda.</h1></center>"
-                    .to_string(),
-            )),
-        }
-    }
-}
-
-macro action_route($name:ident : $route:expr, |$pcx:ident $(,$arg:ident : $arg_ty:ty)*| $body:block) {
-    #[get($route)]
-    pub fn $name(
-        sender: rocket::State<crate::PrirodaSender>
-        $(,$arg:$arg_ty)*
-    ) -> crate::RResult<rocket::response::Flash<rocket::response::Redirect>> {
-        sender.do_work(move |$pcx| {
-            rocket::response::Flash::success(rocket::response::Redirect::to("/"), (||$body)())
-        })
-    }
-}
-
-macro view_route($name:ident : $route:expr, |$pcx:ident $(,$arg:ident : $arg_ty:ty)*| $body:block) {
-    #[get($route)]
-    pub fn $name(
-        sender: rocket::State<crate::PrirodaSender>
-        $(,$arg:$arg_ty)*
-    ) -> crate::RResult<Html<String>> {
-        sender.do_work(move |pcx| {
-            let $pcx = &*pcx;
-            (||$body)()
-        })
-    }
-}
+use priroda::{api, PrirodaSender, RResult};
 
 #[get("/please_panic")]
 #[allow(unreachable_code)]
@@ -223,18 +66,18 @@ fn resources(path: PathBuf) -> Result<Content<&'static str>, std::io::Error> {
 
 #[get("/step_count")]
 fn step_count(sender: State<PrirodaSender>) -> RResult<String> {
-    sender.do_work(|pcx| format!("{}", pcx.step_count))
+    sender.do_work(|pcx| format!("{}", api::step_count(pcx)))
 }
 
 fn server(sender: PrirodaSender) {
     use rocket::config::Value;
-    rocket::ignite()
+    let mut rocket = rocket::ignite()
         .manage(sender)
-        .mount("/", routes![please_panic, resources, step_count])
-        .mount("/", render::routes::routes())
-        .mount("/breakpoints", step::bp_routes::routes())
-        .mount("/step", step::step_routes::routes())
-        .mount("/watch", watch::routes())
+        .mount("/", routes![please_panic, resources, step_count]);
+    for (path, routes) in priroda::web_routes() {
+        rocket = rocket.mount(path, routes);
+    }
+    rocket
         .attach(rocket::fairing::AdHoc::on_launch("Priroda, because code has no privacy rights", |rocket| {
             let config = rocket.config();
             if config.extras.get("spawn_browser") == Some(&Value::Boolean(true)) {
@@ -247,138 +90,98 @@ fn server(sender: PrirodaSender) {
         .launch();
 }
 
-// Copied from miri/bin/miri.rs
-fn find_sysroot() -> String {
-    if let Ok(sysroot) = std::env::var("MIRI_SYSROOT") {
-        return sysroot;
-    }
-
-    // Taken from https://github.com/Manishearth/rust-clippy/pull/911.
-    let home = option_env!("RUSTUP_HOME").or(option_env!("MULTIRUST_HOME"));
-    let toolchain = option_env!("RUSTUP_TOOLCHAIN").or(option_env!("MULTIRUST_TOOLCHAIN"));
-    match (home, toolchain) {
-        (Some(home), Some(toolchain)) => format!("{}/toolchains/{}", home, toolchain),
-        _ => option_env!("RUST_SYSROOT")
-            .expect("need to specify RUST_SYSROOT env var or use rustup or multirust")
-            .to_owned(),
-    }
-}
-
 fn main() {
-    init_logger();
     let mut args: Vec<String> = std::env::args().collect();
-
-    let sysroot_flag = String::from("--sysroot");
-    if !args.contains(&sysroot_flag) {
-        args.push(sysroot_flag);
-        args.push(find_sysroot());
-    }
+    let stdio_commands = args.iter().position(|arg| arg == "--stdio-commands").map(|i| args.remove(i)).is_some();
+    let script = match args.iter().position(|arg| arg == "--script") {
+        Some(i) if i + 1 < args.len() => {
+            args.remove(i);
+            Some(PathBuf::from(args.remove(i)))
+        }
+        Some(_) => {
+            eprintln!("--script requires a file");
+            std::process::exit(1);
+        }
+        None => None,
+    };
+    // stdout is reserved for the command results
+    init_logger(stdio_commands);
+    let results = if stdio_commands { Some(take_stdout()) } else { None };
 
     // setup http server and similar
-    let (sender, receiver) = std::sync::mpsc::channel();
-    let sender = PrirodaSender(Mutex::new(sender));
-    let step_count = Arc::new(Mutex::new(0));
-    let config = Arc::new(Mutex::new(Config::default()));
-
-    let handle = std::thread::spawn(move || {
-        let args = Arc::new(args);
-        let receiver = Arc::new(Mutex::new(receiver));
-        for i in 0..5 {
-            if i != 0 {
-                println!(
-                    "\n============== Miri crashed - restart try {} ==============\n",
-                    i
-                );
+    let (sender, handle) = priroda::spawn_interpreter(args);
+    if let Some(script) = script {
+        match sender.do_work(move |pcx| api::run_script(pcx, &script)) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            Err(_) => {
+                eprintln!("Miri crashed while running the script");
+                std::process::exit(1);
             }
-            let step_count = step_count.clone();
-            let config = config.clone();
-            let receiver = receiver.clone();
-            let args = args.clone();
-            // Ignore result to restart in case of a crash
-            let _ = std::thread::spawn(move || {
-                let _ = rustc_driver::report_ices_to_stderr_if_any(move || {
-                    struct PrirodaCompilerCalls {
-                        step_count: Arc<Mutex<u128>>,
-                        config: Arc<Mutex<Config>>,
-                        receiver: Arc<Mutex<std::sync::mpsc::Receiver<Box<dyn FnOnce(&mut PrirodaContext) + Send>>>>,
-                    }
-
-                    impl rustc_driver::Callbacks for PrirodaCompilerCalls {
-                        fn after_parsing(&mut self, compiler: &interface::Compiler) -> bool {
-                            let attr = (
-                                syntax::symbol::Symbol::intern("miri"),
-                                syntax::feature_gate::AttributeType::Whitelisted,
-                            );
-                            compiler.session().plugin_attributes.borrow_mut().push(attr);
-
-                            // Continue execution
-                            true
-                        }
-
-                        fn after_analysis(&mut self, compiler: &interface::Compiler) -> bool {
-                            compiler.session().abort_if_errors();
-
-                            compiler.global_ctxt().unwrap().peek_mut().enter(|tcx| {
-
-                                let mut step_count =
-                                    self.step_count.lock().unwrap_or_else(|err| err.into_inner());
-                                let mut config =
-                                    self.config.lock().unwrap_or_else(|err| err.into_inner());
-
-                                let mut pcx = PrirodaContext {
-                                    ecx: create_ecx(tcx),
-                                    step_count: &mut *step_count,
-                                    traces: watch::Traces::new(),
-                                    config: &mut *config,
-                                };
-
-                                // Step to the position where miri crashed if it crashed
-                                for _ in 0..*pcx.step_count {
-                                    match pcx.ecx.step() {
-                                        Ok(true) => {}
-                                        res => panic!("Miri is not deterministic causing error {:?}", res),
-                                    }
-                                }
-
-                                // Just ignore poisoning by panicking
-                                let receiver =
-                                    self.receiver.lock().unwrap_or_else(|err| err.into_inner());
-
-                                // process commands
-                                for command in receiver.iter() {
-                                    command(&mut pcx);
-                                }
-                            });
-
-                            compiler.session().abort_if_errors();
-
-                            // Don't continue execution
-                            false
-                        }
-                    }
-
-                    rustc_driver::run_compiler(&*args, &mut PrirodaCompilerCalls {
-                        step_count,
-                        config,
-                        receiver,
-                    }, None, None)
-                });
-            })
-            .join();
-            std::thread::sleep(std::time::Duration::from_millis(200));
         }
-        println!("\n============== Miri crashed too often. Aborting ==============\n");
-    });
+    }
+    if let Some(results) = results {
+        let sender = sender.clone();
+        std::thread::spawn(move || run_stdio_commands(sender, results));
+    }
     server(sender);
     handle.join().unwrap();
 }
 
-fn init_logger() {
+/// Keep stdout for the command results and redirect everything else written to it, like the
+/// output of the interpreted program, the crash messages and Rocket's banner, to stderr.
+#[cfg(unix)]
+fn take_stdout() -> Box<dyn Write + Send> {
+    use std::os::unix::io::FromRawFd;
+    unsafe {
+        let results = libc::dup(libc::STDOUT_FILENO);
+        if results < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            eprintln!("Couldn't redirect stdout: {}", std::io::Error::last_os_error());
+            std::process::exit(1);
+        }
+        Box::new(std::fs::File::from_raw_fd(results))
+    }
+}
+
+#[cfg(not(unix))]
+fn take_stdout() -> Box<dyn Write + Send> {
+    Box::new(std::io::stdout())
+}
+
+/// Execute the commands read line by line from stdin and write one JSON `StepResult` per line to
+/// `results`. The next command is only read after the result of the previous one was written. The
+/// web interface stays available for the same session. EOF exits priroda.
+fn run_stdio_commands(sender: PrirodaSender, mut results: Box<dyn Write + Send>) {
+    use std::io::BufRead;
+    let stdin = std::io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = match sender.do_work(move |pcx| api::command(pcx, &line)) {
+            Ok(result) => serde_json::to_string(&result).unwrap(),
+            Err(_) => serde_json::json!({ "error": "Miri crashed" }).to_string(),
+        };
+        if writeln!(results, "{}", result).and_then(|()| results.flush()).is_err() {
+            break;
+        }
+    }
+    std::process::exit(0);
+}
+
+fn init_logger(to_stderr: bool) {
     const NSPACES: usize = 40;
-    let format = |_fmt: &mut _, record: &log::Record| {
+    let format = move |_fmt: &mut _, record: &log::Record| {
         // prepend spaces to indent the final string
         let indentation = log_settings::settings().indentation;
-        println!(
+        let line = format!(
             "{lvl}:{module}{depth:2}{indent:<indentation$} {text}",
             lvl = record.level(),
             module = record.module_path().unwrap_or(""),
@@ -387,6 +190,11 @@ fn init_logger() {
             indent = "",
             text = record.args()
         );
+        if to_stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
         Ok(())
     };
 
@@ -0,0 +1,317 @@
+//! Comparing the memory against a mark set earlier, to see what a piece of code changed.
+//!
+//! `mark-memory` records every allocation of the interpreter memory. Allocations of up to
+//! `COPY_LIMIT` bytes are copied, larger ones only keep a hash of every chunk of `CHUNK_SIZE`
+//! bytes. Their changes are found with the granularity of a chunk and without the old bytes.
+//! Allocation ids are deterministic, so the mark stays meaningful across restarts and replays.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+
+use rustc::ty::layout::Size;
+
+use horrorshow::prelude::*;
+
+use miri::{Allocation, Stacks, Tag};
+
+use crate::*;
+
+/// Allocations larger than this are recorded as chunk hashes instead of being copied.
+const COPY_LIMIT: usize = 64 * 1024;
+
+/// The granularity of the hashes of large allocations.
+const CHUNK_SIZE: usize = 256;
+
+/// A changed range shows at most this many bytes on the diff page.
+const SHOWN_BYTES: usize = 64;
+
+enum Contents {
+    /// The bytes, `None` for undefined ones
+    Copy(Vec<Option<u8>>),
+    /// The hash of the bytes, undef mask and relocations of every chunk
+    Hashed(Vec<u64>),
+}
+
+struct AllocMark {
+    size: usize,
+    contents: Contents,
+    relocations: BTreeMap<u64, u64>,
+}
+
+pub struct MemoryMark {
+    step: u128,
+    allocs: BTreeMap<u64, AllocMark>,
+}
+
+/// A range of an allocation whose bytes changed since the mark.
+pub struct ChangedRange {
+    pub start: u64,
+    pub end: u64,
+    /// `None` if only the hash of the range was recorded
+    pub old: Option<Vec<Option<u8>>>,
+    pub new: Vec<Option<u8>>,
+}
+
+pub struct AllocDiff {
+    pub alloc_id: u64,
+    pub ranges: Vec<ChangedRange>,
+    /// The offset and the target allocation before and after of every changed relocation
+    pub relocations: Vec<(u64, Option<u64>, Option<u64>)>,
+}
+
+#[derive(Default)]
+pub struct MemoryDiff {
+    pub step: u128,
+    pub added: Vec<u64>,
+    pub freed: Vec<u64>,
+    pub changed: Vec<AllocDiff>,
+}
+
+fn alloc_bytes(alloc: &Allocation<Tag, Stacks>) -> Vec<Option<u8>> {
+    alloc
+        .bytes
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if alloc.undef_mask.get(Size::from_bytes(i as u64)) { Some(b) } else { None })
+        .collect()
+}
+
+fn alloc_relocations(alloc: &Allocation<Tag, Stacks>) -> BTreeMap<u64, u64> {
+    alloc
+        .relocations
+        .iter()
+        .map(|(offset, &(_tag, target))| (offset.bytes(), target.0))
+        .collect()
+}
+
+fn chunk_hashes(bytes: &[Option<u8>], relocations: &BTreeMap<u64, u64>) -> Vec<u64> {
+    bytes
+        .chunks(CHUNK_SIZE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let start = (i * CHUNK_SIZE) as u64;
+            let mut hasher = DefaultHasher::new();
+            chunk.hash(&mut hasher);
+            for reloc in relocations.range(start..start + CHUNK_SIZE as u64) {
+                reloc.hash(&mut hasher);
+            }
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Record the current memory as the mark `/memdiff` compares against.
+pub fn mark(pcx: &mut PrirodaContext) -> String {
+    let allocs = pcx.ecx.memory().alloc_map().iter(|values| {
+        values
+            .map(|(&id, (_kind, alloc))| {
+                let bytes = alloc_bytes(alloc);
+                let relocations = alloc_relocations(alloc);
+                let contents = if bytes.len() <= COPY_LIMIT {
+                    Contents::Copy(bytes)
+                } else {
+                    Contents::Hashed(chunk_hashes(&bytes, &relocations))
+                };
+                (id.0, AllocMark { size: alloc.bytes.len(), contents, relocations })
+            })
+            .collect::<BTreeMap<_, _>>()
+    });
+    let msg = format!("Marked {} allocations at step {}", allocs.len(), pcx.step_count);
+    pcx.memory_mark = Some(MemoryMark { step: *pcx.step_count, allocs });
+    msg
+}
+
+/// The ranges of `old` and `new` that differ, bytes past the end of one of them count as changed.
+fn changed_ranges(old: &[Option<u8>], new: &[Option<u8>]) -> Vec<ChangedRange> {
+    let len = old.len().max(new.len());
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for i in 0..=len {
+        let changed = i < len && old.get(i) != new.get(i);
+        match (start, changed) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                ranges.push(ChangedRange {
+                    start: s as u64,
+                    end: i as u64,
+                    old: Some(old[s.min(old.len())..i.min(old.len())].to_vec()),
+                    new: new[s.min(new.len())..i.min(new.len())].to_vec(),
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// Like `changed_ranges`, but only knowing the chunk hashes of the old bytes. `bytes` has to have
+/// the size the old bytes had.
+fn changed_chunks(old: &[u64], new: &[u64], bytes: &[Option<u8>]) -> Vec<ChangedRange> {
+    let mut ranges: Vec<ChangedRange> = Vec::new();
+    for (i, (old, new)) in old.iter().zip(new).enumerate() {
+        if old == new {
+            continue;
+        }
+        let start = i * CHUNK_SIZE;
+        let end = ((i + 1) * CHUNK_SIZE).min(bytes.len());
+        match ranges.last_mut() {
+            // Merge adjacent chunks
+            Some(last) if last.end == start as u64 => {
+                last.end = end as u64;
+                last.new = bytes[last.start as usize..end].to_vec();
+            }
+            _ => ranges.push(ChangedRange {
+                start: start as u64,
+                end: end as u64,
+                old: None,
+                new: bytes[start..end].to_vec(),
+            }),
+        }
+    }
+    ranges
+}
+
+/// Compare the current memory against the mark, `None` if there is no mark.
+pub fn diff(pcx: &PrirodaContext) -> Option<MemoryDiff> {
+    let mark = pcx.memory_mark.as_ref()?;
+    let mut diff = MemoryDiff { step: mark.step, ..MemoryDiff::default() };
+    let current = pcx.ecx.memory().alloc_map().iter(|values| {
+        values.map(|(&id, (_kind, alloc))| (id.0, alloc_bytes(alloc), alloc_relocations(alloc))).collect::<Vec<_>>()
+    });
+    let live = current.iter().map(|&(id, _, _)| id).collect::<BTreeSet<_>>();
+    diff.freed = mark.allocs.keys().filter(|id| !live.contains(id)).cloned().collect();
+    for (id, bytes, relocations) in current {
+        let old = match mark.allocs.get(&id) {
+            Some(old) => old,
+            None => {
+                diff.added.push(id);
+                continue;
+            }
+        };
+        let ranges = match &old.contents {
+            Contents::Copy(old_bytes) => changed_ranges(old_bytes, &bytes),
+            Contents::Hashed(hashes) if old.size == bytes.len() => {
+                changed_chunks(hashes, &chunk_hashes(&bytes, &relocations), &bytes)
+            }
+            // Allocations don't change their size, but don't rely on it
+            Contents::Hashed(_) => vec![ChangedRange { start: 0, end: bytes.len() as u64, old: None, new: bytes }],
+        };
+        let relocations = old
+            .relocations
+            .keys()
+            .chain(relocations.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|offset| {
+                let before = old.relocations.get(offset).cloned();
+                let after = relocations.get(offset).cloned();
+                if before != after {
+                    Some((*offset, before, after))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if !ranges.is_empty() || !relocations.is_empty() {
+            diff.changed.push(AllocDiff { alloc_id: id, ranges, relocations });
+        }
+    }
+    diff.added.sort();
+    diff.changed.sort_by_key(|a| a.alloc_id);
+    Some(diff)
+}
+
+fn hex(bytes: &[Option<u8>]) -> String {
+    let mut s = bytes
+        .iter()
+        .take(SHOWN_BYTES)
+        .map(|b| match b {
+            Some(b) => format!("{:02x}", b),
+            None => "__".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    if bytes.len() > SHOWN_BYTES {
+        s.push_str(" …");
+    }
+    s
+}
+
+pub fn render_memdiff(pcx: &PrirodaContext) -> Html<String> {
+    let diff = diff(pcx);
+    crate::render::template(
+        pcx,
+        "Memory diff".to_string(),
+        html! {
+            a(href="/") { : "Back" }
+            : " ";
+            a(href="/memdiff/mark") { : "Mark the current memory" }
+            @ if let Some(diff) = diff {
+                p {
+                    : format!(
+                        "Changes since step {}: {} new, {} freed and {} changed allocations",
+                        diff.step,
+                        diff.added.len(),
+                        diff.freed.len(),
+                        diff.changed.len(),
+                    )
+                }
+                @ if !diff.added.is_empty() {
+                    h3 { : "New allocations" }
+                    p {
+                        @ for id in &diff.added {
+                            a(href=format!("/ptr/{}/0", id)) { : format!("alloc {}", id) }
+                            : " ";
+                        }
+                    }
+                }
+                @ if !diff.freed.is_empty() {
+                    h3 { : "Freed allocations" }
+                    p { : diff.freed.iter().map(|id| format!("alloc {}", id)).collect::<Vec<_>>().join(" ") }
+                }
+                @ for alloc in &diff.changed {
+                    h3 { a(href=format!("/ptr/{}/0", alloc.alloc_id)) { : format!("Allocation {}", alloc.alloc_id) } }
+                    table(border="1") {
+                        tr {
+                            th { : "range" }
+                            th { : "old" }
+                            th { : "new" }
+                        }
+                        @ for range in &alloc.ranges {
+                            tr {
+                                td { a(href=format!("/ptr/{}/{}", alloc.alloc_id, range.start)) { : format!("{}..{}", range.start, range.end) } }
+                                td(style="font-family: monospace;") {
+                                    : range.old.as_ref().map(|old| hex(old)).unwrap_or_else(|| "(not recorded)".to_string())
+                                }
+                                td(style="font-family: monospace;") { : hex(&range.new) }
+                            }
+                        }
+                        @ for &(offset, before, after) in &alloc.relocations {
+                            tr {
+                                td { a(href=format!("/ptr/{}/{}", alloc.alloc_id, offset)) { : format!("pointer at {}", offset) } }
+                                td { : before.map(|id| format!("→ alloc {}", id)).unwrap_or_else(|| "no pointer".to_string()) }
+                                td { : after.map(|id| format!("→ alloc {}", id)).unwrap_or_else(|| "no pointer".to_string()) }
+                            }
+                        }
+                    }
+                }
+            } else {
+                p { : "No mark set, mark the memory and come back after stepping" }
+            }
+        },
+    )
+}
+
+pub fn routes() -> Vec<::rocket::Route> {
+    routes![memdiff::show, memdiff::mark_route]
+}
+
+view_route!(show: "/", |pcx| {
+    render_memdiff(pcx)
+});
+
+action_route!(mark_route: "/mark", |pcx| {
+    mark(pcx)
+});
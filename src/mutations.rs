@@ -0,0 +1,306 @@
+use rustc::ty::layout::Size;
+
+use horrorshow::prelude::*;
+use rocket::request::Form;
+use rocket::response::{Flash, Redirect};
+
+use miri::Tag;
+
+use crate::syntax::ast::Mutability;
+use crate::*;
+
+/// The bytes of an allocation range as they were before a mutation, used to undo it.
+#[derive(Debug)]
+pub struct PriorBytes {
+    pub alloc_id: AllocId,
+    pub offset: u64,
+    pub bytes: Vec<u8>,
+    pub defined: Vec<bool>,
+    /// The pointers overlapping the range, with their offsets
+    pub relocations: Vec<(u64, (Tag, AllocId))>,
+}
+
+impl PriorBytes {
+    pub fn capture(pcx: &PrirodaContext, alloc_id: AllocId, offset: u64, len: u64) -> Result<Self, String> {
+        let alloc = pcx
+            .ecx
+            .memory()
+            .get(alloc_id)
+            .map_err(|err| format!("{:?}", err))?;
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= alloc.bytes.len() as u64)
+            .ok_or_else(|| format!("{}..{} is out of bounds for alloc {}", offset, offset.saturating_add(len), alloc_id.0))?;
+        let pointer_size = pcx.ecx.tcx.data_layout.pointer_size.bytes();
+        Ok(PriorBytes {
+            alloc_id,
+            offset,
+            bytes: alloc.bytes[offset as usize..end as usize].to_vec(),
+            defined: (offset..end)
+                .map(|i| alloc.undef_mask.get(Size::from_bytes(i)))
+                .collect(),
+            relocations: alloc
+                .relocations
+                .iter()
+                .filter(|(start, _)| start.bytes() < end && start.bytes() + pointer_size > offset)
+                .map(|(start, &reloc)| (start.bytes(), reloc))
+                .collect(),
+        })
+    }
+
+    fn restore(&self, ecx: &mut InterpretCx) -> Result<(), String> {
+        let alloc = ecx
+            .memory_mut()
+            .get_mut(self.alloc_id)
+            .map_err(|err| format!("{:?}", err))?;
+        let start = self.offset as usize;
+        alloc.bytes[start..start + self.bytes.len()].copy_from_slice(&self.bytes);
+        for (i, &defined) in self.defined.iter().enumerate() {
+            let i = self.offset + i as u64;
+            alloc
+                .undef_mask
+                .set_range(Size::from_bytes(i), Size::from_bytes(i + 1), defined);
+        }
+        for &(start, reloc) in &self.relocations {
+            alloc.relocations.insert(Size::from_bytes(start), reloc);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Mutation {
+    step_count: u128,
+    description: String,
+    prior: PriorBytes,
+    undoable: bool,
+    undone: bool,
+}
+
+/// Every user initiated change of the interpreter state, in the order it was made.
+#[derive(Debug, Default)]
+pub struct MutationLog(Vec<Mutation>);
+
+impl MutationLog {
+    pub fn record(&mut self, step_count: u128, description: String, prior: PriorBytes) {
+        self.0.push(Mutation {
+            step_count,
+            description,
+            prior,
+            undoable: true,
+            undone: false,
+        });
+    }
+
+    /// Mark all mutations as not undoable anymore. This should be called when the evaluation is
+    /// restarted, as the captured bytes refer to allocations of the old evaluation.
+    pub fn freeze(&mut self) {
+        for mutation in &mut self.0 {
+            mutation.undoable = false;
+        }
+    }
+
+    /// Whether a mutation made at or before `step_count` is still part of the current state.
+    pub fn has_active(&self, step_count: u128) -> bool {
+        self.0
+            .iter()
+            .any(|m| m.undoable && !m.undone && m.step_count <= step_count)
+    }
+
+    fn is_undoable(mutation: &Mutation, step_count: u128) -> bool {
+        mutation.undoable && !mutation.undone && mutation.step_count == step_count
+    }
+}
+
+/// Record a mutation that was just made and log it to the event log.
+pub fn record(pcx: &mut PrirodaContext, description: String, prior: PriorBytes) {
+    crate::events::log(pcx, "mutation", json!({
+        "description": &description,
+        "alloc": prior.alloc_id.0,
+        "offset": prior.offset,
+        "len": prior.bytes.len(),
+    }));
+    crate::dirty::record_user_write(pcx, prior.alloc_id);
+    let step_count = *pcx.step_count;
+    pcx.bookmarks.invalidate_after(step_count, &format!("\"{}\" changed the execution before it", description));
+    pcx.mutations.record(step_count, description, prior);
+}
+
+/// Undo the most recent mutation, as long as no step was executed since it was made.
+pub fn undo_last(pcx: &mut PrirodaContext) -> String {
+    let step_count = *pcx.step_count;
+    let idx = match pcx.mutations.0.iter().rposition(|m| !m.undone) {
+        Some(idx) => idx,
+        None => return "Nothing to undo".to_string(),
+    };
+    if !MutationLog::is_undoable(&pcx.mutations.0[idx], step_count) {
+        return format!(
+            "Can't undo \"{}\": execution continued since it was made",
+            pcx.mutations.0[idx].description
+        );
+    }
+    let mutation = &mut pcx.mutations.0[idx];
+    let alloc_id = mutation.prior.alloc_id;
+    let msg = match mutation.prior.restore(&mut pcx.ecx) {
+        Ok(()) => {
+            mutation.undone = true;
+            let msg = format!("Undid \"{}\"", mutation.description);
+            crate::dirty::record_user_write(pcx, alloc_id);
+            msg
+        }
+        Err(err) => format!("Couldn't undo \"{}\": {}", mutation.description, err),
+    };
+    crate::events::log(pcx, "undo", json!({ "message": &msg }));
+    msg
+}
+
+/// Write `bytes` at `offset` of allocation `alloc_id`, making them defined. Pointers overlapping
+/// the range are only overwritten if `clear_pointers` is set, their remaining bytes become
+/// undefined, like miri does for partially overwritten pointers.
+pub fn write_bytes(pcx: &mut PrirodaContext, alloc_id: AllocId, offset: u64, bytes: &[u8], clear_pointers: bool) -> Result<String, String> {
+    let end = offset
+        .checked_add(bytes.len() as u64)
+        .ok_or_else(|| format!("{}..{} is out of bounds for alloc {}", offset, offset.saturating_add(bytes.len() as u64), alloc_id.0))?;
+    let pointer_size = pcx.ecx.tcx.data_layout.pointer_size.bytes();
+    // The captured range covers the pointers overlapping the written range completely, so undoing
+    // the write restores them
+    let (start, capture_end) = match pcx.ecx.memory().get(alloc_id) {
+        Ok(alloc) if alloc.mutability == Mutability::Immutable => {
+            return Err(format!("Allocation {} is read-only", alloc_id.0));
+        }
+        Ok(alloc) => alloc
+            .relocations
+            .keys()
+            .map(|start| start.bytes())
+            .filter(|&start| start < end && start + pointer_size > offset)
+            .fold((offset, end), |(s, e), reloc| (s.min(reloc), e.max(reloc + pointer_size))),
+        Err(_) => return Err(format!("Allocation {} has no bytes", alloc_id.0)),
+    };
+    let prior = PriorBytes::capture(pcx, alloc_id, start, capture_end - start)?;
+    if !prior.relocations.is_empty() && !clear_pointers {
+        let offsets = prior.relocations.iter().map(|(start, _)| start.to_string()).collect::<Vec<_>>();
+        return Err(format!(
+            "{}..{} overlaps the pointers at offsets {}, allow clearing pointers to overwrite them",
+            offset,
+            end,
+            offsets.join(", ")
+        ));
+    }
+    let alloc = pcx
+        .ecx
+        .memory_mut()
+        .get_mut(alloc_id)
+        .map_err(|err| format!("Can't write to allocation {}: {:?}", alloc_id.0, err))?;
+    for &(start, _) in &prior.relocations {
+        alloc.relocations.remove(&Size::from_bytes(start));
+        // The bytes of the pointer outside of the written range don't form a value anymore
+        alloc
+            .undef_mask
+            .set_range(Size::from_bytes(start), Size::from_bytes(start + pointer_size), false);
+    }
+    alloc.bytes[offset as usize..end as usize].copy_from_slice(bytes);
+    alloc
+        .undef_mask
+        .set_range(Size::from_bytes(offset), Size::from_bytes(end), true);
+    let description = if prior.relocations.is_empty() {
+        format!("wrote {} bytes at alloc {}+{}", bytes.len(), alloc_id.0, offset)
+    } else {
+        format!(
+            "wrote {} bytes at alloc {}+{}, clearing {} pointer{}",
+            bytes.len(),
+            alloc_id.0,
+            offset,
+            prior.relocations.len(),
+            if prior.relocations.len() == 1 { "" } else { "s" },
+        )
+    };
+    record(pcx, description.clone(), prior);
+    Ok(description)
+}
+
+pub fn render_mutations(pcx: &PrirodaContext) -> Html<String> {
+    let step_count = *pcx.step_count;
+    let mutations = pcx
+        .mutations
+        .0
+        .iter()
+        .map(|m| {
+            let state = if m.undone {
+                "undone"
+            } else if MutationLog::is_undoable(m, step_count) {
+                "undoable"
+            } else {
+                "not undoable"
+            };
+            (m.step_count, m.description.clone(), m.prior.alloc_id.0, m.prior.offset, m.prior.bytes.len(), state)
+        })
+        .collect::<Vec<_>>();
+    crate::render::template(
+        pcx,
+        "Mutations".to_string(),
+        html! {
+            a(href="/") { : "Back" }
+            : " ";
+            a(href="/mutations/undo") { : "Undo last mutation" }
+            table(border="1") {
+                tr {
+                    th { : "step" }
+                    th { : "mutation" }
+                    th { : "alloc" }
+                    th { : "range" }
+                    th { : "state" }
+                }
+                @ for (step, description, alloc, offset, len, state) in mutations {
+                    tr {
+                        td { : step.to_string() }
+                        td { : description }
+                        td { a(href=format!("/ptr/{}/{}", alloc, offset)) { : alloc.to_string() } }
+                        td { : format!("{}..{}", offset, offset + len as u64) }
+                        td { : state }
+                    }
+                }
+            }
+        },
+    )
+}
+
+pub fn routes() -> Vec<::rocket::Route> {
+    routes![mutations::show, mutations::undo, mutations::write]
+}
+
+view_route!(show: "/", |pcx| {
+    render_mutations(pcx)
+});
+
+action_route!(undo: "/undo", |pcx| {
+    undo_last(pcx)
+});
+
+#[derive(FromForm)]
+pub struct WriteForm {
+    offset: u64,
+    /// Hex bytes, whitespace is ignored
+    bytes: String,
+    clear_pointers: bool,
+}
+
+/// Write the bytes of the form of the allocation page and go back to it.
+#[post("/write/<alloc_id>", data = "<form>")]
+pub fn write(sender: rocket::State<PrirodaSender>, alloc_id: u64, form: Form<WriteForm>) -> RResult<Flash<Redirect>> {
+    sender.do_work(move |pcx| {
+        let target = Redirect::to(format!("/ptr/{}/{}", alloc_id, form.offset));
+        let digits = form.bytes.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+        let bytes = (0..digits.len())
+            .step_by(2)
+            .map(|i| digits.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<u8>>>();
+        let res = match bytes {
+            Some(ref bytes) if !bytes.is_empty() => write_bytes(pcx, AllocId(alloc_id), form.offset, bytes, form.clear_pointers),
+            _ => Err(format!("{:?} are not hex bytes", form.bytes)),
+        };
+        match res {
+            Ok(msg) => Flash::success(target, msg),
+            Err(msg) => Flash::error(target, msg),
+        }
+    })
+}
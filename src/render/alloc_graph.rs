@@ -0,0 +1,123 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Write;
+use std::num::NonZeroU64;
+
+use rustc::ty::layout::Size;
+use rustc_mir::interpret::LocalValue;
+
+use miri::{AllocId, Frame, Operand, Pointer, Scalar, Tag};
+
+use crate::InterpretCx;
+
+/// How many relocations away from the roots allocations are followed by default.
+pub const DEFAULT_DEPTH: usize = 4;
+
+/// How many allocations are expanded by default.
+pub const DEFAULT_NODES: usize = 100;
+
+/// Bytes of an allocation shown in its node.
+const PREVIEW_BYTES: usize = 8;
+
+/// The allocations the live locals of `frame` are stored in.
+pub fn frame_allocs(frame: &Frame<Tag, NonZeroU64>) -> Vec<AllocId> {
+    let mut allocs = frame
+        .locals
+        .iter()
+        .filter_map(|state| match state.value {
+            LocalValue::Live(Operand::Indirect(mplace)) => match mplace.ptr {
+                Scalar::Ptr(ptr) => Some(ptr.alloc_id),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    allocs.sort();
+    allocs.dedup();
+    allocs
+}
+
+/// A DOT graph of the allocations reachable from `roots` through at most `max_depth`
+/// relocations, with an edge labelled with its source offset for every relocation. At most
+/// `max_nodes` allocations are expanded, the targets of their relocations are drawn dashed.
+pub fn alloc_graph_dot(ecx: &InterpretCx, roots: &[AllocId], max_depth: usize, max_nodes: usize) -> String {
+    let mut dot = String::new();
+    writeln!(dot, "digraph allocations {{").unwrap();
+    writeln!(dot, r#"    node [fontname="monospace", shape="box"];"#).unwrap();
+    writeln!(dot, r#"    edge [fontname="monospace"];"#).unwrap();
+
+    // Visited allocations aren't queued again, so cycles terminate
+    let mut visited = roots.iter().cloned().collect::<HashSet<_>>();
+    let mut queue = roots.iter().map(|&id| (id, 0)).collect::<VecDeque<_>>();
+    let mut expanded = 0;
+    while let Some((alloc_id, depth)) = queue.pop_front() {
+        if expanded == max_nodes {
+            writeln!(dot, r#"    a{} [label="alloc {}", style="dashed"];"#, alloc_id.0, alloc_id.0).unwrap();
+            continue;
+        }
+        expanded += 1;
+        let alloc = match ecx.memory().get(alloc_id) {
+            Ok(alloc) => alloc,
+            Err(_) => {
+                let label = match ecx.memory().get_fn(Pointer::new(alloc_id, Size::from_bytes(0)).with_tag(Tag::Untagged)) {
+                    Ok(instance) => format!("fn {}", instance),
+                    Err(_) => format!("alloc {}\nfreed", alloc_id.0),
+                };
+                writeln!(dot, r#"    a{} [label="{}"];"#, alloc_id.0, escape_dot(&label)).unwrap();
+                continue;
+            }
+        };
+        let mut label = format!("alloc {}\n{} bytes", alloc_id.0, alloc.bytes.len());
+        if let Some(kind) = super::locals::alloc_kind(ecx, alloc_id) {
+            label.push_str(&format!(", {}", kind));
+        }
+        if let Some(name) = super::locals::alloc_name(ecx, alloc_id) {
+            label.push_str(&format!("\n{}", name));
+        }
+        let preview = (0..alloc.bytes.len().min(PREVIEW_BYTES))
+            .map(|i| {
+                let defined = alloc
+                    .undef_mask
+                    .is_range_defined(Size::from_bytes(i as u64), Size::from_bytes(i as u64 + 1))
+                    .is_ok();
+                if defined {
+                    format!("{:02x}", alloc.bytes[i])
+                } else {
+                    "__".to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        label.push_str(&format!("\n{}", preview));
+        if alloc.bytes.len() > PREVIEW_BYTES {
+            label.push_str(" …");
+        }
+        writeln!(
+            dot,
+            r#"    a{} [label="{}", URL="/ptr/{}/0"];"#,
+            alloc_id.0,
+            escape_dot(&label),
+            alloc_id.0
+        )
+        .unwrap();
+
+        for (offset, &(_tag, target)) in alloc.relocations.iter() {
+            writeln!(dot, r#"    a{} -> a{} [label="+{}"];"#, alloc_id.0, target.0, offset.bytes()).unwrap();
+            if depth < max_depth && visited.insert(target) {
+                queue.push_back((target, depth + 1));
+            }
+        }
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+/// The graph rendered to svg.
+pub fn render_svg(dot: String) -> Result<String, String> {
+    let graph = ::cgraph::Graph::parse(dot).map_err(|err| format!("{:?}", err))?;
+    let svg = graph.render_dot().map_err(|err| format!("{:?}", err))?;
+    String::from_utf8(svg).map_err(|err| err.to_string())
+}
+
+pub fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,217 @@
+//! The stack view: every frame with its monomorphized function, the kind of shim it runs, its
+//! position and source location.
+//!
+//! Runs of recursive frames are condensed like before. Consecutive frames of the standard library
+//! are hidden behind a toggle, unless the viewed frame is one of them.
+
+use rustc::hir::def_id::DefId;
+use rustc::hir::map::definitions::DefPathData;
+use rustc::mir;
+use rustc::ty::{Instance, InstanceDef, TyCtxt};
+
+use horrorshow::prelude::*;
+use horrorshow::Raw;
+
+use miri::{Frame, Tag};
+
+use std::num::NonZeroU64;
+
+use crate::render::source;
+use crate::*;
+
+/// Crates whose frames are hidden by default.
+const STD_CRATES: &[&str] = &["std", "core", "alloc"];
+
+/// Fewer consecutive std frames than this aren't hidden.
+const MIN_STD_FRAMES: usize = 2;
+
+const LOAD_STACK_RUN_SCRIPT: &str = r#"
+function load_stack_run(details, start, end) {
+    if(details.dataset.loaded) {
+        return;
+    }
+    details.dataset.loaded = true;
+    fetch("/stack_run/" + start + "/" + end).then((res) => res.text()).then((html) => {
+        details.insertAdjacentHTML("beforeend", html);
+    });
+}
+function toggle_std_frames(group) {
+    for(let row of document.querySelectorAll(".std_frames_" + group)) {
+        row.style.display = row.style.display ? "" : "none";
+    }
+}
+"#;
+
+pub struct FrameInfo {
+    /// The path of the function with its generic arguments
+    pub name: String,
+    /// What kind of function the frame runs, `None` for plain functions
+    pub kind: Option<String>,
+    pub def_id: DefId,
+    pub block: mir::BasicBlock,
+    pub stmt: usize,
+    /// Where the frame is, with macro expansions resolved to their call site
+    pub location: String,
+    /// The function belongs to the standard library
+    pub is_std: bool,
+}
+
+fn kind(tcx: TyCtxt, instance: Instance) -> Option<String> {
+    Some(match instance.def {
+        InstanceDef::Item(def_id) if tcx.def_key(def_id).disambiguated_data.data == DefPathData::ClosureExpr => {
+            "closure".to_string()
+        }
+        InstanceDef::Item(_) => return None,
+        InstanceDef::DropGlue(_, Some(ty)) => format!("drop glue for {}", ty),
+        InstanceDef::DropGlue(_, None) => "drop glue".to_string(),
+        InstanceDef::Intrinsic(_) => "intrinsic".to_string(),
+        InstanceDef::Virtual(..) => "virtual call".to_string(),
+        InstanceDef::ClosureOnceShim { .. } => "closure shim".to_string(),
+        InstanceDef::FnPtrShim(..) => "fn pointer shim".to_string(),
+        InstanceDef::CloneShim(..) => "clone shim".to_string(),
+        _ => "shim".to_string(),
+    })
+}
+
+pub fn is_std(tcx: TyCtxt, def_id: DefId) -> bool {
+    !def_id.is_local() && STD_CRATES.contains(&&*tcx.crate_name(def_id.krate).as_str())
+}
+
+pub fn frame_info(tcx: TyCtxt, frame: &Frame<Tag, NonZeroU64>) -> FrameInfo {
+    let def_id = frame.instance.def_id();
+    FrameInfo {
+        // The plain item, as shims are named after their kind
+        name: Instance::new(def_id, frame.instance.substs).to_string(),
+        kind: kind(tcx, frame.instance),
+        def_id,
+        block: frame.block,
+        stmt: frame.stmt,
+        location: source::pretty_src_path(source::current_span(frame)),
+        is_std: is_std(tcx, def_id),
+    }
+}
+
+/// The `FrameInfo` of every stack frame, outermost first.
+pub fn frames(pcx: &PrirodaContext) -> Vec<FrameInfo> {
+    pcx.ecx.stack().iter().map(|frame| frame_info(pcx.ecx.tcx.tcx, frame)).collect()
+}
+
+enum Row {
+    Frame(usize),
+    /// Frames of the same function, see `recursion_runs`
+    Run(usize, usize),
+    /// Consecutive std frames, innermost first
+    Std(Vec<usize>),
+}
+
+/// The rows of the stack table, innermost first.
+fn rows(pcx: &PrirodaContext, frames: &[FrameInfo], display_idx: usize) -> Vec<Row> {
+    let runs = crate::render::recursion_runs(
+        &frames.iter().map(|frame| frame.def_id).collect::<Vec<_>>(),
+        pcx.config.condense_recursion,
+    );
+    let mut rows = Vec::new();
+    let mut i = 0;
+    while i < frames.len() {
+        let end = runs
+            .iter()
+            .find(|&&(start, _)| start == i)
+            .map(|&(_, end)| end)
+            .unwrap_or(i);
+        rows.push(if end == i { Row::Frame(i) } else { Row::Run(i, end) });
+        i = end + 1;
+    }
+    rows.reverse();
+    let mut grouped = Vec::new();
+    let mut std_frames = Vec::new();
+    for row in rows {
+        match row {
+            Row::Frame(i) if frames[i].is_std && i != display_idx => std_frames.push(i),
+            row => {
+                flush_std_frames(&mut grouped, &mut std_frames);
+                grouped.push(row);
+            }
+        }
+    }
+    flush_std_frames(&mut grouped, &mut std_frames);
+    grouped
+}
+
+fn flush_std_frames(rows: &mut Vec<Row>, std_frames: &mut Vec<usize>) {
+    if std_frames.len() >= MIN_STD_FRAMES {
+        rows.push(Row::Std(std::mem::replace(std_frames, Vec::new())));
+    } else {
+        rows.extend(std_frames.drain(..).map(Row::Frame));
+    }
+}
+
+/// The cells of the row of frame `i`.
+pub fn render_frame(frames: &[FrameInfo], i: usize, display_idx: usize) -> Box<dyn RenderBox + '_> {
+    let frame = &frames[i];
+    box_html! {
+        @ if i == display_idx { td { : Raw("&#8594;") } } else { td; }
+        td { : i.to_string() }
+        td {
+            a(href=crate::listing::url(frame.def_id), title=format!("MIR of {:?}", frame.def_id)) { : &frame.name }
+            @ if let Some(kind) = &frame.kind {
+                : " ";
+                span(class="frame-kind") { : kind }
+            }
+        }
+        td { : format!("bb{}[{}]", frame.block.index(), frame.stmt) }
+        td { : &frame.location }
+        @ if i == display_idx { td; } else { td { a(href=format!("/frame/{}/select", i)) { : "View" } } }
+    }
+}
+
+/// The stack table with the frame `display_idx` marked.
+pub fn render_backtrace(pcx: &PrirodaContext, display_idx: usize) -> Box<dyn RenderBox + '_> {
+    let frames = frames(pcx);
+    let rows = rows(pcx, &frames, display_idx);
+    box_html! {
+        script { : Raw(LOAD_STACK_RUN_SCRIPT) }
+        table(border="1") {
+            @ for (group, row) in rows.iter().enumerate() {
+                @ if let Row::Frame(i) = *row {
+                    tr { : render_frame(&frames, i, display_idx) }
+                }
+                @ if let Row::Run(start, end) = *row {
+                    tr {
+                        @ if start <= display_idx && display_idx <= end { td { : Raw("&#8594;") } } else { td; }
+                        td { : format!("{}..{}", start, end) }
+                        td {
+                            details(ontoggle=format!("load_stack_run(this, {}, {})", start, end)) {
+                                summary {
+                                    : format!("{} ×{}", frames[start].name, end - start + 1)
+                                }
+                            }
+                        }
+                        td;
+                        td { : format!("{} … {}", frames[start].location, frames[end].location) }
+                        td {
+                            a(href="#", onclick=format!(
+                                "let d = prompt('View which depth? ({0}..{1})', {1}); if(d !== null) {{ window.location = '/frame/' + d + '/select'; }} return false;",
+                                start, end,
+                            )) { : "View…" }
+                        }
+                    }
+                }
+                @ if let Row::Std(std_frames) = row {
+                    tr {
+                        td;
+                        td(colspan="5") {
+                            a(href="#", onclick=format!("toggle_std_frames({}); return false;", group)) {
+                                : format!("{} std frames", std_frames.len())
+                            }
+                        }
+                    }
+                    @ for &i in std_frames {
+                        tr(class=format!("std_frames_{}", group), style="display: none;") {
+                            : render_frame(&frames, i, display_idx)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! The stacked borrows state of allocations and the errors it raises.
+//!
+//! Miri keeps the borrow stacks private, so they are read from the debug output of the
+//! allocation's `extra` state: a range map of stacks of items like `[Unique for <12>]`, each
+//! granting a permission to a tag, optionally protected by a call.
+
+use horrorshow::prelude::*;
+use horrorshow::Raw;
+
+use miri::{AllocId, Allocation, Immediate, Operand, Pointer, Scalar, ScalarMaybeUndef, Stacks, Tag};
+
+use rustc_mir::interpret::LocalValue;
+
+use crate::render::{escape_html, template};
+use crate::*;
+
+lazy_static::lazy_static! {
+    static ref RANGE: regex::Regex =
+        regex::Regex::new(r"range: (\d+)\.\.(\d+), data: Stack \{ borrows: \[((?:\[[^\]]*\](?:, )?)*)\]").unwrap();
+    static ref ITEM: regex::Regex = regex::Regex::new(r"\[(\w+) for (<[^>]*>)(?: \(call (\d+)\))?\]").unwrap();
+    static ref ACCESS: regex::Regex =
+        regex::Regex::new(r"no item granting (\w+) access to tag (<[^>]*>) found in borrow stack").unwrap();
+    static ref PROTECTED: regex::Regex =
+        regex::Regex::new(r"not granting access to tag (<[^>]*>) because incompatible item is protected: (\[[^\]]*\])").unwrap();
+    static ref REBORROW: regex::Regex =
+        regex::Regex::new(r"trying to reborrow for (\w+), but parent tag (<[^>]*>) does not have an appropriate item").unwrap();
+    static ref DEALLOC: regex::Regex = regex::Regex::new(r"deallocating with active protector \((\d+)\)").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    /// `Unique`, `SharedReadWrite`, `SharedReadOnly` or `Disabled`
+    pub perm: String,
+    /// `<12>` or `<untagged>`
+    pub tag: String,
+    /// The call protecting the item from being popped
+    pub protector: Option<u64>,
+}
+
+/// The borrow stack of every byte range of `alloc`, bottom first.
+pub fn stacks(alloc: &Allocation<Tag, Stacks>) -> Vec<(u64, u64, Vec<Item>)> {
+    let debug = format!("{:?}", alloc.extra);
+    RANGE
+        .captures_iter(&debug)
+        .map(|range| {
+            let items = ITEM
+                .captures_iter(&range[3])
+                .map(|item| Item {
+                    perm: item[1].to_string(),
+                    tag: item[2].to_string(),
+                    protector: item.get(3).and_then(|call| call.as_str().parse().ok()),
+                })
+                .collect();
+            (range[1].parse().unwrap(), range[2].parse().unwrap(), items)
+        })
+        .collect()
+}
+
+fn render_item(item: &Item, marked: Option<&str>) -> String {
+    let mut s = format!("{} for {}", item.perm, item.tag);
+    if let Some(call) = item.protector {
+        s.push_str(&format!(" (protected by call {})", call));
+    }
+    let s = escape_html(&s).into_owned();
+    if marked == Some(&item.tag[..]) {
+        format!("<b style=\"background: yellow;\">{}</b>", s)
+    } else {
+        s
+    }
+}
+
+/// A table of the borrow stacks of `alloc`, top of the stack first, with the items of the tag
+/// `marked` highlighted. Only the stacks of bytes in `window` are shown if it is given.
+pub fn render_stacks(
+    alloc_id: AllocId,
+    alloc: &Allocation<Tag, Stacks>,
+    marked: Option<&str>,
+    window: Option<(u64, u64)>,
+) -> String {
+    let mut stacks = stacks(alloc);
+    if let Some((window_start, window_end)) = window {
+        stacks.retain(|&(start, end, _)| start < window_end && window_start < end);
+    }
+    if stacks.is_empty() {
+        return "<p>No borrow stacks</p>".to_string();
+    }
+    let mut s = "<table border=\"1\"><tr><th>bytes</th><th>stack, top first</th></tr>".to_string();
+    for (start, end, items) in stacks {
+        s.push_str(&format!(
+            "<tr><td><a href=\"/ptr/{}/{}\">{}..{}</a></td><td>{}</td></tr>",
+            alloc_id.0,
+            start,
+            start,
+            end,
+            items.iter().rev().map(|item| render_item(item, marked)).collect::<Vec<_>>().join("<br>"),
+        ));
+    }
+    s.push_str("</table>");
+    s
+}
+
+/// The parts of a stacked borrows error.
+#[derive(Debug, Default)]
+pub struct BorrowError {
+    /// The access or reborrow which was rejected, like `write access` or `reborrow for Unique`
+    pub access: Option<String>,
+    /// The tag whose item was missing or which was denied access
+    pub tag: Option<String>,
+    /// The protected item which prevented the access
+    pub item: Option<String>,
+    /// The call whose protector prevented a deallocation
+    pub call: Option<u64>,
+}
+
+/// The parts of `error` if it was raised by stacked borrows.
+pub fn parse_error(error: &str) -> Option<BorrowError> {
+    if let Some(caps) = ACCESS.captures(error) {
+        return Some(BorrowError {
+            access: Some(format!("{} access", &caps[1])),
+            tag: Some(caps[2].to_string()),
+            ..BorrowError::default()
+        });
+    }
+    if let Some(caps) = PROTECTED.captures(error) {
+        return Some(BorrowError {
+            access: Some("access".to_string()),
+            tag: Some(caps[1].to_string()),
+            item: Some(caps[2].to_string()),
+            ..BorrowError::default()
+        });
+    }
+    if let Some(caps) = REBORROW.captures(error) {
+        return Some(BorrowError {
+            access: Some(format!("reborrow for {}", &caps[1])),
+            tag: Some(caps[2].to_string()),
+            ..BorrowError::default()
+        });
+    }
+    if let Some(caps) = DEALLOC.captures(error) {
+        return Some(BorrowError {
+            access: Some("deallocation".to_string()),
+            call: caps[1].parse().ok(),
+            ..BorrowError::default()
+        });
+    }
+    None
+}
+
+/// The allocations and offsets pointed to by pointers with the tag `tag`, stored in memory or in
+/// the locals of any frame.
+fn tag_targets(ecx: &InterpretCx, tag: &str) -> Vec<(AllocId, u64)> {
+    let mut targets = ecx.memory().alloc_map().iter(|values| {
+        values
+            .flat_map(|(&id, (_, alloc))| {
+                alloc
+                    .relocations
+                    .iter()
+                    .filter(|(_, (reloc_tag, _))| format!("{:?}", reloc_tag) == tag)
+                    .map(|(offset, &(_, target))| {
+                        // The offset into the target is stored in the bytes of the pointer
+                        let target_offset = match alloc.read_ptr_sized(ecx, Pointer::new(id, *offset).with_tag(Tag::Untagged)) {
+                            Ok(ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr))) => ptr.offset.bytes(),
+                            _ => 0,
+                        };
+                        (target, target_offset)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    });
+    for frame in ecx.stack() {
+        for state in frame.locals.iter() {
+            let vals = match state.value {
+                LocalValue::Live(Operand::Immediate(Immediate::Scalar(val))) => vec![val],
+                LocalValue::Live(Operand::Immediate(Immediate::ScalarPair(a, b))) => vec![a, b],
+                _ => continue,
+            };
+            for val in vals {
+                if let ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr)) = val {
+                    if format!("{:?}", ptr.tag) == tag {
+                        targets.push((ptr.alloc_id, ptr.offset.bytes()));
+                    }
+                }
+            }
+        }
+    }
+    targets.sort_by_key(|&(alloc_id, offset)| (alloc_id.0, offset));
+    targets.dedup();
+    targets
+}
+
+/// The allocations with an item for `tag` in one of their borrow stacks.
+fn allocs_with_item(ecx: &InterpretCx, tag: &str) -> Vec<AllocId> {
+    let mut ids = ecx.memory().alloc_map().iter(|values| {
+        values
+            .filter(|(_, (_, alloc))| stacks(alloc).iter().any(|(_, _, items)| items.iter().any(|item| item.tag == tag)))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>()
+    });
+    ids.sort();
+    ids
+}
+
+/// The last stacked borrows error with the borrow stacks of the allocations its tag points to.
+pub fn render_borrow_error(pcx: &PrirodaContext) -> Html<String> {
+    let error = match &pcx.last_stop {
+        Some(crate::step::StopReason::Error(error)) => Some(error.clone()),
+        _ => pcx.events.error().map(str::to_string),
+    };
+    let parsed = error.as_ref().and_then(|error| parse_error(error));
+    let targets = parsed
+        .as_ref()
+        .and_then(|parsed| parsed.tag.as_ref())
+        .map(|tag| tag_targets(&pcx.ecx, tag))
+        .unwrap_or_default();
+    let stacks = targets
+        .iter()
+        .filter_map(|&(alloc_id, offset)| {
+            let alloc = pcx.ecx.memory().get(alloc_id).ok()?;
+            let marked = parsed.as_ref().and_then(|parsed| parsed.tag.as_ref()).map(|tag| &tag[..]);
+            Some((alloc_id, offset, render_stacks(alloc_id, alloc, marked, None)))
+        })
+        .collect::<Vec<_>>();
+    // The protected item is still on the stacks, unlike the item of the tag that was denied access
+    let item_allocs = parsed
+        .as_ref()
+        .and_then(|parsed| parsed.item.as_ref())
+        .and_then(|item| ITEM.captures(item))
+        .map(|item| allocs_with_item(&pcx.ecx, &item[2]))
+        .unwrap_or_default();
+    template(
+        pcx,
+        "Stacked borrows error".to_string(),
+        html! {
+            a(href="/") { : "Back" }
+            @ if let Some(error) = &error {
+                div(class="stop_reason error") { pre { : error } }
+            }
+            @ if let Some(parsed) = &parsed {
+                table(border="1") {
+                    @ if let Some(access) = &parsed.access {
+                        tr { th { : "access" } td { : access } }
+                    }
+                    @ if let Some(tag) = &parsed.tag {
+                        tr { th { : "tag" } td { : tag } }
+                    }
+                    @ if let Some(item) = &parsed.item {
+                        tr {
+                            th { : "protected item" }
+                            td {
+                                : item;
+                                @ for id in &item_allocs {
+                                    : " ";
+                                    a(href=format!("/ptr/{}/0", id.0)) { : format!("alloc {}", id.0) }
+                                }
+                            }
+                        }
+                    }
+                    @ if let Some(call) = parsed.call {
+                        tr { th { : "protecting call" } td { : call.to_string() } }
+                    }
+                }
+                @ if parsed.tag.is_some() && stacks.is_empty() {
+                    p { : "No pointer with this tag is left in memory or in the locals" }
+                }
+                @ for (alloc_id, offset, stack) in &stacks {
+                    h3 {
+                        : "Pointed to at ";
+                        a(href=format!("/ptr/{}/{}", alloc_id.0, offset)) { : format!("allocation {} + {}", alloc_id.0, offset) }
+                    }
+                    : Raw(stack);
+                }
+            } else {
+                p { : "The last error wasn't raised by stacked borrows" }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use miri::{Immediate, Operand, Pointer, Scalar, ScalarMaybeUndef, Tag};
+    use rustc_mir::interpret::LocalValue;
+
+    use crate::testing::{command, with_program};
+    use crate::PrirodaContext;
+
+    const REBORROW: &str = r#"
+fn main() {
+    let mut x = 1u32;
+    let r = &mut x;
+    *r = 2;
+    assert_eq!(x, 2);
+}
+"#;
+
+    /// The pointers held by the locals of the innermost frame.
+    fn local_pointers(pcx: &PrirodaContext) -> Vec<Pointer<Tag>> {
+        pcx.ecx
+            .frame()
+            .locals
+            .iter()
+            .filter_map(|local| match local.value {
+                LocalValue::Live(Operand::Immediate(Immediate::Scalar(ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr))))) => Some(ptr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stacks_of_reborrowed_local() {
+        let (stacks, tag) = with_program(REBORROW, |pcx| {
+            for _ in 0..20 {
+                for ptr in local_pointers(pcx) {
+                    let stacks = super::stacks(pcx.ecx.memory().get(ptr.alloc_id).unwrap());
+                    let tag = format!("{:?}", ptr.tag);
+                    // `r` is pushed onto the stack of `x` when it is retagged
+                    let pushed = stacks
+                        .iter()
+                        .any(|(_, _, items)| items.len() > 1 && items.last().map(|item| &item.tag) == Some(&tag));
+                    if pushed {
+                        return (stacks, tag);
+                    }
+                }
+                command(pcx, "step");
+            }
+            panic!("`r` was never pushed onto the borrow stack of `x`");
+        });
+        assert_eq!(stacks.len(), 1, "{:?}", stacks);
+        let (start, end, items) = &stacks[0];
+        assert_eq!((*start, *end), (0, 4));
+        let top = items.last().unwrap();
+        assert_eq!((&top.perm[..], &top.tag[..], top.protector), ("Unique", &tag[..], None));
+        assert!(items[..items.len() - 1].iter().all(|item| item.tag != tag), "{:?}", items);
+    }
+}
@@ -0,0 +1,263 @@
+//! Reusing the rendered locals of frames which didn't change.
+//!
+//! The locals of every frame are rendered for every page view, following every pointer the
+//! printers know about, even though outer frames rarely change between two steps. The printers
+//! record every allocation they read from in `Limits::reads`. The rendered locals of a frame are
+//! kept with the values of its locals, a fingerprint of every allocation read and whether the
+//! allocations the values read point to still exist, which rendering a pointer shows. While
+//! none of them changed, rendering again gives the same result. Fingerprints are compared
+//! instead of the writes `dirty` tracks, as those miss writes by intrinsics and by foreign
+//! functions emulated by miri.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroU64;
+
+use rustc::ty::Instance;
+use rustc_mir::interpret::{LocalValue, Place};
+
+use miri::{AllocId, Frame, Immediate, Operand, Scalar, ScalarMaybeUndef, Tag};
+
+use crate::render::locals::{Limits, LocalInfo};
+use crate::InterpretCx;
+
+/// Frames whose rendering depends on more allocations than this are rendered again every time.
+const MAX_READ_SET: usize = 256;
+
+/// Frames which read more bytes than this are rendered again every time, fingerprinting them
+/// would cost about as much as rendering.
+const MAX_READ_BYTES: usize = 1 << 20;
+
+/// The rendered locals of every frame, keyed by stack depth and instance.
+#[derive(Default)]
+pub struct LocalsCache<'tcx>(RefCell<HashMap<(usize, Instance<'tcx>), CachedFrame>>);
+
+struct CachedFrame {
+    /// The settings the locals were rendered with
+    settings: Limits<'static>,
+    /// The values of the locals of the frame and of the place it returns to
+    values: Vec<LocalValue<Tag>>,
+    /// The allocations rendering depends on, whether they were read and their `state`
+    reads: Vec<(AllocId, bool, Option<u64>)>,
+    locals: Vec<LocalInfo>,
+}
+
+impl<'tcx> LocalsCache<'tcx> {
+    /// The locals of `frame`, the `depth`th frame of the stack, as `render` renders them with
+    /// `settings`, recording the allocations it reads in the set it is passed. They are only
+    /// rendered again if the values of the locals or the memory read changed since they were last
+    /// rendered with the same settings.
+    pub fn get_or_render<'a>(
+        &self,
+        ecx: &InterpretCx<'a, 'tcx>,
+        frame: &Frame<'tcx, 'tcx, Tag, NonZeroU64>,
+        depth: usize,
+        settings: Limits<'static>,
+        render: impl FnOnce(&RefCell<HashSet<AllocId>>) -> Vec<LocalInfo>,
+    ) -> Vec<LocalInfo>
+    where
+        'tcx: 'a,
+    {
+        let mut frames = self.0.borrow_mut();
+        // Frames that were popped don't exist anymore
+        let stack_len = ecx.stack().len();
+        frames.retain(|&(depth, _), _| depth < stack_len);
+        let key = (depth, frame.instance);
+        let values = frame_values(ecx, frame);
+        if let Some(cached) = frames.get(&key) {
+            if cached.settings == settings
+                && cached.values == values
+                && cached
+                    .reads
+                    .iter()
+                    .all(|&(alloc_id, read, state)| self::state(ecx, alloc_id, read) == state)
+            {
+                return cached.locals.clone();
+            }
+        }
+        let reads = RefCell::new(HashSet::new());
+        let locals = render(&reads);
+        match read_set(ecx, &values, reads.into_inner()) {
+            Some(reads) => {
+                frames.insert(key, CachedFrame { settings, values, reads, locals: locals.clone() });
+            }
+            None => {
+                frames.remove(&key);
+            }
+        }
+        locals
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+/// The values of the locals of `frame`, followed by the place it returns to.
+fn frame_values(ecx: &InterpretCx, frame: &Frame<Tag, NonZeroU64>) -> Vec<LocalValue<Tag>> {
+    let mut values = frame.locals.iter().map(|local| local.value.clone()).collect::<Vec<_>>();
+    match frame.return_place.map(|place| *place) {
+        Some(Place::Ptr(mplace)) => values.push(LocalValue::Live(Operand::Indirect(mplace))),
+        Some(Place::Local { frame, local }) => values.push(ecx.stack()[frame].locals[local].value.clone()),
+        None => {}
+    }
+    values
+}
+
+/// The fingerprints of the allocations `read` and the existence of those the pointers in them
+/// and in `values` point to, which rendering a pointer shows. `None` if there are more than
+/// `MAX_READ_SET` or those read are bigger than `MAX_READ_BYTES`.
+fn read_set(
+    ecx: &InterpretCx,
+    values: &[LocalValue<Tag>],
+    read: HashSet<AllocId>,
+) -> Option<Vec<(AllocId, bool, Option<u64>)>> {
+    let pointee = |val: ScalarMaybeUndef<Tag>| match val {
+        ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr)) => Some(ptr.alloc_id),
+        _ => None,
+    };
+    let mut targets = values
+        .iter()
+        .flat_map(|value| match *value {
+            LocalValue::Live(Operand::Indirect(mplace)) => vec![
+                mplace.ptr.to_ptr().ok().map(|ptr| ptr.alloc_id),
+                // The vtable of trait objects
+                mplace.meta.and_then(|meta| meta.to_ptr().ok()).map(|ptr| ptr.alloc_id),
+            ],
+            LocalValue::Live(Operand::Immediate(Immediate::Scalar(val))) => vec![pointee(val)],
+            LocalValue::Live(Operand::Immediate(Immediate::ScalarPair(a, b))) => vec![pointee(a), pointee(b)],
+            _ => Vec::new(),
+        })
+        .flatten()
+        .collect::<HashSet<_>>();
+    let mut bytes = 0;
+    for &alloc_id in &read {
+        // Functions and freed allocations don't point anywhere
+        if let Ok(alloc) = ecx.memory().get(alloc_id) {
+            bytes += alloc.bytes.len();
+            targets.extend(alloc.relocations.iter().map(|(_, &(_tag, target))| target));
+        }
+    }
+    let targets = targets.difference(&read).cloned().collect::<Vec<_>>();
+    if read.len() + targets.len() > MAX_READ_SET || bytes > MAX_READ_BYTES {
+        return None;
+    }
+    Some(
+        read.iter()
+            .map(|&alloc_id| (alloc_id, true))
+            .chain(targets.into_iter().map(|alloc_id| (alloc_id, false)))
+            .map(|(alloc_id, read)| (alloc_id, read, state(ecx, alloc_id, read)))
+            .collect(),
+    )
+}
+
+/// The state of `alloc_id` the rendered locals depend on: a hash of its bytes, defined bytes and
+/// pointers if it was `read`, only whether it still exists otherwise. `None` if it isn't an
+/// allocation or was freed.
+fn state(ecx: &InterpretCx, alloc_id: AllocId, read: bool) -> Option<u64> {
+    let alloc = ecx.memory().get(alloc_id).ok()?;
+    if !read {
+        return Some(0);
+    }
+    let mut hasher = DefaultHasher::new();
+    alloc.bytes.hash(&mut hasher);
+    alloc.undef_mask.hash(&mut hasher);
+    for (offset, &(_tag, target)) in alloc.relocations.iter() {
+        offset.hash(&mut hasher);
+        target.hash(&mut hasher);
+    }
+    Some(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use rustc_mir::interpret::LocalValue;
+
+    use miri::{Immediate, Operand, Scalar, ScalarMaybeUndef};
+
+    use crate::render::locals::{collect_locals, Limits};
+    use crate::testing::{command, current_fn, with_program};
+    use crate::PrirodaContext;
+
+    const POKE: &str = r#"
+fn poke(p: *mut u32) {
+    let offset = 1;
+    unsafe {
+        *p.add(offset) = 4;
+    }
+}
+
+fn main() {
+    let mut v = vec![1u32, 2, 3];
+    poke(v.as_mut_ptr());
+}
+"#;
+
+    /// Render the locals of `main` through the cache and return whether they were rendered
+    /// again.
+    fn render_main(pcx: &PrirodaContext) -> bool {
+        let depth = pcx
+            .ecx
+            .stack()
+            .iter()
+            .position(|frame| pcx.ecx.tcx.def_path_str(frame.instance.def_id()) == "main")
+            .unwrap();
+        let frame = &pcx.ecx.stack()[depth];
+        let rendered = Cell::new(false);
+        let settings = pcx.config.locals_settings();
+        pcx.locals_cache.get_or_render(&pcx.ecx, frame, depth, settings, |reads| {
+            rendered.set(true);
+            collect_locals(&pcx.ecx, frame, Limits { reads: Some(reads), ..settings })
+        });
+        rendered.get()
+    }
+
+    #[test]
+    fn writes_through_raw_pointers_render_again() {
+        let rendered = with_program(POKE, |pcx| {
+            while current_fn(pcx) != "poke" {
+                command(pcx, "step");
+            }
+            let mut rendered = Vec::new();
+            while current_fn(pcx) == "poke" {
+                rendered.push(render_main(pcx));
+                command(pcx, "step");
+            }
+            rendered
+        });
+        assert!(rendered.len() > 2, "{:?}", rendered);
+        assert!(rendered[0], "{:?}", rendered);
+        // `main` only changes when `poke` writes to the heap buffer of `v`
+        assert_eq!(rendered[1..].iter().filter(|&&rendered| rendered).count(), 1, "{:?}", rendered);
+    }
+
+    #[test]
+    fn user_writes_render_again() {
+        let rendered = with_program(POKE, |pcx| {
+            while current_fn(pcx) != "poke" {
+                command(pcx, "step");
+            }
+            // The argument of `poke` points to the heap buffer of `v`
+            let buffer = pcx
+                .ecx
+                .frame()
+                .locals
+                .iter()
+                .find_map(|local| match local.value {
+                    LocalValue::Live(Operand::Immediate(Immediate::Scalar(ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr))))) => {
+                        Some(ptr)
+                    }
+                    _ => None,
+                })
+                .unwrap();
+            let before = (render_main(pcx), render_main(pcx));
+            crate::mutations::write_bytes(pcx, buffer.alloc_id, buffer.offset.bytes(), &[7], false).unwrap();
+            (before, render_main(pcx))
+        });
+        assert_eq!(rendered, ((true, false), true));
+    }
+}
@@ -0,0 +1,180 @@
+//! Rendering a range of an allocation as a value of a type given by the user.
+
+use rustc::hir;
+use rustc::ty::layout::{LayoutOf, Size};
+use rustc::ty::{Ty, TyCtxt};
+use rustc_mir::interpret::MPlaceTy;
+
+use horrorshow::Raw;
+use rocket::response::content::Html;
+
+use miri::{AllocId, Pointer, Tag};
+
+use crate::render::{locals, template};
+use crate::PrirodaContext;
+
+/// The types suggested by the cast form, any other type can be typed in.
+const PRIMITIVES: &[&str] = &[
+    "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize", "f32",
+    "f64", "bool", "char", "*const u8", "*mut u8",
+];
+
+/// The type named `ty`. Primitives, raw pointers, arrays and structs, enums and unions of the
+/// local crate without generic parameters are supported.
+pub fn parse_type<'a, 'tcx: 'a>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: &str) -> Result<Ty<'tcx>, String> {
+    let ty = ty.trim();
+    if ty.starts_with("*const ") {
+        return Ok(tcx.mk_imm_ptr(parse_type(tcx, &ty["*const ".len()..])?));
+    }
+    if ty.starts_with("*mut ") {
+        return Ok(tcx.mk_mut_ptr(parse_type(tcx, &ty["*mut ".len()..])?));
+    }
+    if ty.starts_with('[') && ty.ends_with(']') {
+        let inner = &ty[1..ty.len() - 1];
+        let semi = inner
+            .rfind(';')
+            .ok_or_else(|| format!("{} has no length, slices can't be read without one", ty))?;
+        let len = inner[semi + 1..]
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid array length in {}", ty))?;
+        return Ok(tcx.mk_array(parse_type(tcx, &inner[..semi])?, len));
+    }
+    let types = &tcx.types;
+    let primitive = match ty {
+        "()" => Some(tcx.mk_unit()),
+        "bool" => Some(types.bool),
+        "char" => Some(types.char),
+        "u8" => Some(types.u8),
+        "u16" => Some(types.u16),
+        "u32" => Some(types.u32),
+        "u64" => Some(types.u64),
+        "u128" => Some(types.u128),
+        "usize" => Some(types.usize),
+        "i8" => Some(types.i8),
+        "i16" => Some(types.i16),
+        "i32" => Some(types.i32),
+        "i64" => Some(types.i64),
+        "i128" => Some(types.i128),
+        "isize" => Some(types.isize),
+        "f32" => Some(types.f32),
+        "f64" => Some(types.f64),
+        _ => None,
+    };
+    if let Some(primitive) = primitive {
+        return Ok(primitive);
+    }
+    let candidates = tcx
+        .hir()
+        .krate()
+        .items
+        .values()
+        .filter(|item| match item.node {
+            hir::ItemKind::Struct(..) | hir::ItemKind::Enum(..) | hir::ItemKind::Union(..) => true,
+            _ => false,
+        })
+        .map(|item| tcx.hir().local_def_id_from_hir_id(item.hir_id))
+        .filter(|&def_id| crate::consts::path_matches(&tcx.def_path_str(def_id), ty))
+        .collect::<Vec<_>>();
+    match &candidates[..] {
+        [] => Err(format!("No type {} in the local crate", ty)),
+        [def_id] if tcx.generics_of(*def_id).count() != 0 => {
+            Err(format!("{} is generic, only types without generic parameters can be read", tcx.def_path_str(*def_id)))
+        }
+        [def_id] => Ok(tcx.type_of(*def_id)),
+        _ => Err(format!(
+            "{} is ambiguous: {}",
+            ty,
+            candidates.iter().map(|&def_id| tcx.def_path_str(def_id)).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// The bytes of allocation `alloc_id` at `offset` read as `ty`, the parsed type and a hexdump of
+/// the bytes grouped by the fields of `ty`.
+fn cast(pcx: &PrirodaContext, alloc_id: AllocId, offset: u64, ty: &str) -> Result<(String, String, String), String> {
+    let ty = parse_type(pcx.ecx.tcx.tcx, ty)?;
+    let layout = pcx.ecx.layout_of(ty).map_err(|err| format!("Layout of {} unknown: {:?}", ty, err))?;
+    if layout.is_unsized() {
+        return Err(format!("{} is unsized", ty));
+    }
+    let alloc = pcx
+        .ecx
+        .memory()
+        .get(alloc_id)
+        .map_err(|_| format!("Allocation {} has no bytes", alloc_id.0))?;
+    let size = layout.size.bytes();
+    let end = match offset.checked_add(size) {
+        Some(end) if end <= alloc.bytes.len() as u64 => end,
+        _ => {
+            return Err(format!(
+                "{} has {} bytes, at offset {} it reaches past the end of allocation {} of {} bytes",
+                ty,
+                size,
+                offset,
+                alloc_id.0,
+                alloc.bytes.len()
+            ));
+        }
+    };
+    let align = layout.align.abi.bytes();
+    if offset % align != 0 || alloc.align.bytes() < align {
+        return Err(format!(
+            "{} needs align {}, offset {} of allocation {} with align {} is misaligned",
+            ty,
+            align,
+            offset,
+            alloc_id.0,
+            alloc.align.bytes()
+        ));
+    }
+    let ptr = Pointer::new(alloc_id, Size::from_bytes(offset)).with_tag(Tag::Untagged);
+    let limits = locals::Limits {
+        // Reading memory as a pointer is mostly done to see where it points
+        auto_deref: pcx.config.auto_deref.max(1),
+        ..pcx.config.locals_settings()
+    };
+    let (_alloc, value, _ranges) = locals::print_operand_with(&pcx.ecx, MPlaceTy::from_aligned_ptr(ptr, layout).into(), limits)
+        .map_err(|()| format!("The bytes at offset {} can't be read as {}", offset, ty))?;
+    let fields = locals::field_ranges(&pcx.ecx, layout, offset);
+    let annotations = locals::Annotations { fields: &fields, ..locals::Annotations::default() };
+    let bytes = locals::print_alloc(&pcx.ecx, alloc_id, alloc, offset, end, &annotations);
+    Ok((ty.to_string(), value, bytes))
+}
+
+pub fn render_cast(pcx: &PrirodaContext, alloc_id: AllocId, offset: u64, ty: Option<String>) -> Html<String> {
+    let (value, error) = match ty.as_ref().map(|ty| cast(pcx, alloc_id, offset, ty)) {
+        Some(Ok(value)) => (Some(value), None),
+        Some(Err(err)) => (None, Some(err)),
+        None => (None, None),
+    };
+    template(
+        pcx,
+        format!("Allocation {} + {}", alloc_id.0, offset),
+        html! {
+            a(href=format!("/ptr/{}/{}", alloc_id.0, offset)) { : format!("Back to allocation {}", alloc_id.0) }
+            form(action="/cast") {
+                input(type="hidden", name="alloc", value=alloc_id.0.to_string());
+                : "Read offset ";
+                input(name="offset", size="6", value=offset.to_string());
+                : " as ";
+                input(name="ty", list="cast_types", placeholder="u32, *const u8, [u16; 4], MyStruct", value=ty.clone().unwrap_or_default());
+                datalist(id="cast_types") {
+                    @ for primitive in PRIMITIVES {
+                        option(value=*primitive);
+                    }
+                }
+                : " ";
+                input(type="submit", value="Read");
+            }
+            @ if let Some(err) = error {
+                div(class="stop_reason error") { : err }
+            }
+            @ if let Some((ty, value, bytes)) = value {
+                h3 { : format!("Allocation {} + {} as {}", alloc_id.0, offset, ty) }
+                div(style="font-family: monospace") { : Raw(value) }
+                : Raw(bytes);
+            }
+        },
+    )
+}
@@ -0,0 +1,82 @@
+//! The control flow graph of a function as a DOT graph with a node per basic block.
+
+use std::fmt::Write;
+
+use rustc::hir::def_id::DefId;
+use rustc::mir;
+use rustc_data_structures::indexed_vec::Idx;
+
+use crate::render::alloc_graph::escape_dot;
+use crate::step::LocalBreakpoints;
+
+/// Statements and terminators are cut off after this many characters.
+const MAX_LINE_CHARS: usize = 60;
+
+/// Blocks show at most this many statements, followed by the number of omitted ones.
+const MAX_STATEMENTS: usize = 12;
+
+fn truncate(s: String) -> String {
+    if s.chars().count() <= MAX_LINE_CHARS {
+        s
+    } else {
+        s.chars().take(MAX_LINE_CHARS - 1).chain(std::iter::once('…')).collect()
+    }
+}
+
+/// The graph of `mir` with the block `current` filled and the blocks with breakpoints outlined in
+/// red. Every block links to its statements in the MIR listing of `def_id`.
+pub fn cfg_dot(def_id: DefId, mir: &mir::Body, current: Option<mir::BasicBlock>, breakpoints: LocalBreakpoints) -> String {
+    let mut dot = String::new();
+    writeln!(dot, "digraph cfg {{").unwrap();
+    writeln!(dot, r#"    node [fontname="monospace", shape="box"];"#).unwrap();
+    writeln!(dot, r#"    edge [fontname="monospace"];"#).unwrap();
+    for (bb, block) in mir.basic_blocks().iter_enumerated() {
+        let mut label = format!("{:?}{}\\l", bb, if block.is_cleanup { " (cleanup)" } else { "" });
+        let shown = block.statements.iter().filter(|statement| !crate::should_hide_stmt(statement)).collect::<Vec<_>>();
+        for statement in shown.iter().take(MAX_STATEMENTS) {
+            label.push_str(&escape_dot(&truncate(format!("{:?}", statement))));
+            label.push_str("\\l");
+        }
+        if shown.len() > MAX_STATEMENTS {
+            label.push_str(&format!("… {} more statements\\l", shown.len() - MAX_STATEMENTS));
+        }
+        let mut head = String::new();
+        block.terminator().kind.fmt_head(&mut head).unwrap();
+        label.push_str(&escape_dot(&truncate(head)));
+        label.push_str("\\l");
+        let has_breakpoint = (0..=block.statements.len()).any(|stmt| breakpoints.breakpoint_exists(bb, stmt));
+        let mut attrs = String::new();
+        if Some(bb) == current {
+            attrs.push_str(r#", style="filled", fillcolor="lightcoral""#);
+        }
+        if has_breakpoint {
+            attrs.push_str(r#", color="red", penwidth="2""#);
+        }
+        writeln!(
+            dot,
+            r#"    bb{} [label="{}", URL="{}#bb{}_0"{}];"#,
+            bb.index(),
+            label,
+            crate::listing::url(def_id),
+            bb.index(),
+            attrs,
+        )
+        .unwrap();
+    }
+    for (source, block) in mir.basic_blocks().iter_enumerated() {
+        let terminator = block.terminator();
+        for (&target, label) in terminator.successors().zip(terminator.kind.fmt_successor_labels()) {
+            writeln!(
+                dot,
+                r#"    bb{} -> bb{} [label="{}"{}];"#,
+                source.index(),
+                target.index(),
+                escape_dot(&label),
+                if label == "unwind" { r#", style="dashed""# } else { "" },
+            )
+            .unwrap();
+        }
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+}
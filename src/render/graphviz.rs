@@ -9,18 +9,21 @@
 // except according to those terms.
 
 use rustc::mir::*;
+use rustc::ty::TyCtxt;
 use crate::step::LocalBreakpoints;
+use super::escape_html;
+use std::collections::HashMap;
 use miri::{Frame, Tag};
 use std::fmt::{self, Debug, Write};
 use std::num::NonZeroU64;
 
-pub fn render_html(frame: &Frame<Tag, NonZeroU64>, breakpoints: LocalBreakpoints) -> String {
-    let mut rendered = String::new();
-    render_mir_svg(&frame.mir, breakpoints, &mut rendered, None).unwrap();
-    for (i, promoted) in frame.mir.promoted.iter_enumerated() {
-        println!("promoted: {:?}", i);
-        render_mir_svg(promoted, breakpoints, &mut rendered, Some(i.index())).unwrap();
-    }
+pub fn render_html(
+    tcx: TyCtxt,
+    frame: &Frame<Tag, NonZeroU64>,
+    breakpoints: LocalBreakpoints,
+    goto_links: bool,
+) -> String {
+    let mut rendered = render_fn_html(&frame.mir, breakpoints);
     let (bb, stmt) = {
         let blck = &frame.mir.basic_blocks()[frame.block];
         (
@@ -83,6 +86,37 @@ pub fn render_html(frame: &Frame<Tag, NonZeroU64>, breakpoints: LocalBreakpoints
                 .join(",")
         )
     };
+    let stmt_counts = format!(
+        "let stmt_counts = [{}];",
+        frame
+            .mir
+            .basic_blocks()
+            .iter()
+            .map(|blck| blck.statements.len().to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let inlined = {
+        let mut inlined = HashMap::new();
+        // Finding the inlined functions goes through the whole MIR, so it's done once per scope
+        let mut chains = HashMap::new();
+        for (bb, blck) in frame.mir.basic_blocks().iter_enumerated() {
+            let source_infos = blck
+                .statements
+                .iter()
+                .map(|stmt| stmt.source_info)
+                .chain(::std::iter::once(blck.terminator().source_info));
+            for (stmt, source_info) in source_infos.enumerate() {
+                let chain = chains
+                    .entry(source_info.scope)
+                    .or_insert_with(|| super::source::inlined_from(tcx, &frame.mir, source_info.scope).join("\n"));
+                if !chain.is_empty() {
+                    inlined.insert(format!("{}:{}", bb.index(), stmt), chain.clone());
+                }
+            }
+        }
+        format!("let inlined = {};", ::serde_json::to_string(&inlined).unwrap())
+    };
     rendered
         .write_fmt(format_args!(
             r##"<style>
@@ -109,15 +143,59 @@ pub fn render_html(frame: &Frame<Tag, NonZeroU64>, breakpoints: LocalBreakpoints
                 el.classList.add("edge-" + edge_colors[title]);
             }}
         }}
+        {stmt_counts}
+        {inlined}
+        // `node{{n}}` is basic block `n - 1`, its statements start at the sixth child
+        for(let node of document.querySelectorAll("#mir > svg:first-child #graph0 .node")) {{
+            let bb = parseInt(node.id.substr(4)) - 1;
+            let len = stmt_counts[bb];
+            Array.from(node.children).forEach((el, i) => {{
+                let stmt = i + 1 - 6;
+                if(len == 0 ? stmt != 0 : (stmt < 0 || stmt == len || stmt > len + 1)) {{
+                    return;
+                }}
+                if(stmt == len + 1) {{
+                    stmt = len;
+                }}
+                if((bb + ":" + stmt) in inlined) {{
+                    let title = document.createElementNS("http://www.w3.org/2000/svg", "title");
+                    title.textContent = inlined[bb + ":" + stmt];
+                    el.appendChild(title);
+                }}
+                if({goto_links}) {{
+                    el.style.cursor = "pointer";
+                    el.addEventListener("dblclick", () => {{
+                        window.location = "/step/goto/" + bb + "/" + stmt;
+                    }});
+                }}
+            }});
+        }}
         </script>"##,
             bb,
             stmt,
-            edge_colors = edge_colors
+            edge_colors = edge_colors,
+            goto_links = goto_links,
+            stmt_counts = stmt_counts,
+            inlined = inlined,
         ))
         .unwrap();
     rendered
 }
 
+/// The colors alternately used for groups of statements inlined from the same call.
+const INLINED_COLORS: [&str; 2] = ["#1f5fbf", "#8a2be2"];
+
+/// The graphs of `mir` and its promoted constants, without highlighting a position.
+pub fn render_fn_html(mir: &Body, breakpoints: LocalBreakpoints) -> String {
+    let mut rendered = String::new();
+    render_mir_svg(mir, breakpoints, &mut rendered, None).unwrap();
+    for (i, promoted) in mir.promoted.iter_enumerated() {
+        println!("promoted: {:?}", i);
+        render_mir_svg(promoted, breakpoints, &mut rendered, Some(i.index())).unwrap();
+    }
+    rendered
+}
+
 /// Write a graphviz DOT graph of a list of MIRs.
 pub fn render_mir_svg<W: Write>(
     mir: &Body,
@@ -177,17 +255,32 @@ fn write_node_label<W: Write>(
     // List of statements in the middle.
     if !data.statements.is_empty() {
         write!(w, r#"<tr><td align="left" balign="left">"#)?;
+        // Consecutive statements inlined from the same call get the same color
+        let mut prev_inlined = None;
+        let mut color = INLINED_COLORS[1];
         for (stmt_index, statement) in data.statements.iter().enumerate() {
+            let inlined = super::source::inlined_root(mir, statement.source_info.scope);
+            if inlined.is_some() && inlined != prev_inlined {
+                color = if color == INLINED_COLORS[0] { INLINED_COLORS[1] } else { INLINED_COLORS[0] };
+            }
+            prev_inlined = inlined;
+            if inlined.is_some() {
+                write!(w, r#"<font color="{}">"#, color)?;
+            }
             if breakpoints.breakpoint_exists(block, stmt_index) {
                 write!(w, "+ ")?;
             } else {
                 write!(w, "&nbsp; ")?;
             }
             if crate::should_hide_stmt(statement) {
-                write!(w, "&lt;+&gt;<br/>")?;
+                write!(w, "&lt;+&gt;")?;
             } else {
-                write!(w, "{}<br/>", escape(statement))?;
+                write!(w, "{}", escape(statement))?;
+            }
+            if inlined.is_some() {
+                write!(w, "</font>")?;
             }
+            write!(w, "<br/>")?;
         }
         write!(w, "</td></tr>")?;
     }
@@ -257,7 +350,3 @@ fn node(promoted: Option<usize>, block: BasicBlock) -> String {
 fn escape<T: Debug>(t: &T) -> String {
     escape_html(&format!("{:?}", t)).into_owned()
 }
-
-fn escape_html(s: &str) -> ::std::borrow::Cow<str> {
-    ::rocket::http::RawStr::from_str(s).html_escape()
-}
@@ -1,80 +1,552 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroU64;
 
-use rustc::mir::{self, interpret::InterpError};
+use rustc::hir::{self, def::CtorKind, def_id::DefId};
+use rustc::mir::{self, interpret::{GlobalAlloc, InterpError, InterpErrorInfo}, LocalKind};
+use rustc_data_structures::indexed_vec::Idx;
 use rustc::ty::{
-    layout::{Abi, Size},
-    subst::Subst,
-    ParamEnv, TyKind, TyS, TypeAndMut,
+    layout::{Abi, DiscriminantKind, LayoutOf, Size, TyLayout, VariantIdx, Variants},
+    subst::{Subst, SubstsRef},
+    AdtDef, Instance, ParamEnv, Ty, TyKind, TyS, TypeAndMut,
 };
 
 use miri::{
-    Allocation, InterpResult, Frame, OpTy, Operand, Pointer,
+    AllocId, Allocation, InterpResult, Frame, OpTy, Operand, Pointer,
     Scalar, ScalarMaybeUndef, Stacks, Tag, Immediate,
 };
 
+use rustc_mir::interpret::{AllocMap, LocalValue, MemoryKind, MPlaceTy, Place};
+
 use horrorshow::prelude::*;
 use horrorshow::Template;
 
+use crate::render::cache::LocalsCache;
+use crate::render::escape_html;
+use crate::syntax::ast::Mutability;
 use crate::InterpretCx;
 
-pub fn render_locals<'a, 'tcx: 'a>(
+/// Everything the locals table shows about a local, shared by the html, plain text and json
+/// renderings.
+#[derive(Serialize, Debug, Clone)]
+pub struct LocalInfo {
+    pub id: usize,
+    pub name: String,
+    pub ty: String,
+    pub alloc: Option<u64>,
+    /// The html shown in the value column, the pretty and raw renderings joined with any badges
+    pub value: String,
+    /// `None` for values that couldn't be pretty printed and for dead or uninitialized locals
+    pub pretty: Option<String>,
+    /// The bytes or scalars backing the value, empty for dead or uninitialized locals
+    pub raw: String,
+    /// One of `return`, `argument`, `variable` and `temporary`
+    pub kind: &'static str,
+    #[serde(skip)]
+    pub style: &'static str,
+    #[serde(skip)]
+    pub ranges: Vec<(u64, u64)>,
+}
+
+fn kind_name(kind: LocalKind) -> &'static str {
+    match kind {
+        LocalKind::ReturnPointer => "return",
+        LocalKind::Arg => "argument",
+        LocalKind::Var => "variable",
+        LocalKind::Temp => "temporary",
+    }
+}
+
+/// Every local of `frame`. `settings` gives the limits and formats independent of the local, the
+/// depth, elements and budget are those of the locals table.
+pub fn collect_locals<'a, 'tcx: 'a>(
     ecx: &InterpretCx<'a, 'tcx>,
     frame: &Frame<'tcx, 'tcx, Tag, NonZeroU64>,
-) -> String {
-    let &Frame {
-        ref mir,
-        ref return_place,
-        ref instance,
-        ..
-    } = frame;
-
-    //               name    ty      alloc        val     style
-    let locals: Vec<(String, String, Option<u64>, String, &str)> = mir
+    settings: Limits,
+) -> Vec<LocalInfo> {
+    let frame_index = ecx.stack().iter().position(|f| ::std::ptr::eq(f, frame));
+
+    frame
+        .mir
         .local_decls
-        .iter_enumerated()
-        .map(|(id, local_decl)| {
-            let name = local_decl
-                .name
-                .map(|n| n.as_str().to_string())
+        .indices()
+        .map(|id| {
+            let full_url = frame_index
+                .map(|frame| format!("/frame/{}/local/{}/full", frame, id.index()))
                 .unwrap_or_else(String::new);
+            let budget = Cell::new(LOCAL_BUDGET);
+            local_info(ecx, frame, id, Limits {
+                depth: frame_index.map(|_| MAX_DEPTH),
+                full_url: &full_url,
+                elements: Some(MAX_ELEMENTS),
+                budget: frame_index.map(|_| &budget),
+                ..settings
+            })
+        })
+        .collect()
+}
 
-            // FIXME Don't panic when trying to read from uninit variable.
-            // Panic message:
-            // > error: internal compiler error: src/librustc_mir/interpret/eval_context.rs:142:
-            // > The type checker should prevent reading from a never-written local
-            let op_ty = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                if id == mir::RETURN_PLACE {
-                return_place.map(|p| {
-                    ecx.place_to_op(p).unwrap()
-                    }).ok_or(false)
-            } else {
-                    ecx.access_local(frame, id, None).map_err(|_| false)
-                }
-            })) {
-                Ok(op_ty) => op_ty,
-                Err(_) => Err(true),
-            };
+fn local_info<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    frame: &Frame<'tcx, 'tcx, Tag, NonZeroU64>,
+    id: mir::Local,
+    limits: Limits,
+) -> LocalInfo {
+    let local_decl = &frame.mir.local_decls[id];
+    let name = local_decl
+        .name
+        .map(|n| n.as_str().to_string())
+        .unwrap_or_else(String::new);
+    let ty = ecx.tcx.normalize_erasing_regions(ParamEnv::reveal_all(), local_decl.ty.subst(ecx.tcx.tcx, frame.instance.substs));
 
-            let (alloc, val, style) = match op_ty {
-                Err(false) => (None, "&lt;dead&gt;".to_owned(), "font-size: 0;"),
-                Err(true) => (None, "&lt;uninit&gt;".to_owned(), "color: darkmagenta;"),
-                Ok(op_ty) => {
-                    match print_operand(ecx, op_ty) {
-                        Ok((alloc, text)) => (alloc, text, ""),
-                        Err(()) => (None, "&lt;error&gt;".to_owned(), "color: red;"),
-                    }
-                }
-            };
-            let ty = ecx.tcx.normalize_erasing_regions(ParamEnv::reveal_all(), local_decl.ty.subst(ecx.tcx.tcx, instance.substs));
-            (name, ty.to_string(), alloc, val, style)
-        })
-        .collect();
+    let (printed, style) = match local_operand(ecx, frame, id) {
+        Err(LocalError::Dead) | Err(LocalError::Uninit) if id == mir::RETURN_PLACE => {
+            (Err(return_slot(ecx, frame)), "color: gray;")
+        }
+        Err(LocalError::Dead) => (Err("&lt;dead&gt;".to_owned()), "color: gray;"),
+        Err(LocalError::Uninit) => (Err("&lt;uninit&gt;".to_owned()), "color: darkmagenta;"),
+        // One local which can't be evaluated doesn't prevent rendering the others
+        Err(LocalError::Interp(err)) => (Err(format!("&lt;error: {}&gt;", escape_html(&err))), "color: red;"),
+        Ok(op_ty) => match print_operand_parts(ecx, op_ty, limits) {
+            Ok(printed) => (Ok(printed), ""),
+            Err(()) => (Err("&lt;error&gt;".to_owned()), "color: red;"),
+        },
+    };
+    let (alloc, value, pretty, raw, ranges) = match printed {
+        Ok(printed) => (printed.alloc, printed.text(), printed.pretty, printed.raw, printed.ranges),
+        Err(value) => (None, value, None, String::new(), Vec::new()),
+    };
+    LocalInfo {
+        id: id.index(),
+        name,
+        ty: ty.to_string(),
+        alloc,
+        value,
+        pretty,
+        raw,
+        kind: kind_name(frame.mir.local_kind(id)),
+        style,
+        ranges,
+    }
+}
+
+/// Why the operand of a local can't be read.
+enum LocalError {
+    Dead,
+    Uninit,
+    /// Any other error of the interpreter, like an unsupported place
+    Interp(String),
+}
+
+impl<'tcx> From<InterpErrorInfo<'tcx>> for LocalError {
+    fn from(err: InterpErrorInfo<'tcx>) -> Self {
+        match err.kind {
+            InterpError::DeadLocal => LocalError::Dead,
+            InterpError::ReadUndefBytes(_) => LocalError::Uninit,
+            kind => LocalError::Interp(format!("{:?}", kind)),
+        }
+    }
+}
+
+/// The operand of local `id`.
+fn local_operand<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    frame: &Frame<'tcx, 'tcx, Tag, NonZeroU64>,
+    id: mir::Local,
+) -> Result<OpTy<'tcx, miri::Tag>, LocalError> {
+    // FIXME Don't panic when trying to read from uninit variable.
+    // Panic message:
+    // > error: internal compiler error: src/librustc_mir/interpret/eval_context.rs:142:
+    // > The type checker should prevent reading from a never-written local
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        if id == mir::RETURN_PLACE {
+            // The return place of a frame below the top one is a place in its caller, which
+            // `place_to_op` resolves in the frame it belongs to
+            match frame.return_place {
+                Some(place) => ecx.place_to_op(place).map_err(LocalError::from),
+                None => Err(LocalError::Dead),
+            }
+        } else {
+            ecx.access_local(frame, id, None).map_err(LocalError::from)
+        }
+    })) {
+        Ok(op_ty) => op_ty,
+        Err(_) => Err(LocalError::Uninit),
+    }
+}
+
+/// How much of a value to render.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Limits<'a> {
+    /// Fields nested deeper than this are replaced by a link to `full_url`, `None` is unlimited
+    pub depth: Option<usize>,
+    /// Only render the bytes, not the pretty printed value
+    pub raw: bool,
+    /// The route rendering the value at full fidelity, see `render_local`
+    pub full_url: &'a str,
+    /// How many levels of references, raw pointers and `Box<T>` are rendered as their pointee
+    pub auto_deref: usize,
+    /// How integers are printed
+    pub int_format: IntFormat,
+    /// Annotate fields with their offset and enums with the placement of their discriminant
+    pub layout: bool,
+    /// Arrays and slices show at most this many elements, `None` is unlimited
+    pub elements: Option<u64>,
+    /// Strings show at most this many bytes, the rest is linked, `None` is unlimited
+    pub string_bytes: Option<u64>,
+    /// The number of bytes of HTML left for the value. Once it is used up, the remaining parts
+    /// are replaced by a link to `full_url`. `None` is unlimited
+    pub budget: Option<&'a Cell<usize>>,
+    /// Every allocation the value is read from is added to this, see `cache`
+    pub reads: Option<&'a RefCell<HashSet<AllocId>>>,
+}
+
+/// The base integers are printed in.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntFormat {
+    Dec,
+    Hex,
+    Both,
+}
+
+impl IntFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "dec" => Some(IntFormat::Dec),
+            "hex" => Some(IntFormat::Hex),
+            "both" => Some(IntFormat::Both),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntFormat::Dec => "dec",
+            IntFormat::Hex => "hex",
+            IntFormat::Both => "both",
+        }
+    }
+
+    /// `dec` is the decimal rendering of the integer with the two's complement `bits`.
+    fn format(self, bits: u128, dec: String) -> String {
+        match self {
+            IntFormat::Dec => dec,
+            IntFormat::Hex => format!("0x{:X}", bits),
+            IntFormat::Both => format!("{} / 0x{:X}", dec, bits),
+        }
+    }
+}
+
+/// The nesting depth rendered in the locals table.
+pub const MAX_DEPTH: usize = 4;
+
+/// The number of bytes of HTML rendered for a single local in the locals table.
+pub const LOCAL_BUDGET: usize = 16 * 1024;
+
+/// The number of elements of arrays and slices shown in the locals table.
+pub const MAX_ELEMENTS: u64 = 32;
+
+
+impl Limits<'static> {
+    pub const UNLIMITED: Self = Limits {
+        depth: None,
+        raw: false,
+        full_url: "",
+        auto_deref: 0,
+        int_format: IntFormat::Dec,
+        layout: false,
+        elements: None,
+        string_bytes: None,
+        budget: None,
+        reads: None,
+    };
+}
+
+impl<'a> Limits<'a> {
+    fn nested(self) -> Self {
+        Limits {
+            depth: self.depth.map(|depth| depth.saturating_sub(1)),
+            ..self
+        }
+    }
+
+    fn deref(self) -> Self {
+        Limits {
+            auto_deref: self.auto_deref - 1,
+            ..self.nested()
+        }
+    }
+
+    fn exhausted(self) -> bool {
+        self.budget.map_or(false, |budget| budget.get() == 0)
+    }
+
+    /// Remember that the value is read from the allocation `ptr` points to.
+    fn read(self, ptr: Scalar<Tag>) {
+        if let (Some(reads), Ok(ptr)) = (self.reads, ptr.to_ptr()) {
+            reads.borrow_mut().insert(ptr.alloc_id);
+        }
+    }
+
+    /// Remember that the value is read from `place`, and from the vtable of trait objects.
+    fn read_place(self, place: MPlaceTy<Tag>) {
+        self.read(place.ptr);
+        if let Some(meta) = place.meta {
+            self.read(meta);
+        }
+    }
+
+    /// Remember that the value is read from `op_ty` if it is stored in memory.
+    fn read_operand(self, op_ty: OpTy<Tag>) {
+        if let Ok(place) = op_ty.try_as_mplace() {
+            self.read_place(place);
+        }
+    }
+
+    /// `… and N more bytes` for a string with `remaining` bytes after the first `shown`. The
+    /// string of a local itself links to the next chunk, nested ones to `full_url`.
+    fn more_bytes(self, remaining: u64, shown: u64, level: usize) -> String {
+        let text = format!("… and {} more bytes", remaining);
+        if self.full_url.is_empty() {
+            text
+        } else if level == 0 {
+            format!("<a href=\"{}/chunk?offset={}\">{}</a>", self.full_url, shown, text)
+        } else {
+            format!("<a href=\"{}\">{}</a>", self.full_url, text)
+        }
+    }
+
+    fn truncation_marker(self, total_depth: usize) -> String {
+        format!(
+            "<a href=\"{}?depth={}\" title=\"Render deeper\">…</a>",
+            self.full_url,
+            total_depth * 2 + 1,
+        )
+    }
+}
+
+/// Where the return value of `frame` will be written, shown instead of the return place when it
+/// can't be read yet.
+fn return_slot<'a, 'tcx: 'a>(ecx: &InterpretCx<'a, 'tcx>, frame: &Frame<'tcx, 'tcx, Tag, NonZeroU64>) -> String {
+    match frame.return_place.map(|place| *place) {
+        Some(Place::Local { frame: caller, local }) => format!(
+            "return slot in caller <a href=\"/frame/{caller}\">frame {caller}</a> _{local} of {instance}",
+            caller = caller,
+            local = local.index(),
+            instance = ecx
+                .stack()
+                .get(caller)
+                .map(|caller| escape_html(&caller.instance.to_string()).into_owned())
+                .unwrap_or_else(|| "&lt;popped frame&gt;".to_string()),
+        ),
+        Some(Place::Ptr(mplace)) => format!("return slot at {}", print_scalar(ecx, mplace.ptr)),
+        None => "no return slot, the function doesn't return".to_string(),
+    }
+}
+
+/// The index of local `local` of `frame`. Indices taken from urls are checked before the index is
+/// created, as indexing with a local the frame doesn't have panics.
+pub fn frame_local(frame: &Frame<'_, '_, Tag, NonZeroU64>, local: usize) -> Result<mir::Local, String> {
+    if local < frame.mir.local_decls.len() {
+        Ok(mir::Local::new(local))
+    } else {
+        Err(format!("No local _{} in {}", local, frame.instance))
+    }
+}
+
+/// Local `local` of `frame` rendered with `limits`.
+pub fn render_local<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    frame: &Frame<'tcx, 'tcx, Tag, NonZeroU64>,
+    local: mir::Local,
+    limits: Limits,
+) -> Result<LocalInfo, String> {
+    if frame.mir.local_decls.get(local).is_none() {
+        return Err(format!("No local _{} in {}", local.index(), frame.instance));
+    }
+    Ok(local_info(ecx, frame, local, limits))
+}
+
+/// The values of the locals of every frame as last rendered by `render_locals`, keyed by stack
+/// depth and instance, to highlight the locals that changed since.
+#[derive(Default)]
+pub struct LocalsHistory<'tcx>(RefCell<HashMap<(usize, Instance<'tcx>), RenderedFrame>>);
+
+struct RenderedFrame {
+    /// The step the frame was last rendered at
+    step: u128,
+    /// The values when the frame was rendered at an earlier step
+    previous: Option<Vec<(Option<u64>, String)>>,
+    current: Vec<(Option<u64>, String)>,
+}
+
+impl<'tcx> LocalsHistory<'tcx> {
+    /// Record the values of the locals of a frame rendered at `step` and return which of them
+    /// changed since the frame was rendered at an earlier step. Rendering the same step again
+    /// keeps the highlights.
+    fn changed(
+        &self,
+        stack_len: usize,
+        depth: usize,
+        instance: Instance<'tcx>,
+        step: u128,
+        values: Vec<(Option<u64>, String)>,
+    ) -> Vec<bool> {
+        let mut frames = self.0.borrow_mut();
+        // Frames that were popped don't exist anymore
+        frames.retain(|&(depth, _), _| depth < stack_len);
+        let frame = frames.entry((depth, instance)).or_insert_with(|| RenderedFrame {
+            step,
+            previous: None,
+            current: values.clone(),
+        });
+        if frame.step != step {
+            frame.previous = Some(::std::mem::replace(&mut frame.current, values.clone()));
+            frame.step = step;
+        }
+        match &frame.previous {
+            Some(previous) => values.iter().enumerate().map(|(i, value)| previous.get(i) != Some(value)).collect(),
+            None => vec![false; values.len()],
+        }
+    }
+}
+
+/// Which locals the locals table shows.
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LocalsFilter {
+    pub hide_temporaries: bool,
+    pub hide_dead: bool,
+    pub show: LocalsShown,
+}
+
+impl Default for LocalsFilter {
+    fn default() -> Self {
+        LocalsFilter {
+            hide_temporaries: false,
+            hide_dead: false,
+            show: LocalsShown::All,
+        }
+    }
+}
+
+impl LocalsFilter {
+    fn shows(self, kind: LocalKind, dead: bool) -> bool {
+        if dead && self.hide_dead {
+            return false;
+        }
+        match (self.show, kind) {
+            (LocalsShown::All, LocalKind::Temp) => !self.hide_temporaries,
+            (LocalsShown::All, _) => true,
+            (LocalsShown::Arguments, LocalKind::Arg) => true,
+            (LocalsShown::Variables, LocalKind::Var) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The kinds of locals shown.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocalsShown {
+    All,
+    Arguments,
+    Variables,
+}
+
+impl LocalsShown {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "all" => Some(LocalsShown::All),
+            "arguments" => Some(LocalsShown::Arguments),
+            "variables" => Some(LocalsShown::Variables),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LocalsShown::All => "all",
+            LocalsShown::Arguments => "arguments",
+            LocalsShown::Variables => "variables",
+        }
+    }
+}
+
+/// Whether `local` of `frame` is dead. The return place lives in the caller.
+fn is_dead(frame: &Frame<Tag, NonZeroU64>, local: mir::Local) -> bool {
+    if local == mir::RETURN_PLACE {
+        return false;
+    }
+    match frame.locals[local].value {
+        LocalValue::Dead => true,
+        _ => false,
+    }
+}
+
+/// The locals table of `frame`. The locals of frames on the stack are rendered through `cache`.
+/// Rows of locals whose value changed since the frame was rendered at an earlier step have the
+/// `changed` class.
+pub fn render_locals<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    frame: &Frame<'tcx, 'tcx, Tag, NonZeroU64>,
+    settings: Limits<'static>,
+    filter: LocalsFilter,
+    history: &LocalsHistory<'tcx>,
+    step: u128,
+    cache: &LocalsCache<'tcx>,
+) -> String {
+    let mir = &frame.mir;
+    let frame_index = ecx.stack().iter().position(|f| ::std::ptr::eq(f, frame));
+    let locals = match frame_index {
+        Some(depth) => cache.get_or_render(ecx, frame, depth, settings, |reads| {
+            collect_locals(ecx, frame, Limits { reads: Some(reads), ..settings })
+        }),
+        None => collect_locals(ecx, frame, settings),
+    };
+    let layout = settings.layout;
+    let changed = match frame_index {
+        Some(depth) => history.changed(
+            ecx.stack().len(),
+            depth,
+            frame.instance,
+            step,
+            locals.iter().map(|local| (local.alloc, local.value.clone())).collect(),
+        ),
+        None => vec![false; locals.len()],
+    };
+    let layouts = if layout {
+        mir.local_decls
+            .iter()
+            .map(|local_decl| {
+                let ty = ecx.tcx.normalize_erasing_regions(ParamEnv::reveal_all(), local_decl.ty.subst(ecx.tcx.tcx, frame.instance.substs));
+                ecx.layout_of(ty).map(|layout| layout_summary(ecx, layout)).unwrap_or_else(|_| "&lt;error&gt;".to_string())
+            })
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
 
-    let (arg_count, var_count, tmp_count) = (
-        mir.args_iter().count(),
-        mir.vars_iter().count(),
-        mir.temps_iter().count(),
-    );
+    // The rows of every section, the locals aren't necessarily ordered by kind
+    let sections = [
+        (LocalKind::ReturnPointer, "Return"),
+        (LocalKind::Arg, "Arguments"),
+        (LocalKind::Var, "Variables"),
+        (LocalKind::Temp, "Temporaries"),
+    ]
+    .iter()
+    .map(|&(kind, label)| {
+        let rows = mir
+            .local_decls
+            .indices()
+            .filter(|&local| mir.local_kind(local) == kind && filter.shows(kind, is_dead(frame, local)))
+            .map(|local| local.index())
+            .collect::<Vec<_>>();
+        (label, rows)
+    })
+    .filter(|(_, rows)| !rows.is_empty())
+    .collect::<Vec<_>>();
 
     (html! {
         table(border="1") {
@@ -85,62 +557,433 @@ pub fn render_locals<'a, 'tcx: 'a>(
                 th { : "alloc" }
                 th { : "memory" }
                 th { : "type" }
+                @ if layout {
+                    th { : "layout" }
+                }
             }
-            @ for (i, &(ref name, ref ty, alloc, ref text, ref style)) in locals.iter().enumerate() {
-                tr(style=style) {
-                    @if i == 0 {
-                        th(rowspan=1) { span(class="vertical") { : "Return" } }
-                    } else if i == 1 && arg_count != 0 {
-                        th(rowspan=arg_count) { span(class="vertical") { : "Arguments" } }
-                    } else if i == arg_count + 1 && var_count != 0 {
-                        th(rowspan=var_count) { span(class="vertical") { : "Variables" } }
-                    } else if i == var_count + arg_count + 1 && tmp_count != 0 {
-                        th(rowspan=tmp_count) { span(class="vertical") { : "Temporaries" } }
-                    }
-                    td { : format!("_{}", i) }
-                    td { : name }
-                    @if let Some(alloc) = alloc {
-                        td { : alloc.to_string() }
-                    } else {
-                        td;
+            @ for &(label, ref rows) in &sections {
+                @ for (row, &i) in rows.iter().enumerate() {
+                    tr(class=if changed[i] { "changed" } else { "" }, style=locals[i].style, data-alloc=locals[i].alloc.map(|alloc| alloc.to_string()), data-ranges=format_ranges(&locals[i].ranges)) {
+                        @ if row == 0 {
+                            th(rowspan=rows.len()) { span(class="vertical") { : label } }
+                        }
+                        td { : format!("_{}", i) }
+                        td { : &locals[i].name }
+                        @ if let Some(alloc) = locals[i].alloc {
+                            td { : Raw(alloc_link(ecx, AllocId(alloc))) }
+                        } else {
+                            td;
+                        }
+                        td { : Raw(&locals[i].value) }
+                        @ if let Some(frame) = frame_index {
+                            td { a(href=format!("/frame/{}/local/{}/layout", frame, i)) { : &locals[i].ty } }
+                        } else {
+                            td { : &locals[i].ty }
+                        }
+                        @ if let Some(layout) = layouts.get(i) {
+                            td { : Raw(layout) }
+                        }
                     }
-                    td { : Raw(text) }
-                    td { : ty }
                 }
             }
         }
+        script { : Raw(HIGHLIGHT_BYTES_SCRIPT) }
     }).into_string()
         .unwrap()
 }
 
-fn print_scalar_maybe_undef(val: ScalarMaybeUndef<miri::Tag>) -> String {
+/// The locals as a monospace table, with the html of the values converted to plain text.
+pub fn render_locals_text(locals: &[LocalInfo]) -> String {
+    let header = ["id", "name", "alloc", "type", "value"];
+    let rows = locals
+        .iter()
+        .map(|local| {
+            [
+                format!("_{}", local.id),
+                local.name.clone(),
+                local.alloc.map(|alloc| alloc.to_string()).unwrap_or_else(String::new),
+                local.ty.clone(),
+                crate::api::html_to_text(&local.value),
+            ]
+        })
+        .collect::<Vec<_>>();
+    // The value is the last column, it isn't padded
+    let mut widths = [0; 4];
+    for (i, width) in widths.iter_mut().enumerate() {
+        *width = rows
+            .iter()
+            .map(|row| row[i].chars().count())
+            .chain(::std::iter::once(header[i].len()))
+            .max()
+            .unwrap();
+    }
+    let mut text = String::new();
+    let header = header.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    for row in ::std::iter::once(&header[..]).chain(rows.iter().map(|row| &row[..])) {
+        for (cell, width) in row.iter().zip(widths.iter()) {
+            text.push_str(&format!("{:width$}  ", cell, width = width));
+        }
+        text.push_str(&row[4]);
+        text.push('\n');
+    }
+    text
+}
+
+/// `size=24 align=8`, followed by the placement of the discriminant for enums.
+fn layout_summary<'a, 'tcx: 'a>(ecx: &InterpretCx<'a, 'tcx>, layout: TyLayout<'tcx>) -> String {
+    let mut summary = if layout.is_unsized() {
+        format!("unsized align={}", layout.align.abi.bytes())
+    } else {
+        format!("size={} align={}", layout.size.bytes(), layout.align.abi.bytes())
+    };
+    if let Some(discr) = discriminant_layout(ecx, layout) {
+        summary.push_str(&format!(" {}", discr));
+    }
+    summary
+}
+
+/// The bytes holding the discriminant of the enum `layout`, or the field of the dataful variant
+/// they are a niche in. `None` if `layout` has a single variant.
+fn discriminant_layout<'a, 'tcx: 'a>(ecx: &InterpretCx<'a, 'tcx>, layout: TyLayout<'tcx>) -> Option<String> {
+    let (discr, discr_kind, discr_index) = match layout.variants {
+        Variants::Single { .. } => return None,
+        Variants::Multiple { ref discr, ref discr_kind, discr_index, .. } => (discr, discr_kind, discr_index),
+    };
+    let start = layout.fields.offset(discr_index).bytes();
+    let end = start + discr.value.size(ecx).bytes();
+    Some(match *discr_kind {
+        DiscriminantKind::Tag => format!("discriminant={}..{}", start, end),
+        DiscriminantKind::Niche { dataful_variant, .. } => {
+            match niche_field(ecx, layout, dataful_variant, start, end) {
+                Some(i) => format!("discriminant={}..{} (niche in field {})", start, end, i),
+                None => format!("discriminant={}..{} (niche)", start, end),
+            }
+        }
+    })
+}
+
+/// The field of `dataful_variant` containing the niche at bytes `start..end`.
+fn niche_field<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    layout: TyLayout<'tcx>,
+    dataful_variant: VariantIdx,
+    start: u64,
+    end: u64,
+) -> Option<usize> {
+    let dataful = layout.for_variant(ecx, dataful_variant);
+    (0..dataful.fields.count()).find(|&i| {
+        let field_start = dataful.fields.offset(i).bytes();
+        dataful.field(ecx, i).ok().map_or(false, |field| {
+            field_start <= start && end <= field_start + field.size.bytes()
+        })
+    })
+}
+
+/// `(discriminant = 2, tag at offset 8, size 1)` for enums with a tag and
+/// `(niche: value 0 in field 0 means None)` for niche filling enums in `variant`, whose
+/// discriminant is `discr`. `None` for enums with a single variant.
+fn discriminant_details<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    variant: VariantIdx,
+    discr: u128,
+) -> Option<String> {
+    let (discr_layout, discr_kind, discr_index) = match op_ty.layout.variants {
+        Variants::Single { .. } => return None,
+        Variants::Multiple { ref discr, ref discr_kind, discr_index, .. } => (discr, discr_kind, discr_index),
+    };
+    let offset = op_ty.layout.fields.offset(discr_index).bytes();
+    let size = discr_layout.value.size(ecx).bytes();
+    match *discr_kind {
+        DiscriminantKind::Tag => Some(format!("(discriminant = {}, tag at offset {}, size {})", discr, offset, size)),
+        DiscriminantKind::Niche { dataful_variant, .. } => {
+            let location = match niche_field(ecx, op_ty.layout, dataful_variant, offset, offset + size) {
+                Some(i) => format!("field {}", i),
+                None => format!("offset {}", offset),
+            };
+            if variant == dataful_variant {
+                return Some(format!("(niche: {} holds no niche value)", location));
+            }
+            let name = match op_ty.layout.ty.sty {
+                TyKind::Adt(adt_def, _) => adt_def.variants[variant].ident.as_str().to_string(),
+                _ => return None,
+            };
+            match read_tag(ecx, op_ty).ok()? {
+                ScalarMaybeUndef::Scalar(Scalar::Raw { data, .. }) => {
+                    Some(format!("(niche: value {} in {} means {})", data, location, name))
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+/// The tag or niche of the enum `op_ty` as stored, even if it isn't a valid discriminant.
+fn read_tag<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<miri::Tag>> {
+    match op_ty.layout.variants {
+        Variants::Single { .. } => Err(InterpError::AssumptionNotHeld.into()),
+        Variants::Multiple { discr_index, .. } => ecx.read_scalar(ecx.operand_field(op_ty, discr_index as u64)?),
+    }
+}
+
+/// The layout of the monomorphized type of local `local` of `frame`, as nested lists of its fields
+/// and variants.
+pub fn render_type_layout<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    frame: &Frame<'tcx, 'tcx, Tag, NonZeroU64>,
+    local: mir::Local,
+) -> Result<(Ty<'tcx>, String), String> {
+    let layout = local_layout(ecx, frame, local)?;
+    let mut rendered = String::new();
+    layout_tree(ecx, layout, &mut Vec::new(), &mut rendered);
+    Ok((layout.ty, rendered))
+}
+
+/// The layout of the monomorphized type of local `local` of `frame`.
+pub fn local_layout<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    frame: &Frame<'tcx, 'tcx, Tag, NonZeroU64>,
+    local: mir::Local,
+) -> Result<TyLayout<'tcx>, String> {
+    let local_decl = frame
+        .mir
+        .local_decls
+        .get(local)
+        .ok_or_else(|| format!("No local _{} in {}", local.index(), frame.instance))?;
+    let ty = ecx.tcx.normalize_erasing_regions(ParamEnv::reveal_all(), local_decl.ty.subst(ecx.tcx.tcx, frame.instance.substs));
+    ecx.layout_of(ty).map_err(|err| format!("Layout of {} unknown: {:?}", ty, err))
+}
+
+/// How deep `layout_tree` descends into fields, types are rarely nested deeper
+const MAX_LAYOUT_DEPTH: usize = 16;
+
+fn abi_name(abi: &Abi) -> &'static str {
+    match *abi {
+        Abi::Uninhabited => "uninhabited",
+        Abi::Scalar(_) => "scalar",
+        Abi::ScalarPair(..) => "scalar pair",
+        Abi::Vector { .. } => "vector",
+        Abi::Aggregate { sized: true } => "aggregate",
+        Abi::Aggregate { sized: false } => "unsized aggregate",
+    }
+}
+
+/// `<ul>` of the fields of `layout` and for enums of its variants, each with its own layout.
+/// `outer` are the types `layout` is nested in, to stop at recursive types.
+fn layout_tree<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    layout: TyLayout<'tcx>,
+    outer: &mut Vec<Ty<'tcx>>,
+    out: &mut String,
+) {
+    out.push_str(&format!(" <span style=\"color: gray;\">{}, {}</span>", layout_summary(ecx, layout), abi_name(&layout.abi)));
+    if outer.contains(&layout.ty) {
+        out.push_str(" (recursive)");
+        return;
+    }
+    if outer.len() >= MAX_LAYOUT_DEPTH {
+        out.push_str(" …");
+        return;
+    }
+    outer.push(layout.ty);
+    let adt_def = match layout.ty.sty {
+        TyKind::Adt(adt_def, _) => Some(adt_def),
+        _ => None,
+    };
+    match layout.variants {
+        Variants::Multiple { ref variants, .. } => {
+            out.push_str("<ul>");
+            for variant in variants.indices() {
+                let name = adt_def.map_or_else(|| format!("variant {}", variant.index()), |adt_def| {
+                    adt_def.variants[variant].ident.as_str().to_string()
+                });
+                out.push_str(&format!("<li>{}", escape_html(&name)));
+                layout_fields(ecx, layout.for_variant(ecx, variant), Some(variant), outer, out);
+                out.push_str("</li>");
+            }
+            out.push_str("</ul>");
+        }
+        Variants::Single { index } => {
+            layout_fields(ecx, layout, adt_def.map(|_| index), outer, out);
+        }
+    }
+    outer.pop();
+}
+
+/// `<ul>` of the fields of the variant `variant` of `layout`, named after the fields of the ADT if
+/// it is one.
+fn layout_fields<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    layout: TyLayout<'tcx>,
+    variant: Option<VariantIdx>,
+    outer: &mut Vec<Ty<'tcx>>,
+    out: &mut String,
+) {
+    // Pointers, scalars and arrays, whose elements are all alike, have no interesting fields
+    let count = match layout.ty.sty {
+        TyKind::Array(..) | TyKind::Slice(_) | TyKind::Str => 0,
+        _ => layout.fields.count(),
+    };
+    if count == 0 {
+        return;
+    }
+    out.push_str("<ul>");
+    for i in 0..count {
+        let name = match (&layout.ty.sty, variant) {
+            (&TyKind::Adt(adt_def, _), Some(variant)) => adt_def.variants[variant]
+                .fields
+                .get(i)
+                .map_or_else(|| i.to_string(), |field| field.ident.as_str().to_string()),
+            _ => i.to_string(),
+        };
+        match layout.field(ecx, i) {
+            Ok(field) => {
+                out.push_str(&format!(
+                    "<li>{} @{}: {}",
+                    escape_html(&name),
+                    layout.fields.offset(i).bytes(),
+                    escape_html(&field.ty.to_string())
+                ));
+                layout_tree(ecx, field, outer, out);
+                out.push_str("</li>");
+            }
+            Err(err) => out.push_str(&format!("<li>{}: {}</li>", escape_html(&name), escape_html(&format!("{:?}", err)))),
+        }
+    }
+    out.push_str("</ul>");
+}
+
+/// `name`, followed by `offset` if `limits.layout` is set.
+fn field_name(name: String, offset: Size, limits: Limits) -> String {
+    if limits.layout {
+        format!("{} @{}", name, offset.bytes())
+    } else {
+        name
+    }
+}
+
+/// Highlights the bytes of a local when hovering its row and the locals stored in a byte when
+/// hovering the byte. Rows carry `data-alloc` and `data-ranges` (see `format_ranges`), the
+/// bytes rendered by `print_alloc` carry `data-alloc` and `data-offset`.
+pub const HIGHLIGHT_BYTES_SCRIPT: &str = r#"
+(function() {
+    function ranges(row) {
+        return row.dataset.ranges.split(",").filter((r) => r != "").map((r) => r.split(":").map(Number));
+    }
+    function contains(row, alloc, offset) {
+        return row.dataset.alloc == alloc && ranges(row).some(([start, len]) => start <= offset && offset < start + len);
+    }
+    function highlight(elems, on) {
+        for (let elem of elems) {
+            elem.style.outline = on ? "2px solid orange" : "";
+        }
+    }
+    for (let row of document.querySelectorAll("tr[data-ranges]")) {
+        let bytes = () => Array.from(document.querySelectorAll("[data-offset]"))
+            .filter((byte) => contains(row, byte.dataset.alloc, Number(byte.dataset.offset)));
+        row.addEventListener("mouseenter", () => highlight(bytes(), true));
+        row.addEventListener("mouseleave", () => highlight(bytes(), false));
+    }
+    for (let byte of document.querySelectorAll("[data-offset]")) {
+        let rows = () => Array.from(document.querySelectorAll("tr[data-ranges]"))
+            .filter((row) => contains(row, byte.dataset.alloc, Number(byte.dataset.offset)));
+        byte.addEventListener("mouseenter", () => highlight(rows(), true));
+        byte.addEventListener("mouseleave", () => highlight(rows(), false));
+    }
+})();
+"#;
+
+/// `offset:len` pairs separated by commas.
+pub fn format_ranges(ranges: &[(u64, u64)]) -> String {
+    ranges
+        .iter()
+        .map(|(offset, len)| format!("{}:{}", offset, len))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn print_scalar_maybe_undef(ecx: &InterpretCx, val: ScalarMaybeUndef<miri::Tag>) -> String {
     match val {
-        ScalarMaybeUndef::Undef => "&lt;undef &gt;".to_string(),
-        ScalarMaybeUndef::Scalar(val) => print_scalar(val),
+        ScalarMaybeUndef::Undef => "<span style='color: gray;'>&lt;undef&gt;</span>".to_string(),
+        ScalarMaybeUndef::Scalar(val) => print_scalar(ecx, val),
     }
 }
 
-fn print_scalar(val: Scalar<miri::Tag>) -> String {
+fn print_scalar(ecx: &InterpretCx, val: Scalar<miri::Tag>) -> String {
     match val {
-        Scalar::Ptr(ptr) => format!(
-            "<a href=\"/ptr/{alloc}/{offset}\">Pointer({alloc})[{offset}]</a>",
-            alloc = ptr.alloc_id.0,
-            offset = ptr.offset.bytes()
-        ),
+        Scalar::Ptr(ptr) => match (ecx.memory().get_fn(ptr), ecx.memory().get(ptr.alloc_id)) {
+            (Ok(instance), _) => fn_link(instance, ptr.alloc_id),
+            (Err(_), Ok(alloc)) => {
+                let mut s = format!(
+                    "<a href=\"/ptr/{alloc}/{offset}\">Pointer({alloc})[{offset}]</a>",
+                    alloc = ptr.alloc_id.0,
+                    offset = ptr.offset.bytes()
+                );
+                if let Some(name) = alloc_name(ecx, ptr.alloc_id) {
+                    s.push_str(&format!(" <span style=\"color: gray;\">{}</span>", escape_html(&name)));
+                }
+                // A pointer one past the end is fine
+                let size = alloc.bytes.len() as u64;
+                if ptr.offset.bytes() > size {
+                    s.push_str(&format!(
+                        " <span style=\"color: white; background: red;\">out-of-bounds (+{} past end)</span>",
+                        ptr.offset.bytes() - size,
+                    ));
+                }
+                s
+            }
+            // Freed allocations can't be viewed, so there is no link
+            (Err(_), Err(_)) => format!(
+                "<span style=\"color: red;\">Pointer({alloc})[{offset}]</span> \
+                 <span style=\"color: white; background: red;\">dangling</span>",
+                alloc = ptr.alloc_id.0,
+                offset = ptr.offset.bytes()
+            ),
+        },
         Scalar::Raw { data, size } => {
             if size == 0 {
                 "&lt;zst&gt;".to_string()
             } else {
-                format!("0x{:0width$X}", data, width = (size as usize) / 8)
+                // Two hex digits per byte, so partially written values keep their leading zeros
+                format!("0x{:0width$X}", data, width = size as usize * 2)
             }
         }
     }
 }
 
+/// Pretty prints `op_ty`, charging the output to `limits.budget`.
 fn pp_operand<'a, 'tcx: 'a>(
     ecx: &InterpretCx<'a, 'tcx>,
     op_ty: OpTy<'tcx, miri::Tag>,
+    limits: Limits,
+    level: usize,
+) -> InterpResult<'tcx, String> {
+    limits.read_operand(op_ty);
+    let budget = match limits.budget {
+        Some(budget) => budget,
+        None => return pp_operand_unbudgeted(ecx, op_ty, limits, level),
+    };
+    if budget.get() == 0 {
+        return Ok(limits.truncation_marker(level));
+    }
+    let before = budget.get();
+    let pretty = pp_operand_unbudgeted(ecx, op_ty, limits, level)?;
+    // The output of nested values is part of `pretty` and was already charged
+    budget.set(before.saturating_sub(pretty.len()));
+    Ok(pretty)
+}
+
+fn pp_operand_unbudgeted<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    limits: Limits,
+    level: usize,
 ) -> InterpResult<'tcx, String> {
+    // Why a `Vec` or `String` is printed field by field instead of by its contents
+    let mut broken_vec = None;
     match op_ty.layout.ty.sty {
         TyKind::RawPtr(TypeAndMut {
             ty: &TyS {
@@ -161,75 +1004,139 @@ fn pp_operand<'a, 'tcx: 'a>(
                     ScalarMaybeUndef::Scalar(Scalar::Raw { data: len, .. }),
                 ) = val
                 {
-                    if let Ok(allocation) = ecx.memory().get(ptr.alloc_id) {
-                        let offset = ptr.offset.bytes();
-                        if (offset as u128) < allocation.bytes.len() as u128 {
-                            let alloc_bytes = &allocation.bytes[offset as usize
-                                ..(offset as usize)
-                                    .checked_add(len as usize)
-                                    .ok_or(InterpError::AssumptionNotHeld)?];
-                            let s = String::from_utf8_lossy(alloc_bytes);
-                            return Ok(format!("\"{}\"", s));
-                        }
+                    if let Ok(pretty) = pp_str(ecx, Scalar::Ptr(ptr), len as u64, limits, level) {
+                        return Ok(pretty);
                     }
                 }
             }
         }
-        TyKind::Adt(adt_def, _substs) => {
-            if let Operand::Immediate(Immediate::Scalar(ScalarMaybeUndef::Undef)) = *op_ty {
-                Err(InterpError::AssumptionNotHeld)?;
+        TyKind::Adt(adt_def, _) if rc_name(ecx, adt_def).is_some() => {
+            if let Ok(pretty) = pp_rc(ecx, op_ty, rc_name(ecx, adt_def).unwrap(), limits, level) {
+                return Ok(pretty);
             }
-
-            let variant = ecx.read_discriminant(op_ty)?.1;
-            let adt_fields = &adt_def.variants[variant].fields;
-
-            let should_collapse = adt_fields.len() > 1;
-
-            //println!("{:?} {:?} {:?}", val, ty, adt_def.variants);
-            let mut pretty = ecx
-                .tcx
-                .def_path_str(adt_def.did)
-                .replace("<", "&lt;")
-                .replace(">", "&gt;")
-                .to_string();
-
-            if adt_def.is_enum() {
-                pretty.push_str("::");
-                pretty.push_str(&*adt_def.variants[variant].ident.as_str());
+        }
+        TyKind::Adt(adt_def, _) if cell_kind(ecx, adt_def).is_some() => {
+            if let Ok(pretty) = pp_cell(ecx, op_ty, adt_def, cell_kind(ecx, adt_def).unwrap(), limits, level) {
+                return Ok(pretty);
             }
-            pretty.push_str(" { ");
-
-            if should_collapse {
-                pretty.push_str("<details>");
+        }
+        TyKind::Adt(adt_def, _) if map_kind(ecx, adt_def).is_some() => {
+            if let Ok(pretty) = pp_map(ecx, op_ty, map_kind(ecx, adt_def).unwrap(), limits, level) {
+                return Ok(pretty);
             }
-
-            for (i, adt_field) in adt_fields.iter().enumerate() {
-                let field_pretty: InterpResult<String> = try {
-                    let field_op_ty = ecx.operand_field(op_ty, i as u64)?;
-                    pp_operand(ecx, field_op_ty)?
-                };
-
-                pretty.push_str(&format!(
-                    "{}: {}, ",
-                    adt_field.ident.as_str(),
-                    match field_pretty {
-                        Ok(field_pretty) => field_pretty,
-                        Err(_err) => "<span style='color: red;'>&lt;err&gt;</span>".to_string(),
-                    }
-                ));
-                if should_collapse {
-                    pretty.push_str("<br>");
+        }
+        TyKind::Adt(adt_def, substs) if vec_kind(ecx, adt_def).is_some() => {
+            match pp_vec(ecx, op_ty, substs, limits, level) {
+                Ok(pretty) => return Ok(pretty),
+                Err(reason) => broken_vec = Some(reason),
+            }
+        }
+        TyKind::Array(..) => {
+            let len = op_ty.layout.fields.count() as u64;
+            return Ok(pp_elements(ecx, len, |i| ecx.operand_field(op_ty, i), limits, level));
+        }
+        TyKind::Ref(_, &TyS { sty: TyKind::Slice(_), .. }, _)
+        | TyKind::Ref(_, &TyS { sty: TyKind::Array(..), .. }, _) => {
+            let place = ecx.deref_operand(op_ty)?;
+            limits.read_place(place);
+            let len = place.len(ecx)?;
+            return Ok(pp_elements(ecx, len, |i| Ok(ecx.mplace_field(place, i)?.into()), limits, level));
+        }
+        TyKind::Str | TyKind::Slice(_) | TyKind::Dynamic(..) if op_ty.layout.is_unsized() => {
+            if let Ok(place) = op_ty.try_as_mplace() {
+                return pp_unsized_place(ecx, place, limits, level);
+            }
+        }
+        TyKind::Ref(..) | TyKind::RawPtr(..) | TyKind::Adt(..) if is_trait_object_ptr(op_ty.layout.ty) => {
+            if let Some(pretty) = pp_trait_object(ecx, op_ty, limits, level) {
+                return Ok(pretty);
+            }
+        }
+        TyKind::Ref(..) | TyKind::RawPtr(..) | TyKind::Adt(..)
+            if limits.auto_deref > 0 && op_ty.layout.ty.builtin_deref(true).is_some() =>
+        {
+            if let Some(pretty) = pp_pointee(ecx, op_ty, limits, level)? {
+                return Ok(pretty);
+            }
+        }
+        _ => {}
+    }
+
+    match op_ty.layout.ty.sty {
+        TyKind::Adt(adt_def, _substs) => {
+            let (discr, variant) = match ecx.read_discriminant(op_ty) {
+                Ok(discr) => discr,
+                Err(err) => return match read_tag(ecx, op_ty) {
+                    Ok(ScalarMaybeUndef::Undef) => {
+                        Ok("<span style=\"color: red;\">invalid discriminant (undef)</span>".to_string())
+                    }
+                    Ok(ScalarMaybeUndef::Scalar(Scalar::Raw { data, size })) => Ok(format!(
+                        "<span style=\"color: red;\">invalid discriminant 0x{:0width$x}</span>",
+                        data,
+                        width = size as usize * 2
+                    )),
+                    _ => Err(err),
+                },
+            };
+            let adt_fields = &adt_def.variants[variant].fields;
+
+            // `Some(42)` instead of `core::option::Option::Some { 0: 42, }`
+            let path = ecx.tcx.def_path_str(adt_def.did);
+            if path == "core::option::Option" || path == "core::result::Result" {
+                let name = adt_def.variants[variant].ident.as_str().to_string();
+                if adt_fields.is_empty() {
+                    return Ok(name);
+                }
+                if limits.depth != Some(0) {
+                    let payload: InterpResult<String> = try {
+                        let variant_op = ecx.operand_downcast(op_ty, variant)?;
+                        pp_operand(ecx, ecx.operand_field(variant_op, 0)?, limits.nested(), level + 1)?
+                    };
+                    // Otherwise the verbose form below shows what could be read
+                    if let Ok(payload) = payload {
+                        return Ok(format!("{}({})", name, payload));
+                    }
                 }
             }
 
-            if should_collapse {
-                pretty.push_str("</details>");
+            let mut header = escape_html(&ecx.tcx.def_path_str(adt_def.did)).into_owned();
+            if adt_def.is_enum() {
+                header.push_str("::");
+                header.push_str(&*adt_def.variants[variant].ident.as_str());
+                if let Some(details) = discriminant_details(ecx, op_ty, variant, discr) {
+                    header.push_str(&format!(" {}", details));
+                }
             }
 
-            pretty.push_str("}");
-            println!("pretty adt: {}", pretty);
+            //println!("{:?} {:?} {:?}", val, ty, adt_def.variants);
+            let variant_layout = op_ty.layout.for_variant(ecx, variant);
+            // `Wrapper(42)` instead of `Wrapper { 0: 42, }`, unless the offsets are shown
+            let mut pretty = if adt_def.variants[variant].ctor_kind == CtorKind::Fn && !limits.layout {
+                let fields = (0..adt_fields.len()).map(|i| ecx.operand_field(op_ty, i as u64)).collect();
+                pp_positional(ecx, header, fields, limits, level)
+            } else {
+                let fields = adt_fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, adt_field)| {
+                        let name = field_name(adt_field.ident.as_str().to_string(), variant_layout.fields.offset(i), limits);
+                        (name, ecx.operand_field(op_ty, i as u64))
+                    })
+                    .collect::<Vec<_>>();
+                pp_fields(ecx, header, fields, limits, level)
+            };
+            if let Some(reason) = broken_vec {
+                pretty.push_str(&format!(" <span style=\"color: white; background: red;\">{}</span>", reason));
+            }
             return Ok(pretty);
         }
+        TyKind::Closure(def_id, _) | TyKind::Generator(def_id, _, _) => {
+            return Ok(pp_closure(ecx, op_ty, def_id, limits, level));
+        }
+        TyKind::Tuple(_) => {
+            let fields = (0..op_ty.layout.fields.count()).map(|i| ecx.operand_field(op_ty, i as u64)).collect();
+            return Ok(pp_positional(ecx, String::new(), fields, limits, level));
+        }
         _ => {}
     }
 
@@ -242,7 +1149,7 @@ fn pp_operand<'a, 'tcx: 'a>(
     }
     let scalar = ecx.read_scalar(op_ty)?;
     if let ScalarMaybeUndef::Scalar(Scalar::Ptr(_)) = &scalar {
-        return Ok(print_scalar_maybe_undef(scalar)); // If the value is a ptr, print it
+        return Ok(print_scalar_maybe_undef(ecx, scalar)); // If the value is a ptr, print it
     }
     let bits = scalar.to_bits(op_ty.layout.size)?;
     match op_ty.layout.ty.sty {
@@ -255,125 +1162,1763 @@ fn pp_operand<'a, 'tcx: 'a>(
                 Err(InterpError::AssumptionNotHeld.into())
             }
         }
-        TyKind::Char if bits < ::std::char::MAX as u128 => {
-            let chr = ::std::char::from_u32(bits as u32).unwrap();
-            if chr.is_ascii() {
-                Ok(format!("'{}'", chr))
-            } else {
-                Err(InterpError::AssumptionNotHeld.into())
+        TyKind::Char => {
+            // Rejects surrogates and values above `char::MAX`
+            match ::std::char::from_u32(bits as u32).filter(|_| bits <= ::std::u32::MAX as u128) {
+                Some(chr) => Ok(format!("'{}'", escape_html(&chr.escape_debug().to_string()))),
+                None => Ok(format!("<span style='color: red;'>invalid char 0x{:X}</span>", bits)),
             }
         }
-        TyKind::Uint(_) => Ok(format!("{0}", bits)),
-        TyKind::Int(_) => Ok(format!(
+        TyKind::Uint(_) => Ok(limits.int_format.format(bits, format!("{0}", bits))),
+        TyKind::Int(_) => Ok(limits.int_format.format(bits, format!(
             "{0}",
             ::miri::sign_extend(bits, op_ty.layout.size) as i128
-        )),
+        ))),
         TyKind::Float(float_ty) => {
             use crate::syntax::ast::FloatTy::*;
-            match float_ty {
-                F32 if bits < ::std::u32::MAX as u128 => {
-                    Ok(format!("{}", <f32>::from_bits(bits as u32)))
-                }
-                F64 if bits < ::std::u64::MAX as u128 => {
-                    Ok(format!("{}", <f64>::from_bits(bits as u64)))
-                }
-                _ => Err(InterpError::AssumptionNotHeld.into()),
-            }
+            let pretty = match float_ty {
+                F32 => pp_float(bits, 32, 23, || format!("{:?}", <f32>::from_bits(bits as u32))),
+                F64 => pp_float(bits, 64, 52, || format!("{:?}", <f64>::from_bits(bits as u64))),
+            };
+            Ok(format!(
+                "<span title=\"bits 0x{:0width$x}\">{}</span>",
+                bits,
+                pretty,
+                width = op_ty.layout.size.bytes() as usize * 2
+            ))
         }
         _ => Err(InterpError::AssumptionNotHeld.into()),
     }
 }
 
+/// `NaN (payload 0x1)`, `-inf`, `-0.0` or `finite`, which formats the value so it parses back to
+/// the same bits. `size` and `mantissa` are the number of bits of the float and its mantissa.
+fn pp_float<F: FnOnce() -> String>(bits: u128, size: u32, mantissa: u32, finite: F) -> String {
+    let sign_bit = 1 << (size - 1);
+    let sign = if bits & sign_bit != 0 { "-" } else { "" };
+    let payload = bits & ((1 << mantissa) - 1);
+    let exponent_mask = (sign_bit - 1) & !((1 << mantissa) - 1);
+    if bits & exponent_mask == exponent_mask {
+        if payload == 0 {
+            format!("{}inf", sign)
+        } else {
+            format!("{}NaN (payload 0x{:x})", sign, payload)
+        }
+    } else if bits & !sign_bit == 0 {
+        format!("{}0.0", sign)
+    } else {
+        finite()
+    }
+}
+
+/// `header { name: value, ... }`, collapsed into a `<details>` when there is more than one field.
+fn pp_fields<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    header: String,
+    fields: Vec<(String, InterpResult<'tcx, OpTy<'tcx, miri::Tag>>)>,
+    limits: Limits,
+    level: usize,
+) -> String {
+    let fields = fields.into_iter().map(|(name, field)| (Some(name), field)).collect();
+    pp_field_list(ecx, header, fields, limits, level)
+}
+
+/// `header(value, ...)` for tuples and tuple structs, collapsed like `pp_fields`.
+fn pp_positional<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    header: String,
+    fields: Vec<InterpResult<'tcx, OpTy<'tcx, miri::Tag>>>,
+    limits: Limits,
+    level: usize,
+) -> String {
+    let fields = fields.into_iter().map(|field| (None, field)).collect();
+    pp_field_list(ecx, header, fields, limits, level)
+}
+
+/// The fields of `pp_fields` and `pp_positional`, positional if they have no names.
+fn pp_field_list<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    header: String,
+    fields: Vec<(Option<String>, InterpResult<'tcx, OpTy<'tcx, miri::Tag>>)>,
+    limits: Limits,
+    level: usize,
+) -> String {
+    let positional = fields.iter().all(|(name, _)| name.is_none());
+    let (open, close) = if positional { ("(", ")") } else { (" { ", "}") };
+    if fields.is_empty() && positional {
+        return format!("{}()", header);
+    }
+    if limits.depth == Some(0) && !fields.is_empty() {
+        return format!("{}{}{}{}", header, open, limits.truncation_marker(level), close);
+    }
+
+    let should_collapse = fields.len() > 1;
+
+    let mut pretty = header;
+    pretty.push_str(open);
+
+    if should_collapse {
+        pretty.push_str("<details>");
+    }
+
+    // Every field is read on its own, an undef or unreadable field doesn't hide the others
+    let count = fields.len();
+    for (i, (name, field_op_ty)) in fields.into_iter().enumerate() {
+        let field_pretty = match field_op_ty {
+            Ok(field_op_ty) => pp_operand(ecx, field_op_ty, limits.nested(), level + 1)
+                .unwrap_or_else(|err| pp_error(ecx, Some(field_op_ty), err)),
+            Err(err) => pp_error(ecx, None, err),
+        };
+        match name {
+            Some(name) => pretty.push_str(&format!("{}: {}, ", name, field_pretty)),
+            None if i + 1 < count => pretty.push_str(&format!("{}, ", field_pretty)),
+            None => pretty.push_str(&field_pretty),
+        }
+        if should_collapse {
+            pretty.push_str("<br>");
+        }
+    }
+
+    if should_collapse {
+        pretty.push_str("</details>");
+    }
+
+    pretty.push_str(close);
+    pretty
+}
+
+/// A gray `<undef>` for a nested value `op_ty` which couldn't be printed because it is
+/// uninitialized, otherwise a red `<err>` with `err` as its title.
+fn pp_error<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: Option<OpTy<'tcx, miri::Tag>>,
+    err: InterpErrorInfo<'tcx>,
+) -> String {
+    let undef = match err.kind {
+        InterpError::ReadUndefBytes(_) => true,
+        _ => op_ty.map_or(false, |op_ty| match ecx.read_scalar(op_ty) {
+            Ok(ScalarMaybeUndef::Undef) => true,
+            _ => false,
+        }),
+    };
+    if undef {
+        "<span style='color: gray;'>&lt;undef&gt;</span>".to_string()
+    } else {
+        format!(
+            "<span style='color: red;' title='{}'>&lt;err&gt;</span>",
+            escape_html(&format!("{:?}", err.kind))
+        )
+    }
+}
+
+/// A closure or generator with its captured variables named after the variables they capture and
+/// labeled with the capture mode. Falls back to the field index when the MIR of the closure isn't
+/// available.
+fn pp_closure<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    def_id: DefId,
+    limits: Limits,
+    level: usize,
+) -> String {
+    let mut header = escape_html(&op_ty.layout.ty.to_string()).into_owned();
+    let mir = if ecx.tcx.is_mir_available(def_id) {
+        Some(ecx.tcx.optimized_mir(def_id))
+    } else {
+        None
+    };
+    let upvars = if let Some(mir) = mir {
+        mir.__upvar_debuginfo_codegen_only_do_not_use
+            .iter()
+            .map(|upvar| {
+                let mode = if upvar.by_ref { "by ref" } else { "moved" };
+                format!("{} ({})", upvar.debug_name.as_str(), mode)
+            })
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+    // The fields of a generator after the captured variables hold its state
+    let count = match op_ty.layout.ty.sty {
+        TyKind::Generator(..) => upvars.len(),
+        _ => op_ty.layout.fields.count(),
+    };
+    let mut fields = (0..count)
+        .map(|i| {
+            let name = upvars.get(i).cloned().unwrap_or_else(|| i.to_string());
+            let name = field_name(name, op_ty.layout.fields.offset(i), limits);
+            (name, ecx.operand_field(op_ty, i as u64))
+        })
+        .collect::<Vec<_>>();
+    if let TyKind::Generator(..) = op_ty.layout.ty.sty {
+        match generator_state(ecx, op_ty, mir, limits) {
+            Ok((state, saved)) => {
+                header.push_str(&format!(" {}", state));
+                fields.extend(saved);
+            }
+            Err(_) => header.push_str(" <span style=\"color: red;\">unknown state</span>"),
+        }
+    }
+    if fields.is_empty() {
+        return header;
+    }
+    pp_fields(ecx, header, fields, limits, level)
+}
+
+/// `unresumed`, `returned`, `panicked` or `suspended at yield point N` for the generator `op_ty`
+/// and the locals it keeps across that yield point, named after the variables of `mir` they
+/// store where `mir` is available.
+fn generator_state<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    mir: Option<&'tcx mir::Body<'tcx>>,
+    limits: Limits,
+) -> InterpResult<'tcx, (String, Vec<(String, InterpResult<'tcx, OpTy<'tcx, miri::Tag>>)>)> {
+    let variant = ecx.read_discriminant(op_ty)?.1;
+    // The first three variants are `GeneratorSubsts::{UNRESUMED, RETURNED, POISONED}`, one per
+    // yield point follows
+    let state = match variant.as_u32() {
+        0 => "unresumed".to_string(),
+        1 => "returned".to_string(),
+        2 => "panicked".to_string(),
+        n => format!("suspended at yield point {}", n - 3),
+    };
+    let variant_op = ecx.operand_downcast(op_ty, variant)?;
+    let saved_locals = mir
+        .and_then(|mir| mir.generator_layout.as_ref())
+        .and_then(|layout| Some((layout, layout.variant_fields.get(variant)?)));
+    let saved = (0..variant_op.layout.fields.count())
+        .map(|i| {
+            let name = saved_locals
+                .and_then(|(layout, fields)| {
+                    let saved_local = *fields.get(mir::Field::new(i))?;
+                    layout.__local_debuginfo_codegen_only_do_not_use[saved_local].name
+                })
+                .map_or_else(|| i.to_string(), |name| name.as_str().to_string());
+            let name = field_name(name, variant_op.layout.fields.offset(i), limits);
+            (name, ecx.operand_field(variant_op, i as u64))
+        })
+        .collect();
+    Ok((state, saved))
+}
+
+/// The name `Rc`, `Arc` and their `Weak` pointers are printed with.
+fn rc_name(ecx: &InterpretCx, adt_def: &AdtDef) -> Option<&'static str> {
+    match &ecx.tcx.def_path_str(adt_def.did)[..] {
+        "alloc::rc::Rc" => Some("Rc"),
+        "alloc::rc::Weak" => Some("rc::Weak"),
+        "alloc::sync::Arc" => Some("Arc"),
+        "alloc::sync::Weak" => Some("sync::Weak"),
+        _ => None,
+    }
+}
+
+/// `Rc(strong=3, weak=1) -> value`, read through the `RcBox` or `ArcInner` the pointer `op_ty`
+/// points to. A freed or corrupt inner allocation is shown as the pointer with a badge.
+fn pp_rc<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    name: &str,
+    limits: Limits,
+    level: usize,
+) -> InterpResult<'tcx, String> {
+    // `ptr: NonNull<RcBox<T>>` is the first field of all of them
+    let inner_ptr = ecx.operand_field(ecx.operand_field(op_ty, 0)?, 0)?;
+    let scalar = ecx.read_scalar(inner_ptr)?;
+    // `Weak::new` doesn't allocate
+    if let ScalarMaybeUndef::Scalar(Scalar::Raw { .. }) = scalar {
+        return Ok(format!("{}(dangling)", name));
+    }
+    let counts: InterpResult<(OpTy<'tcx, miri::Tag>, u64, u64)> = try {
+        let inner = ecx.deref_operand(inner_ptr)?;
+        limits.read_place(inner);
+        // `strong` and `weak` are a `Cell<usize>` or `AtomicUsize`, both wrapping an
+        // `UnsafeCell<usize>`
+        let mut counters = [0; 2];
+        for (i, counter) in counters.iter_mut().enumerate() {
+            let cell = ecx.operand_field(ecx.mplace_field(inner, i as u64)?.into(), 0)?;
+            *counter = ecx.read_scalar(ecx.operand_field(cell, 0)?)?.to_usize(&ecx.tcx.tcx)?;
+        }
+        (ecx.mplace_field(inner, 2)?.into(), counters[0], counters[1])
+    };
+    let (value, strong, weak) = match counts {
+        Ok(counts) => counts,
+        Err(_) => {
+            return Ok(format!(
+                "{}({}) <span style=\"color: white; background: red;\">freed or corrupt</span>",
+                name,
+                print_scalar_maybe_undef(ecx, scalar),
+            ));
+        }
+    };
+    // All strong pointers together hold one weak reference, like `Rc::weak_count` this isn't
+    // counted
+    let weak = if strong > 0 { weak.saturating_sub(1) } else { weak };
+    let header = format!("{}(strong={}, weak={})", name, strong, weak);
+    if strong == 0 {
+        return Ok(format!("{} -> &lt;dropped&gt;", header));
+    }
+    if limits.depth == Some(0) {
+        return Ok(format!("{} -> {}", header, limits.truncation_marker(level)));
+    }
+    let pretty = match pp_operand(ecx, value, limits.nested(), level + 1) {
+        Ok(pretty) => pretty,
+        Err(_) => "<span style='color: red;'>&lt;err&gt;</span>".to_string(),
+    };
+    Ok(format!("{} -> {}", header, pretty))
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum CellKind {
+    UnsafeCell,
+    Cell,
+    RefCell,
+    AtomicBool,
+    /// The other atomics, printed as the integer or pointer they wrap
+    Atomic,
+}
+
+fn cell_kind(ecx: &InterpretCx, adt_def: &AdtDef) -> Option<CellKind> {
+    match &ecx.tcx.def_path_str(adt_def.did)[..] {
+        "core::cell::UnsafeCell" => Some(CellKind::UnsafeCell),
+        "core::cell::Cell" => Some(CellKind::Cell),
+        "core::cell::RefCell" => Some(CellKind::RefCell),
+        "core::sync::atomic::AtomicBool" => Some(CellKind::AtomicBool),
+        path if path.starts_with("core::sync::atomic::Atomic") => Some(CellKind::Atomic),
+        _ => None,
+    }
+}
+
+/// The field `name` of the struct `op_ty`. Fields are looked up by name so a change to the
+/// internals of std makes the caller fall back to printing the fields instead of misreading them.
+fn named_field<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    name: &str,
+) -> InterpResult<'tcx, OpTy<'tcx, miri::Tag>> {
+    let adt_def = match op_ty.layout.ty.sty {
+        TyKind::Adt(adt_def, _) if adt_def.is_struct() || adt_def.is_union() => adt_def,
+        _ => Err(InterpError::AssumptionNotHeld)?,
+    };
+    let i = adt_def
+        .non_enum_variant()
+        .fields
+        .iter()
+        .position(|field| field.ident.as_str() == name)
+        .ok_or(InterpError::AssumptionNotHeld)?;
+    ecx.operand_field(op_ty, i as u64)
+}
+
+/// The value wrapped by the cell or atomic `op_ty`, looking through the `UnsafeCell` they all
+/// contain.
+fn cell_value<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    kind: CellKind,
+) -> InterpResult<'tcx, OpTy<'tcx, miri::Tag>> {
+    let unsafe_cell = match kind {
+        CellKind::UnsafeCell => op_ty,
+        CellKind::Cell | CellKind::RefCell => named_field(ecx, op_ty, "value")?,
+        // `AtomicPtr` calls its field `p`, the others `v`
+        CellKind::AtomicBool | CellKind::Atomic => {
+            named_field(ecx, op_ty, "v").or_else(|_| named_field(ecx, op_ty, "p"))?
+        }
+    };
+    named_field(ecx, unsafe_cell, "value")
+}
+
+/// `not borrowed`, `shared (2)` or `mutably borrowed` for the borrow flag of the `RefCell` `op_ty`.
+/// The flag is an `isize` counting the shared borrows, a mutable borrow makes it negative.
+fn refcell_borrow<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+) -> InterpResult<'tcx, String> {
+    let flag = cell_value(ecx, named_field(ecx, op_ty, "borrow")?, CellKind::Cell)?;
+    let flag = ecx.read_scalar(flag)?.not_undef()?.to_isize(&ecx.tcx.tcx)?;
+    Ok(if flag == 0 {
+        "not borrowed".to_string()
+    } else if flag > 0 {
+        format!("shared ({})", flag)
+    } else {
+        "mutably borrowed".to_string()
+    })
+}
+
+/// `Cell(42)`, `RefCell(shared (2)) -> 42` or `AtomicUsize(42)`, the value of the cell without
+/// the wrappers around it.
+fn pp_cell<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    adt_def: &AdtDef,
+    kind: CellKind,
+    limits: Limits,
+    level: usize,
+) -> InterpResult<'tcx, String> {
+    let value = cell_value(ecx, op_ty, kind)?;
+    let path = ecx.tcx.def_path_str(adt_def.did);
+    let name = path.rsplit("::").next().unwrap();
+    if kind == CellKind::AtomicBool {
+        return match ecx.read_scalar(value)?.to_bits(value.layout.size)? {
+            0 => Ok(format!("{}(false)", name)),
+            1 => Ok(format!("{}(true)", name)),
+            _ => Err(InterpError::AssumptionNotHeld.into()),
+        };
+    }
+    let pretty = if limits.depth == Some(0) {
+        limits.truncation_marker(level)
+    } else {
+        match pp_operand(ecx, value, limits.nested(), level + 1) {
+            Ok(pretty) => pretty,
+            Err(_) => "<span style='color: red;'>&lt;err&gt;</span>".to_string(),
+        }
+    };
+    if kind == CellKind::RefCell {
+        let borrow = refcell_borrow(ecx, op_ty)
+            .unwrap_or_else(|_| "<span style='color: red;'>unreadable borrow flag</span>".to_string());
+        return Ok(format!("{}({}) -> {}", name, borrow, pretty));
+    }
+    Ok(format!("{}({})", name, pretty))
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum MapKind {
+    HashMap,
+    BTreeMap,
+}
+
+fn map_kind(ecx: &InterpretCx, adt_def: &AdtDef) -> Option<MapKind> {
+    match &ecx.tcx.def_path_str(adt_def.did)[..] {
+        "std::collections::hash::map::HashMap" => Some(MapKind::HashMap),
+        "alloc::collections::btree::map::BTreeMap" => Some(MapKind::BTreeMap),
+        _ => None,
+    }
+}
+
+/// `HashMap { key: value, … and 17 more }` for the `HashMap` or `BTreeMap` `op_ty`, up to
+/// `limits.elements` entries. The internals of the map are checked for consistency while walking
+/// them, any error makes the caller print the fields instead.
+fn pp_map<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    kind: MapKind,
+    limits: Limits,
+    level: usize,
+) -> InterpResult<'tcx, String> {
+    let (name, (len, entries)) = match kind {
+        MapKind::HashMap => ("HashMap", hash_map_entries(ecx, op_ty, limits)?),
+        MapKind::BTreeMap => ("BTreeMap", btree_map_entries(ecx, op_ty, limits)?),
+    };
+    if len == 0 {
+        return Ok(format!("{} {{}}", name));
+    }
+    if limits.depth == Some(0) {
+        return Ok(format!("{} {{ {} }}", name, limits.truncation_marker(level)));
+    }
+    let pretty = |op_ty| {
+        pp_operand(ecx, op_ty, limits.nested(), level + 1)
+            .unwrap_or_else(|_| "<span style='color: red;'>&lt;err&gt;</span>".to_string())
+    };
+    let shown = entries.len() as u64;
+    let mut rendered = entries
+        .into_iter()
+        .take_while(|_| !limits.exhausted())
+        .map(|(key, value)| format!("{}: {}", pretty(key), pretty(value)))
+        .collect::<Vec<_>>();
+    if (rendered.len() as u64) < shown {
+        rendered.push(limits.truncation_marker(level));
+    } else if shown < len {
+        rendered.push(format!("… and {} more", len - shown));
+    }
+    Ok(format!("{} {{ {} }}", name, rendered.join(", ")))
+}
+
+/// The length of the `HashMap` `op_ty` and up to `limits.elements` of its entries, read from the
+/// control bytes and buckets of its hashbrown `RawTable`.
+fn hash_map_entries<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    limits: Limits,
+) -> InterpResult<'tcx, (u64, Vec<(OpTy<'tcx, miri::Tag>, OpTy<'tcx, miri::Tag>)>)> {
+    let table = named_field(ecx, named_field(ecx, op_ty, "base")?, "table")?;
+    let read_usize = |name| -> InterpResult<'tcx, u64> {
+        ecx.read_scalar(named_field(ecx, table, name)?)?.to_usize(&ecx.tcx.tcx)
+    };
+    let items = read_usize("items")?;
+    if items == 0 {
+        return Ok((0, Vec::new()));
+    }
+    let buckets = read_usize("bucket_mask")? + 1;
+    let ctrl = ecx.read_scalar(named_field(ecx, named_field(ecx, table, "ctrl")?, "pointer")?)?.not_undef()?;
+    limits.read(ctrl);
+    // A control byte with the top bit cleared marks a full bucket, `EMPTY` and `DELETED` have it set
+    let full = ecx
+        .memory()
+        .read_bytes(ctrl, Size::from_bytes(buckets))?
+        .iter()
+        .enumerate()
+        .filter(|&(_, &ctrl)| ctrl & 0x80 == 0)
+        .map(|(i, _)| i as u64)
+        .collect::<Vec<_>>();
+    if full.len() as u64 != items {
+        Err(InterpError::AssumptionNotHeld)?;
+    }
+    let data = ecx.deref_operand(named_field(ecx, named_field(ecx, table, "data")?, "pointer")?)?;
+    limits.read_place(data);
+    let data_ptr = data.ptr.to_ptr()?;
+    let entries = full
+        .into_iter()
+        .take(limits.elements.unwrap_or(items) as usize)
+        .map(|i| -> InterpResult<'tcx, _> {
+            let ptr = data_ptr.offset(Size::from_bytes(i * data.layout.size.bytes()), ecx)?;
+            let bucket = MPlaceTy::from_aligned_ptr(ptr, data.layout);
+            Ok((ecx.mplace_field(bucket, 0)?.into(), ecx.mplace_field(bucket, 1)?.into()))
+        })
+        .collect::<InterpResult<'tcx, Vec<_>>>()?;
+    Ok((items, entries))
+}
+
+/// The length of the `BTreeMap` `op_ty` and up to `limits.elements` of its entries in order,
+/// read by walking its nodes.
+fn btree_map_entries<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    limits: Limits,
+) -> InterpResult<'tcx, (u64, Vec<(OpTy<'tcx, miri::Tag>, OpTy<'tcx, miri::Tag>)>)> {
+    let len = ecx.read_scalar(named_field(ecx, op_ty, "length")?)?.to_usize(&ecx.tcx.tcx)?;
+    if len == 0 {
+        return Ok((0, Vec::new()));
+    }
+    let root = named_field(ecx, op_ty, "root")?;
+    let height = ecx.read_scalar(named_field(ecx, root, "height")?)?.to_usize(&ecx.tcx.tcx)?;
+    // A node of `len` entries has a height of at most log2(len)
+    if height > 64 {
+        Err(InterpError::AssumptionNotHeld)?;
+    }
+    let node = btree_node(ecx, named_field(ecx, root, "node")?, limits)?;
+    // Internal nodes start with a `LeafNode` and are only pointed to as one, their type is the
+    // one of the `parent` pointer of a leaf
+    let internal = match named_field(ecx, node.into(), "parent")?.layout.ty.builtin_deref(true) {
+        Some(pointee) => ecx.layout_of(pointee.ty)?,
+        None => Err(InterpError::AssumptionNotHeld)?,
+    };
+    let max = limits.elements.unwrap_or(len).min(len);
+    let mut entries = Vec::new();
+    btree_node_entries(ecx, node, height, internal, max, limits, &mut entries)?;
+    if (entries.len() as u64) < max {
+        Err(InterpError::AssumptionNotHeld)?;
+    }
+    Ok((len, entries))
+}
+
+/// The `LeafNode` the `BoxedNode` `boxed` points to.
+fn btree_node<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    boxed: OpTy<'tcx, miri::Tag>,
+    limits: Limits,
+) -> InterpResult<'tcx, MPlaceTy<'tcx, miri::Tag>> {
+    let node = ecx.deref_operand(named_field(ecx, named_field(ecx, boxed, "ptr")?, "pointer")?)?;
+    limits.read_place(node);
+    Ok(node)
+}
+
+/// Appends the entries of the subtree `node` of height `height` to `entries` in order, until
+/// there are `max` of them.
+fn btree_node_entries<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    node: MPlaceTy<'tcx, miri::Tag>,
+    height: u64,
+    internal: TyLayout<'tcx>,
+    max: u64,
+    limits: Limits,
+    entries: &mut Vec<(OpTy<'tcx, miri::Tag>, OpTy<'tcx, miri::Tag>)>,
+) -> InterpResult<'tcx, ()> {
+    // `keys` and `vals` are `MaybeUninit<[T; CAPACITY]>`, a union wrapping a `ManuallyDrop`
+    let array = |name| -> InterpResult<'tcx, OpTy<'tcx, miri::Tag>> {
+        let field = named_field(ecx, node.into(), name)?;
+        Ok(named_field(ecx, field, "value")
+            .and_then(|value| named_field(ecx, value, "value"))
+            .unwrap_or(field))
+    };
+    let len = ecx.read_scalar(named_field(ecx, node.into(), "len")?)?.to_bits(Size::from_bytes(2))? as u64;
+    let (keys, vals) = (array("keys")?, array("vals")?);
+    match keys.layout.ty.sty {
+        TyKind::Array(..) if len <= keys.layout.fields.count() as u64 => {}
+        _ => Err(InterpError::AssumptionNotHeld)?,
+    }
+    for i in 0..=len {
+        if entries.len() as u64 >= max {
+            break;
+        }
+        if height > 0 {
+            let internal_node = MPlaceTy::from_aligned_ptr(node.ptr.to_ptr()?, internal);
+            let edges = named_field(ecx, internal_node.into(), "edges")?;
+            let child = btree_node(ecx, ecx.operand_field(edges, i)?, limits)?;
+            btree_node_entries(ecx, child, height - 1, internal, max, limits, entries)?;
+        }
+        if i < len && (entries.len() as u64) < max {
+            entries.push((ecx.operand_field(keys, i)?, ecx.operand_field(vals, i)?));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum VecKind {
+    Vec,
+    String,
+}
+
+fn vec_kind(ecx: &InterpretCx, adt_def: &AdtDef) -> Option<VecKind> {
+    match &ecx.tcx.def_path_str(adt_def.did)[..] {
+        "alloc::vec::Vec" => Some(VecKind::Vec),
+        "alloc::string::String" => Some(VecKind::String),
+        _ => None,
+    }
+}
+
+/// The contents of the `Vec` or `String` `op_ty`, read through the pointer, capacity and length
+/// of its `RawVec`. The error describes why the contents can't be read, the caller prints the
+/// fields instead then.
+fn pp_vec<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    substs: SubstsRef<'tcx>,
+    limits: Limits,
+    level: usize,
+) -> Result<String, String> {
+    let adt_def = op_ty.layout.ty.ty_adt_def().unwrap();
+    let kind = vec_kind(ecx, adt_def).unwrap();
+    let (len, cap, ptr) = vec_fields(ecx, op_ty, kind).map_err(|_| "unreadable fields".to_string())?;
+    if len > cap {
+        return Err(format!("len {} > cap {}", len, cap));
+    }
+    let elem_ty = match kind {
+        VecKind::Vec => substs.type_at(0),
+        VecKind::String => ecx.tcx.types.u8,
+    };
+    let elem = ecx.layout_of(elem_ty).map_err(|_| "unknown element layout".to_string())?;
+    if len == 0 || elem.size.bytes() == 0 {
+        return Ok(match kind {
+            VecKind::Vec => format!("vec![{}]", if len == 0 { String::new() } else { format!("&lt;zst&gt;; {}", len) }),
+            VecKind::String => "\"\"".to_string(),
+        });
+    }
+    let ptr = ptr.to_ptr().map_err(|_| "dangling pointer".to_string())?;
+    limits.read(Scalar::Ptr(ptr));
+    match ecx.memory().get(ptr.alloc_id) {
+        Ok(alloc) if ptr.offset.bytes() + len * elem.size.bytes() <= alloc.bytes.len() as u64 => {}
+        Ok(_) => return Err("len exceeds the allocation".to_string()),
+        Err(_) => return Err("dangling pointer".to_string()),
+    }
+    match kind {
+        VecKind::Vec => {
+            let element = |i: u64| -> InterpResult<'tcx, OpTy<'tcx, miri::Tag>> {
+                let ptr = ptr.offset(Size::from_bytes(i * elem.size.bytes()), ecx)?;
+                Ok(MPlaceTy::from_aligned_ptr(ptr, elem).into())
+            };
+            Ok(format!("vec!{}", pp_elements(ecx, len, element, limits, level)))
+        }
+        VecKind::String => pp_str(ecx, Scalar::Ptr(ptr), len, limits, level),
+    }
+}
+
+/// The length, capacity and pointer of the `Vec` or `String` `op_ty`.
+fn vec_fields<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    kind: VecKind,
+) -> InterpResult<'tcx, (u64, u64, Scalar<miri::Tag>)> {
+    // String { vec: Vec<u8> }, Vec<T> { buf: RawVec<T>, len }, RawVec<T> { ptr: Unique<T>, cap, .. }
+    let vec = match kind {
+        VecKind::Vec => op_ty,
+        VecKind::String => ecx.operand_field(op_ty, 0)?,
+    };
+    let raw_vec = ecx.operand_field(vec, 0)?;
+    let len = ecx.read_scalar(ecx.operand_field(vec, 1)?)?.to_usize(&ecx.tcx.tcx)?;
+    let cap = ecx.read_scalar(ecx.operand_field(raw_vec, 1)?)?.to_usize(&ecx.tcx.tcx)?;
+    let unique = ecx.operand_field(raw_vec, 0)?;
+    let ptr = ecx.read_scalar(ecx.operand_field(unique, 0)?)?.not_undef()?;
+    Ok((len, cap, ptr))
+}
+
+/// The string of `len` bytes at `ptr`, truncated to `limits.string_bytes` bytes. The error
+/// describes why it can't be read.
+fn pp_str<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    ptr: Scalar<miri::Tag>,
+    len: u64,
+    limits: Limits,
+    level: usize,
+) -> Result<String, String> {
+    limits.read(ptr);
+    let s = read_str_prefix(ecx, ptr, len, limits.string_bytes.unwrap_or(len))?;
+    let shown = s.len() as u64;
+    if shown < len {
+        Ok(format!("\"{}\"{}", escape_html(s), limits.more_bytes(len - shown, shown, level)))
+    } else {
+        Ok(format!("\"{}\"", escape_html(s)))
+    }
+}
+
+/// At most `max` bytes of the UTF-8 string of `len` bytes at `ptr`. A truncated string ends
+/// before the char `max` would split.
+fn read_str_prefix<'a, 'tcx: 'a>(
+    ecx: &'a InterpretCx<'a, 'tcx>,
+    ptr: Scalar<miri::Tag>,
+    len: u64,
+    max: u64,
+) -> Result<&'a str, String> {
+    let shown = max.min(len);
+    if shown == 0 {
+        return Ok("");
+    }
+    let bytes = ecx
+        .memory()
+        .read_bytes(ptr, Size::from_bytes(shown))
+        .map_err(|_| "undefined bytes".to_string())?;
+    match ::std::str::from_utf8(bytes) {
+        Ok(s) => Ok(s),
+        Err(err) if shown < len && err.error_len().is_none() => {
+            Ok(::std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap())
+        }
+        Err(_) => Err("invalid UTF-8".to_string()),
+    }
+}
+
+/// The start, bytes `start..` and total length of the `String`, `&str` or `str` local `local` of
+/// `frame`, at most `max` bytes of it. `start` is `offset` moved forward to the next char boundary.
+pub fn string_chunk<'a, 'tcx: 'a>(
+    ecx: &'a InterpretCx<'a, 'tcx>,
+    frame: &Frame<'tcx, 'tcx, Tag, NonZeroU64>,
+    local: mir::Local,
+    offset: u64,
+    max: u64,
+) -> Result<(u64, &'a str, u64), String> {
+    let op_ty = local_operand(ecx, frame, local).map_err(|err| match err {
+        LocalError::Interp(err) => err,
+        LocalError::Dead | LocalError::Uninit => "The local is dead or uninitialized".to_string(),
+    })?;
+    let string: InterpResult<(Scalar<miri::Tag>, u64)> = try {
+        match op_ty.layout.ty.sty {
+            TyKind::Adt(adt_def, _) if vec_kind(ecx, adt_def) == Some(VecKind::String) => {
+                let (len, _cap, ptr) = vec_fields(ecx, op_ty, VecKind::String)?;
+                (ptr, len)
+            }
+            TyKind::Ref(_, &TyS { sty: TyKind::Str, .. }, _) => {
+                let place = ecx.deref_operand(op_ty)?;
+                (place.ptr, place.len(ecx)?)
+            }
+            TyKind::Str => {
+                let place = op_ty.try_as_mplace().map_err(|_| InterpError::AssumptionNotHeld)?;
+                (place.ptr, place.len(ecx)?)
+            }
+            _ => Err(InterpError::AssumptionNotHeld)?,
+        }
+    };
+    let (ptr, len) = string.map_err(|_| "The local is not a readable String or str".to_string())?;
+    if offset > len {
+        return Err(format!("Offset {} is past the end of the string of {} bytes", offset, len));
+    }
+    let ptr = ptr.to_ptr().map_err(|_| "dangling pointer".to_string())?;
+    let at = |offset: u64| ptr.offset(Size::from_bytes(offset), ecx).map_err(|_| "dangling pointer".to_string());
+    // Skip UTF-8 continuation bytes
+    let mut start = offset;
+    while start < len && start < offset + 3 {
+        let byte = ecx
+            .memory()
+            .read_bytes(Scalar::Ptr(at(start)?), Size::from_bytes(1))
+            .map_err(|_| "undefined bytes".to_string())?[0];
+        if byte & 0xC0 != 0x80 {
+            break;
+        }
+        start += 1;
+    }
+    let s = read_str_prefix(ecx, Scalar::Ptr(at(start)?), len - start, max)?;
+    Ok((start, s, len))
+}
+
+/// `[a, b, …]` for the `len` elements returned by `element`, up to `limits.elements` of them.
+/// An element which can't be printed doesn't prevent printing the others.
+fn pp_elements<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    len: u64,
+    element: impl Fn(u64) -> InterpResult<'tcx, OpTy<'tcx, miri::Tag>>,
+    limits: Limits,
+    level: usize,
+) -> String {
+    if len == 0 {
+        return "[]".to_string();
+    }
+    if limits.depth == Some(0) {
+        return format!("[{}]", limits.truncation_marker(level));
+    }
+    let shown = limits.elements.map_or(len, |max| max.min(len));
+    let mut elements = (0..shown)
+        .take_while(|_| !limits.exhausted())
+        .map(|i| match element(i) {
+            Ok(elem) => pp_operand(ecx, elem, limits.nested(), level + 1)
+                .unwrap_or_else(|err| pp_error(ecx, Some(elem), err)),
+            Err(err) => pp_error(ecx, None, err),
+        })
+        .collect::<Vec<_>>();
+    let rendered = elements.len() as u64;
+    if rendered < shown {
+        elements.push(limits.truncation_marker(level));
+    } else if shown < len {
+        elements.push(format!("… and {} more", len - shown));
+    }
+    format!("[{}]", elements.join(", "))
+}
+
+/// Pretty prints an unsized local of type `str`, `[T]` or `dyn Trait`, which only exist in
+/// functions using the `unsized_locals` feature.
+fn pp_unsized_place<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    place: MPlaceTy<'tcx, miri::Tag>,
+    limits: Limits,
+    level: usize,
+) -> InterpResult<'tcx, String> {
+    match place.layout.ty.sty {
+        TyKind::Str => {
+            let len = place.len(ecx)?;
+            pp_str(ecx, place.ptr, len, limits, level).map_err(|_| InterpError::AssumptionNotHeld.into())
+        }
+        TyKind::Dynamic(..) => {
+            let vtable = place.meta.ok_or(InterpError::AssumptionNotHeld)?;
+            let data = ScalarMaybeUndef::Scalar(place.ptr);
+            Ok(pp_dyn(ecx, place.layout.ty, data, ScalarMaybeUndef::Scalar(vtable), limits, level)
+                .ok_or(InterpError::AssumptionNotHeld)?)
+        }
+        _ => {
+            let len = place.len(ecx)?;
+            Ok(pp_elements(ecx, len, |i| Ok(ecx.mplace_field(place, i)?.into()), limits, level))
+        }
+    }
+}
+
+fn is_trait_object_ptr(ty: Ty) -> bool {
+    match ty.builtin_deref(true) {
+        Some(TypeAndMut { ty: &TyS { sty: TyKind::Dynamic(..), .. }, .. }) => true,
+        _ => false,
+    }
+}
+
+/// The type of the trait object pointer `op_ty` followed by the concrete type behind it and its
+/// value. The concrete type is the type the drop glue in the vtable drops. `None` if the vtable
+/// can't be read.
+fn pp_trait_object<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    limits: Limits,
+    level: usize,
+) -> Option<String> {
+    let (data, vtable) = match *ecx.read_immediate(op_ty).ok()? {
+        Immediate::ScalarPair(data, vtable) => (data, vtable),
+        Immediate::Scalar(_) => return None,
+    };
+    pp_dyn(ecx, op_ty.layout.ty, data, vtable, limits, level)
+}
+
+/// `ty` followed by the concrete type and value of the trait object with the data pointer `data`
+/// and the vtable `vtable`.
+fn pp_dyn<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    ty: Ty<'tcx>,
+    data: ScalarMaybeUndef<miri::Tag>,
+    vtable: ScalarMaybeUndef<miri::Tag>,
+    limits: Limits,
+    level: usize,
+) -> Option<String> {
+    let vtable = vtable.not_undef().ok()?.to_ptr().ok()?;
+    let (_drop_fn, concrete) = ecx.read_drop_type_from_vtable(vtable).ok()?;
+    let header = escape_html(&format!("{} (concrete: {})", ty, concrete)).into_owned();
+    if limits.depth == Some(0) {
+        return Some(format!("{} {}", header, limits.truncation_marker(level)));
+    }
+    let layout = ecx.layout_of(concrete).ok()?;
+    let pretty = data
+        .not_undef()
+        .and_then(|data| data.to_ptr())
+        .and_then(|data| pp_operand(ecx, MPlaceTy::from_aligned_ptr(data, layout).into(), limits.nested(), level + 1));
+    Some(match pretty {
+        Ok(pretty) => format!("{} {}", header, pretty),
+        Err(_) => header,
+    })
+}
+
+/// The rendering of the pointee of the reference, raw pointer or box `op_ty` prefixed by `*`. A
+/// dangling or unreadable pointee is rendered as the pointer with a badge, `None` means the
+/// pointee can't be pretty printed and `op_ty` should be rendered as usual. Misaligned pointers
+/// aren't followed, `misalignment` marks them.
+fn pp_pointee<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    limits: Limits,
+    level: usize,
+) -> InterpResult<'tcx, Option<String>> {
+    let pointee = ecx.layout_of(op_ty.layout.ty.builtin_deref(true).unwrap().ty)?;
+    // Pointers to zero sized types are usually dangling on purpose
+    if pointee.is_unsized() || pointee.size.bytes() == 0 {
+        return Ok(None);
+    }
+    let scalar = ecx.read_scalar(op_ty)?;
+    if let ScalarMaybeUndef::Scalar(ptr) = scalar {
+        limits.read(ptr);
+    }
+    let readable = match scalar {
+        ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr)) => match ecx.memory().get(ptr.alloc_id) {
+            Ok(alloc) => {
+                if ptr.offset.bytes() % pointee.align.abi.bytes() != 0 || alloc.align < pointee.align.abi {
+                    return Ok(None);
+                }
+                ptr.offset.bytes() + pointee.size.bytes() <= alloc.bytes.len() as u64
+            }
+            // `print_scalar` already flags pointers to freed allocations
+            Err(_) => return Ok(None),
+        },
+        _ => false,
+    };
+    if !readable {
+        return Ok(Some(format!(
+            "{} <span style=\"color: white; background: gray;\">dangling</span>",
+            print_scalar_maybe_undef(ecx, scalar)
+        )));
+    }
+    let place = ecx.deref_operand(op_ty)?;
+    Ok(pp_operand(ecx, place.into(), limits.deref(), level + 1)
+        .ok()
+        .map(|pretty| format!("*{}", pretty)))
+}
+
+/// Returns the allocation the operand is stored in, its rendering and the byte ranges it occupies
+/// in the allocation as `(offset, len)` pairs.
 pub fn print_operand<'a, 'tcx: 'a>(
     ecx: &InterpretCx<'a, 'tcx>,
     op_ty: OpTy<'tcx, miri::Tag>,
-) -> Result<(Option<u64>, String), ()> {
-    let pretty = pp_operand(ecx, op_ty);
+) -> Result<(Option<u64>, String, Vec<(u64, u64)>), ()> {
+    print_operand_with(ecx, op_ty, Limits::UNLIMITED)
+}
 
-    let (alloc, txt) = match *op_ty {
+pub fn print_operand_with<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    limits: Limits,
+) -> Result<(Option<u64>, String, Vec<(u64, u64)>), ()> {
+    let printed = print_operand_parts(ecx, op_ty, limits)?;
+    let txt = printed.text();
+    Ok((printed.alloc, txt, printed.ranges))
+}
+
+/// The renderings of an operand before they are joined into the text shown in the locals table.
+struct PrintedOperand {
+    alloc: Option<u64>,
+    /// `None` when the value couldn't be pretty printed or raw output was requested
+    pretty: Option<String>,
+    raw: String,
+    /// Warnings about the value, like misaligned or dangling pointers
+    badges: String,
+    ranges: Vec<(u64, u64)>,
+}
+
+impl PrintedOperand {
+    fn text(&self) -> String {
+        match self.pretty {
+            Some(ref pretty) => format!("{} ({}){}", pretty, self.raw, self.badges),
+            None => format!("{}{}", self.raw, self.badges),
+        }
+    }
+}
+
+fn print_operand_parts<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+    limits: Limits,
+) -> Result<PrintedOperand, ()> {
+    // The bytes are dumped even if the value isn't pretty printed
+    limits.read_operand(op_ty);
+    let pretty = if limits.raw {
+        None
+    } else {
+        pp_operand(ecx, op_ty, limits, 0).ok()
+    };
+
+    let (alloc, txt, ranges) = match *op_ty {
         Operand::Indirect(place) => {
             let size: u64 = op_ty.layout.size.bytes();
             if place.meta.is_none() {
                 let ptr = place.to_scalar_ptr_align().0;
-                let (alloc, txt, _len) = print_ptr(ecx, ptr, Some(size))?;
-                (alloc, txt)
+                let (alloc, txt, _len) = print_ptr(ecx, ptr, size, Some(op_ty.layout))?;
+                let offset = ptr.to_ptr().map(|ptr| ptr.offset.bytes()).map_err(|_| ())?;
+                let ranges = match op_ty.layout.abi {
+                    // The padding between the two scalars isn't part of the value
+                    Abi::ScalarPair(ref a, ref b) => {
+                        let a_size = a.value.size(ecx);
+                        let b_offset = a_size.align_to(b.value.align(ecx).abi);
+                        vec![
+                            (offset, a_size.bytes()),
+                            (offset + b_offset.bytes(), b.value.size(ecx).bytes()),
+                        ]
+                    }
+                    _ => vec![(offset, size)],
+                };
+                (alloc, txt, ranges)
             } else {
-                (None, format!("{:?}", place)) // FIXME better printing for unsized locals
+                print_unsized(ecx, op_ty)?
             }
         }
-        Operand::Immediate(Immediate::Scalar(scalar)) => (None, print_scalar_maybe_undef(scalar)),
+        Operand::Immediate(Immediate::Scalar(scalar)) => (None, print_scalar_maybe_undef(ecx, scalar), Vec::new()),
         Operand::Immediate(Immediate::ScalarPair(val, extra)) => (
             None,
             format!(
                 "{}, {}",
-                print_scalar_maybe_undef(val),
-                print_scalar_maybe_undef(extra)
+                print_scalar_maybe_undef(ecx, val),
+                print_scalar_maybe_undef(ecx, extra)
             ),
+            Vec::new(),
         ),
     };
-    let txt = if let Ok(pretty) = pretty {
-        format!("{} ({})", pretty, txt)
-    } else {
-        txt
+    let mut badges = String::new();
+    if let Some(badge) = misalignment(ecx, op_ty) {
+        badges.push_str(&format!(" <span style=\"color: white; background: red;\">{}</span>", badge));
+    }
+    if let Some(origin) = mutable_pointer_to_immutable(ecx, op_ty) {
+        badges.push_str(&format!(
+            " <span style=\"color: black; background: orange;\">mutable pointer to {}</span>",
+            origin
+        ));
+    }
+    Ok(PrintedOperand {
+        alloc,
+        pretty,
+        raw: txt,
+        badges,
+        ranges,
+    })
+}
+
+/// The bytes of the unsized place `op_ty`, whose size is given by its metadata. Trait objects
+/// additionally link to their vtable.
+fn print_unsized<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+) -> Result<(Option<u64>, String, Vec<(u64, u64)>), ()> {
+    let place = op_ty.try_as_mplace().map_err(|_| ())?;
+    // `None` for extern types, whose size is unknown
+    let (size, _align) = ecx.size_and_align_of_mplace(place).map_err(|_| ())?.ok_or(())?;
+    let (alloc, mut txt, _len) = print_ptr(ecx, place.ptr, size.bytes(), None)?;
+    let offset = place.ptr.to_ptr().map(|ptr| ptr.offset.bytes()).map_err(|_| ())?;
+    if let TyKind::Dynamic(..) = op_ty.layout.ty.sty {
+        if let Some(Ok(vtable)) = place.meta.map(|meta| meta.to_ptr()) {
+            txt.push_str(&format!(
+                " <a href=\"/ptr/{}/{}\">vtable</a>",
+                vtable.alloc_id.0,
+                vtable.offset.bytes(),
+            ));
+        }
+    }
+    Ok((alloc, txt, vec![(offset, size.bytes())]))
+}
+
+/// The origin of the allocation `op_ty` points to if it is a `&mut T` or `*mut T` and the
+/// allocation is immutable. Writing through such a pointer is UB.
+fn mutable_pointer_to_immutable<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    op_ty: OpTy<'tcx, miri::Tag>,
+) -> Option<String> {
+    match op_ty.layout.ty.sty {
+        TyKind::Ref(_, _, hir::MutMutable) | TyKind::RawPtr(TypeAndMut { mutbl: hir::MutMutable, .. }) => {}
+        _ => return None,
+    }
+    match ecx.read_scalar(op_ty).ok()?.not_undef().ok()? {
+        Scalar::Ptr(ptr) => immutable_origin(ecx, ptr.alloc_id),
+        Scalar::Raw { .. } => None,
+    }
+}
+
+/// What the allocation `alloc_id` is: one of the `api::ORIGINS` of the interpreter memory, or
+/// `static`, `const` or `function` for allocations only in the tcx. `None` for freed allocations.
+pub fn alloc_kind(ecx: &InterpretCx, alloc_id: AllocId) -> Option<&'static str> {
+    // Statics are copied into the interpreter memory when they are first written
+    if let Ok((kind, _)) = ecx.memory().alloc_map().get_or(alloc_id, || Err(())) {
+        return Some(crate::api::origin(kind));
+    }
+    match ecx.tcx.alloc_map.lock().get(alloc_id) {
+        Some(GlobalAlloc::Static(_)) => Some("static"),
+        Some(GlobalAlloc::Memory(_)) => Some("const"),
+        Some(GlobalAlloc::Function(_)) => Some("function"),
+        None => None,
+    }
+}
+
+/// The frames and locals of the stack stored in the allocation `alloc_id`.
+pub fn backing_locals(ecx: &InterpretCx, alloc_id: AllocId) -> Vec<(usize, mir::Local)> {
+    ecx.stack()
+        .iter()
+        .enumerate()
+        .flat_map(|(i, frame)| {
+            frame.locals.iter_enumerated().filter_map(move |(local, state)| match state.value {
+                LocalValue::Live(Operand::Indirect(mplace)) => match mplace.ptr {
+                    Scalar::Ptr(ptr) if ptr.alloc_id == alloc_id => Some((i, local)),
+                    _ => None,
+                },
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+/// The frames and locals of the stack holding a pointer to `alloc_id` as an immediate value.
+/// Locals stored in memory are found through the relocations of their allocation instead.
+pub fn referring_locals(ecx: &InterpretCx, alloc_id: AllocId) -> Vec<(usize, mir::Local)> {
+    let points_here = |val: ScalarMaybeUndef<Tag>| match val {
+        ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr)) => ptr.alloc_id == alloc_id,
+        _ => false,
     };
-    Ok((alloc, txt))
+    ecx.stack()
+        .iter()
+        .enumerate()
+        .flat_map(|(i, frame)| {
+            frame.locals.iter_enumerated().filter_map(move |(local, state)| {
+                let refers = match state.value {
+                    LocalValue::Live(Operand::Immediate(Immediate::Scalar(val))) => points_here(val),
+                    LocalValue::Live(Operand::Immediate(Immediate::ScalarPair(a, b))) => points_here(a) || points_here(b),
+                    _ => false,
+                };
+                if refers {
+                    Some((i, local))
+                } else {
+                    None
+                }
+            })
+        })
+        .collect()
 }
 
-pub fn print_ptr(
-    ecx: &InterpretCx,
+/// A link to the allocation `alloc_id` with a badge for its `alloc_kind` and its `alloc_name`.
+pub fn alloc_link(ecx: &InterpretCx, alloc_id: AllocId) -> String {
+    let mut s = format!("<a href=\"/ptr/{alloc}/0\">{alloc}</a>", alloc = alloc_id.0);
+    if let Some(kind) = alloc_kind(ecx, alloc_id) {
+        s.push_str(&format!(" <span class=\"alloc-kind\">{}</span>", kind));
+    }
+    if let Some(name) = alloc_name(ecx, alloc_id) {
+        s.push_str(&format!(" {}", escape_html(&name)));
+    }
+    s
+}
+
+/// `static my_crate::CONFIG`, `const allocation`, `vtable` or `fn foo` for allocations that
+/// don't belong to the stack or heap, `None` for those that do.
+pub fn alloc_name(ecx: &InterpretCx, alloc_id: AllocId) -> Option<String> {
+    match ecx.tcx.alloc_map.lock().get(alloc_id) {
+        Some(GlobalAlloc::Static(def_id)) => return Some(format!("static {}", ecx.tcx.def_path_str(def_id))),
+        Some(GlobalAlloc::Memory(_)) => return Some("const allocation".to_string()),
+        Some(GlobalAlloc::Function(instance)) => return Some(format!("fn {}", instance)),
+        None => {}
+    }
+    match ecx.memory().alloc_map().get_or(alloc_id, || Err(())) {
+        Ok((MemoryKind::Vtable, _)) => Some("vtable".to_string()),
+        _ => None,
+    }
+}
+
+/// The slots of the vtable `alloc_id` as a table: the drop glue, the size and alignment of the
+/// concrete type and the methods, linking to their MIR. `None` if `alloc_id` isn't a vtable.
+pub fn render_vtable(ecx: &InterpretCx, alloc_id: AllocId) -> Option<String> {
+    match ecx.memory().alloc_map().get_or(alloc_id, || Err(())) {
+        Ok((MemoryKind::Vtable, _)) => {}
+        _ => return None,
+    }
+    let alloc = ecx.memory().get(alloc_id).ok()?;
+    let ptr_size = ecx.tcx.data_layout.pointer_size.bytes();
+    let vtable = Pointer::new(alloc_id, Size::from_bytes(0)).with_tag(Tag::Untagged);
+    let concrete = match ecx.read_drop_type_from_vtable(vtable) {
+        Ok((_drop_fn, ty)) => escape_html(&ty.to_string()).into_owned(),
+        Err(_) => "unknown type".to_string(),
+    };
+    let mut s = format!("<table border=\"1\"><tr><th>slot</th><th colspan=\"2\">vtable of {}</th></tr>", concrete);
+    for slot in 0..alloc.bytes.len() as u64 / ptr_size {
+        let offset = slot * ptr_size;
+        let role = match slot {
+            0 => "drop",
+            1 => "size",
+            2 => "align",
+            _ => "method",
+        };
+        let value = match alloc.relocations.get(&Size::from_bytes(offset)) {
+            Some(&(_tag, target)) => match ecx.memory().get_fn(Pointer::new(target, Size::from_bytes(0)).with_tag(Tag::Untagged)) {
+                Ok(instance) => fn_link(instance, target),
+                // Not a function, show the pointer like the hexdump does
+                Err(_) => format!("→ {}", alloc_link(ecx, target)),
+            },
+            None => match alloc.read_ptr_sized(ecx, Pointer::new(alloc_id, Size::from_bytes(offset)).with_tag(Tag::Untagged)) {
+                // Methods with `where Self: Sized` bounds and types without drop glue have no fn
+                Ok(ScalarMaybeUndef::Scalar(Scalar::Raw { data: 0, .. })) if slot != 1 && slot != 2 => "null".to_string(),
+                Ok(ScalarMaybeUndef::Scalar(Scalar::Raw { data, .. })) => data.to_string(),
+                _ => "&lt;undef&gt;".to_string(),
+            },
+        };
+        s.push_str(&format!(
+            "<tr><td><a href=\"/ptr/{}/{}\">{}</a></td><td>{}</td><td>{}</td></tr>",
+            alloc_id.0, offset, slot, role, value
+        ));
+    }
+    s.push_str("</table>");
+    Some(s)
+}
+
+/// Describes where the allocation `alloc_id` comes from if it is immutable, `None` if it is
+/// mutable or doesn't exist.
+pub fn immutable_origin(ecx: &InterpretCx, alloc_id: AllocId) -> Option<String> {
+    let alloc = ecx.memory().get(alloc_id).ok()?;
+    if alloc.mutability != Mutability::Immutable {
+        return None;
+    }
+    Some(match ecx.tcx.alloc_map.lock().get(alloc_id) {
+        Some(GlobalAlloc::Static(def_id)) => format!("static {}", ecx.tcx.def_path_str(def_id)),
+        Some(GlobalAlloc::Memory(_)) => format!("literal or promoted constant (alloc {})", alloc_id.0),
+        _ => format!("immutable alloc {}", alloc_id.0),
+    })
+}
+
+/// Describes why `op_ty` is misaligned if it is a pointer to a sized type and isn't aligned for
+/// that type. Integer addresses are checked directly.
+fn misalignment<'a, 'tcx: 'a>(ecx: &InterpretCx<'a, 'tcx>, op_ty: OpTy<'tcx, miri::Tag>) -> Option<String> {
+    let pointee = match op_ty.layout.ty.sty {
+        TyKind::Ref(_, ty, _) | TyKind::RawPtr(TypeAndMut { ty, .. }) => ty,
+        _ => return None,
+    };
+    let layout = ecx.layout_of(pointee).ok()?;
+    if layout.is_unsized() {
+        return None;
+    }
+    let align = layout.align.abi.bytes();
+    match ecx.read_scalar(op_ty).ok()?.not_undef().ok()? {
+        Scalar::Ptr(ptr) => {
+            // The allocation itself may be less aligned than the pointee requires
+            let alloc_align = ecx.memory().get(ptr.alloc_id).ok()?.align.bytes();
+            let offset = ptr.offset.bytes();
+            if offset % align != 0 || alloc_align < align {
+                Some(format!("misaligned (offset {}, align {}, allocation align {})", offset, align, alloc_align))
+            } else {
+                None
+            }
+        }
+        Scalar::Raw { data, .. } => {
+            if data % u128::from(align) != 0 {
+                Some(format!("misaligned (address 0x{:x}, align {})", data, align))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// The bytes `ptr` points to, grouped by the fields of `layout` if the type of the value there is
+/// known.
+pub fn print_ptr<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
     ptr: Scalar<Tag>,
-    size: Option<u64>,
+    size: u64,
+    layout: Option<TyLayout<'tcx>>,
 ) -> Result<(Option<u64>, String, u64), ()> {
     let ptr = ptr.to_ptr().map_err(|_| ())?;
     match (ecx.memory().get(ptr.alloc_id), ecx.memory().get_fn(ptr)) {
         (Ok(alloc), Err(_)) => {
-            let s = print_alloc(ecx.tcx.data_layout.pointer_size.bytes(), ptr, alloc, size);
+            let fields = layout.map(|layout| field_ranges(ecx, layout, ptr.offset.bytes())).unwrap_or_default();
+            let s = print_alloc_inline(ecx, ptr, alloc, size, &fields);
             Ok((Some(ptr.alloc_id.0), s, alloc.bytes.len() as u64))
         }
-        (Err(_), Ok(_)) => {
-            // FIXME: print function name
-            Ok((None, "function pointer".to_string(), 16))
-        }
+        (Err(_), Ok(instance)) => Ok((None, fn_link(instance, ptr.alloc_id), 16)),
         (Err(_), Err(_)) => Err(()),
         (Ok(_), Ok(_)) => unreachable!(),
     }
 }
 
-pub fn print_alloc(ptr_size: u64, ptr: Pointer<Tag>, alloc: &Allocation<Tag, Stacks>, size: Option<u64>) -> String {
+/// A link to the MIR of the function `instance`, which function pointers to `alloc_id` point to.
+fn fn_link(instance: Instance, alloc_id: AllocId) -> String {
+    format!(
+        "<a href=\"/fn/{}\">fn: {}</a>",
+        alloc_id.0,
+        escape_html(&instance.to_string()),
+    )
+}
+
+/// Bytes per row of the hexdumps, like `xxd`.
+pub const HEXDUMP_ROW: u64 = 16;
+
+/// Undefined bytes are shown as two of these, picked by the allocation and offset. The varying
+/// glyphs make it obvious that the bytes have no value, not even zero.
+/// The start of the span underlining the cells of the field `field`, the `index`th one of the
+/// value. Neighbouring fields alternate colors, padding is dotted.
+fn field_span(index: usize, field: &FieldRange) -> String {
+    match &field.name {
+        Some(name) => format!(
+            "<span title=\"{} ({}..{})\" style=\"border-bottom: 2px solid {};\">",
+            escape_html(name),
+            field.start,
+            field.end,
+            if index % 2 == 0 { "#4a90d9" } else { "#d9904a" },
+        ),
+        None => format!(
+            "<span title=\"padding ({}..{}), not part of any field and never written by copies of the value\" style=\"border-bottom: 2px dotted gray;\">",
+            field.start, field.end,
+        ),
+    }
+}
+
+const UNDEF_GLYPHS: [char; 13] = ['∅', '∆', '∇', '∓', '∞', '⊙', '⊠', '⊘', '⊗', '⊛', '⊝', '⊡', '⊟'];
+
+/// Rows of a value shown in the locals table, larger values link to their allocation.
+const INLINE_ROWS: u64 = 4;
+
+/// A hexdump of the `size` bytes of `alloc` at `ptr`, up to `INLINE_ROWS` rows, so huge values
+/// don't make huge locals tables.
+fn print_alloc_inline(ecx: &InterpretCx, ptr: Pointer<Tag>, alloc: &Allocation<Tag, Stacks>, size: u64, fields: &[FieldRange]) -> String {
+    let len = alloc.bytes.len() as u64;
+    let start = ptr.offset.bytes().min(len);
+    let requested_end = ptr.offset.bytes() + size;
+    // Partial writes and bugged places can reach past the end, the bytes there don't exist
+    let end = requested_end.min(len);
+    let annotations = Annotations { fields, ..Annotations::default() };
+    let mut s = hexdump(ecx, ptr.alloc_id, alloc, start, end, Some(INLINE_ROWS), &annotations);
+    if requested_end > len {
+        s.push_str(&format!(
+            " <span style=\"color: white; background: red;\">… ({} bytes past end of allocation)</span>",
+            requested_end - len
+        ));
+    }
+    s
+}
+
+/// A hexdump of the bytes `start..end` of `alloc`. The range is clamped to the allocation.
+pub fn print_alloc(
+    ecx: &InterpretCx,
+    alloc_id: AllocId,
+    alloc: &Allocation<Tag, Stacks>,
+    start: u64,
+    end: u64,
+    annotations: &Annotations,
+) -> String {
+    let len = alloc.bytes.len() as u64;
+    hexdump(ecx, alloc_id, alloc, start.min(len), end.min(len), None, annotations)
+}
+
+/// What a hexdump points out besides the bytes themselves.
+#[derive(Default)]
+pub struct Annotations<'a> {
+    /// The byte outlined in red
+    pub cursor: Option<u64>,
+    /// Ranges of bytes with a yellow background
+    pub highlight: &'a [(u64, u64)],
+    /// The fields of a value stored in the dumped bytes, see `field_ranges`
+    pub fields: &'a [FieldRange],
+}
+
+/// A field of a value, or the padding between fields.
+#[derive(Debug, Clone)]
+pub struct FieldRange {
+    pub start: u64,
+    pub end: u64,
+    /// The path of the field like `buf.cap`, `None` for padding
+    pub name: Option<String>,
+}
+
+/// `field_ranges` stops after this many fields, large structs and nested structs are cut off.
+const MAX_FIELD_RANGES: usize = 256;
+
+/// The innermost fields of the value with layout `layout` at `offset` ordered by offset, with the
+/// padding between them. Arrays, unions, enums with several variants and scalars aren't split
+/// up. Empty if the value has no fields to tell apart.
+pub fn field_ranges<'a, 'tcx: 'a>(ecx: &InterpretCx<'a, 'tcx>, layout: TyLayout<'tcx>, offset: u64) -> Vec<FieldRange> {
+    let mut fields = Vec::new();
+    leaf_fields(ecx, layout, offset, "", &mut fields);
+    fields.sort_by_key(|field| field.start);
+    let mut ranges = Vec::new();
+    let mut pos = offset;
+    for field in fields {
+        if field.start > pos {
+            ranges.push(FieldRange { start: pos, end: field.start, name: None });
+        }
+        pos = pos.max(field.end);
+        ranges.push(field);
+    }
+    let end = offset + layout.size.bytes();
+    if pos < end {
+        ranges.push(FieldRange { start: pos, end, name: None });
+    }
+    // A single field is the value itself
+    if ranges.len() < 2 {
+        ranges.clear();
+    }
+    ranges
+}
+
+fn leaf_fields<'a, 'tcx: 'a>(
+    ecx: &InterpretCx<'a, 'tcx>,
+    layout: TyLayout<'tcx>,
+    offset: u64,
+    path: &str,
+    out: &mut Vec<FieldRange>,
+) {
+    if out.len() >= MAX_FIELD_RANGES || layout.is_zst() {
+        return;
+    }
+    let count = match (&layout.ty.sty, &layout.variants) {
+        (TyKind::Array(..), _) | (TyKind::Slice(_), _) | (TyKind::Str, _) | (_, Variants::Multiple { .. }) => 0,
+        (TyKind::Adt(adt_def, _), _) if adt_def.is_union() => 0,
+        _ => layout.fields.count(),
+    };
+    if count == 0 {
+        out.push(FieldRange {
+            start: offset,
+            end: offset + layout.size.bytes(),
+            name: Some(if path.is_empty() { layout.ty.to_string() } else { path.to_string() }),
+        });
+        return;
+    }
+    for i in 0..count {
+        let name = match (&layout.ty.sty, &layout.variants) {
+            (&TyKind::Adt(adt_def, _), &Variants::Single { index }) => adt_def.variants[index]
+                .fields
+                .get(i)
+                .map_or_else(|| i.to_string(), |field| field.ident.as_str().to_string()),
+            _ => i.to_string(),
+        };
+        let path = if path.is_empty() { name } else { format!("{}.{}", path, name) };
+        if let Ok(field) = layout.field(ecx, i) {
+            leaf_fields(ecx, field, offset + layout.fields.offset(i).bytes(), &path, out);
+        }
+    }
+}
+
+/// Rows of `HEXDUMP_ROW` bytes of `alloc` in `start..end`, each with its offset and the printable
+/// bytes in an ascii gutter. Relocations are links spanning the cells of the pointer and undef
+/// bytes are shown as two glyphs, both keep the columns aligned. A relocation crossing the end of
+/// a row, which only unaligned pointers do, extends the row. Every row is followed by a row of
+/// class `undef_mask` showing which bytes are defined, which is hidden unless
+/// `Config::show_undef_mask` is set. The cells of every field in `annotations` are underlined and
+/// titled with its name, and without `max_rows` the fields are listed below the rows.
+fn hexdump(
+    ecx: &InterpretCx,
+    alloc_id: AllocId,
+    alloc: &Allocation<Tag, Stacks>,
+    start: u64,
+    end: u64,
+    max_rows: Option<u64>,
+    annotations: &Annotations,
+) -> String {
     use std::fmt::Write;
-    let end = size
-        .map(|s| s + ptr.offset.bytes())
-        .unwrap_or(alloc.bytes.len() as u64);
-    let mut s = String::new();
-    let mut i = ptr.offset.bytes();
+    let ptr_size = ecx.tcx.data_layout.pointer_size.bytes();
+    let mut s = "<span class=\"hexdump\">".to_string();
+    let fields = annotations.fields;
+    // The first field not ending before the current byte, and the field whose span is open
+    let mut next_field = 0;
+    let mut open_field = None;
+    let mut i = start;
+    let mut rows = 0;
     while i < end {
-        if let Some((_tag, reloc)) = alloc.relocations.get(&Size::from_bytes(i)) {
-            i += ptr_size;
-            write!(&mut s,
-                "<a style=\"text-decoration: none\" href=\"/ptr/{alloc}/{offset}\">┠{nil:─<wdt$}┨</a>",
-                alloc = reloc,
-                offset = ptr.offset.bytes(),
-                nil = "",
-                wdt = (ptr_size * 2 - 2) as usize,
-            ).unwrap();
-        } else {
+        if max_rows == Some(rows) {
+            write!(&mut s, "<a href=\"/ptr/{}/{}\">view full allocation</a>\n", alloc_id.0, start).unwrap();
+            break;
+        }
+        rows += 1;
+        let row_start = i;
+        let row_end = (i + HEXDUMP_ROW).min(end);
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        while i < row_end {
+            while next_field < fields.len() && fields[next_field].end <= i {
+                next_field += 1;
+            }
+            let field = Some(next_field).filter(|&f| f < fields.len() && fields[f].start <= i);
+            if field != open_field {
+                if open_field.is_some() {
+                    hex.push_str("</span>");
+                }
+                if let Some(f) = field {
+                    hex.push_str(&field_span(f, &fields[f]));
+                }
+                open_field = field;
+            }
+            let highlighted = annotations.highlight.iter().any(|&(start, end)| start <= i && i < end);
+            let style = match (annotations.cursor == Some(i), highlighted) {
+                (true, _) => " style=\"outline: 1px solid red;\"",
+                (false, true) => " style=\"background: yellow;\"",
+                (false, false) => "",
+            };
+            if let Some((_tag, reloc)) = alloc.relocations.get(&Size::from_bytes(i)) {
+                // The offset into the target is stored in the bytes of the pointer
+                let target_offset = match alloc.read_ptr_sized(ecx, Pointer::new(alloc_id, Size::from_bytes(i)).with_tag(Tag::Untagged)) {
+                    Ok(ScalarMaybeUndef::Scalar(Scalar::Ptr(target))) => target.offset.bytes(),
+                    _ => 0,
+                };
+                // Vtable slots and stored function pointers point to functions
+                let title = match (ecx.memory().get_fn(Pointer::new(*reloc, Size::from_bytes(0)).with_tag(Tag::Untagged)), ecx.memory().get(*reloc)) {
+                    (Ok(instance), _) => format!("fn: {}", escape_html(&instance.to_string())),
+                    (Err(_), Ok(target)) => format!("alloc {} + {} (size {})", reloc.0, target_offset, target.bytes.len()),
+                    (Err(_), Err(_)) => format!("alloc {} + {} (freed)", reloc.0, target_offset),
+                };
+                // Two digits and a space per byte, without the last space and the brackets
+                let width = (ptr_size * 3 - 3) as usize;
+                let label = [
+                    format!("→ alloc {} + 0x{:x}", reloc.0, target_offset),
+                    format!("→{}+0x{:x}", reloc.0, target_offset),
+                    String::new(),
+                ]
+                .iter()
+                .find(|label| label.chars().count() <= width)
+                .cloned()
+                .unwrap();
+                write!(&mut hex,
+                    "<a style=\"text-decoration: none\" href=\"/ptr/{alloc}/{offset}\" title=\"{title}\" data-alloc=\"{this}\" data-offset=\"{i}\"{style}>┠{label}{nil:─<wdt$}┨</a> ",
+                    title = title,
+                    alloc = reloc.0,
+                    offset = target_offset,
+                    this = alloc_id.0,
+                    i = i,
+                    style = style,
+                    label = label,
+                    nil = "",
+                    wdt = width - label.chars().count(),
+                ).unwrap();
+                for _ in 0..ptr_size {
+                    ascii.push('▪');
+                }
+                i += ptr_size;
+                continue;
+            }
             if alloc
                 .undef_mask
                 .is_range_defined(Size::from_bytes(i), Size::from_bytes(i + 1))
                 .is_ok()
             {
-                write!(&mut s, "{:02x}", alloc.bytes[i as usize] as usize).unwrap();
+                let byte = alloc.bytes[i as usize];
+                write!(&mut hex, "<span data-alloc=\"{}\" data-offset=\"{}\"{}>{:02x}</span> ", alloc_id.0, i, style, byte).unwrap();
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    ascii.push_str(&escape_html(&(byte as char).to_string()));
+                } else {
+                    ascii.push('.');
+                }
             } else {
-                let ub_chars = [
-                    '∅', '∆', '∇', '∓', '∞', '⊙', '⊠', '⊘', '⊗', '⊛', '⊝',
-                    '⊡', '⊠',
-                ];
-                let c1 = (ptr.alloc_id.0 * 769 + i as u64 * 5689) as usize % ub_chars.len();
-                let c2 = (ptr.alloc_id.0 * 997 + i as u64 * 7193) as usize % ub_chars.len();
-                write!(&mut s, "<mark>{}{}</mark>", ub_chars[c1], ub_chars[c2]).unwrap();
+                let c1 = (alloc_id.0 * 769 + i as u64 * 5689) as usize % UNDEF_GLYPHS.len();
+                let c2 = (alloc_id.0 * 997 + i as u64 * 7193) as usize % UNDEF_GLYPHS.len();
+                write!(&mut hex, "<mark data-alloc=\"{}\" data-offset=\"{}\"{}>{}{}</mark> ", alloc_id.0, i, style, UNDEF_GLYPHS[c1], UNDEF_GLYPHS[c2]).unwrap();
+                ascii.push('·');
             }
             i += 1;
         }
+        if open_field.take().is_some() {
+            hex.push_str("</span>");
+        }
+        // Pad short rows so the ascii gutters line up
+        for _ in (i - row_start).min(HEXDUMP_ROW)..HEXDUMP_ROW {
+            hex.push_str("   ");
+        }
+        write!(&mut s, "{:08x}  {} {}\n", row_start, hex, ascii).unwrap();
+        let mask = (row_start..i)
+            .map(|j| if alloc.undef_mask.get(Size::from_bytes(j)) { "██ " } else { "·· " })
+            .collect::<String>();
+        write!(&mut s, "<span class=\"undef_mask\">{:8}  {}\n</span>", "", mask).unwrap();
     }
+    let defined = (start..i).filter(|&j| alloc.undef_mask.get(Size::from_bytes(j))).count();
+    write!(&mut s, "<span class=\"undef_mask\">{}/{} bytes defined\n</span>", defined, i - start).unwrap();
+    // The inline hexdumps of the locals table only have the titles, to stay small
+    let listed = if max_rows.is_some() { &[][..] } else { fields };
+    let shown = listed
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| field.start < i && field.end > start)
+        .map(|(f, field)| {
+            format!(
+                "{}{}..{} {}</span>",
+                field_span(f, field),
+                field.start,
+                field.end,
+                escape_html(field.name.as_ref().map_or("padding", |name| &name[..])),
+            )
+        })
+        .collect::<Vec<_>>();
+    if !shown.is_empty() {
+        write!(&mut s, "{}\n", shown.join(", ")).unwrap();
+    }
+    s.push_str("</span>");
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use rustc::ty::layout::Align;
+
+    use super::*;
+    use crate::testing::with_program;
+    use crate::PrirodaContext;
+
+    const EMPTY_MAIN: &str = "fn main() {}\n";
+
+    #[test]
+    fn print_raw_scalars() {
+        let printed = with_program(EMPTY_MAIN, |pcx| {
+            [
+                (0x7f, 1),
+                (0xbeef, 2),
+                (0x1, 4),
+                (0xdead_beef_0000_0001, 8),
+                (::std::u128::MAX, 16),
+                (1 << 127, 16),
+                (0, 0),
+            ]
+            .iter()
+            .map(|&(data, size)| print_scalar(&pcx.ecx, Scalar::Raw { data, size }))
+            .collect::<Vec<_>>()
+        });
+        assert_eq!(printed, [
+            "0x7F",
+            "0xBEEF",
+            "0x00000001",
+            "0xDEADBEEF00000001",
+            "0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+            "0x80000000000000000000000000000000",
+            "&lt;zst&gt;",
+        ]);
+    }
+
+    #[test]
+    fn pp_float_special_values() {
+        let finite = || "finite".to_string();
+        assert_eq!(pp_float(0x7f80_0000, 32, 23, finite), "inf");
+        assert_eq!(pp_float(0xff80_0000, 32, 23, finite), "-inf");
+        assert_eq!(pp_float(0x7fc0_0001, 32, 23, finite), "NaN (payload 0x400001)");
+        assert_eq!(pp_float(0x8000_0000, 32, 23, finite), "-0.0");
+        assert_eq!(pp_float(0, 32, 23, finite), "0.0");
+        assert_eq!(pp_float(u128::from(1.5f32.to_bits()), 32, 23, finite), "finite");
+        assert_eq!(pp_float(0xfff0_0000_0000_0000, 64, 52, finite), "-inf");
+        assert_eq!(pp_float(0x7ff0_0000_0000_0001, 64, 52, finite), "NaN (payload 0x1)");
+        assert_eq!(pp_float(0x8000_0000_0000_0000, 64, 52, finite), "-0.0");
+        let bits = u128::from(0.1f64.to_bits());
+        assert_eq!(pp_float(bits, 64, 52, || format!("{:?}", f64::from_bits(bits as u64))), "0.1");
+    }
+
+    #[test]
+    fn escape_markup() {
+        assert_eq!(
+            crate::render::escape_html("<b class=\"x\">Tom & 'Jerry'"),
+            "&lt;b class=&quot;x&quot;&gt;Tom &amp; &#x27;Jerry&#x27;",
+        );
+    }
+
+    #[test]
+    fn pp_str_escapes_markup() {
+        let source = r#"
+fn show(_s: &str) {}
+
+fn main() {
+    show("<script>alert('hi')</script> & more");
+}
+"#;
+        let text = with_program(source, |pcx| {
+            crate::testing::command(pcx, "continue-to-fn show");
+            let frame = pcx.ecx.frame();
+            render_local(&pcx.ecx, frame, mir::Local::new(1), Limits::UNLIMITED).unwrap().3
+        });
+        assert!(text.contains("&lt;script&gt;alert(&#x27;hi&#x27;)&lt;&#x2F;script&gt; &amp; more"), "{}", text);
+        assert!(!text.contains("<script>"), "{}", text);
+    }
+
+    #[test]
+    fn local_errors_by_kind() {
+        match LocalError::from(InterpErrorInfo::from(InterpError::DeadLocal)) {
+            LocalError::Dead => {}
+            _ => panic!("DeadLocal isn't a dead local"),
+        }
+        match LocalError::from(InterpErrorInfo::from(InterpError::ReadUndefBytes(Size::ZERO))) {
+            LocalError::Uninit => {}
+            _ => panic!("ReadUndefBytes isn't an uninitialized local"),
+        }
+        match LocalError::from(InterpErrorInfo::from(InterpError::ReadPointerAsBytes)) {
+            LocalError::Interp(err) => assert!(err.contains("ReadPointerAsBytes"), "{}", err),
+            _ => panic!("ReadPointerAsBytes isn't reported"),
+        }
+    }
+
+    #[test]
+    fn pp_error_by_kind() {
+        let (undef, err) = with_program(EMPTY_MAIN, |pcx| {
+            (
+                pp_error(&pcx.ecx, None, InterpError::ReadUndefBytes(Size::ZERO).into()),
+                pp_error(&pcx.ecx, None, InterpError::ReadPointerAsBytes.into()),
+            )
+        });
+        assert!(undef.contains("&lt;undef&gt;"), "{}", undef);
+        assert!(err.contains("&lt;err&gt;") && err.contains("ReadPointerAsBytes"), "{}", err);
+    }
+
+    #[test]
+    fn dead_local_info() {
+        let source = r#"
+fn main() {
+    let late = [7u8; 3];
+    drop(late);
+}
+"#;
+        let (value, style) = with_program(source, |pcx| {
+            let frame = pcx.ecx.frame();
+            let (late, _) = frame
+                .mir
+                .local_decls
+                .iter_enumerated()
+                .find(|(_, decl)| decl.name.map_or(false, |name| name.as_str() == "late"))
+                .unwrap();
+            let info = local_info(&pcx.ecx, frame, late, Limits::UNLIMITED);
+            (info.value, info.style)
+        });
+        assert_eq!(value, "&lt;dead&gt;");
+        assert_eq!(style, "color: gray;");
+    }
+
+    /// A new allocation of `bytes`, all defined.
+    fn test_alloc(pcx: &mut PrirodaContext, bytes: &[u8]) -> AllocId {
+        let size = Size::from_bytes(bytes.len() as u64);
+        let ptr = pcx.ecx.memory_mut().allocate(size, Align::from_bytes(1).unwrap(), MemoryKind::Stack);
+        let alloc = pcx.ecx.memory_mut().get_mut(ptr.alloc_id).unwrap();
+        alloc.bytes.copy_from_slice(bytes);
+        alloc.undef_mask.set_range(Size::from_bytes(0), size, true);
+        ptr.alloc_id
+    }
+
+    #[test]
+    fn print_alloc_clamps_to_allocation() {
+        let (dump, past_end, inline) = with_program(EMPTY_MAIN, |pcx| {
+            let id = test_alloc(pcx, &[0x11, 0x22, 0x33, 0x44]);
+            let alloc = pcx.ecx.memory().get(id).unwrap();
+            let ptr = Pointer::new(id, Size::from_bytes(0)).with_tag(Tag::Untagged);
+            (
+                print_alloc(&pcx.ecx, id, alloc, 0, 1 << 20, &Annotations::default()),
+                print_alloc(&pcx.ecx, id, alloc, 8, 16, &Annotations::default()),
+                print_alloc_inline(&pcx.ecx, ptr, alloc, 16),
+            )
+        });
+        assert!(dump.contains(">44</span>"), "{}", dump);
+        assert!(dump.contains("4/4 bytes defined"), "{}", dump);
+        assert!(past_end.contains("0/0 bytes defined"), "{}", past_end);
+        assert!(inline.contains("(12 bytes past end of allocation)"), "{}", inline);
+    }
+
+    #[test]
+    fn relocation_labels() {
+        let (target, dump) = with_program(EMPTY_MAIN, |pcx| {
+            let target = test_alloc(pcx, &[0; 16]);
+            let source = test_alloc(pcx, &[0; 32]);
+            let ptr_size = pcx.ecx.tcx.data_layout.pointer_size.bytes() as usize;
+            let alloc = pcx.ecx.memory_mut().get_mut(source).unwrap();
+            // The offset into the target is stored in the bytes of the pointer
+            alloc.bytes[8..8 + ptr_size].copy_from_slice(&4u64.to_le_bytes()[..ptr_size]);
+            alloc.relocations.insert(Size::from_bytes(8), (Tag::Untagged, target));
+            let alloc = pcx.ecx.memory().get(source).unwrap();
+            (target, print_alloc(&pcx.ecx, source, alloc, 0, 32, &Annotations::default()))
+        });
+        assert!(dump.contains(&format!("href=\"/ptr/{}/4\"", target.0)), "{}", dump);
+        assert!(dump.contains(&format!("title=\"alloc {} + 4 (size 16)\"", target.0)), "{}", dump);
+        assert!(dump.contains(&format!("→ alloc {} + 0x4", target.0)), "{}", dump);
+        assert!(dump.contains("data-offset=\"8\""), "{}", dump);
+    }
+}
@@ -1,18 +1,37 @@
+pub mod alloc_graph;
+pub mod backtrace;
+pub mod borrows;
+pub mod cache;
+pub mod cast;
+pub mod cfg;
 mod graphviz;
 pub mod locals;
-mod source;
+pub mod source;
+pub mod terminator;
 
-use rustc::hir::map::definitions::DefPathData;
+use std::borrow::Cow;
+
+use rustc::mir;
 use rustc::ty::layout::Size;
 
-use horrorshow::{Raw, Template};
+use rustc_data_structures::indexed_vec::Idx;
+
+use horrorshow::{Raw, RenderBox, Template};
+use rocket::http::RawStr;
 use rocket::response::content::Html;
 
-use miri::{AllocId, Frame, Pointer};
+use miri::{AllocId, Pointer};
 
-use crate::step::Breakpoint;
+use crate::render::locals::{IntFormat, LocalsShown};
+use crate::step::{Breakpoint, StopReason};
 use crate::PrirodaContext;
 
+/// Escape `s` for use in HTML. All data coming from the interpreted program or from miri, like string
+/// contents, paths, types and errors, has to pass through this before being inserted with `Raw`.
+pub fn escape_html(s: &str) -> Cow<str> {
+    RawStr::from_str(s).html_escape()
+}
+
 pub fn template(pcx: &PrirodaContext, title: String, t: impl Template) -> Html<String> {
     let mut buf = String::new();
     (html! {
@@ -22,6 +41,9 @@ pub fn template(pcx: &PrirodaContext, title: String, t: impl Template) -> Html<S
                 meta(charset = "UTF-8") {}
                 script(src="/resources/svg-pan-zoom.js") {}
                 script(src="/resources/zoom_mir.js") {}
+                @ if !pcx.config.show_undef_mask {
+                    style { : ".undef_mask { display: none; }" }
+                }
                 : Raw(refresh_script(pcx))
             }
             body(onload="enable_mir_mousewheel()") {
@@ -59,6 +81,79 @@ pub fn refresh_script(pcx: &PrirodaContext) -> String {
     }
 }
 
+/// The page shown while a command runs in the background. It can't use `template`, as the
+/// interpreter thread is busy.
+pub fn render_running(steps: u64) -> Html<String> {
+    let mut buf = String::new();
+    (html! {
+        html {
+            head {
+                title { : "Running" }
+                meta(charset = "UTF-8") {}
+                meta(http-equiv="refresh", content="1") {}
+            }
+            body {
+                p { : format!("Running, {} steps executed so far", steps) }
+                a(href="/step/cancel") { : "Cancel" }
+            }
+        }
+    })
+    .write_to_string(&mut buf)
+    .unwrap();
+    Html(buf)
+}
+
+/// Why the last stepping command stopped. Breakpoints link to their row in the breakpoint
+/// list, errors are shown in a box and the end of the program with a summary of its leaks. The
+/// other reasons are already part of the message.
+fn render_stop_reason(pcx: &PrirodaContext) -> Box<dyn RenderBox + '_> {
+    match &pcx.last_stop {
+        Some(reason @ StopReason::Breakpoint(_)) => {
+            let id = pcx.config.bptree.id_at(&pcx.ecx);
+            box_html! {
+                p(class="stop_reason") {
+                    : "Stopped at ";
+                    @ if let Some(id) = id {
+                        a(href=format!("#bp{}", id)) { : format!("breakpoint {}", id) }
+                    } else {
+                        : reason.to_string()
+                    }
+                }
+            }
+        }
+        Some(StopReason::Error(error)) => {
+            let borrow_error = borrows::parse_error(error).is_some();
+            box_html! {
+                div(class="stop_reason error") {
+                    pre { : error }
+                    @ if borrow_error {
+                        a(href="/borrow_error") { : "Borrow stacks of the failed access" }
+                    }
+                }
+            }
+        }
+        Some(StopReason::Finished) => {
+            let leaked = crate::api::leaks(pcx);
+            let heap = pcx.traces.heap();
+            let summary = format!(
+                "{} allocations ({} bytes) leaked, {} of {} freed, at most {} bytes live. ",
+                leaked.len(),
+                leaked.iter().map(|alloc| alloc.size).sum::<u64>(),
+                heap.freed,
+                heap.made,
+                heap.peak_live_bytes,
+            );
+            box_html! {
+                p(class="stop_reason") {
+                    : summary;
+                    a(href="/postmortem#leaks") { : "Leak report" }
+                }
+            }
+        }
+        _ => box_html! {},
+    }
+}
+
 pub fn render_main_window(
     pcx: &PrirodaContext,
     display_frame: Option<usize>,
@@ -71,41 +166,51 @@ pub fn render_main_window(
     let frame = display_frame
         .and_then(|frame| pcx.ecx.stack().get(frame))
         .or_else(|| pcx.ecx.stack().last());
-    let stack: Vec<(String, String, String)> = pcx
-        .ecx
-        .stack()
-        .iter()
-        .map(|&Frame { instance, span, .. }| {
-            let name = if pcx
-                .ecx
-                .tcx
-                .def_key(instance.def_id())
-                .disambiguated_data
-                .data
-                == DefPathData::ClosureExpr
-            {
-                "inside call to closure".to_string()
-            } else {
-                instance.to_string()
-            };
-            let span = self::source::pretty_src_path(span);
-            (name, span, format!("{:?}", instance.def_id()))
-        })
-        .collect();
-    let rendered_breakpoints: Vec<String> = pcx
+    let stack_len = pcx.ecx.stack().len();
+    let display_idx = display_frame.unwrap_or(stack_len - 1);
+    //                             id     bp      hits condition preview
+    let rendered_breakpoints: Vec<(usize, String, u64, String, String)> = pcx
         .config
         .bptree
-        .iter()
-        .map(|&Breakpoint(def_id, bb, stmt)| format!("{:?}@{}:{}", def_id, bb.index(), stmt))
+        .iter_info()
+        .map(|(&Breakpoint(def_id, bb, stmt), info)| {
+            let (condition, preview) = match &info.condition {
+                Some(condition) => (
+                    condition.source().to_string(),
+                    pcx.config.bptree.with_session(&pcx.ecx, *pcx.step_count, |session| {
+                        condition.preview(session)
+                    }),
+                ),
+                None => (String::new(), String::new()),
+            };
+            (info.id, format!("{:?}@{}:{}", def_id, bb.index(), stmt), info.hits, condition, preview)
+        })
         .collect();
     let rendered_locals = frame
-        .map(|frame| locals::render_locals(&pcx.ecx, frame))
+        .map(|frame| locals::render_locals(
+            &pcx.ecx,
+            frame,
+            pcx.config.locals_settings(),
+            pcx.config.locals_filter,
+            &pcx.locals_history,
+            *pcx.step_count,
+            &pcx.locals_cache,
+        ))
         .unwrap_or_else(String::new);
 
     let rendered_source = source::render_source(pcx.ecx.tcx.tcx, frame);
+    let stopped_with_error = match pcx.last_stop {
+        Some(StopReason::Error(_)) => true,
+        _ => false,
+    };
 
     let mir_graph = frame.map(|frame| {
-        graphviz::render_html(frame, pcx.config.bptree.for_def_id(frame.instance.def_id()))
+        graphviz::render_html(
+            pcx.ecx.tcx.tcx,
+            frame,
+            pcx.config.bptree.for_def_id(frame.instance.def_id()),
+            is_active_stack_frame,
+        )
     });
 
     let filename = pcx
@@ -124,19 +229,56 @@ pub fn render_main_window(
                 div(id="commands") {
                     @ if is_active_stack_frame {
                         a(href="/step/single") { div(title="Execute next MIR statement/terminator") { : "Step" } }
+                        a(href="/step/toggle_drops") {
+                            div(title="Whether Step runs drop glue completely instead of stepping into it") {
+                                : if pcx.config.step_over_drops { "Step over drops: on" } else { "Step over drops: off" }
+                            }
+                        }
+                        a(href="/step/toggle_strict_writes") {
+                            div(title="Whether stepping stops before statements assigning to immutable allocations") {
+                                : if pcx.config.strict_writes { "Strict writes: on" } else { "Strict writes: off" }
+                            }
+                        }
+                        a(href="/step/toggle_track_writes") {
+                            div(title="Whether stepping remembers which allocations were written, for the allocations page") {
+                                : if pcx.config.track_writes { "Track writes: on" } else { "Track writes: off" }
+                            }
+                        }
                         a(href="/step/next") { div(title="Run until after the next MIR statement/terminator") { : "Next" } }
+                        a(href="/step/block") { div(title="Run until the terminator of the current basic block") { : "Step block" } }
+                        a(href="/step/until") { div(title="Run until the current loop is left") { : "Until" } }
                         a(href="/step/return") { div(title="Run until the function returns") { : "Return" } }
                         a(href="/step/single_back") { div(title="Execute previous MIR statement/terminator (restarts and steps till one stmt before the current stmt)") { : "Step back (slow)" } }
                         a(href="/step/continue") { div(title="Run until termination or breakpoint") { : "Continue" } }
+                        a(href="/step/continue_to_output") { div(title="Run until the program writes to stdout or stderr") { : "Continue to output" } }
+                        a(href="/step/reverse_continue") { div(title="Go back to the last breakpoint hit before the current statement (restarts and replays twice)") { : "Reverse continue (slow)" } }
+                        a(href="/step/undo") { div(title="Go back to the state before the last command that moved the execution (restarts and replays)") { : "Undo (slow)" } }
                         a(href="/step/restart") { div(title="Abort execution and restart") { : "Restart" } }
                         a(href="/breakpoints/add_here") { div(title="Add breakpoint at current location") { : "Add breakpoint here"} }
                         a(href="/breakpoints/remove_all") { div(title="Remove all breakpoints") { : "Remove all breakpoints"} }
                     } else {
-                        a(href="/") { div(title="Go to active stack frame") { : "Go back to active stack frame" } }
+                        a(href=format!("/frame/{}/select", stack_len - 1)) { div(title="Go to active stack frame") { : "Go back to active stack frame" } }
                     }
                 }
                 div(id="messages") {
-                    p { : message }
+                    @ if !is_active_stack_frame {
+                        p(class="selected_frame") {
+                            : format!(
+                                "Showing frame {} of {}, not the innermost one. Stepping goes back to the innermost frame.",
+                                display_idx,
+                                stack_len,
+                            )
+                        }
+                    }
+                    // Errors are long, they are only shown in the box of `render_stop_reason`
+                    @ if !message.is_empty() && !stopped_with_error {
+                        p { : message }
+                    }
+                    : render_stop_reason(pcx);
+                    : terminator::render_pending_terminator(pcx, display_idx);
+                    @ if let Some(error) = pcx.events.error() {
+                        p(style="color: red;") { : error }
+                    }
                 }
                 div(id="mir") {
                     : Raw(mir_graph.unwrap_or_else(|| "no current function".to_string()))
@@ -145,92 +287,857 @@ pub fn render_main_window(
             div(id="right") {
                 div {
                     : format!("Step count: {}", pcx.step_count);
+                    : " ";
+                    a(href="/mutations") { : "Mutations" }
+                    : " ";
+                    a(href="/postmortem") { : "Post-mortem" }
+                    : " ";
+                    a(href="/allocations") { : "Allocations" }
+                    : " ";
+                    a(href="/memdiff") { : "Memory diff" }
+                    : " ";
+                    a(href="/timeline") { : "Memory timeline" }
+                    : " ";
+                    a(href="/search") { : "Search memory" }
+                    : " ";
+                    a(href=format!("/alloc_graph?frame={}", display_idx)) { : "Pointer graph" }
+                    : " ";
+                    a(href="/trace") { : "Trace" }
+                    : " ";
+                    a(href="/heatmap") { : "Execution counts" }
+                    : " ";
+                    a(href="/mir") { : "MIR listing" }
+                    : " ";
+                    a(href=format!("/source?frame={}", display_idx)) { : "Source" }
+                    form(action="/step/limit") {
+                        : "Step limit: ";
+                        input(name="n", size="10", value=pcx.config.step_limit.map(|limit| limit.to_string()).unwrap_or_else(|| "unlimited".to_string()));
+                    }
                 }
+                : panel_toggle(pcx, "stack", "Stack");
+                @ if panel_visible(pcx, "stack") {
                 div(id="stack") {
+                    : backtrace::render_backtrace(pcx, display_idx)
+                }
+                }
+                : panel_toggle(pcx, "breakpoints", "Breakpoints");
+                @ if panel_visible(pcx, "breakpoints") {
+                div(id="breakpoints") {
                     table(border="1") {
-                        @ for (i, &(ref s, ref span, ref def_id)) in stack.iter().enumerate().rev() {
-                            tr {
-                                @ if i == display_frame.unwrap_or(stack.len() - 1) { td { : Raw("&#8594;") } } else { td; }
-                                td { : s }
-                                td { : span }
-                                td { : def_id }
-                                @ if i == display_frame.unwrap_or(stack.len() - 1) { td; } else { td { a(href=format!("/frame/{}", i)) { : "View" } } }
+                        @ for (id, bp, hits, condition, preview) in rendered_breakpoints {
+                            tr(id=format!("bp{}", id)) {
+                                td { : id.to_string() }
+                                td { : &bp }
+                                td(title="hits") { : hits.to_string() }
+                                td {
+                                    form(action=format!("/breakpoints/condition/{}", bp)) {
+                                        input(name="condition", value=condition, placeholder="$step > 1000 && $hits(1) > 0");
+                                    }
+                                    : preview
+                                }
+                                td { a(href=format!("/breakpoints/remove/{}", bp)) { : "remove" } }
                             }
                         }
                     }
                 }
-                div(id="breakpoints") {
-                    : "Breakpoints: "; br;
+                }
+                form(action="/step/continue_to_fn") {
+                    input(name="pattern", placeholder="continue until a function matching this is called");
+                }
+                form(action="/const") {
+                    input(name="path", placeholder="const path, e.g. MyType::MAX_LEN");
+                }
+                : panel_toggle(pcx, "bookmarks", "Bookmarks");
+                @ if panel_visible(pcx, "bookmarks") {
+                div(id="bookmarks") {
+                    : crate::bookmarks::render_bookmarks(pcx)
+                }
+                }
+                : panel_toggle(pcx, "skip", "Functions not stepped into");
+                @ if panel_visible(pcx, "skip") {
+                div(id="skip") {
                     table(border="1") {
-                        @ for bp in rendered_breakpoints {
+                        @ for pattern in &pcx.config.skip {
                             tr {
-                                td { : &bp }
-                                td { a(href=format!("/breakpoints/remove/{}", bp)) { : "remove" } }
+                                td { : pattern }
+                                td { a(href=format!("/skip/remove?pattern={}", rocket::http::uri::Uri::percent_encode(pattern))) { : "remove" } }
                             }
                         }
                     }
+                    form(action="/skip/add") {
+                        input(name="pattern", placeholder="path::substring");
+                    }
+                }
                 }
+                : panel_toggle(pcx, "locals", "Locals");
+                @ if panel_visible(pcx, "locals") {
                 div(id="locals") {
+                    a(href=format!("/step/auto_deref?n={}", (pcx.config.auto_deref + 1) % 3), title="How many levels of references, raw pointers and boxes are rendered as their pointee") {
+                        : format!("Auto-deref: {}", pcx.config.auto_deref)
+                    }
+                    : " ";
+                    a(href="/step/toggle_layout", title="Show the size and alignment of locals, the offsets of fields and where enums store their discriminant") {
+                        : if pcx.config.show_layout { "Layout: on" } else { "Layout: off" }
+                    }
+                    : " ";
+                    a(href="/step/toggle_undef_mask", title="Show which bytes of the hexdumps are defined in a row below every row of bytes") {
+                        : if pcx.config.show_undef_mask { "Undef mask: on" } else { "Undef mask: off" }
+                    }
+                    : " Integers:";
+                    @ for format in &[IntFormat::Dec, IntFormat::Hex, IntFormat::Both] {
+                        : " ";
+                        @ if *format == pcx.config.int_format {
+                            b { : format.name() }
+                        } else {
+                            a(href=int_format_url(*format, display_frame)) { : format.name() }
+                        }
+                    }
+                    br;
+                    : render_locals_filter(pcx, display_frame);
                     : Raw(rendered_locals)
                 }
+                }
+                : panel_toggle(pcx, "source", "Source");
+                @ if panel_visible(pcx, "source") {
                 div(id="source") {
                     : rendered_source
                 }
+                }
+                form(method="post", action="/layout/reset") {
+                    button(type="submit") { : "Reset layout" }
+                }
+            }
+        },
+    )
+}
+
+/// The panels of the main window which can be collapsed.
+pub const PANELS: &[&str] = &["stack", "breakpoints", "bookmarks", "skip", "locals", "source"];
+
+/// Switches to `format`, returning to the frame `display_frame` afterwards.
+fn int_format_url(format: IntFormat, display_frame: Option<usize>) -> String {
+    match display_frame {
+        Some(frame) => format!("/step/int_format?format={}&frame={}", format.name(), frame),
+        None => format!("/step/int_format?format={}", format.name()),
+    }
+}
+
+/// The links changing `Config::locals_filter`, returning to the frame `display_frame`.
+fn render_locals_filter(pcx: &PrirodaContext, display_frame: Option<usize>) -> Box<dyn RenderBox + '_> {
+    let filter = pcx.config.locals_filter;
+    let url = move |param: &str| match display_frame {
+        Some(frame) => format!("/layout/locals?{}&frame={}", param, frame),
+        None => format!("/layout/locals?{}", param),
+    };
+    box_html! {
+        a(href=url(&format!("temporaries={}", !filter.hide_temporaries))) {
+            : if filter.hide_temporaries { "Temporaries: hidden" } else { "Temporaries: shown" }
+        }
+        : " ";
+        a(href=url(&format!("dead={}", !filter.hide_dead))) {
+            : if filter.hide_dead { "Dead locals: hidden" } else { "Dead locals: shown" }
+        }
+        : " Show:";
+        @ for shown in &[LocalsShown::All, LocalsShown::Arguments, LocalsShown::Variables] {
+            : " ";
+            @ if *shown == filter.show {
+                b { : shown.name() }
+            } else {
+                a(href=url(&format!("show={}", shown.name()))) { : shown.name() }
+            }
+        }
+    }
+}
+
+fn panel_visible(pcx: &PrirodaContext, panel: &str) -> bool {
+    !pcx.config.hidden_panels.contains(panel)
+}
+
+/// A button collapsing or expanding `panel`. The state is kept in the session config, so it
+/// survives reloads.
+fn panel_toggle<'a>(pcx: &PrirodaContext, panel: &'a str, title: &'a str) -> impl horrorshow::RenderOnce + 'a {
+    let visible = panel_visible(pcx, panel);
+    owned_html! {
+        form(method="post", action=format!("/layout/toggle/{}", panel), class="panel_toggle") {
+            button(type="submit", title=if visible { "Collapse" } else { "Expand" }) {
+                : format!("{} {}", if visible { "▾" } else { "▸" }, title)
+            }
+        }
+    }
+}
+
+/// The inclusive ranges of runs of at least `threshold` consecutive equal elements of `ids`.
+/// A threshold of 0 disables condensing.
+pub fn recursion_runs<T: PartialEq>(ids: &[T], threshold: usize) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    if threshold == 0 {
+        return runs;
+    }
+    let mut start = 0;
+    for i in 1..=ids.len() {
+        if i == ids.len() || ids[i] != ids[start] {
+            if i - start >= threshold.max(2) {
+                runs.push((start, i - 1));
+            }
+            start = i;
+        }
+    }
+    runs
+}
+
+/// The rows of the stack table for the frames `start..=end`, innermost first.
+pub fn render_stack_run(pcx: &PrirodaContext, start: usize, end: usize) -> Html<String> {
+    let frames = backtrace::frames(pcx);
+    let end = end.min(frames.len().saturating_sub(1));
+    let mut buf = String::new();
+    (html! {
+        table {
+            @ for i in (start..=end).rev() {
+                tr { : backtrace::render_frame(&frames, i, usize::max_value()) }
+            }
+        }
+    })
+    .write_to_string(&mut buf)
+    .unwrap();
+    Html(buf)
+}
+
+/// The number of bytes of a string shown on one page by `render_string_chunk`.
+const STRING_CHUNK_BYTES: u64 = 64 * 1024;
+
+/// The bytes `offset..` of the string in local `local` of frame `frame`, with links to the
+/// neighbouring chunks.
+pub fn render_string_chunk(pcx: &PrirodaContext, frame: usize, local: usize, offset: u64) -> Html<String> {
+    let stack_frame = match pcx.ecx.stack().get(frame) {
+        Some(stack_frame) => stack_frame,
+        None => return Html(format!("No frame {}, the stack has {} frames", frame, pcx.ecx.stack().len())),
+    };
+    let local = match locals::frame_local(stack_frame, local) {
+        Ok(local) => local,
+        Err(err) => return Html(escape_html(&err).into_owned()),
+    };
+    let (offset, chunk, len) = match locals::string_chunk(&pcx.ecx, stack_frame, local, offset, STRING_CHUNK_BYTES) {
+        Ok(chunk) => chunk,
+        Err(err) => return Html(escape_html(&err).into_owned()),
+    };
+    let end = offset + chunk.len() as u64;
+    let url = |offset: u64| format!("/frame/{}/local/{}/full/chunk?offset={}", frame, local.index(), offset);
+    template(
+        pcx,
+        format!("_{} bytes {}..{}", local.index(), offset, end),
+        html! {
+            p {
+                : format!("Bytes {}..{} of {} ", offset, end, len);
+                @ if offset > 0 {
+                    a(href=url(offset.saturating_sub(STRING_CHUNK_BYTES))) { : "previous" }
+                    : " ";
+                }
+                @ if end < len {
+                    a(href=url(end)) { : "next" }
+                }
+            }
+            pre { : chunk }
+        },
+    )
+}
+
+/// The layout of the type of local `local` of frame `frame`, with the layouts of its fields and
+/// variants.
+pub fn render_local_layout(pcx: &PrirodaContext, frame: usize, local: usize) -> Html<String> {
+    let stack_frame = match pcx.ecx.stack().get(frame) {
+        Some(stack_frame) => stack_frame,
+        None => return Html(format!("No frame {}, the stack has {} frames", frame, pcx.ecx.stack().len())),
+    };
+    let local = match locals::frame_local(stack_frame, local) {
+        Ok(local) => local,
+        Err(err) => return Html(escape_html(&err).into_owned()),
+    };
+    let (ty, layout) = match locals::render_type_layout(&pcx.ecx, stack_frame, local) {
+        Ok(layout) => layout,
+        Err(err) => return Html(escape_html(&err).into_owned()),
+    };
+    template(
+        pcx,
+        format!("Layout of {}", ty),
+        html! {
+            h2 { : ty.to_string() }
+            p { : format!("_{} of {}", local.index(), stack_frame.instance) }
+            div(style="font-family: monospace") {
+                : ty.to_string();
+                : Raw(layout)
             }
         },
     )
 }
 
-pub fn render_reverse_ptr(pcx: &PrirodaContext, alloc_id: u64) -> Html<String> {
-    let allocs: Vec<_> = pcx
+/// The MIR of the function function pointers to `alloc_id` point to.
+pub fn render_fn(pcx: &PrirodaContext, alloc_id: AllocId) -> Html<String> {
+    let ptr = Pointer::new(alloc_id, Size::from_bytes(0)).with_tag(miri::Tag::Untagged);
+    let instance = match pcx.ecx.memory().get_fn(ptr) {
+        Ok(instance) => instance,
+        Err(_) => return Html(format!("Allocation {} is not a function", alloc_id)),
+    };
+    let graph = match pcx.ecx.load_mir(instance.def) {
+        Ok(mir) => graphviz::render_fn_html(mir, pcx.config.bptree.for_def_id(instance.def_id())),
+        Err(_) => "This function has no MIR, it is a foreign function or emulated by miri".to_string(),
+    };
+    template(
+        pcx,
+        format!("fn {}", instance),
+        html! {
+            h2 { : instance.to_string() }
+            a(href=crate::listing::url(instance.def_id())) { : "MIR listing" }
+            div(id="mir") { : Raw(graph) }
+        },
+    )
+}
+
+/// How many referrers `render_reverse_ptr` shows per page.
+const REFERRERS_PER_PAGE: usize = 200;
+
+/// The pointers to `alloc_id` in the relocations of all allocations and in the immediate locals
+/// of the stack, split into pages of `REFERRERS_PER_PAGE`.
+pub fn render_reverse_ptr(pcx: &PrirodaContext, alloc_id: u64, page: usize) -> Html<String> {
+    let target = AllocId(alloc_id);
+    let mut referrers: Vec<(AllocId, u64)> = pcx
         .ecx
         .memory()
-        .alloc_map().iter(|values| values.filter_map(|(&id, (_kind, alloc))| {
+        .alloc_map().iter(|values| values.flat_map(|(&id, (_kind, alloc))| {
             alloc
                 .relocations
-                .values()
-                .find(|&&(_tag, reloc)| reloc == id)
-                .map(|_| id)
+                .iter()
+                .filter(|&(_offset, &(_tag, reloc))| reloc == target)
+                .map(|(offset, _)| (id, offset.bytes()))
+                .collect::<Vec<_>>()
         }).collect());
+    referrers.sort();
+    let locals = locals::referring_locals(&pcx.ecx, target);
+    let pages = (referrers.len() + REFERRERS_PER_PAGE - 1) / REFERRERS_PER_PAGE;
+    let shown = referrers
+        .iter()
+        .skip(page * REFERRERS_PER_PAGE)
+        .take(REFERRERS_PER_PAGE)
+        .map(|&(id, offset)| {
+            // Pointers stored in stack allocations are held by the locals backed by them
+            let backing = locals::backing_locals(&pcx.ecx, id)
+                .into_iter()
+                .map(|(frame, local)| local_description(pcx, frame, local))
+                .collect::<Vec<_>>();
+            (id, offset, backing)
+        })
+        .collect::<Vec<_>>();
     template(
         pcx,
-        format!("Allocations with pointers to Allocation {}", alloc_id),
+        format!("Pointers to allocation {}", alloc_id),
         html!{
-            @for id in allocs {
-                a(href=format!("/ptr/{}", id)) { : format!("Allocation {}", id) }
+            a(href=format!("/ptr/{}/0", alloc_id)) { : format!("Back to allocation {}", alloc_id) }
+            p { : format!("{} pointers in memory, {} in locals", referrers.len(), locals.len()) }
+            @ for &(frame, local) in &locals {
+                a(href=format!("/frame/{}", frame)) { : local_description(pcx, frame, local) }
+                br;
+            }
+            @ if pages > 1 {
+                p {
+                    @ for i in 0..pages {
+                        @ if i == page {
+                            : format!("{} ", i + 1);
+                        } else {
+                            a(href=format!("/reverse_ptr/{}?page={}", alloc_id, i)) { : (i + 1).to_string() }
+                            : " ";
+                        }
+                    }
+                }
+            }
+            @ for (id, offset, backing) in shown {
+                a(href=format!("/ptr/{}/{}", id.0, offset)) { : format!("alloc {} at offset 0x{:x}", id.0, offset) }
+                @ for local in backing {
+                    : format!(", {}", local);
+                }
                 br;
             }
         },
     )
 }
 
-pub fn render_ptr_memory(pcx: &PrirodaContext, alloc_id: AllocId, offset: u64) -> Html<String> {
-    let (mem, offset, rest) = if let Ok((_, mem, bytes)) = locals::print_ptr(
-        &pcx.ecx,
-        Pointer::new(alloc_id, Size::from_bytes(offset)).with_tag(miri::Tag::Untagged).into(),
-        None,
-    ) {
-        if bytes * 2 > offset {
-            (mem, offset, (bytes * 2 - offset - 1) as usize)
-        } else if bytes * 2 == 0 && offset == 0 {
-            (mem, 0, 0)
-        } else {
-            ("out of bounds offset".to_string(), 0, 0)
+/// `frame 2 (my_fn) local _5 (buf)`
+fn local_description(pcx: &PrirodaContext, frame: usize, local: mir::Local) -> String {
+    let stack_frame = &pcx.ecx.stack()[frame];
+    let mut s = format!("frame {} ({}) local _{}", frame, stack_frame.instance, local.index());
+    if let Some(name) = stack_frame.mir.local_decls[local].name {
+        s.push_str(&format!(" ({})", name));
+    }
+    s
+}
+
+/// The bytes of an allocation shown at once when no length is given.
+pub const ALLOC_WINDOW: u64 = 4096;
+
+/// The allocation page, showing `len` bytes, by default `ALLOC_WINDOW`, from the row containing
+/// `offset`, with the byte at `offset` outlined.
+pub fn render_ptr_memory(pcx: &PrirodaContext, alloc_id: AllocId, offset: u64, len: Option<u64>, message: String) -> Html<String> {
+    // Whole rows, so the offsets in the hexdump stay aligned
+    let window_len = (len.unwrap_or(ALLOC_WINDOW).saturating_add(locals::HEXDUMP_ROW - 1) / locals::HEXDUMP_ROW).max(1) * locals::HEXDUMP_ROW;
+    let window_start = offset - offset % locals::HEXDUMP_ROW;
+    let mut window = None;
+    let backing = locals::backing_locals(&pcx.ecx, alloc_id);
+    // Locals own their allocation, so the fields of their type start at offset 0
+    let fields = backing
+        .first()
+        .and_then(|&(frame, local)| locals::local_layout(&pcx.ecx, &pcx.ecx.stack()[frame], local).ok())
+        .map(|layout| locals::field_ranges(&pcx.ecx, layout, 0))
+        .unwrap_or_default();
+    let mem = match pcx.ecx.memory().get(alloc_id) {
+        Ok(alloc) if offset <= alloc.bytes.len() as u64 => {
+            let end = window_start.saturating_add(window_len).min(alloc.bytes.len() as u64);
+            window = Some((window_start.min(end), end));
+            let annotations = locals::Annotations { cursor: Some(offset), fields: &fields, ..locals::Annotations::default() };
+            locals::print_alloc(&pcx.ecx, alloc_id, alloc, window_start, end, &annotations)
         }
-    } else {
-        ("unknown memory".to_string(), 0, 0)
+        Ok(_) => "out of bounds offset".to_string(),
+        // Function pointers point to allocations without bytes
+        Err(_) => locals::print_ptr(
+            &pcx.ecx,
+            Pointer::new(alloc_id, Size::from_bytes(0)).with_tag(miri::Tag::Untagged).into(),
+            0,
+            None,
+        )
+        .map(|(_, mem, _)| mem)
+        .unwrap_or_else(|()| "unknown memory".to_string()),
+    };
+    let len_query = match len {
+        Some(_) => format!("?len={}", window_len),
+        None => String::new(),
     };
+    // The locals of the viewed frame stored in this allocation
+    let locals = match pcx.view_frame().and_then(|frame| pcx.ecx.stack().get(frame)) {
+        Some(frame) => locals::collect_locals(&pcx.ecx, frame, locals::Limits {
+            auto_deref: 0,
+            layout: false,
+            ..pcx.config.locals_settings()
+        })
+            .into_iter()
+            .filter(|local| local.alloc == Some(alloc_id.0))
+            .collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+    let name = locals::alloc_name(&pcx.ecx, alloc_id);
+    let kind = locals::alloc_kind(&pcx.ecx, alloc_id);
+    // Only the shown bytes are counted, allocations can be huge
+    let metadata = pcx.ecx.memory().get(alloc_id).ok().map(|alloc| {
+        let (start, end) = window.unwrap_or((0, 0));
+        (
+            alloc.bytes.len(),
+            (start..end).filter(|&i| alloc.undef_mask.get(Size::from_bytes(i))).count(),
+            alloc.align.bytes(),
+            alloc.mutability == crate::syntax::ast::Mutability::Mutable,
+            alloc.relocations.len(),
+        )
+    });
+    let vtable = locals::render_vtable(&pcx.ecx, alloc_id);
+    let borrow_stacks = pcx
+        .ecx
+        .memory()
+        .get(alloc_id)
+        .ok()
+        .map(|alloc| borrows::render_stacks(alloc_id, alloc, None, Some(window.unwrap_or((0, 0)))));
     template(
         pcx,
         format!("Allocation {}", alloc_id),
         html!{
-            span(style="font-family: monospace") {
-                : format!("{nil:.<offset$}┌{nil:─<rest$}", nil = "", offset = offset as usize, rest = rest)
+            h2 {
+                : format!("Allocation {} ", alloc_id.0);
+                @ if let Some(kind) = kind {
+                    span(class="alloc-kind") { : kind }
+                }
+                @ if let Some(name) = name {
+                    : format!(" {}", name)
+                }
+            }
+            @ if let Some(step) = pcx.dirty.last_write(alloc_id) {
+                p {
+                    : format!("Last written at step {}", step);
+                    @ if pcx.dirty.modified_by_last_command(alloc_id) {
+                        : " ";
+                        span(class="modified") { : "modified during the last command" }
+                    }
+                }
+            }
+            @ if !message.is_empty() {
+                p(class="stop_reason") { : message }
             }
+            @ if let Some((size, defined, align, mutable, relocations)) = metadata {
+                p {
+                    : format!(
+                        "size {} bytes ({}), align {}, {}, {} relocation{}",
+                        size,
+                        match window {
+                            Some((start, end)) if start != 0 || end != size as u64 => {
+                                format!("{} of bytes {}..{} defined", defined, start, end)
+                            }
+                            _ => format!("{} defined", defined),
+                        },
+                        align,
+                        if mutable { "mutable" } else { "read-only" },
+                        relocations,
+                        if relocations == 1 { "" } else { "s" },
+                    );
+                }
+            }
+            @ for &(frame, local) in &backing {
+                p {
+                    : "Backs ";
+                    a(href=format!("/frame/{}", frame)) { : format!("frame {}", frame) }
+                    : format!(" local _{}", local.index());
+                    @ if let Some(name) = pcx.ecx.stack()[frame].mir.local_decls[local].name {
+                        : format!(" ({})", name)
+                    }
+                }
+            }
+            @ if let Some(vtable) = vtable {
+                : Raw(vtable);
+            }
+            @ if let (Some((start, end)), Some((size, _, _, _, _))) = (window, metadata) {
+                @ if start != 0 || end != size as u64 {
+                    p {
+                        : format!("Bytes {}..{} of {} ", start, end, size);
+                        @ if start > 0 {
+                            a(href=format!("/ptr/{}/{}{}", alloc_id.0, start.saturating_sub(window_len), len_query)) { : "previous" }
+                            : " ";
+                        }
+                        @ if end < size as u64 {
+                            a(href=format!("/ptr/{}/{}{}", alloc_id.0, end, len_query)) { : "next" }
+                        }
+                    }
+                }
+                form(action=format!("/ptr/{}", alloc_id.0)) {
+                    : "Show ";
+                    input(name="len", size="8", value=window_len.to_string());
+                    : " bytes from offset ";
+                    input(name="offset", size="10", value=offset.to_string());
+                    : " ";
+                    input(type="submit", value="Go");
+                }
+            }
+            : Raw(mem);
+            br;
+            a(href=format!("/reverse_ptr/{}", alloc_id.0)) { : "List the pointers to this allocation" }
             br;
-            span(style="font-family: monospace") { : Raw(mem) }
+            a(href=format!("/alloc_graph?alloc={}", alloc_id.0)) { : "Pointer graph from this allocation" }
             br;
-            a(href=format!("/reverse_ptr/{}", alloc_id)) { : "List allocations with pointers into this allocation" }
+            a(href=format!("/ptr/{}/download", alloc_id.0)) { : "Download the bytes" }
+            : " (";
+            a(href=format!("/ptr/{}/download.json", alloc_id.0)) { : "undefined and pointer ranges" }
+            : ")";
+            br;
+            form(action="/search") {
+                input(type="hidden", name="alloc", value=alloc_id.0.to_string());
+                input(name="q", placeholder="search this allocation");
+            }
+            form(action="/compare") {
+                input(type="hidden", name="a", value=alloc_id.0.to_string());
+                input(name="b", size="6", placeholder="allocation");
+                : " ";
+                input(type="submit", value="Compare");
+            }
+            form(action="/cast") {
+                input(type="hidden", name="alloc", value=alloc_id.0.to_string());
+                : "Read offset ";
+                input(name="offset", size="6", value=offset.to_string());
+                : " as ";
+                input(name="ty", placeholder="u32, *const u8, [u16; 4], MyStruct");
+            }
+            @ if metadata.map_or(false, |(_, _, _, mutable, _)| mutable) {
+                form(method="post", action=format!("/mutations/write/{}", alloc_id.0)) {
+                    : "Write ";
+                    input(name="bytes", size="24", placeholder="hex bytes, like 01 00");
+                    : " at offset ";
+                    input(name="offset", size="6", value=offset.to_string());
+                    : " ";
+                    label {
+                        input(type="checkbox", name="clear_pointers");
+                        : " overwrite pointers";
+                    }
+                    : " ";
+                    input(type="submit", value="Write");
+                }
+            }
+            a(href=format!("/step/until_changed/{}", alloc_id.0)) { : "Step until this allocation changes" }
+            @ if let Some(borrow_stacks) = borrow_stacks {
+                details {
+                    summary { : "Borrow stacks" }
+                    : Raw(borrow_stacks);
+                }
+            }
+            @ if !locals.is_empty() {
+                table(border="1") {
+                    @ for local in &locals {
+                        tr(data-alloc=alloc_id.0.to_string(), data-ranges=locals::format_ranges(&local.ranges)) {
+                            td { : format!("_{}", local.id) }
+                            td { : &local.name }
+                            td { : &local.ty }
+                        }
+                    }
+                }
+            }
+            script { : Raw(locals::HIGHLIGHT_BYTES_SCRIPT) }
+        },
+    )
+}
+
+/// Where the pointer graph starts: the allocation `alloc` if given, otherwise the allocations of
+/// the locals of `frame`, which defaults to the viewed frame.
+pub fn alloc_graph_roots(pcx: &PrirodaContext, alloc: Option<u64>, frame: Option<usize>) -> Result<Vec<AllocId>, String> {
+    if let Some(alloc) = alloc {
+        return Ok(vec![AllocId(alloc)]);
+    }
+    let frame = frame.or_else(|| pcx.view_frame()).unwrap_or(0);
+    match pcx.ecx.stack().get(frame) {
+        Some(stack_frame) => Ok(alloc_graph::frame_allocs(stack_frame)),
+        None => Err(format!("No frame {}, the stack has {} frames", frame, pcx.ecx.stack().len())),
+    }
+}
+
+/// The allocations reachable from `alloc_graph_roots` as an svg, with a form to change the limits
+/// and a link to the DOT source.
+pub fn render_alloc_graph(pcx: &PrirodaContext, alloc: Option<u64>, frame: Option<usize>, depth: usize, nodes: usize) -> Html<String> {
+    let roots = match alloc_graph_roots(pcx, alloc, frame) {
+        Ok(roots) => roots,
+        Err(e) => return Html(escape_html(&e).into_owned()),
+    };
+    let graph = match alloc_graph::render_svg(alloc_graph::alloc_graph_dot(&pcx.ecx, &roots, depth, nodes)) {
+        Ok(svg) => svg,
+        Err(err) => format!("<p>Could not render the graph: {}</p>", escape_html(&err)),
+    };
+    let root = match (alloc, frame) {
+        (Some(alloc), _) => format!("alloc={}&", alloc),
+        (None, Some(frame)) => format!("frame={}&", frame),
+        (None, None) => String::new(),
+    };
+    template(
+        pcx,
+        "Pointer graph".to_string(),
+        html! {
+            a(href="/") { : "Back" }
+            : " ";
+            a(href=format!("/alloc_graph.dot?{}depth={}&nodes={}", root, depth, nodes)) { : "DOT source" }
+            form(action="/alloc_graph") {
+                @ if let Some(alloc) = alloc {
+                    input(type="hidden", name="alloc", value=alloc.to_string());
+                }
+                @ if let Some(frame) = frame {
+                    input(type="hidden", name="frame", value=frame.to_string());
+                }
+                : "Depth: ";
+                input(name="depth", size="4", value=depth.to_string());
+                : " Allocations: ";
+                input(name="nodes", size="6", value=nodes.to_string());
+                input(type="submit", value="Update");
+            }
+            @ if roots.is_empty() {
+                p { : "No locals of this frame are stored in memory" }
+            }
+            div(id="alloc_graph") { : Raw(graph) }
+        },
+    )
+}
+
+/// How many allocations `render_allocations` shows per page.
+const ALLOCATIONS_PER_PAGE: usize = 500;
+
+/// All allocations, except for those with an origin in `hidden`, ordered by `sort` (`id`, `size`,
+/// `relocations` or `written`) and split in pages of `ALLOCATIONS_PER_PAGE`.
+pub fn render_allocations(pcx: &PrirodaContext, hidden: &[String], sort: &str, page: usize) -> Html<String> {
+    let all = crate::api::allocations(pcx);
+    let mut allocs = all
+        .iter()
+        .filter(|alloc| !hidden.iter().any(|h| h == alloc.origin))
+        .collect::<Vec<_>>();
+    match sort {
+        // Largest first, that's what is looked for
+        "size" => allocs.sort_by_key(|alloc| ::std::cmp::Reverse(alloc.size)),
+        "relocations" => allocs.sort_by_key(|alloc| ::std::cmp::Reverse(alloc.relocations)),
+        // Most recently written first, never written ones last
+        "written" => allocs.sort_by_key(|alloc| ::std::cmp::Reverse(pcx.dirty.last_write(AllocId(alloc.alloc_id)))),
+        _ => {}
+    }
+    let total_bytes: u64 = allocs.iter().map(|alloc| alloc.size).sum();
+    let pages = (allocs.len() + ALLOCATIONS_PER_PAGE - 1) / ALLOCATIONS_PER_PAGE;
+    let first = page * ALLOCATIONS_PER_PAGE;
+    let shown = allocs.iter().skip(first).take(ALLOCATIONS_PER_PAGE).collect::<Vec<_>>();
+    let url = |hide: &[String], sort: &str, page: usize| {
+        format!(
+            "/allocations?hide={}&sort={}&page={}",
+            rocket::http::uri::Uri::percent_encode(&hide.join(",")),
+            sort,
+            page
+        )
+    };
+    let toggle = |origin: &str| {
+        let mut hide = hidden.iter().filter(|h| *h != origin).cloned().collect::<Vec<_>>();
+        if hide.len() == hidden.len() {
+            hide.push(origin.to_string());
+        }
+        url(&hide, sort, 0)
+    };
+    template(
+        pcx,
+        "Allocations".to_string(),
+        html! {
+            a(href="/") { : "Back" }
+            p {
+                @ for origin in crate::api::ORIGINS {
+                    a(href=toggle(origin)) {
+                        : format!(
+                            "{} {} ({})",
+                            if hidden.iter().any(|h| h == origin) { "show" } else { "hide" },
+                            origin,
+                            all.iter().filter(|alloc| alloc.origin == *origin).count(),
+                        )
+                    }
+                    : " ";
+                }
+            }
+            p {
+                : format!("{} allocations, {} bytes", allocs.len(), total_bytes);
+                @ if pages > 1 {
+                    : format!(
+                        ", showing {}..{}",
+                        first.min(allocs.len()),
+                        (first + ALLOCATIONS_PER_PAGE).min(allocs.len()),
+                    );
+                }
+            }
+            @ if pages > 1 {
+                p {
+                    @ for i in 0..pages {
+                        @ if i == page {
+                            : format!("{} ", i + 1);
+                        } else {
+                            a(href=url(hidden, sort, i)) { : (i + 1).to_string() }
+                            : " ";
+                        }
+                    }
+                }
+            }
+            table(border="1") {
+                tr {
+                    th { a(href=url(hidden, "id", 0)) { : "allocation" } }
+                    th { : "origin" }
+                    th { a(href=url(hidden, "size", 0)) { : "size" } }
+                    th { : "align" }
+                    th { a(href=url(hidden, "relocations", 0)) { : "relocations" } }
+                    th { a(href=url(hidden, "written", 0)) { : "last written" } }
+                }
+                @ for alloc in shown {
+                    tr {
+                        td {
+                            a(href=format!("/ptr/{}/0", alloc.alloc_id)) { : format!("Allocation {}", alloc.alloc_id) }
+                            @ if pcx.dirty.modified_by_last_command(AllocId(alloc.alloc_id)) {
+                                : " ";
+                                span(class="modified") { : "modified" }
+                            }
+                        }
+                        td { : alloc.origin }
+                        td { : alloc.size.to_string() }
+                        td { : alloc.align.to_string() }
+                        td { : alloc.relocations.to_string() }
+                        td { : pcx.dirty.last_write(AllocId(alloc.alloc_id)).map(|step| format!("step {}", step)).unwrap_or_default() }
+                    }
+                }
+            }
+        },
+    )
+}
+
+pub fn render_postmortem(pcx: &PrirodaContext) -> Html<String> {
+    let pm = crate::api::postmortem(pcx);
+    let hex = |memory: &crate::api::Memory| {
+        memory
+            .bytes
+            .iter()
+            .map(|byte| byte.map(|byte| format!("{:02x}", byte)).unwrap_or_else(|| "__".to_string()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    template(
+        pcx,
+        "Post-mortem".to_string(),
+        html! {
+            a(href="/") { : "Back" }
+            : " ";
+            a(href="/postmortem.json") { : "JSON" }
+            h2 { : format!("Result: {}", pm.result) }
+            p { : format!("{} steps, {} heap allocations, {} leaked", pm.step_count, pm.allocations, pm.leaked.len()) }
+            p {
+                : format!(
+                    "{} allocations made, {} freed ({} bytes), {} leaked ({} bytes), at most {} bytes live",
+                    pm.made,
+                    pm.freed,
+                    pm.freed_bytes,
+                    pm.leaked.len(),
+                    pm.leaked_bytes,
+                    pm.peak_live_bytes,
+                )
+            }
+            p {
+                : "Leaked by origin: ";
+                : pm.leaked_by_origin.iter().map(|(origin, count)| {
+                    if crate::api::MACHINE_ORIGINS.contains(origin) {
+                        format!("{} {} (interpreter)", count, origin)
+                    } else {
+                        format!("{} {}", count, origin)
+                    }
+                }).collect::<Vec<_>>().join(", ");
+            }
+            h3 { : "Changed statics" }
+            table(border="1") {
+                tr { th { : "static" } th { : "initial" } th { : "last" } }
+                @ for changed in &pm.changed_statics {
+                    tr {
+                        td { : &changed.name }
+                        td(style="font-family: monospace") { : hex(&changed.initial) }
+                        td(style="font-family: monospace") { : hex(&changed.last) }
+                    }
+                }
+            }
+            h3(id="leaks") { : "Leaked allocations" }
+            table(border="1") {
+                tr { th { : "size" } th { : "count" } th { : "allocations" } }
+                @ for (size, ids) in &pm.leaked_by_size {
+                    tr {
+                        td { : size.to_string() }
+                        td { : ids.len().to_string() }
+                        td {
+                            @ for id in ids {
+                                a(href=format!("/ptr/{}/0", id)) { : format!("alloc {}", id) }
+                                : " ";
+                            }
+                        }
+                    }
+                }
+            }
+            table(border="1") {
+                tr { th { : "allocation" } th { : "origin" } th { : "size" } th { : "created" } }
+                @ for leaked in &pm.leaked {
+                    tr {
+                        td { a(href=format!("/ptr/{}/0", leaked.alloc_id)) { : format!("Allocation {}", leaked.alloc_id) } }
+                        td { : leaked.origin }
+                        td { : leaked.size.to_string() }
+                        td { : leaked.created.clone().unwrap_or_else(|| "before the first step".to_string()) }
+                    }
+                }
+            }
+            h3 { : "Functions by steps" }
+            table(border="1") {
+                tr { th { : "function" } th { : "steps" } }
+                @ for (function, steps) in &pm.functions {
+                    tr { td { : function } td { : steps.to_string() } }
+                }
+            }
+            h3 { : "Errors" }
+            @ for error in &pm.errors {
+                p(style="color: red;") { : error }
+            }
         },
     )
 }
@@ -246,16 +1153,78 @@ impl<'a, 'r> ::rocket::request::FromRequest<'a, 'r> for FlashString {
     }
 }
 
+pub mod layout_routes {
+    use rocket::response::Redirect;
+    use rocket::State;
+
+    use crate::{PrirodaSender, RResult};
+
+    pub fn routes() -> Vec<::rocket::Route> {
+        routes![toggle, reset, locals_filter]
+    }
+
+    /// Change `Config::locals_filter` and go back to the frame `frame`.
+    #[get("/locals?<temporaries>&<dead>&<show>&<frame>")]
+    pub fn locals_filter(
+        sender: State<PrirodaSender>,
+        temporaries: Option<bool>,
+        dead: Option<bool>,
+        show: Option<String>,
+        frame: Option<usize>,
+    ) -> RResult<Redirect> {
+        sender.do_work(move |pcx| {
+            let filter = &mut pcx.config.locals_filter;
+            if let Some(hide) = temporaries {
+                filter.hide_temporaries = hide;
+            }
+            if let Some(hide) = dead {
+                filter.hide_dead = hide;
+            }
+            if let Some(show) = show.as_ref().and_then(|show| super::locals::LocalsShown::parse(show)) {
+                filter.show = show;
+            }
+            Redirect::to(frame.map_or_else(|| "/".to_string(), |frame| format!("/frame/{}", frame)))
+        })
+    }
+
+    #[post("/toggle/<panel>")]
+    pub fn toggle(sender: State<PrirodaSender>, panel: String) -> RResult<Redirect> {
+        sender.do_work(move |pcx| {
+            if super::PANELS.contains(&&panel[..]) && !pcx.config.hidden_panels.remove(&panel) {
+                pcx.config.hidden_panels.insert(panel);
+            }
+            Redirect::to("/")
+        })
+    }
+
+    #[post("/reset")]
+    pub fn reset(sender: State<PrirodaSender>) -> RResult<Redirect> {
+        sender.do_work(|pcx| {
+            pcx.config.hidden_panels = crate::default_hidden_panels();
+            Redirect::to("/")
+        })
+    }
+}
+
 pub mod routes {
     use super::*;
     use crate::*;
 
     pub fn routes() -> Vec<::rocket::Route> {
-        routes![index, frame, frame_invalid, ptr, reverse_ptr]
+        routes![index, frame, select_frame, frame_invalid, string_chunk, stack_run, ptr, ptr_window, download, download_json, cast, borrow_error, source_page, fn_, reverse_ptr, allocations, alloc_graph, alloc_graph_dot, postmortem, postmortem_json, local_full, local_full_json, locals_json, locals_text, local_layout]
     }
 
     view_route!(index: "/", |pcx, flash: FlashString| {
-        render::render_main_window(pcx, None, flash.0)
+        render::render_main_window(pcx, pcx.selected_frame(), flash.0)
+    });
+
+    action_route!(select_frame: "/frame/<frame>/select", |pcx, frame: usize| {
+        let len = pcx.ecx.stack().len();
+        if frame >= len {
+            return format!("No frame {}, the stack has {} frames", frame, len);
+        }
+        pcx.selected_frame = if frame == len - 1 { None } else { Some(frame) };
+        format!("Showing frame {}", frame)
     });
 
     view_route!(frame: "/frame/<frame>", |pcx, flash: FlashString, frame: usize| {
@@ -270,11 +1239,215 @@ pub mod routes {
         )))
     }
 
-    view_route!(ptr: "/ptr/<alloc_id>/<offset>", |pcx, alloc_id: u64, offset: u64| {
-        render::render_ptr_memory(pcx, AllocId(alloc_id), offset)
+    /// A single local without any truncation, or with the given limits. This is an HTML fragment.
+    #[get("/frame/<frame>/local/<local>/full?<depth>&<raw>", rank = 2)]
+    pub fn local_full(
+        sender: rocket::State<PrirodaSender>,
+        frame: usize,
+        local: usize,
+        depth: Option<usize>,
+        raw: Option<bool>,
+    ) -> RResult<Html<String>> {
+        sender.do_work(move |pcx| {
+            let stack_frame = match pcx.ecx.stack().get(frame) {
+                Some(stack_frame) => stack_frame,
+                None => return Html(format!("No frame {}, the stack has {} frames", frame, pcx.ecx.stack().len())),
+            };
+            let full_url = format!("/frame/{}/local/{}/full", frame, local);
+            let limits = locals::Limits {
+                depth,
+                raw: raw.unwrap_or(false),
+                full_url: &full_url,
+                string_bytes: None,
+                ..pcx.config.locals_settings()
+            };
+            let info = locals::frame_local(stack_frame, local).and_then(|id| locals::render_local(&pcx.ecx, stack_frame, id, limits));
+            Html(match info {
+                Ok(info) => (html! {
+                    div(class="local") {
+                        : format!("_{} {}: {} ", local, info.name, info.ty);
+                        @ if let Some(alloc) = info.alloc {
+                            a(href=format!("/ptr/{}/0", alloc)) { : format!("(allocation {}) ", alloc) }
+                        }
+                        : Raw(info.value)
+                    }
+                }).into_string().unwrap(),
+                Err(e) => escape_html(&e).into_owned(),
+            })
+        })
+    }
+
+    #[get("/frame/<frame>/local/<local>/full?<depth>&<raw>", format = "json", rank = 1)]
+    pub fn local_full_json(
+        sender: rocket::State<PrirodaSender>,
+        frame: usize,
+        local: usize,
+        depth: Option<usize>,
+        raw: Option<bool>,
+    ) -> RResult<Json<String>> {
+        sender.do_work(move |pcx| {
+            Json(match api::local_full(pcx, frame, local, depth, raw.unwrap_or(false)) {
+                Ok(local) => serde_json::to_string(&local).unwrap(),
+                Err(e) => json!({ "error": e }).to_string(),
+            })
+        })
+    }
+
+    #[get("/frame/<frame>/locals.json")]
+    pub fn locals_json(sender: rocket::State<PrirodaSender>, frame: usize) -> RResult<Json<String>> {
+        sender.do_work(move |pcx| {
+            Json(match api::locals(pcx, frame) {
+                Some(locals) => serde_json::to_string_pretty(&locals).unwrap(),
+                None => json!({ "error": format!("No frame {}, the stack has {} frames", frame, pcx.ecx.stack().len()) }).to_string(),
+            })
+        })
+    }
+
+    #[get("/frame/<frame>/locals.txt")]
+    pub fn locals_text(sender: rocket::State<PrirodaSender>, frame: usize) -> RResult<String> {
+        sender.do_work(move |pcx| match pcx.ecx.stack().get(frame) {
+            Some(stack_frame) => locals::render_locals_text(&locals::collect_locals(&pcx.ecx, stack_frame, pcx.config.locals_settings())),
+            None => format!("No frame {}, the stack has {} frames", frame, pcx.ecx.stack().len()),
+        })
+    }
+
+    view_route!(local_layout: "/frame/<frame>/local/<local>/layout", |pcx, frame: usize, local: usize| {
+        render::render_local_layout(pcx, frame, local)
+    });
+
+    view_route!(string_chunk: "/frame/<frame>/local/<local>/full/chunk?<offset>", |pcx, frame: usize, local: usize, offset: u64| {
+        render::render_string_chunk(pcx, frame, local, offset)
+    });
+
+    view_route!(stack_run: "/stack_run/<start>/<end>", |pcx, start: usize, end: usize| {
+        render::render_stack_run(pcx, start, end)
+    });
+
+    view_route!(ptr: "/ptr/<alloc_id>/<offset>?<len>", |pcx, flash: FlashString, alloc_id: u64, offset: u64, len: Option<u64>| {
+        render::render_ptr_memory(pcx, AllocId(alloc_id), offset, len, flash.0)
+    });
+
+    // The target of the form jumping to an offset of the allocation page
+    view_route!(ptr_window: "/ptr/<alloc_id>?<offset>&<len>", |pcx, flash: FlashString, alloc_id: u64, offset: Option<u64>, len: Option<u64>| {
+        render::render_ptr_memory(pcx, AllocId(alloc_id), offset.unwrap_or(0), len, flash.0)
+    });
+
+    /// Parse the filler byte of `download`, hex with an optional `0x` prefix.
+    fn parse_fill(fill: Option<String>) -> Result<u8, String> {
+        match fill {
+            Some(fill) => u8::from_str_radix(fill.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("Invalid filler byte {:?}", fill)),
+            None => Ok(0),
+        }
+    }
+
+    fn format_ranges(ranges: impl Iterator<Item = (u64, u64)>) -> String {
+        ranges.map(|(start, end)| format!("{}-{}", start, end)).collect::<Vec<_>>().join(",")
+    }
+
+    /// The bytes of an allocation as a file, see `api::raw_bytes`. Which bytes were replaced by the
+    /// filler is in the `X-Priroda-Undef-Ranges` and `X-Priroda-Pointer-Ranges` headers and in
+    /// `download_json`.
+    #[get("/ptr/<alloc_id>/download?<offset>&<len>&<fill>", rank = 1)]
+    pub fn download(
+        sender: rocket::State<PrirodaSender>,
+        alloc_id: u64,
+        offset: Option<u64>,
+        len: Option<u64>,
+        fill: Option<String>,
+    ) -> RResult<Result<rocket::Response<'static>, BadRequest<String>>> {
+        // Responses aren't `Send`, so only the bytes are collected on the interpreter thread
+        let raw = sender.do_work(move |pcx| api::raw_bytes(pcx, alloc_id, offset.unwrap_or(0), len, parse_fill(fill)?))?;
+        Ok(raw.map_err(|e| BadRequest(Some(e))).map(|raw| {
+            let filename = if raw.offset == 0 && len.is_none() {
+                format!("alloc{}.bin", raw.alloc_id)
+            } else {
+                format!("alloc{}_{}-{}.bin", raw.alloc_id, raw.offset, raw.offset + raw.len)
+            };
+            rocket::Response::build()
+                .header(rocket::http::ContentType::Binary)
+                .raw_header("Content-Disposition", format!("attachment; filename=\"{}\"", filename))
+                .raw_header("X-Priroda-Undef-Ranges", format_ranges(raw.undef.iter().cloned()))
+                .raw_header("X-Priroda-Pointer-Ranges", format_ranges(raw.relocations.iter().map(|&(start, end, _)| (start, end))))
+                .sized_body(std::io::Cursor::new(raw.bytes))
+                .finalize()
+        }))
+    }
+
+    #[get("/ptr/<alloc_id>/download.json?<offset>&<len>", rank = 1)]
+    pub fn download_json(sender: rocket::State<PrirodaSender>, alloc_id: u64, offset: Option<u64>, len: Option<u64>) -> RResult<Json<String>> {
+        sender.do_work(move |pcx| {
+            Json(match api::raw_bytes(pcx, alloc_id, offset.unwrap_or(0), len, 0) {
+                Ok(raw) => serde_json::to_string_pretty(&raw).unwrap(),
+                Err(e) => json!({ "error": e }).to_string(),
+            })
+        })
+    }
+
+    view_route!(cast: "/cast?<alloc>&<offset>&<ty>", |pcx, alloc: u64, offset: Option<u64>, ty: Option<String>| {
+        cast::render_cast(pcx, AllocId(alloc), offset.unwrap_or(0), ty)
+    });
+
+    view_route!(borrow_error: "/borrow_error", |pcx| {
+        borrows::render_borrow_error(pcx)
+    });
+
+    view_route!(source_page: "/source?<frame>", |pcx, frame: Option<usize>| {
+        render::source::render_source_page(pcx, frame)
+    });
+
+    view_route!(fn_: "/fn/<alloc_id>", |pcx, alloc_id: u64| {
+        render::render_fn(pcx, AllocId(alloc_id))
     });
 
-    view_route!(reverse_ptr: "/reverse_ptr/<ptr>", |pcx, ptr: u64| {
-        render::render_reverse_ptr(pcx, ptr)
+    view_route!(reverse_ptr: "/reverse_ptr/<ptr>?<page>", |pcx, ptr: u64, page: Option<usize>| {
+        render::render_reverse_ptr(pcx, ptr, page.unwrap_or(0))
     });
+
+    view_route!(allocations: "/allocations?<hide>&<sort>&<page>", |pcx, hide: Option<String>, sort: Option<String>, page: Option<usize>| {
+        let hidden = match hide {
+            Some(hide) => hide.split(',').filter(|h| !h.is_empty()).map(str::to_string).collect(),
+            None => crate::api::MACHINE_ORIGINS.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+        };
+        render::render_allocations(pcx, &hidden, sort.as_ref().map_or("id", |sort| &sort[..]), page.unwrap_or(0))
+    });
+
+    view_route!(alloc_graph: "/alloc_graph?<alloc>&<frame>&<depth>&<nodes>", |pcx, alloc: Option<u64>, frame: Option<usize>, depth: Option<usize>, nodes: Option<usize>| {
+        render::render_alloc_graph(
+            pcx,
+            alloc,
+            frame,
+            depth.unwrap_or(alloc_graph::DEFAULT_DEPTH),
+            nodes.unwrap_or(alloc_graph::DEFAULT_NODES),
+        )
+    });
+
+    /// The graph of `alloc_graph` as DOT source, for rendering it with other tools.
+    #[get("/alloc_graph.dot?<alloc>&<frame>&<depth>&<nodes>")]
+    pub fn alloc_graph_dot(
+        sender: rocket::State<PrirodaSender>,
+        alloc: Option<u64>,
+        frame: Option<usize>,
+        depth: Option<usize>,
+        nodes: Option<usize>,
+    ) -> RResult<String> {
+        sender.do_work(move |pcx| match render::alloc_graph_roots(pcx, alloc, frame) {
+            Ok(roots) => alloc_graph::alloc_graph_dot(
+                &pcx.ecx,
+                &roots,
+                depth.unwrap_or(alloc_graph::DEFAULT_DEPTH),
+                nodes.unwrap_or(alloc_graph::DEFAULT_NODES),
+            ),
+            Err(e) => e,
+        })
+    }
+
+    view_route!(postmortem: "/postmortem", |pcx| {
+        render::render_postmortem(pcx)
+    });
+
+    #[get("/postmortem.json")]
+    pub fn postmortem_json(sender: rocket::State<PrirodaSender>) -> RResult<Json<String>> {
+        sender.do_work(|pcx| Json(serde_json::to_string_pretty(&api::postmortem(pcx)).unwrap()))
+    }
 }
@@ -2,11 +2,14 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::num::NonZeroU64;
 
+use rustc::hir::def_id::DefId;
+use rustc::mir::{Body, SourceInfo, SourceScope};
 use rustc::ty::TyCtxt;
 use crate::syntax::source_map::Span;
 use miri::{Frame, Tag};
 
 use horrorshow::prelude::*;
+use rocket::response::content::Html;
 use syntect::easy::HighlightLines;
 use syntect::highlighting::{Style, ThemeSet};
 use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
@@ -14,6 +17,8 @@ use syntect::parsing::SyntaxSet;
 use syntect::util::{split_at, LinesWithEndings};
 
 use self::rent_highlight_cache::*;
+use super::template;
+use crate::PrirodaContext;
 
 lazy_static::lazy_static! {
     static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_nonewlines();
@@ -43,6 +48,65 @@ pub fn pretty_src_path(span: Span) -> String {
     }
 }
 
+/// The innermost scope of `scope`'s scope chain which was created by inlining a function body.
+pub fn inlined_root(mir: &Body, mut scope: SourceScope) -> Option<SourceScope> {
+    while let Some(parent) = mir.source_scopes[scope].parent_scope {
+        // Lexical scopes are nested in their parent scope. The outermost scope of an inlined
+        // function body spans the callee instead and is attached to the scope of the call.
+        if !mir.source_scopes[parent].span.contains(mir.source_scopes[scope].span) {
+            return Some(scope);
+        }
+        scope = parent;
+    }
+    None
+}
+
+/// The function of the local crate whose body contains `span`, `None` for the bodies of other
+/// crates.
+pub fn enclosing_fn(tcx: TyCtxt, span: Span) -> Option<DefId> {
+    let span = span.source_callsite();
+    tcx.body_owners()
+        .filter_map(|def_id| Some((def_id, tcx.hir().span_if_local(def_id)?)))
+        .filter(|&(_, body)| body.contains(span))
+        // Closures are nested in the body of the function defining them
+        .min_by_key(|&(_, body)| body.hi() - body.lo())
+        .map(|(def_id, _)| def_id)
+}
+
+/// The function the body inlined as scope `root` of `mir` belongs to, see `inlined_root`. The MIR
+/// doesn't record it, but the statements of the inlined body keep their spans, which point into
+/// the function. `None` if it belongs to another crate.
+pub fn inlined_callee(tcx: TyCtxt, mir: &Body, root: SourceScope) -> Option<DefId> {
+    let span = mir
+        .basic_blocks()
+        .iter()
+        .flat_map(|blck| {
+            blck.statements
+                .iter()
+                .map(|stmt| stmt.source_info)
+                .chain(std::iter::once(blck.terminator().source_info))
+        })
+        // Statements inlined into the body from yet another function point into that one
+        .find(|source_info| inlined_root(mir, source_info.scope) == Some(root))?
+        .span;
+    enclosing_fn(tcx, span)
+}
+
+/// Describe the chain of inlined calls `scope` originates from, innermost call first.
+pub fn inlined_from(tcx: TyCtxt, mir: &Body, scope: SourceScope) -> Vec<String> {
+    let mut chain = Vec::new();
+    let mut scope = scope;
+    while let Some(root) = inlined_root(mir, scope) {
+        let span = mir.source_scopes[root].span;
+        chain.push(match inlined_callee(tcx, mir, root) {
+            Some(def_id) => format!("inlined from {} ({})", tcx.def_path_str(def_id), pretty_src_path(span)),
+            None => format!("inlined from {}", pretty_src_path(span)),
+        });
+        scope = mir.source_scopes[root].parent_scope.unwrap();
+    }
+    chain
+}
+
 thread_local! {
     // This is a thread local, because a `Span` is only valid within one thread
     static CACHED_HIGHLIGHTED_FILES: RefCell<HashMap<u64, HighlightCacheEntry>> = {
@@ -61,77 +125,109 @@ rental! {
     }
 }
 
-pub fn render_source(tcx: TyCtxt, frame: Option<&Frame<Tag, NonZeroU64>>) -> Box<dyn RenderBox + Send> {
-    let before_time = ::std::time::Instant::now();
-
-    if frame.is_none() {
-        return Box::new(FnRenderer::new(|_| {}));
-    }
-    let frame = frame.unwrap();
-    let mut instr_spans = vec![if frame.stmt == frame.mir[frame.block].statements.len() {
-        frame.mir[frame.block].terminator().source_info.span
+/// The source info of the statement or terminator `frame` is about to execute.
+fn current_source_info(frame: &Frame<Tag, NonZeroU64>) -> SourceInfo {
+    if frame.stmt == frame.mir[frame.block].statements.len() {
+        frame.mir[frame.block].terminator().source_info
     } else {
-        frame.mir[frame.block].statements[frame.stmt]
-            .source_info
-            .span
-    }];
+        frame.mir[frame.block].statements[frame.stmt].source_info
+    }
+}
+
+/// `span` followed by the call sites of the macros it was expanded from, outermost last.
+fn expansion_chain(span: Span) -> Vec<Span> {
+    let mut spans = vec![span];
     // Get the original macro caller
-    while let Some(span) = instr_spans
+    while let Some(span) = spans
         .last()
         .unwrap()
         .macro_backtrace()
         .get(0)
         .map(|b| b.call_site)
     {
-        instr_spans.push(span);
+        spans.push(span);
     }
+    spans
+}
+
+/// The span of the statement or terminator `frame` is about to execute, or of the outermost macro
+/// call it was expanded from.
+pub fn current_span(frame: &Frame<Tag, NonZeroU64>) -> Span {
+    *expansion_chain(current_source_info(frame).span).last().unwrap()
+}
+
+/// The syntax highlighted source of the file containing `sp` with `sp` marked and the number of
+/// lines of the file.
+fn highlighted_file(tcx: TyCtxt, sp: Span) -> Result<(String, usize), String> {
+    let (src, lo, hi) = get_file_source_for_span(tcx, sp)?;
+    let lines = src.lines().count();
+
+    CACHED_HIGHLIGHTED_FILES.with(|highlight_cache| {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        src.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let marked = highlight_cache
+            .borrow_mut()
+            .entry(hash)
+            .or_insert_with(|| {
+                HighlightCacheEntry::new(src, |src| {
+                    let before_time = ::std::time::Instant::now();
+                    let highlighted = syntax_highlight(src);
+                    let after_time = ::std::time::Instant::now();
+                    println!("h: {:?}", after_time - before_time);
+                    highlighted
+                })
+            })
+            .rent(|highlighted| mark_span(highlighted, lo, hi));
+        Ok((marked, lines))
+    })
+}
+
+fn code_style() -> String {
+    if let Some(bg_color) = THEME_SET.themes["Solarized (dark)"].settings.background {
+        format!("background-color: #{:02x}{:02x}{:02x}; display: block;", bg_color.r, bg_color.g, bg_color.b)
+    } else {
+        String::new()
+    }
+}
+
+pub fn render_source(tcx: TyCtxt, frame: Option<&Frame<Tag, NonZeroU64>>) -> Box<dyn RenderBox + Send> {
+    let before_time = ::std::time::Instant::now();
+
+    if frame.is_none() {
+        return Box::new(FnRenderer::new(|_| {}));
+    }
+    let frame = frame.unwrap();
+    let source_info = current_source_info(frame);
+    let inlined = inlined_from(tcx, &frame.mir, source_info.scope);
+    let instr_spans = expansion_chain(source_info.span);
 
     let highlighted_sources = instr_spans
         .into_iter()
         .rev()
-        .map(|sp| {
-            let (src, lo, hi) = match get_file_source_for_span(tcx, sp) {
-                Ok(res) => res,
-                Err(err) => return (format!("{:?}", sp), err),
-            };
-
-            CACHED_HIGHLIGHTED_FILES.with(|highlight_cache| {
-                use std::collections::hash_map::DefaultHasher;
-                use std::hash::{Hash, Hasher};
-
-                let mut hasher = DefaultHasher::new();
-                src.hash(&mut hasher);
-                let hash = hasher.finish();
-
-                highlight_cache
-                    .borrow_mut()
-                    .entry(hash)
-                    .or_insert_with(|| {
-                        HighlightCacheEntry::new(src, |src| {
-                            let before_time = ::std::time::Instant::now();
-                            let highlighted = syntax_highlight(src);
-                            let after_time = ::std::time::Instant::now();
-                            println!("h: {:?}", after_time - before_time);
-                            highlighted
-                        })
-                    })
-                    .rent(|highlighted| (pretty_src_path(sp), mark_span(highlighted, lo, hi)))
-            })
+        .map(|sp| match highlighted_file(tcx, sp) {
+            Ok((source, _lines)) => (pretty_src_path(sp), source),
+            Err(err) => (format!("{:?}", sp), super::escape_html(&err).into_owned()),
         })
         .collect::<Vec<_>>();
 
     let after_time = ::std::time::Instant::now();
     println!("s: {:?}", after_time - before_time);
 
-    let style = if let Some(bg_color) = THEME_SET.themes["Solarized (dark)"].settings.background {
-        format!("background-color: #{:02x}{:02x}{:02x}; display: block;", bg_color.r, bg_color.g, bg_color.b)
-    } else {
-        String::new()
-    };
+    let style = code_style();
 
     box_html! {
         pre {
             code(id="the_code", style=style) {
+                @ for line in &inlined {
+                    span(style = "color: orange;") {
+                        : line; br;
+                    }
+                }
                 @ for (sp, source) in highlighted_sources {
                     span(style = "color: aqua;") {
                         :sp; br;
@@ -144,6 +240,96 @@ pub fn render_source(tcx: TyCtxt, frame: Option<&Frame<Tag, NonZeroU64>>) -> Box
     }
 }
 
+/// The file containing the statement frame `frame` is about to execute, with line numbers and the
+/// statement marked. Statements expanded from a macro are shown at the outermost macro call, and
+/// if the file can't be read only the location and the text the compiler has of the span are
+/// shown.
+pub fn render_source_page(pcx: &PrirodaContext, frame: Option<usize>) -> Html<String> {
+    let tcx = pcx.ecx.tcx.tcx;
+    let stack = pcx.ecx.stack();
+    let frame = match frame.or_else(|| pcx.view_frame()) {
+        Some(frame) if frame < stack.len() => frame,
+        _ => return template(pcx, "Source".to_string(), html! {
+            a(href="/") { : "Back" }
+            p { : format!("No frame, the stack has {} frames", stack.len()) }
+        }),
+    };
+    let source_info = current_source_info(&stack[frame]);
+    let inlined = inlined_from(tcx, &stack[frame].mir, source_info.scope);
+    let chain = expansion_chain(source_info.span);
+    let span = *chain.last().unwrap();
+    let source_map = tcx.sess.source_map();
+    let (lo, hi) = (source_map.lookup_char_pos(span.lo()), source_map.lookup_char_pos(span.hi()));
+    let location = format!(
+        "{}:{}:{}: {}:{}",
+        source_map.span_to_filename(span),
+        lo.line,
+        lo.col.0 + 1,
+        hi.line,
+        hi.col.0 + 1,
+    );
+    let expanded_from = chain[..chain.len() - 1].iter().map(|&sp| pretty_src_path(sp)).collect::<Vec<_>>();
+    let (file, error) = match highlighted_file(tcx, span) {
+        Ok(file) => (Some(file), None),
+        Err(err) => (None, Some(err)),
+    };
+    let snippet = source_map.span_to_snippet(span).ok();
+    let style = code_style();
+    template(
+        pcx,
+        format!("Source of frame {}", frame),
+        html! {
+            a(href="/") { : "Back" }
+            h3 { : format!("Frame {}: {}", frame, stack[frame].instance) }
+            p { : &location }
+            @ for line in &inlined {
+                p(style="color: orange;") { : line }
+            }
+            @ if !expanded_from.is_empty() {
+                p {
+                    : "Expanded from macros at:";
+                    @ for sp in &expanded_from {
+                        br;
+                        : sp;
+                    }
+                }
+            }
+            @ if let Some((source, lines)) = &file {
+                table(class="source_listing") {
+                    tr {
+                        td {
+                            pre(class="line_numbers") {
+                                @ for line in 1..=*lines {
+                                    @ if lo.line <= line && line <= hi.line {
+                                        span(id=format!("L{}", line), class="marked") { : line.to_string() }
+                                    } else {
+                                        span(id=format!("L{}", line)) { : line.to_string() }
+                                    }
+                                    : "\n";
+                                }
+                            }
+                        }
+                        td {
+                            pre { code(style=&style) { : Raw(source) } }
+                        }
+                    }
+                }
+                script {
+                    : Raw(format!("document.getElementById('L{}').scrollIntoView({{ block: 'center' }});", lo.line))
+                }
+            }
+            @ if let Some(err) = &error {
+                p { : format!("{}, only the text of the span is available", err) }
+                @ if let Some(snippet) = &snippet {
+                    pre { : snippet }
+                } else {
+                    p { : "The compiler has no text for this span" }
+                }
+            }
+        },
+    )
+}
+
 fn get_file_source_for_span(tcx: TyCtxt, sp: Span) -> Result<(String, usize, usize), String> {
     let source_map = tcx.sess.source_map();
     let _ = source_map.span_to_snippet(sp); // Ensure file src is loaded
@@ -0,0 +1,146 @@
+//! The operands of the terminator a frame is about to execute, evaluated, so calls, drops and
+//! assertions can be inspected before stepping into them.
+//!
+//! Operands can only be evaluated in the innermost frame, as miri resolves locals in the current
+//! frame. Outer frames are in the middle of their call, so the callee is the next frame.
+
+use rustc::mir;
+use rustc::ty::{self, Instance, InstanceDef, ParamEnv};
+
+use horrorshow::prelude::*;
+use horrorshow::Raw;
+
+use miri::{InterpResult, OpTy, Tag};
+
+use crate::render::{escape_html, locals};
+use crate::*;
+
+struct Row {
+    label: String,
+    /// The MIR of the operand or place
+    operand: String,
+    /// The rendered value or why it couldn't be evaluated
+    value: Result<String, String>,
+}
+
+/// The value of `op` rendered like the locals table, or why it couldn't be evaluated.
+fn value<'a, 'tcx: 'a>(pcx: &PrirodaContext<'a, 'tcx>, op: InterpResult<'tcx, OpTy<'tcx, Tag>>) -> Result<String, String> {
+    let op = op.map_err(|err| format!("{:?}", err))?;
+    locals::print_operand_with(&pcx.ecx, op, pcx.config.locals_settings())
+        .map(|(_alloc, value, _ranges)| value)
+        .map_err(|()| "The value can't be printed".to_string())
+}
+
+fn callee<'a, 'tcx: 'a>(ecx: &InterpretCx<'a, 'tcx>, func: &mir::Operand<'tcx>) -> Result<Instance<'tcx>, String> {
+    let func = ecx.eval_operand(func, None).map_err(|err| format!("{:?}", err))?;
+    match func.layout.ty.sty {
+        ty::FnPtr(_) => {
+            let fn_ptr = ecx
+                .read_scalar(func)
+                .and_then(|scalar| scalar.to_ptr())
+                .map_err(|err| format!("{:?}", err))?;
+            ecx.memory().get_fn(fn_ptr).map_err(|err| format!("{:?}", err))
+        }
+        ty::FnDef(def_id, substs) => {
+            let substs = ecx.tcx.subst_and_normalize_erasing_regions(
+                ecx.frame().instance.substs,
+                ParamEnv::reveal_all(),
+                &substs,
+            );
+            Instance::resolve(*ecx.tcx, ParamEnv::reveal_all(), def_id, substs)
+                .ok_or_else(|| format!("{} can't be resolved", ecx.tcx.def_path_str(def_id)))
+        }
+        _ => Err(format!("Can't call a {}", func.layout.ty)),
+    }
+}
+
+/// The callee linked to its MIR listing if it has MIR of its own.
+fn render_instance(instance: Instance) -> String {
+    let name = escape_html(&instance.to_string()).into_owned();
+    match instance.def {
+        InstanceDef::Item(def_id) => format!("<a href=\"{}\">{}</a>", crate::listing::url(def_id), name),
+        _ => name,
+    }
+}
+
+/// The kind of the terminator of frame `frame` and its evaluated operands, `None` if the frame
+/// isn't at a call, drop or assertion.
+fn rows(pcx: &PrirodaContext, frame: usize) -> Option<(&'static str, Vec<Row>)> {
+    let stack = pcx.ecx.stack();
+    let stack_frame = stack.get(frame)?;
+    let block = &stack_frame.mir.basic_blocks()[stack_frame.block];
+    if stack_frame.stmt != block.statements.len() {
+        return None;
+    }
+    let innermost = frame + 1 == stack.len();
+    let row = |label: &str, operand: String, value: Result<String, String>| Row { label: label.to_string(), operand, value };
+    match &block.terminator().kind {
+        mir::TerminatorKind::Call { func, args, destination, .. } => {
+            if !innermost {
+                let callee = stack.get(frame + 1).map(|callee| render_instance(callee.instance));
+                return Some(("Call in progress", vec![row(
+                    "callee",
+                    format!("{:?}", func),
+                    callee.ok_or_else(|| "No callee frame".to_string()),
+                ), row(
+                    "arguments",
+                    format!("{:?}", args),
+                    Err(format!("The arguments are the locals of frame {}", frame + 1)),
+                )]));
+            }
+            let mut rows = vec![row("callee", format!("{:?}", func), callee(&pcx.ecx, func).map(render_instance))];
+            for (i, arg) in args.iter().enumerate() {
+                rows.push(row(&format!("argument {}", i + 1), format!("{:?}", arg), value(pcx, pcx.ecx.eval_operand(arg, None))));
+            }
+            rows.push(match destination {
+                Some((place, target)) => row("destination", format!("{:?}", place), Ok(format!("returns to {:?}", target))),
+                None => row("destination", String::new(), Ok("diverges".to_string())),
+            });
+            Some(("Call", rows))
+        }
+        mir::TerminatorKind::Drop { location, .. } | mir::TerminatorKind::DropAndReplace { location, .. } if innermost => {
+            let mut rows = vec![row("dropped", format!("{:?}", location), value(pcx, pcx.ecx.eval_place_to_op(location, None)))];
+            if let mir::TerminatorKind::DropAndReplace { value: replacement, .. } = &block.terminator().kind {
+                rows.push(row("replaced by", format!("{:?}", replacement), value(pcx, pcx.ecx.eval_operand(replacement, None))));
+            }
+            Some(("Drop", rows))
+        }
+        mir::TerminatorKind::Assert { cond, expected, msg, .. } if innermost => Some((
+            "Assert",
+            vec![
+                row("condition", format!("{:?}", cond), value(pcx, pcx.ecx.eval_operand(cond, None))),
+                row("expected", String::new(), Ok(expected.to_string())),
+                row("message", String::new(), Ok(escape_html(&format!("{:?}", msg)).into_owned())),
+            ],
+        )),
+        _ => None,
+    }
+}
+
+/// A table of the operands of the terminator frame `frame` is about to execute, if it is a call,
+/// drop or assertion.
+pub fn render_pending_terminator(pcx: &PrirodaContext, frame: usize) -> Box<dyn RenderBox + '_> {
+    let (kind, rows) = match rows(pcx, frame) {
+        Some(rows) => rows,
+        None => return box_html! {},
+    };
+    box_html! {
+        div(id="terminator") {
+            h4 { : kind }
+            table(border="1") {
+                @ for row in &rows {
+                    tr {
+                        th { : &row.label }
+                        td(style="font-family: monospace;") { : &row.operand }
+                        @ if let Ok(value) = &row.value {
+                            td { : Raw(value) }
+                        }
+                        @ if let Err(err) = &row.value {
+                            td(class="error") { : err }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
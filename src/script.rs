@@ -0,0 +1,64 @@
+//! Command scripts reproducing a debugging session.
+//!
+//! A script contains one command per line, in the syntax of `step::step_command`. Empty lines
+//! and lines starting with `#` are ignored. `--script <file>` executes a script before the web
+//! interface is started, `record-script <file>` appends every following successful command to a
+//! script.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::*;
+
+#[derive(Default)]
+pub struct ScriptRecorder(Option<(PathBuf, File)>);
+
+/// Execute the commands of the script at `path` in order, stopping at the first one failing.
+pub fn run_script(pcx: &mut PrirodaContext, path: &Path) -> Result<(), String> {
+    let file = File::open(path).map_err(|err| format!("Couldn't open {}: {}", path.display(), err))?;
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+        let cmd = line.trim();
+        if cmd.is_empty() || cmd.starts_with('#') {
+            continue;
+        }
+        if let Err(msg) = crate::step::checked_step_command(pcx, cmd) {
+            return Err(format!("{}:{}: `{}` failed: {}", path.display(), i + 1, cmd, msg));
+        }
+    }
+    Ok(())
+}
+
+pub fn start_recording(pcx: &mut PrirodaContext, file: &str) -> Result<String, String> {
+    match OpenOptions::new().create(true).append(true).open(file) {
+        Ok(f) => {
+            pcx.script.0 = Some((PathBuf::from(file), f));
+            Ok(format!("Recording commands to {}", file))
+        }
+        Err(err) => Err(format!("Couldn't open {}: {}", file, err)),
+    }
+}
+
+pub fn stop_recording(pcx: &mut PrirodaContext) -> String {
+    match pcx.script.0.take() {
+        Some((path, _)) => format!("Stopped recording commands to {}", path.display()),
+        None => "Commands aren't recorded".to_string(),
+    }
+}
+
+/// Append `cmd` to the recorded script, if recording is enabled. Toggling the recording itself
+/// isn't recorded.
+pub fn record(pcx: &mut PrirodaContext, cmd: &str) {
+    if cmd.trim_start().starts_with("record-script") {
+        return;
+    }
+    let failed = match &mut pcx.script.0 {
+        Some((_, file)) => writeln!(file, "{}", cmd.trim()).is_err(),
+        None => false,
+    };
+    if failed {
+        let (path, _) = pcx.script.0.take().unwrap();
+        crate::events::log(pcx, "error", json!({ "error": format!("Couldn't write to {}, stopped recording", path.display()) }));
+    }
+}
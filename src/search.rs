@@ -0,0 +1,201 @@
+//! Finding the allocations containing a byte pattern.
+//!
+//! A query is either a string in double quotes, searched as UTF-8, a `0x` prefixed integer,
+//! searched in the byte order of the target, or hex bytes in memory order like `de ad be ef`.
+
+use rustc::ty::layout::{Endian, Size};
+
+use horrorshow::prelude::*;
+
+use miri::{Allocation, Stacks, Tag};
+
+use crate::*;
+
+/// The search stops after this many matches.
+const MAX_MATCHES: usize = 200;
+
+/// Bytes shown before and after a match.
+const CONTEXT_BYTES: usize = 4;
+
+pub struct Match {
+    pub alloc_id: u64,
+    pub offset: u64,
+    /// The bytes around the match, `None` for undefined ones
+    pub context: Vec<Option<u8>>,
+    /// The index of the match in `context`
+    pub context_start: usize,
+    /// The match overlaps the bytes of a pointer, which aren't its actual value
+    pub in_pointer: bool,
+}
+
+/// The bytes searched for by `query`.
+pub fn parse_pattern(query: &str, endian: Endian) -> Result<Vec<u8>, String> {
+    let query = query.trim();
+    if query.len() >= 2 && query.starts_with('"') && query.ends_with('"') {
+        return Ok(query[1..query.len() - 1].as_bytes().to_vec());
+    }
+    if query.starts_with("0x") {
+        let digits = query[2..].replace('_', "");
+        let value = u64::from_str_radix(&digits, 16).map_err(|err| format!("Invalid integer {:?}: {}", query, err))?;
+        // The width follows the number of digits, so `0x00ff` is searched as two bytes
+        let size = match (digits.len() + 1) / 2 {
+            0..=1 => 1,
+            2 => 2,
+            3..=4 => 4,
+            _ => 8,
+        };
+        let bytes = value.to_le_bytes()[..size].to_vec();
+        return Ok(match endian {
+            Endian::Little => bytes,
+            Endian::Big => bytes.into_iter().rev().collect(),
+        });
+    }
+    let digits = query.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    // Slicing two bytes at a time below requires ASCII
+    if digits.is_empty() || digits.len() % 2 != 0 || !digits.is_ascii() {
+        return Err(format!("{:?} is neither a quoted string, a 0x integer nor hex bytes", query));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("{:?} is neither a quoted string, a 0x integer nor hex bytes", query))
+        })
+        .collect()
+}
+
+fn search_alloc(alloc_id: u64, alloc: &Allocation<Tag, Stacks>, pattern: &[u8], pointer_size: u64, matches: &mut Vec<Match>) {
+    if pattern.is_empty() || alloc.bytes.len() < pattern.len() {
+        return;
+    }
+    for offset in 0..=alloc.bytes.len() - pattern.len() {
+        if matches.len() == MAX_MATCHES {
+            return;
+        }
+        let end = offset + pattern.len();
+        if &alloc.bytes[offset..end] != pattern {
+            continue;
+        }
+        let defined = alloc
+            .undef_mask
+            .is_range_defined(Size::from_bytes(offset as u64), Size::from_bytes(end as u64))
+            .is_ok();
+        if !defined {
+            continue;
+        }
+        let in_pointer = alloc
+            .relocations
+            .keys()
+            .any(|reloc| reloc.bytes() < end as u64 && reloc.bytes() + pointer_size > offset as u64);
+        let context_start = offset.saturating_sub(CONTEXT_BYTES);
+        let context_end = (end + CONTEXT_BYTES).min(alloc.bytes.len());
+        matches.push(Match {
+            alloc_id,
+            offset: offset as u64,
+            context: (context_start..context_end)
+                .map(|i| if alloc.undef_mask.get(Size::from_bytes(i as u64)) { Some(alloc.bytes[i]) } else { None })
+                .collect(),
+            context_start: offset - context_start,
+            in_pointer,
+        });
+    }
+}
+
+/// The first `MAX_MATCHES` occurrences of `pattern` in defined bytes of the live allocations, or
+/// only of allocation `alloc`.
+pub fn search(pcx: &PrirodaContext, pattern: &[u8], alloc: Option<u64>) -> Result<Vec<Match>, String> {
+    let pointer_size = pcx.ecx.tcx.data_layout.pointer_size.bytes();
+    let mut matches = Vec::new();
+    match alloc {
+        Some(id) => {
+            let alloc = pcx.ecx.memory().get(AllocId(id)).map_err(|_| format!("Allocation {} has no bytes", id))?;
+            search_alloc(id, alloc, pattern, pointer_size, &mut matches);
+        }
+        None => {
+            let mut allocs = pcx.ecx.memory().alloc_map().iter(|values| values.map(|(&id, _)| id).collect::<Vec<_>>());
+            allocs.sort();
+            for id in allocs {
+                if let Ok(alloc) = pcx.ecx.memory().get(id) {
+                    search_alloc(id.0, alloc, pattern, pointer_size, &mut matches);
+                }
+            }
+        }
+    }
+    Ok(matches)
+}
+
+fn hex(bytes: &[Option<u8>]) -> String {
+    bytes
+        .iter()
+        .map(|b| match b {
+            Some(b) => format!("{:02x}", b),
+            None => "__".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn render_search(pcx: &PrirodaContext, query: Option<String>, alloc: Option<u64>) -> Html<String> {
+    let results = query.as_ref().map(|query| {
+        parse_pattern(query, pcx.ecx.tcx.data_layout.endian).and_then(|pattern| {
+            search(pcx, &pattern, alloc).map(|matches| (pattern.len(), matches))
+        })
+    });
+    let (found, error) = match results {
+        Some(Ok(found)) => (Some(found), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    crate::render::template(
+        pcx,
+        "Search memory".to_string(),
+        html! {
+            a(href="/") { : "Back" }
+            form(action="/search") {
+                input(name="q", size="40", placeholder="\"text\", 0xdeadbeef or de ad be ef", value=query.clone().unwrap_or_default());
+                : " in allocation ";
+                input(name="alloc", size="6", placeholder="all", value=alloc.map(|id| id.to_string()).unwrap_or_default());
+                input(type="submit", value="Search");
+            }
+            @ if let Some(e) = error {
+                p { : e }
+            }
+            @ if let Some((len, matches)) = found {
+                p {
+                    : format!("{} match{}", matches.len(), if matches.len() == 1 { "" } else { "es" });
+                    @ if matches.len() == MAX_MATCHES {
+                        : ", stopped searching"
+                    }
+                }
+                table(border="1") {
+                    tr {
+                        th { : "match" }
+                        th { : "bytes" }
+                        th {}
+                    }
+                    @ for m in &matches {
+                        tr {
+                            td { a(href=format!("/ptr/{}/{}", m.alloc_id, m.offset)) { : format!("alloc {} + {}", m.alloc_id, m.offset) } }
+                            td(style="font-family: monospace;") {
+                                : hex(&m.context[..m.context_start]);
+                                : " ";
+                                b { : hex(&m.context[m.context_start..m.context_start + len]) }
+                                : " ";
+                                : hex(&m.context[m.context_start + len..]);
+                            }
+                            td { : if m.in_pointer { "overlaps a pointer" } else { "" } }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+pub fn routes() -> Vec<::rocket::Route> {
+    routes![search::show]
+}
+
+view_route!(show: "/?<q>&<alloc>", |pcx, q: Option<String>, alloc: Option<u64>| {
+    render_search(pcx, q, alloc)
+});
@@ -1,11 +1,17 @@
-use rustc::hir::def_id::{CrateNum, DefId, DefIndex};
+use rustc::hir::def_id::{CrateNum, DefId, DefIndex, LOCAL_CRATE};
 use rustc::mir;
 use rustc_data_structures::indexed_vec::Idx;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::iter::Iterator;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
+use miri::{AllocId, Operand};
+use rustc_mir::interpret::{LocalValue, Place};
 use serde::de::{Deserialize, Deserializer, Error as SerdeError};
 
+use crate::condition::{Condition, Session};
 use crate::{InterpretCx, PrirodaContext};
 
 pub enum ShouldContinue {
@@ -13,60 +19,252 @@ pub enum ShouldContinue {
     Stop,
 }
 
+/// Why `step` stopped executing.
+#[derive(Clone, Debug)]
+pub enum StopReason {
+    /// The `continue_while` callback returned `ShouldContinue::Stop`
+    StepComplete,
+    Breakpoint(Breakpoint),
+    Finished,
+    /// The evaluation failed, formatted with `{:?}` as the error borrows the interpreter
+    Error(String),
+    Interrupted { steps: u128 },
+    StepLimit { steps: u128, location: String },
+    /// In `strict_writes` mode, the next statement assigns to an immutable allocation
+    ImmutableWrite { statement: String, origin: String },
+}
+
+impl StopReason {
+    /// A short name of the variant, used in the JSON api and the event log.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StopReason::StepComplete => "step_complete",
+            StopReason::Breakpoint(_) => "breakpoint",
+            StopReason::Finished => "finished",
+            StopReason::Error(_) => "error",
+            StopReason::Interrupted { .. } => "interrupted",
+            StopReason::StepLimit { .. } => "step_limit",
+            StopReason::ImmutableWrite { .. } => "immutable_write",
+        }
+    }
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StopReason::StepComplete => Ok(()),
+            StopReason::Breakpoint(Breakpoint(def_id, bb, stmt)) => {
+                write!(f, "Hit breakpoint {:?}@{}:{}", def_id, bb.index(), stmt)
+            }
+            StopReason::Finished => write!(f, "interpretation finished"),
+            StopReason::Error(error) => write!(f, "{}", error),
+            StopReason::Interrupted { steps } => write!(f, "interrupted by user after {} steps", steps),
+            StopReason::StepLimit { steps, location } => write!(
+                f,
+                "stopped after {} steps without hitting a breakpoint, in {}",
+                steps, location
+            ),
+            StopReason::ImmutableWrite { statement, origin } => write!(
+                f,
+                "stopped before `{}`, which writes to {}",
+                statement, origin
+            ),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct Breakpoint(pub DefId, pub mir::BasicBlock, pub usize);
 
 #[derive(Default)]
-pub struct BreakpointTree(HashMap<DefId, HashSet<Breakpoint>>);
+pub struct BreakpointTree {
+    bps: HashMap<DefId, HashSet<Breakpoint>>,
+    info: HashMap<Breakpoint, BreakpointInfo>,
+    next_id: usize,
+    /// The steps a breakpoint triggered at since the evaluation started. `None` if the
+    /// breakpoints changed since, as the earlier steps weren't checked against the new ones.
+    hit_steps: Option<Vec<u128>>,
+    /// The function every inlined scope of every function was inlined from, see
+    /// `source::inlined_callee`
+    inlined_callees: RefCell<HashMap<(DefId, mir::SourceScope), Option<DefId>>>,
+}
+
+pub struct BreakpointInfo {
+    /// The id used to refer to the breakpoint in conditions
+    pub id: usize,
+    pub hits: u64,
+    pub condition: Option<Condition>,
+}
 
 impl<'de> Deserialize<'de> for BreakpointTree {
     fn deserialize<D: Deserializer<'de>>(deser: D) -> Result<Self, D::Error> {
-        let mut map = HashMap::new();
+        let mut tree = BreakpointTree::default();
         for (k, v) in HashMap::<String, HashSet<(usize, usize)>>::deserialize(deser)? {
             let def_id = parse_def_id(&k).map_err(SerdeError::custom)?;
-            map.insert(
-                def_id,
-                v.into_iter()
-                    .map(|(bb, instr)| Breakpoint(def_id, mir::BasicBlock::new(bb), instr))
-                    .collect::<HashSet<Breakpoint>>(),
-            );
+            for (bb, instr) in v {
+                tree.add_breakpoint(Breakpoint(def_id, mir::BasicBlock::new(bb), instr));
+            }
         }
-        Ok(BreakpointTree(map))
+        Ok(tree)
     }
 }
 
 impl BreakpointTree {
     pub fn add_breakpoint(&mut self, bp: Breakpoint) {
-        self.0.entry(bp.0).or_insert_with(HashSet::new).insert(bp);
+        if self.bps.entry(bp.0).or_insert_with(HashSet::new).insert(bp) {
+            self.hit_steps = None;
+            self.next_id += 1;
+            self.info.insert(bp, BreakpointInfo {
+                id: self.next_id,
+                hits: 0,
+                condition: None,
+            });
+        }
     }
 
     pub fn remove_breakpoint(&mut self, bp: Breakpoint) -> bool {
-        self.0
+        self.hit_steps = None;
+        self.info.remove(&bp);
+        self.bps
             .get_mut(&bp.0)
             .map(|local| local.remove(&bp))
             .unwrap_or(false)
     }
 
     pub fn remove_all(&mut self) {
-        self.0.clear();
+        self.hit_steps = None;
+        self.bps.clear();
+        self.info.clear();
+    }
+
+    /// Forget how often and when the breakpoints were hit, for when the evaluation restarts.
+    pub fn reset_hits(&mut self) {
+        for info in self.info.values_mut() {
+            info.hits = 0;
+        }
+        self.hit_steps = Some(Vec::new());
+    }
+
+    /// The last step before `step` a breakpoint triggered at, `Some(None)` if there is none.
+    /// `None` if it isn't known, because the breakpoints changed since the evaluation started.
+    pub fn last_hit_before(&self, step: u128) -> Option<Option<u128>> {
+        let hit_steps = self.hit_steps.as_ref()?;
+        Some(hit_steps.iter().rev().cloned().find(|&hit| hit < step))
+    }
+
+    /// Returns `false` if there is no such breakpoint.
+    pub fn set_condition(&mut self, bp: Breakpoint, condition: Option<Condition>) -> bool {
+        match self.info.get_mut(&bp) {
+            Some(info) => {
+                info.condition = condition;
+                self.hit_steps = None;
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn for_def_id(&self, def_id: DefId) -> LocalBreakpoints {
-        if let Some(bps) = self.0.get(&def_id) {
+        if let Some(bps) = self.bps.get(&def_id) {
             LocalBreakpoints::SomeBps(bps)
         } else {
             LocalBreakpoints::NoBp
         }
     }
 
-    pub fn is_at_breakpoint(&self, ecx: &InterpretCx) -> bool {
+    /// The breakpoint at the current position. Statements inlined from a function of the local
+    /// crate also stop at the breakpoints of that function at the statement they are a copy of,
+    /// which has the same span.
+    fn breakpoint_at(&self, ecx: &InterpretCx) -> Option<Breakpoint> {
         let frame = ecx.frame();
-        self.for_def_id(frame.instance.def_id())
-            .breakpoint_exists(frame.block, frame.stmt)
+        let bp = Breakpoint(frame.instance.def_id(), frame.block, frame.stmt);
+        if self.info.contains_key(&bp) {
+            return Some(bp);
+        }
+        if self.info.is_empty() {
+            return None;
+        }
+        let source_info = source_info(&frame.mir, frame.block, frame.stmt)?;
+        let root = crate::render::source::inlined_root(&frame.mir, source_info.scope)?;
+        let callee = (*self
+            .inlined_callees
+            .borrow_mut()
+            .entry((bp.0, root))
+            .or_insert_with(|| crate::render::source::inlined_callee(ecx.tcx.tcx, &frame.mir, root)))?;
+        let callee_mir = ecx.tcx.optimized_mir(callee);
+        self.bps.get(&callee)?.iter().cloned().find(|bp| {
+            source_info(callee_mir, bp.1, bp.2).map(|info| info.span) == Some(source_info.span)
+        })
+    }
+
+    /// Whether there is a breakpoint at the current position, regardless of its condition.
+    pub fn is_at_breakpoint(&self, ecx: &InterpretCx) -> bool {
+        self.breakpoint_at(ecx).is_some()
+    }
+
+    /// Whether a breakpoint at the current position triggers. This counts the hit.
+    pub fn check_breakpoint(&mut self, ecx: &InterpretCx, step_count: u128) -> bool {
+        let bp = match self.breakpoint_at(ecx) {
+            Some(bp) => bp,
+            None => return false,
+        };
+        let hit = match self.info.get(&bp) {
+            None => return false,
+            // A broken condition stops, so the error isn't silently ignored
+            Some(info) => match &info.condition {
+                Some(condition) => self.with_session(ecx, step_count, |session| condition.eval(session).unwrap_or(true)),
+                None => true,
+            },
+        };
+        if hit {
+            self.info.get_mut(&bp).unwrap().hits += 1;
+            if let Some(hit_steps) = &mut self.hit_steps {
+                hit_steps.push(step_count);
+            }
+        }
+        hit
+    }
+
+    /// The id of the breakpoint at the current position.
+    pub fn id_at(&self, ecx: &InterpretCx) -> Option<usize> {
+        self.breakpoint_at(ecx).and_then(|bp| self.info.get(&bp)).map(|info| info.id)
+    }
+
+    /// Evaluate `f` with the session state conditions are evaluated against.
+    pub fn with_session<R>(&self, ecx: &InterpretCx, step_count: u128, f: impl FnOnce(&Session) -> R) -> R {
+        let hits = |id| self.info.values().find(|info| info.id == id).map(|info| info.hits);
+        f(&Session {
+            step: step_count,
+            depth: ecx.stack().len(),
+            frame_fn: ecx
+                .stack()
+                .last()
+                .map(|frame| ecx.tcx.def_path_str(frame.instance.def_id()))
+                .unwrap_or_else(String::new),
+            hits: &hits,
+        })
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
-        self.0.values().flat_map(|local| local.iter())
+        self.bps.values().flat_map(|local| local.iter())
+    }
+
+    /// All breakpoints ordered by their id.
+    pub fn iter_info(&self) -> impl Iterator<Item = (&Breakpoint, &BreakpointInfo)> {
+        let mut bps = self.info.iter().collect::<Vec<_>>();
+        bps.sort_by_key(|(_, info)| info.id);
+        bps.into_iter()
+    }
+}
+
+/// The source info of statement `stmt` of block `bb` of `mir`, or of its terminator if `stmt` is
+/// the number of statements.
+fn source_info(mir: &mir::Body, bb: mir::BasicBlock, stmt: usize) -> Option<mir::SourceInfo> {
+    let blck = mir.basic_blocks().get(bb)?;
+    if stmt == blck.statements.len() {
+        Some(blck.terminator().source_info)
+    } else {
+        blck.statements.get(stmt).map(|stmt| stmt.source_info)
     }
 }
 
@@ -85,47 +283,228 @@ impl<'a> LocalBreakpoints<'a> {
     }
 }
 
-pub fn step<F>(pcx: &mut PrirodaContext, continue_while: F) -> String
+/// A command running in the background. This is shared with the web server, so the command can be
+/// watched and cancelled while the interpreter thread is busy with it.
+#[derive(Default)]
+pub struct RunState {
+    running: AtomicBool,
+    cancel: AtomicBool,
+    steps: AtomicU64,
+    /// The message of the last finished command, until it is shown
+    message: Mutex<Option<String>>,
+}
+
+impl RunState {
+    /// Returns `false` if a command is already running.
+    pub fn start(&self) -> bool {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        self.cancel.store(false, Ordering::SeqCst);
+        self.steps.store(0, Ordering::SeqCst);
+        true
+    }
+
+    /// Marks the running command as finished when dropped, even if miri crashes.
+    pub fn guard(self: &Arc<Self>) -> RunGuard {
+        RunGuard(self.clone(), None)
+    }
+
+    /// Ask the running command to stop at the next statement. Returns `false` if nothing runs.
+    pub fn cancel(&self) -> bool {
+        let running = self.running.load(Ordering::SeqCst);
+        if running {
+            self.cancel.store(true, Ordering::SeqCst);
+        }
+        running
+    }
+
+    /// The steps executed so far by the running command, `None` if no command is running.
+    pub fn progress(&self) -> Option<u64> {
+        if self.running.load(Ordering::SeqCst) {
+            Some(self.steps.load(Ordering::SeqCst))
+        } else {
+            None
+        }
+    }
+
+    pub fn take_message(&self) -> Option<String> {
+        self.message.lock().unwrap_or_else(|err| err.into_inner()).take()
+    }
+}
+
+pub struct RunGuard(Arc<RunState>, Option<String>);
+
+impl RunGuard {
+    pub fn finish(mut self, message: String) {
+        self.1 = Some(message);
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        let message = self.1.take().unwrap_or_else(|| "Miri crashed".to_string());
+        *self.0.message.lock().unwrap_or_else(|err| err.into_inner()) = Some(message);
+        self.0.cancel.store(false, Ordering::SeqCst);
+        self.0.running.store(false, Ordering::SeqCst);
+    }
+}
+
+pub fn step<F>(pcx: &mut PrirodaContext, continue_while: F) -> StopReason
 where
     F: Fn(&InterpretCx) -> ShouldContinue,
 {
-    let mut message = None;
-    loop {
+    pcx.selected_frame = None;
+    let mut steps = 0;
+    let reason = loop {
         if pcx.ecx.stack().len() <= 1 && is_ret(&pcx.ecx) {
-            break;
+            break StopReason::Finished;
+        }
+        if pcx.run.cancel.swap(false, Ordering::SeqCst) {
+            break StopReason::Interrupted { steps };
         }
+        if Some(steps) == pcx.config.step_limit {
+            let frame = pcx.ecx.frame();
+            break StopReason::StepLimit {
+                steps,
+                location: format!("{} at {}:{}", frame.instance, frame.block.index(), frame.stmt),
+            };
+        }
+        crate::trace::record(pcx);
+        crate::heatmap::record(pcx);
+        crate::dirty::record(pcx);
         match pcx.ecx.step() {
             Ok(true) => {
                 *pcx.step_count += 1;
+                steps += 1;
+                pcx.run.steps.store(steps as u64, Ordering::Relaxed);
                 crate::watch::step_callback(pcx);
+                // Every step is checked, even when stopping for another reason, so the hits and
+                // the steps they happened at are the same as when replaying
+                let at_breakpoint = pcx.config.bptree.check_breakpoint(&pcx.ecx, *pcx.step_count);
 
                 if let Some(frame) = pcx.ecx.stack().last() {
                     let blck = &frame.mir.basic_blocks()[frame.block];
                     if frame.stmt != blck.statements.len()
                         && crate::should_hide_stmt(&blck.statements[frame.stmt])
-                        && !pcx.config.bptree.is_at_breakpoint(&pcx.ecx)
+                        && !at_breakpoint
                     {
                         continue;
                     }
                 }
+                if pcx.config.strict_writes {
+                    if let Some((statement, origin)) = immutable_write(&mut pcx.ecx) {
+                        break StopReason::ImmutableWrite { statement, origin };
+                    }
+                }
                 if let ShouldContinue::Stop = continue_while(&pcx.ecx) {
-                    break;
+                    break StopReason::StepComplete;
                 }
-                if pcx.config.bptree.is_at_breakpoint(&pcx.ecx) {
-                    break;
+                if at_breakpoint {
+                    let id = pcx.config.bptree.id_at(&pcx.ecx);
+                    crate::events::log(pcx, "breakpoint", json!({ "id": id }));
+                    let frame = pcx.ecx.frame();
+                    break StopReason::Breakpoint(Breakpoint(frame.instance.def_id(), frame.block, frame.stmt));
                 }
             }
-            Ok(false) => {
-                message = Some("interpretation finished".to_string());
-                break;
-            }
+            Ok(false) => break StopReason::Finished,
             Err(e) => {
-                message = Some(format!("{:?}", e));
-                break;
+                let error = format!("{:?}", e);
+                crate::events::log(pcx, "error", json!({ "error": &error }));
+                break StopReason::Error(error);
             }
         }
+    };
+    pcx.last_stop = Some(reason.clone());
+    reason
+}
+
+/// The allocation the next statement assigns to, `None` if it assigns to a local which isn't
+/// stored in memory or isn't an assignment.
+pub fn assigned_alloc(ecx: &mut InterpretCx) -> Option<AllocId> {
+    let place = {
+        let frame = ecx.stack().last()?;
+        let stmt = frame.mir.basic_blocks()[frame.block].statements.get(frame.stmt)?;
+        match stmt.kind {
+            mir::StatementKind::Assign(ref place, _) | mir::StatementKind::SetDiscriminant { ref place, .. } => place.clone(),
+            _ => return None,
+        }
+    };
+    place_alloc(ecx, &place)
+}
+
+/// The allocation `place` of the innermost frame is stored in, `None` for locals which aren't
+/// stored in memory.
+pub fn place_alloc(ecx: &mut InterpretCx, place: &mir::Place) -> Option<AllocId> {
+    let mplace = match *ecx.eval_place(place).ok()? {
+        Place::Ptr(mplace) => mplace,
+        Place::Local { frame, local } => match ecx.stack()[frame].locals[local].value {
+            LocalValue::Live(Operand::Indirect(mplace)) => mplace,
+            _ => return None,
+        },
+    };
+    Some(mplace.ptr.to_ptr().ok()?.alloc_id)
+}
+
+/// The next statement and the origin of the allocation it assigns to, if that allocation is
+/// immutable.
+fn immutable_write(ecx: &mut InterpretCx) -> Option<(String, String)> {
+    let alloc_id = assigned_alloc(ecx)?;
+    let origin = crate::render::locals::immutable_origin(ecx, alloc_id)?;
+    let frame = ecx.stack().last()?;
+    let statement = format!("{:?}", frame.mir.basic_blocks()[frame.block].statements[frame.stmt]);
+    Some((statement, origin))
+}
+
+/// Restart the evaluation and execute exactly `target` steps, rebuilding the traces on the way.
+///
+/// The interpreter state can't be cloned, so this is how earlier states are restored. It relies
+/// on miri being deterministic.
+pub fn replay_to(pcx: &mut PrirodaContext, target: u128) -> Result<(), String> {
+    pcx.restart();
+    while *pcx.step_count < target {
+        crate::trace::record_replayed(pcx);
+        crate::heatmap::record(pcx);
+        crate::dirty::record(pcx);
+        match pcx.ecx.step() {
+            Ok(true) => {
+                *pcx.step_count += 1;
+                crate::watch::step_callback(pcx);
+                // Count the hits again, conditions may depend on them
+                if !pcx.ecx.stack().is_empty() {
+                    pcx.config.bptree.check_breakpoint(&pcx.ecx, *pcx.step_count);
+                }
+            }
+            res => return Err(format!("Miri is not deterministic causing error {:?}", res)),
+        }
+    }
+    Ok(())
+}
+
+/// Return to the last step before the current one a breakpoint triggered at, with its condition
+/// evaluated like `continue` does. Stops at the start if there is none.
+fn reverse_continue(pcx: &mut PrirodaContext) -> String {
+    let current = *pcx.step_count;
+    if current == 0 {
+        return "already at the start".to_string();
+    }
+    let last_hit = match pcx.config.bptree.last_hit_before(current) {
+        Some(last_hit) => last_hit,
+        // The breakpoints changed since the start, so the earlier steps have to be checked again
+        None => {
+            if let Err(e) = replay_to(pcx, current) {
+                return e;
+            }
+            pcx.config.bptree.last_hit_before(current).unwrap()
+        }
+    };
+    if let Err(e) = replay_to(pcx, last_hit.unwrap_or(0)) {
+        return e;
+    }
+    match last_hit {
+        Some(step) => format!("Hit a breakpoint at step {}", step),
+        None => format!("No breakpoint was hit before step {}, stopped at the start", current),
     }
-    message.unwrap_or_else(String::new)
 }
 
 pub fn is_ret(ecx: &InterpretCx) -> bool {
@@ -141,7 +520,7 @@ pub fn is_ret(ecx: &InterpretCx) -> bool {
     }
 }
 
-fn parse_breakpoint_from_url(s: &str) -> Result<Breakpoint, String> {
+pub fn parse_breakpoint_from_url(s: &str) -> Result<Breakpoint, String> {
     let regex = ::regex::Regex::new(r#"([^@]+)@(\d+):(\d+)"#).unwrap();
     // DefId(1:14824 ~ mycrate::main)@1:3
     //       ^ ^                      ^ ^
@@ -196,114 +575,1269 @@ fn parse_def_id(s: &str) -> Result<DefId, String> {
     })
 }
 
+/// Execute a textual stepping command. The returned message describes why stepping stopped.
+///
+/// * `step [n]`: execute `n` statements, entering calls
+/// * `next [n]`: execute `n` statements, stepping over calls
+/// * `step-block`: run to the terminator of the current block
+/// * `until`: run until the current loop is left
+/// * `return [n]`: run until the `n`th frame is about to return
+/// * `continue`: run until a breakpoint, an error or the end of the program
+/// * `continue-to-fn <pattern>`: run until a function whose path contains `pattern` is called
+/// * `continue-to-output`: run until the program writes to stdout or stderr
+/// * `step-until-changed <alloc_id>`: run until the allocation changes or is freed
+/// * `goto <bb> <stmt>`: run until statement `stmt` of block `bb` of the current frame
+/// * `step_back`: go back one statement
+/// * `reverse-continue`: go back to the previous breakpoint hit
+/// * `restart`: restart the program and run to the start of `main`
+/// * `undo`: revert the last command that moved the execution
+/// * `bookmark <name>`: bookmark the current step
+/// * `bookmarks`: list the bookmarks
+/// * `goto-bookmark <name>`: go to a bookmarked step
+/// * `break <breakpoint>`: add a breakpoint given as `DefId(..)@bb:stmt`
+/// * `delete <breakpoint>`: remove a breakpoint
+/// * `limit <n|unlimited>`: set how many steps a single command may execute
+/// * `trace ...`: control the execution trace, see `trace::trace_command`
+/// * `counts on|off|reset`: control the per-statement execution counts of `/heatmap`
+/// * `record-script <file|off>`: append every following command to a file, see `script`
+/// * `const <path>`: show the value of a const item
+/// * `mark-memory`: record the memory `/memdiff` compares against
+/// * `step-over-drops [on|off]`: toggle whether `step` enters drop glue
+/// * `strict-writes [on|off]`: toggle stopping before assignments to immutable allocations
+/// * `track-writes [on|off]`: toggle remembering which allocations were written
+/// * `memory-timeline [on|off]`: toggle sampling the live heap memory for `/timeline`
+/// * `auto-deref <0-2>`: set how many levels of references the locals table dereferences
+/// * `int-format <dec|hex|both>`: set how the locals table prints integers
+/// * `layout [on|off]`: toggle the layout column of the locals table and the field offsets
+/// * `undef-mask [on|off]`: toggle the hexdump rows showing which bytes are defined
+pub fn step_command(pcx: &mut PrirodaContext, cmd: &str) -> String {
+    match checked_step_command(pcx, cmd) {
+        Ok(msg) | Err(msg) => msg,
+    }
+}
+
+/// Like `step_command`, but fails for unknown or invalid commands, commands which couldn't be
+/// executed and commands stopping with an error.
+pub fn checked_step_command(pcx: &mut PrirodaContext, cmd: &str) -> Result<String, String> {
+    crate::events::log(pcx, "command", json!({ "command": cmd }));
+    crate::dirty::start_command(pcx);
+    pcx.last_stop = None;
+    let before = *pcx.step_count;
+    let res = run_step_command(pcx, cmd).and_then(|msg| match pcx.last_stop {
+        Some(StopReason::Error(_)) => Err(msg),
+        _ => Ok(msg),
+    });
+    if *pcx.step_count != before && cmd.trim() != "undo" {
+        let depth = pcx.config.undo_depth;
+        pcx.undo_history.push(before, cmd, depth);
+    }
+    if res.is_ok() {
+        crate::script::record(pcx, cmd);
+    }
+    let msg = match &res {
+        Ok(msg) | Err(msg) => msg,
+    };
+    let reason = pcx.last_stop.as_ref().map(StopReason::kind);
+    crate::events::log(pcx, "stop", json!({ "message": msg, "reason": reason }));
+    res
+}
+
+/// Fails for unknown commands, invalid arguments and commands which couldn't be executed.
+fn run_step_command(pcx: &mut PrirodaContext, cmd: &str) -> Result<String, String> {
+    let args = cmd.split_whitespace().collect::<Vec<_>>();
+    Ok(match &args[..] {
+        ["restart"] => restart(pcx),
+        ["step"] => step_into(pcx),
+        ["step", n] => match parse_count(n) {
+            Ok(n) => step_n(pcx, n),
+            Err(e) => return Err(e),
+        },
+        ["step_back"] => step_back(pcx),
+        ["reverse-continue"] => reverse_continue(pcx),
+        ["next"] => next_n(pcx, 1),
+        ["next", n] => match parse_count(n) {
+            Ok(n) => next_n(pcx, n),
+            Err(e) => return Err(e),
+        },
+        ["return"] => return_n(pcx, 1),
+        ["return", n] => match parse_count(n) {
+            Ok(n) => return_n(pcx, n),
+            Err(e) => return Err(e),
+        },
+        ["continue"] => step(pcx, |_ecx| ShouldContinue::Continue).to_string(),
+        ["continue-to-output"] => continue_to_output(pcx),
+        // Paths of trait methods contain spaces, e.g. `<Foo as Bar>::baz`
+        ["continue-to-fn", pattern..] if !pattern.is_empty() => continue_to_fn(pcx, &pattern.join(" ")),
+        ["step-block"] => step_block(pcx),
+        ["until"] => until(pcx),
+        ["trace", args..] => crate::trace::trace_command(pcx, args)?,
+        ["counts", args..] => crate::heatmap::counts_command(pcx, args)?,
+        ["const", path] => crate::consts::eval_const(pcx, path)?,
+        ["bookmark", name] => crate::bookmarks::add(pcx, name),
+        ["bookmarks"] => crate::bookmarks::list(pcx),
+        ["goto-bookmark", name] => crate::bookmarks::goto(pcx, name)?,
+        ["undo"] => crate::bookmarks::undo(pcx)?,
+        ["mark-memory"] => crate::memdiff::mark(pcx),
+        ["step-until-changed", alloc_id] => match alloc_id.parse::<u64>() {
+            Ok(alloc_id) => step_until_changed(pcx, alloc_id),
+            Err(_) => return Err(format!("Invalid allocation id {:?}", alloc_id)),
+        },
+        ["goto", bb, stmt] => match (bb.parse::<usize>(), stmt.parse::<usize>()) {
+            (Ok(bb), Ok(stmt)) => run_to(pcx, bb, stmt),
+            _ => return Err(format!("Invalid position {}:{}", bb, stmt)),
+        },
+        ["strict-writes"] => {
+            pcx.config.strict_writes = !pcx.config.strict_writes;
+            strict_writes_message(pcx)
+        }
+        ["strict-writes", "on"] => {
+            pcx.config.strict_writes = true;
+            strict_writes_message(pcx)
+        }
+        ["strict-writes", "off"] => {
+            pcx.config.strict_writes = false;
+            strict_writes_message(pcx)
+        }
+        ["track-writes"] => {
+            pcx.config.track_writes = !pcx.config.track_writes;
+            track_writes_message(pcx)
+        }
+        ["track-writes", "on"] => {
+            pcx.config.track_writes = true;
+            track_writes_message(pcx)
+        }
+        ["track-writes", "off"] => {
+            pcx.config.track_writes = false;
+            track_writes_message(pcx)
+        }
+        ["step-over-drops"] => {
+            pcx.config.step_over_drops = !pcx.config.step_over_drops;
+            step_over_drops_message(pcx)
+        }
+        ["step-over-drops", "on"] => {
+            pcx.config.step_over_drops = true;
+            step_over_drops_message(pcx)
+        }
+        ["step-over-drops", "off"] => {
+            pcx.config.step_over_drops = false;
+            step_over_drops_message(pcx)
+        }
+        ["auto-deref", n] => match n.parse::<usize>() {
+            Ok(n) if n <= 2 => {
+                pcx.config.auto_deref = n;
+                format!("Locals are dereferenced {} levels deep", n)
+            }
+            _ => return Err(format!("Auto-deref depth {:?} is not 0, 1 or 2", n)),
+        },
+        ["layout"] => {
+            pcx.config.show_layout = !pcx.config.show_layout;
+            layout_message(pcx)
+        }
+        ["layout", "on"] => {
+            pcx.config.show_layout = true;
+            layout_message(pcx)
+        }
+        ["layout", "off"] => {
+            pcx.config.show_layout = false;
+            layout_message(pcx)
+        }
+        ["undef-mask"] => {
+            pcx.config.show_undef_mask = !pcx.config.show_undef_mask;
+            undef_mask_message(pcx)
+        }
+        ["undef-mask", "on"] => {
+            pcx.config.show_undef_mask = true;
+            undef_mask_message(pcx)
+        }
+        ["undef-mask", "off"] => {
+            pcx.config.show_undef_mask = false;
+            undef_mask_message(pcx)
+        }
+        ["memory-timeline"] => {
+            pcx.config.memory_timeline = !pcx.config.memory_timeline;
+            memory_timeline_message(pcx)
+        }
+        ["memory-timeline", "on"] => {
+            pcx.config.memory_timeline = true;
+            memory_timeline_message(pcx)
+        }
+        ["memory-timeline", "off"] => {
+            pcx.config.memory_timeline = false;
+            memory_timeline_message(pcx)
+        }
+        ["int-format", format] => match crate::render::locals::IntFormat::parse(format) {
+            Some(format) => {
+                pcx.config.int_format = format;
+                format!("Integers are printed as {}", format.name())
+            }
+            None => return Err(format!("Integer format {:?} is not dec, hex or both", format)),
+        },
+        ["limit"] => match pcx.config.step_limit {
+            Some(limit) => format!("Commands stop after {} steps", limit),
+            None => "Commands don't have a step limit".to_string(),
+        },
+        ["limit", "unlimited"] => {
+            pcx.config.step_limit = None;
+            "Removed the step limit".to_string()
+        }
+        ["limit", n] => match parse_count(n) {
+            Ok(n) => {
+                pcx.config.step_limit = Some(u128::from(n));
+                format!("Commands stop after {} steps", n)
+            }
+            Err(e) => return Err(e),
+        },
+        ["break", bp..] => match crate::api::add_breakpoint(pcx, &bp.join(" ")) {
+            Ok(()) => format!("Breakpoint added for {}", bp.join(" ")),
+            Err(e) => return Err(e),
+        },
+        ["delete", bp..] => match crate::api::remove_breakpoint(pcx, &bp.join(" ")) {
+            Ok(true) => format!("Breakpoint removed for {}", bp.join(" ")),
+            Ok(false) => format!("No breakpoint for {}", bp.join(" ")),
+            Err(e) => return Err(e),
+        },
+        ["record-script", "off"] => crate::script::stop_recording(pcx),
+        ["record-script", file] => crate::script::start_recording(pcx, file)?,
+        _ => return Err(format!("Unknown command {:?}", cmd)),
+    })
+}
+
+/// Restart the evaluation and run to the first statement of `main`. Breakpoints, watched
+/// allocations and settings are kept.
+fn restart(pcx: &mut PrirodaContext) -> String {
+    pcx.restart();
+    let main_id = pcx.ecx.tcx.entry_fn(LOCAL_CRATE).map(|(def_id, _)| def_id);
+    let in_main = |ecx: &InterpretCx| ecx.stack().last().map(|frame| frame.instance.def_id()) == main_id;
+    if in_main(&pcx.ecx) {
+        return "restarted".to_string();
+    }
+    match step(pcx, |ecx| if in_main(ecx) { ShouldContinue::Stop } else { ShouldContinue::Continue }) {
+        StopReason::StepComplete => "restarted".to_string(),
+        reason => format!("restarted, but stopped before reaching main: {}", reason),
+    }
+}
+
+/// Execute one statement. Calls to functions matching the skip list are executed completely, as
+/// are `Drop` terminators if `step_over_drops` is enabled.
+fn step_into(pcx: &mut PrirodaContext) -> String {
+    let frame = pcx.ecx.stack().len();
+    let skip = pcx.config.skip.clone();
+    let over_drop = pcx.config.step_over_drops && is_at_drop(&pcx.ecx);
+    step(pcx, |ecx| {
+        if over_drop && ecx.stack().len() > frame {
+            return ShouldContinue::Continue;
+        }
+        // `frame` is the index of the first frame pushed by the current statement
+        if let Some(callee) = ecx.stack().get(frame) {
+            let path = ecx.tcx.def_path_str(callee.instance.def_id());
+            if skip.iter().any(|pattern| path.contains(&pattern[..])) {
+                return ShouldContinue::Continue;
+            }
+        }
+        ShouldContinue::Stop
+    })
+    .to_string()
+}
+
+fn step_over_drops_message(pcx: &PrirodaContext) -> String {
+    if pcx.config.step_over_drops {
+        "Step runs drop glue completely".to_string()
+    } else {
+        "Step steps into drop glue".to_string()
+    }
+}
+
+/// Run until a new frame for a function whose path contains `pattern` is pushed. The current
+/// frame doesn't count, even if it matches.
+fn continue_to_fn(pcx: &mut PrirodaContext, pattern: &str) -> String {
+    let depth = ::std::cell::Cell::new(pcx.ecx.stack().len());
+    let reason = step(pcx, |ecx| {
+        if ecx.stack().len() > depth.get() {
+            let path = ecx.tcx.def_path_str(ecx.frame().instance.def_id());
+            if path.contains(pattern) {
+                return ShouldContinue::Stop;
+            }
+        }
+        depth.set(ecx.stack().len());
+        ShouldContinue::Continue
+    });
+    match reason {
+        StopReason::StepComplete => format!("Entered {}", pcx.ecx.frame().instance),
+        StopReason::Finished => format!("No function matching {:?} was called before the program finished", pattern),
+        reason => reason.to_string(),
+    }
+}
+
+/// Run until right after a statement that wrote to stdout or stderr.
+fn continue_to_output(pcx: &mut PrirodaContext) -> String {
+    let outputs = pcx.traces.outputs();
+    // Writes to other file descriptors stop as well, so keep going until stdout or stderr
+    // were written to
+    while pcx.traces.outputs() == outputs {
+        let at_write = ::std::cell::Cell::new(false);
+        let reason = step(pcx, |ecx| {
+            if at_write.get() {
+                return ShouldContinue::Stop;
+            }
+            at_write.set(is_at_write(ecx));
+            ShouldContinue::Continue
+        });
+        match reason {
+            StopReason::StepComplete => {}
+            StopReason::Finished => return "The program finished without producing output".to_string(),
+            reason => return reason.to_string(),
+        }
+    }
+    let (_, fd, bytes) = pcx.traces.last_output().unwrap();
+    let mut text = String::from_utf8_lossy(bytes).into_owned();
+    if text.chars().count() > 200 {
+        text = text.chars().take(200).collect::<String>() + "...";
+    }
+    format!("Wrote {:?} to {}", text, if *fd == 1 { "stdout" } else { "stderr" })
+}
+
+/// Whether the next step calls the foreign `write` function.
+fn is_at_write(ecx: &InterpretCx) -> bool {
+    let frame = match ecx.stack().last() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    let block = &frame.mir.basic_blocks()[frame.block];
+    if frame.stmt != block.statements.len() {
+        return false;
+    }
+    match block.terminator().kind {
+        mir::TerminatorKind::Call { ref func, .. } => match func.ty(frame.mir, ecx.tcx.tcx).sty {
+            rustc::ty::TyKind::FnDef(def_id, _) => crate::watch::is_write_shim(ecx.tcx.tcx, def_id),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn strict_writes_message(pcx: &PrirodaContext) -> String {
+    if pcx.config.strict_writes {
+        "Stepping stops before writes to immutable allocations".to_string()
+    } else {
+        "Stepping doesn't check writes to immutable allocations".to_string()
+    }
+}
+
+fn track_writes_message(pcx: &PrirodaContext) -> String {
+    if pcx.config.track_writes {
+        "Stepping remembers which allocations were written".to_string()
+    } else {
+        "Stepping doesn't remember which allocations were written".to_string()
+    }
+}
+
+fn layout_message(pcx: &PrirodaContext) -> String {
+    if pcx.config.show_layout {
+        "The locals table shows layouts".to_string()
+    } else {
+        "The locals table hides layouts".to_string()
+    }
+}
+
+fn undef_mask_message(pcx: &PrirodaContext) -> String {
+    if pcx.config.show_undef_mask {
+        "Hexdumps show which bytes are defined".to_string()
+    } else {
+        "Hexdumps hide which bytes are defined".to_string()
+    }
+}
+
+fn memory_timeline_message(pcx: &PrirodaContext) -> String {
+    if pcx.config.memory_timeline {
+        "Sampling the live heap memory for the memory timeline".to_string()
+    } else {
+        "Stopped sampling the memory timeline".to_string()
+    }
+}
+
+fn is_at_drop(ecx: &InterpretCx) -> bool {
+    let frame = match ecx.stack().last() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    let block = &frame.mir.basic_blocks()[frame.block];
+    if frame.stmt != block.statements.len() {
+        return false;
+    }
+    match block.terminator().kind {
+        mir::TerminatorKind::Drop { .. } | mir::TerminatorKind::DropAndReplace { .. } => true,
+        _ => false,
+    }
+}
+
+fn parse_count(n: &str) -> Result<u64, String> {
+    match n.parse::<u64>() {
+        Ok(0) => Err("Can't execute 0 steps, use `continue` to run until a breakpoint".to_string()),
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("Step count {:?} is not a positive integer", n)),
+    }
+}
+
+/// Describe why a command meant to execute `n` steps stopped after `done` steps.
+fn stopped_early(reason: StopReason, done: u64, n: u64) -> String {
+    match reason {
+        _ if done == n => reason.to_string(),
+        StopReason::Breakpoint(_) => format!("Hit a breakpoint after {} of {} steps", done, n),
+        reason => format!("{} (after {} of {} steps)", reason, done, n),
+    }
+}
+
+fn step_n(pcx: &mut PrirodaContext, n: u64) -> String {
+    let done = ::std::cell::Cell::new(0);
+    let reason = step(pcx, |_ecx| {
+        done.set(done.get() + 1);
+        if done.get() == n {
+            ShouldContinue::Stop
+        } else {
+            ShouldContinue::Continue
+        }
+    });
+    stopped_early(reason, done.get(), n)
+}
+
+fn next_n(pcx: &mut PrirodaContext, n: u64) -> String {
+    let done = ::std::cell::Cell::new(0);
+    let frame = ::std::cell::Cell::new(pcx.ecx.stack().len());
+    let reason = step(pcx, |ecx| {
+        // A deeper stack means we are inside a call made by the frame. Any step ending in the
+        // frame itself (or a caller, if it returned) executed one of its statements or
+        // terminators, so we are at a new position even if a loop jumped backwards or a block
+        // jumped to itself.
+        if ecx.stack().len() <= frame.get() {
+            done.set(done.get() + 1);
+            if done.get() == n {
+                return ShouldContinue::Stop;
+            }
+            frame.set(ecx.stack().len());
+        }
+        ShouldContinue::Continue
+    });
+    stopped_early(reason, done.get(), n)
+}
+
+/// Run until the `n`th frame counted from the innermost one is about to return, so `return 1`
+/// finishes the current frame. Runs to the end if the stack has fewer frames.
+fn return_n(pcx: &mut PrirodaContext, n: u64) -> String {
+    let len = pcx.ecx.stack().len() as u64;
+    let target = (len + 1).saturating_sub(n);
+    let reason = step(pcx, |ecx| {
+        if ecx.stack().len() as u64 <= target && is_ret(&ecx) {
+            ShouldContinue::Stop
+        } else {
+            ShouldContinue::Continue
+        }
+    });
+    match (&reason, pcx.ecx.stack().last()) {
+        (StopReason::StepComplete, Some(frame)) => {
+            format!("Returning from frame {} ({})", pcx.ecx.stack().len() - 1, frame.instance)
+        }
+        (_, Some(frame)) if n > 1 => format!("{}, in frame {} ({})", reason, pcx.ecx.stack().len() - 1, frame.instance),
+        _ => reason.to_string(),
+    }
+}
+
+/// Run until the current frame leaves the innermost loop containing the current block. Outside
+/// of loops this is `next`.
+fn until(pcx: &mut PrirodaContext) -> String {
+    let frame = pcx.ecx.stack().len();
+    let body = match innermost_loop(pcx.ecx.frame().mir, pcx.ecx.frame().block) {
+        Some(body) => body,
+        None => return next_n(pcx, 1),
+    };
+    step(pcx, |ecx| {
+        if ecx.stack().len() > frame {
+            ShouldContinue::Continue
+        } else if ecx.stack().len() < frame || !body.contains(&ecx.frame().block) {
+            ShouldContinue::Stop
+        } else {
+            ShouldContinue::Continue
+        }
+    })
+    .to_string()
+}
+
+/// The blocks of the innermost natural loop containing `block`. An edge is a back-edge of a
+/// loop if its target dominates its source.
+fn innermost_loop(mir: &mir::Body, block: mir::BasicBlock) -> Option<HashSet<mir::BasicBlock>> {
+    let dominators = mir.dominators();
+    let mut preds = HashMap::<mir::BasicBlock, Vec<mir::BasicBlock>>::new();
+    for (bb, data) in mir.basic_blocks().iter_enumerated() {
+        for &succ in data.terminator().successors() {
+            preds.entry(succ).or_insert_with(Vec::new).push(bb);
+        }
+    }
+    let mut innermost: Option<HashSet<mir::BasicBlock>> = None;
+    for (bb, data) in mir.basic_blocks().iter_enumerated() {
+        if !dominators.is_reachable(bb) {
+            continue;
+        }
+        for &header in data.terminator().successors() {
+            if !dominators.is_dominated_by(bb, header) {
+                continue;
+            }
+            // Everything reaching the back-edge without passing the header is in the loop
+            let mut body = HashSet::new();
+            body.insert(header);
+            let mut todo = vec![bb];
+            while let Some(node) = todo.pop() {
+                if body.insert(node) {
+                    todo.extend(preds.get(&node).into_iter().flatten().cloned());
+                }
+            }
+            if body.contains(&block) && innermost.as_ref().map_or(true, |inner| body.len() < inner.len()) {
+                innermost = Some(body);
+            }
+        }
+    }
+    innermost
+}
+
+/// A hash of the bytes, the undef mask and the relocations of an allocation, `None` if it
+/// doesn't exist (anymore).
+fn hash_alloc(ecx: &InterpretCx, alloc_id: miri::AllocId) -> Option<u64> {
+    use rustc::ty::layout::Size;
+    use std::hash::{Hash, Hasher};
+    let alloc = ecx.memory().get(alloc_id).ok()?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    alloc.bytes.hash(&mut hasher);
+    for i in 0..alloc.bytes.len() {
+        alloc.undef_mask.get(Size::from_bytes(i as u64)).hash(&mut hasher);
+    }
+    for (offset, &(_tag, target)) in alloc.relocations.iter() {
+        (offset.bytes(), target.0).hash(&mut hasher);
+    }
+    Some(hasher.finish())
+}
+
+/// Run until the contents of the allocation change or it is deallocated.
+fn step_until_changed(pcx: &mut PrirodaContext, alloc_id: u64) -> String {
+    let before = match crate::api::memory(pcx, alloc_id) {
+        Some(memory) => memory,
+        None => return format!("No allocation {}", alloc_id),
+    };
+    let alloc_id = miri::AllocId(alloc_id);
+    let hash = hash_alloc(&pcx.ecx, alloc_id);
+    let reason = step(pcx, |ecx| {
+        if hash_alloc(ecx, alloc_id) == hash {
+            ShouldContinue::Continue
+        } else {
+            ShouldContinue::Stop
+        }
+    });
+    match (reason, crate::api::memory(pcx, alloc_id.0)) {
+        (StopReason::StepComplete, None) | (StopReason::Breakpoint(_), None) => {
+            format!("Allocation {} was deallocated", alloc_id.0)
+        }
+        (StopReason::StepComplete, Some(ref after)) if *after == before => {
+            format!("Allocation {} didn't change", alloc_id.0)
+        }
+        (StopReason::StepComplete, Some(ref after)) | (StopReason::Breakpoint(_), Some(ref after)) if *after != before => {
+            format!("Allocation {} changed: {}", alloc_id.0, diff_memory(&before, after))
+        }
+        (reason, _) => reason.to_string(),
+    }
+}
+
+/// The changed bytes and pointers as `offset: old -> new`, formatted like `print_alloc`.
+fn diff_memory(before: &crate::api::Memory, after: &crate::api::Memory) -> String {
+    let hex = |byte: Option<&Option<u8>>| match byte {
+        Some(Some(byte)) => format!("{:02x}", byte),
+        Some(None) => "__".to_string(),
+        None => "--".to_string(),
+    };
+    let mut changes = (0..before.bytes.len().max(after.bytes.len()))
+        .filter(|&i| before.bytes.get(i) != after.bytes.get(i))
+        .map(|i| format!("{}: {} -> {}", i, hex(before.bytes.get(i)), hex(after.bytes.get(i))))
+        .collect::<Vec<_>>();
+    let target = |memory: &crate::api::Memory, offset| {
+        memory
+            .relocations
+            .iter()
+            .find(|&&(o, _)| o == offset)
+            .map(|&(_, target)| format!("pointer to {}", target))
+            .unwrap_or_else(|| "no pointer".to_string())
+    };
+    let mut offsets = before.relocations.iter().chain(&after.relocations).map(|&(offset, _)| offset).collect::<Vec<_>>();
+    offsets.sort();
+    offsets.dedup();
+    for offset in offsets {
+        let (old, new) = (target(before, offset), target(after, offset));
+        if old != new {
+            changes.push(format!("{}: {} -> {}", offset, old, new));
+        }
+    }
+    if changes.len() > 32 {
+        let more = changes.len() - 32;
+        changes.truncate(32);
+        changes.push(format!("and {} more", more));
+    }
+    changes.join(", ")
+}
+
+/// Run until the terminator of the current block is the next thing to execute. If it already
+/// is, execute it and stop at the start of the successor block.
+fn step_block(pcx: &mut PrirodaContext) -> String {
+    let frame = pcx.ecx.stack().len();
+    let block = pcx.ecx.frame().block;
+    step(pcx, |ecx| {
+        if ecx.stack().len() > frame {
+            return ShouldContinue::Continue;
+        }
+        let cur = ecx.frame();
+        if ecx.stack().len() < frame
+            || cur.block != block
+            || cur.stmt == cur.mir.basic_blocks()[cur.block].statements.len()
+        {
+            ShouldContinue::Stop
+        } else {
+            ShouldContinue::Continue
+        }
+    })
+    .to_string()
+}
+
+fn step_back(pcx: &mut PrirodaContext) -> String {
+    if *pcx.step_count > 0 {
+        let target = *pcx.step_count - 1;
+        match replay_to(pcx, target) {
+            Ok(()) => "stepped back".to_string(),
+            Err(e) => e,
+        }
+    } else {
+        "already at the start".to_string()
+    }
+}
+
+/// Run until statement `stmt` of block `bb` is reached in the current stack frame.
+fn run_to(pcx: &mut PrirodaContext, bb: usize, stmt: usize) -> String {
+    let frame = pcx.ecx.stack().len();
+    let def_id = pcx.ecx.frame().instance.def_id();
+    let block = mir::BasicBlock::new(bb);
+    match pcx.ecx.frame().mir.basic_blocks().get(block) {
+        Some(data) if stmt <= data.statements.len() => {}
+        _ => return format!("No statement {}:{} in {:?}", bb, stmt, def_id),
+    }
+    let reached = ::std::cell::Cell::new(false);
+    let reason = step(pcx, |ecx| {
+        if ecx.stack().len() == frame
+            && ecx.frame().instance.def_id() == def_id
+            && ecx.frame().block == block
+            && ecx.frame().stmt == stmt
+        {
+            reached.set(true);
+            ShouldContinue::Stop
+        } else if ecx.stack().len() < frame || (ecx.stack().len() == frame && is_ret(&ecx)) {
+            ShouldContinue::Stop
+        } else {
+            ShouldContinue::Continue
+        }
+    });
+    match reason {
+        StopReason::StepComplete if reached.get() => format!("Reached {}:{}", bb, stmt),
+        StopReason::StepComplete => format!("Function returned before reaching {}:{}", bb, stmt),
+        reason => reason.to_string(),
+    }
+}
+
 pub mod step_routes {
     use super::*;
-    use crate::action_route;
+    use crate::{action_route, PrirodaSender, RResult};
+    use rocket::response::content::Html;
+    use rocket::response::{Flash, Redirect};
+    use rocket::State;
 
     pub fn routes() -> Vec<::rocket::Route> {
-        routes![restart, single, single_back, next, return_, continue_]
+        routes![restart, single, single_n, single_back, next, next_n, block, until, return_, continue_, continue_to_fn, continue_to_output, reverse_continue, status, cancel, goto, until_changed, limit, auto_deref, int_format, undo, toggle_drops, toggle_strict_writes, toggle_track_writes, toggle_layout, toggle_undef_mask]
     }
 
     action_route!(restart: "/restart", |pcx| {
-        pcx.restart();
-        "restarted".to_string()
+        step_command(pcx, "restart")
     });
 
     action_route!(single: "/single", |pcx| {
-        step(pcx, |_ecx| ShouldContinue::Stop)
+        step_command(pcx, "step")
+    });
+
+    action_route!(single_n: "/single/<n>", |pcx, n: String| {
+        step_command(pcx, &format!("step {}", n))
     });
 
     action_route!(single_back: "/single_back", |pcx| {
-        let orig_step_count = *pcx.step_count;
-        pcx.restart();
-        *pcx.step_count = orig_step_count;
-        if *pcx.step_count > 0 {
-            *pcx.step_count -= 1;
-            for _ in 0..*pcx.step_count {
-                match pcx.ecx.step() {
-                    Ok(true) => crate::watch::step_callback(pcx), // Rebuild traces till the current instruction
-                    res => return format!("Miri is not deterministic causing error {:?}", res),
-                }
-            }
-            "stepped back".to_string()
-        } else {
-            "already at the start".to_string()
-        }
+        step_command(pcx, "step_back")
     });
 
     action_route!(next: "/next", |pcx| {
-        let frame = pcx.ecx.stack().len();
-        let stmt = pcx.ecx.frame().stmt;
-        let block = pcx.ecx.frame().block;
-        step(pcx, |ecx| {
-            if ecx.stack().len() <= frame && (block < ecx.frame().block || stmt < ecx.frame().stmt) {
-                ShouldContinue::Stop
-            } else {
-                ShouldContinue::Continue
-            }
-        })
+        step_command(pcx, "next")
+    });
+
+    action_route!(next_n: "/next/<n>", |pcx, n: String| {
+        step_command(pcx, &format!("next {}", n))
+    });
+
+    action_route!(block: "/block", |pcx| {
+        step_command(pcx, "step-block")
+    });
+
+    action_route!(until: "/until", |pcx| {
+        step_command(pcx, "until")
     });
 
     action_route!(return_: "/return", |pcx| {
-        let frame = pcx.ecx.stack().len();
-        step(pcx, |ecx| {
-            if ecx.stack().len() <= frame && is_ret(&ecx) {
-                ShouldContinue::Stop
-            } else {
-                ShouldContinue::Continue
-            }
+        step_command(pcx, "return")
+    });
+
+    #[derive(Responder)]
+    pub enum Status {
+        Running(Html<String>),
+        Finished(Flash<Redirect>),
+    }
+
+    action_route!(reverse_continue: "/reverse_continue", |pcx| {
+        step_command(pcx, "reverse-continue")
+    });
+
+    /// Start `continue` in the background and show its progress.
+    #[get("/continue")]
+    pub fn continue_(sender: State<PrirodaSender>) -> RResult<Redirect> {
+        sender.spawn_run(|pcx| step_command(pcx, "continue"))?;
+        Ok(Redirect::to("/step/status"))
+    }
+
+    /// Start `continue-to-fn` in the background and show its progress.
+    #[get("/continue_to_fn?<pattern>")]
+    pub fn continue_to_fn(sender: State<PrirodaSender>, pattern: String) -> RResult<Redirect> {
+        sender.spawn_run(move |pcx| step_command(pcx, &format!("continue-to-fn {}", pattern)))?;
+        Ok(Redirect::to("/step/status"))
+    }
+
+    /// Start `continue-to-output` in the background and show its progress.
+    #[get("/continue_to_output")]
+    pub fn continue_to_output(sender: State<PrirodaSender>) -> RResult<Redirect> {
+        sender.spawn_run(|pcx| step_command(pcx, "continue-to-output"))?;
+        Ok(Redirect::to("/step/status"))
+    }
+
+    /// This doesn't go through the interpreter thread, which is busy while a command runs.
+    #[get("/status")]
+    pub fn status(sender: State<PrirodaSender>) -> Status {
+        match sender.run_state().progress() {
+            Some(steps) => Status::Running(crate::render::render_running(steps)),
+            None => Status::Finished(Flash::success(
+                Redirect::to("/"),
+                sender.run_state().take_message().unwrap_or_else(String::new),
+            )),
+        }
+    }
+
+    #[get("/cancel")]
+    pub fn cancel(sender: State<PrirodaSender>) -> Redirect {
+        sender.run_state().cancel();
+        Redirect::to("/step/status")
+    }
+
+    action_route!(goto: "/goto/<bb>/<stmt>", |pcx, bb: usize, stmt: usize| {
+        step_command(pcx, &format!("goto {} {}", bb, stmt))
+    });
+
+    action_route!(until_changed: "/until_changed/<alloc_id>", |pcx, alloc_id: u64| {
+        step_command(pcx, &format!("step-until-changed {}", alloc_id))
+    });
+
+    action_route!(auto_deref: "/auto_deref?<n>", |pcx, n: String| {
+        step_command(pcx, &format!("auto-deref {}", n))
+    });
+
+    /// Like an action route, but returns to the frame the toggle was clicked on.
+    #[get("/int_format?<format>&<frame>")]
+    pub fn int_format(
+        sender: State<PrirodaSender>,
+        format: String,
+        frame: Option<usize>,
+    ) -> RResult<Flash<Redirect>> {
+        sender.do_work(move |pcx| {
+            let msg = step_command(pcx, &format!("int-format {}", format));
+            let back = frame.map_or_else(|| "/".to_string(), |frame| format!("/frame/{}", frame));
+            Flash::success(Redirect::to(back), msg)
         })
+    }
+
+    action_route!(undo: "/undo", |pcx| {
+        step_command(pcx, "undo")
+    });
+
+    action_route!(toggle_strict_writes: "/toggle_strict_writes", |pcx| {
+        step_command(pcx, "strict-writes")
     });
 
-    action_route!(continue_: "/continue", |pcx| {
-        step(pcx, |_ecx| ShouldContinue::Continue)
+    action_route!(toggle_track_writes: "/toggle_track_writes", |pcx| {
+        step_command(pcx, "track-writes")
+    });
+
+    action_route!(toggle_layout: "/toggle_layout", |pcx| {
+        step_command(pcx, "layout")
+    });
+
+    action_route!(toggle_undef_mask: "/toggle_undef_mask", |pcx| {
+        step_command(pcx, "undef-mask")
+    });
+
+    action_route!(toggle_drops: "/toggle_drops", |pcx| {
+        step_command(pcx, "step-over-drops")
+    });
+
+    action_route!(limit: "/limit?<n>", |pcx, n: String| {
+        step_command(pcx, &format!("limit {}", n))
     });
 }
 
 pub mod bp_routes {
     use super::*;
     use crate::action_route;
+    use crate::api;
     use std::path::PathBuf;
 
     pub fn routes() -> Vec<::rocket::Route> {
-        routes![add_here, add, remove, remove_all]
+        routes![add_here, add, remove, remove_all, condition]
     }
 
     action_route!(add_here: "/add_here", |pcx| {
         let frame = pcx.ecx.frame();
-        pcx.config.bptree.add_breakpoint(Breakpoint(frame.instance.def_id(), frame.block, frame.stmt));
-        format!("Breakpoint added for {:?}@{}:{}", frame.instance.def_id(), frame.block.index(), frame.stmt)
+        let bp = format!("{:?}@{}:{}", frame.instance.def_id(), frame.block.index(), frame.stmt);
+        step_command(pcx, &format!("break {}", bp))
     });
 
     action_route!(add: "/add/<path..>", |pcx, path: PathBuf| {
-        let path = path.to_string_lossy();
-        let res = parse_breakpoint_from_url(&path);
-        match res {
-            Ok(breakpoint) => {
-                pcx.config.bptree.add_breakpoint(breakpoint);
-                format!("Breakpoint added for {:?}@{}:{}", breakpoint.0, breakpoint.1.index(), breakpoint.2)
-            }
-            Err(e) => e,
-        }
+        let path = path.to_string_lossy().replace("%20", " ");
+        step_command(pcx, &format!("break {}", path))
     });
 
     action_route!(remove: "/remove/<path..>", |pcx, path: PathBuf| {
-        let path = path.to_string_lossy();
-        let res = parse_breakpoint_from_url(&path);
-        match res {
-            Ok(breakpoint) => {
-                if pcx.config.bptree.remove_breakpoint(breakpoint) {
-                    format!("Breakpoint removed for {:?}@{}:{}", breakpoint.0, breakpoint.1.index(), breakpoint.2)
-                } else {
-                    format!("No breakpoint for for {:?}@{}:{}", breakpoint.0, breakpoint.1.index(), breakpoint.2)
-                }
-            }
+        let path = path.to_string_lossy().replace("%20", " ");
+        step_command(pcx, &format!("delete {}", path))
+    });
+
+    action_route!(condition: "/condition/<path..>?<condition>", |pcx, path: PathBuf, condition: String| {
+        let path = path.to_string_lossy().replace("%20", " ");
+        match api::set_breakpoint_condition(pcx, &path, &condition) {
+            Ok(()) if condition.trim().is_empty() => format!("Condition removed for {}", path),
+            Ok(()) => format!("Condition set for {}", path),
             Err(e) => e,
         }
     });
 
     action_route!(remove_all: "/remove_all", |pcx| {
-        pcx.config.bptree.remove_all();
+        api::remove_all_breakpoints(pcx);
         "All breakpoints removed".to_string()
     });
 }
+
+pub mod skip_routes {
+    use crate::action_route;
+    use crate::api;
+
+    pub fn routes() -> Vec<::rocket::Route> {
+        routes![add, remove]
+    }
+
+    action_route!(add: "/add?<pattern>", |pcx, pattern: String| {
+        if api::add_skip_pattern(pcx, &pattern) {
+            format!("Skipping functions matching {:?}", pattern)
+        } else {
+            format!("Already skipping functions matching {:?}", pattern)
+        }
+    });
+
+    action_route!(remove: "/remove?<pattern>", |pcx, pattern: String| {
+        if api::remove_skip_pattern(pcx, &pattern) {
+            format!("Not skipping functions matching {:?} anymore", pattern)
+        } else {
+            format!("Functions matching {:?} weren't skipped", pattern)
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{command, current_fn, restart, with_program};
+
+    const LOOP: &str = r#"
+fn main() {
+    let mut sum = 0;
+    for i in 0..3 {
+        sum += i;
+    }
+    assert_eq!(sum, 3);
+}
+"#;
+
+    const NESTED_LOOPS: &str = r#"
+fn main() {
+    let mut sum = 0;
+    for i in 0..2 {
+        for j in 0..2 {
+            sum += i * j;
+        }
+    }
+    assert_eq!(sum, 1);
+}
+"#;
+
+    const CALL: &str = r#"
+fn helper(x: u32) -> u32 {
+    let y = x * 2;
+    y + 1
+}
+
+fn main() {
+    let a = 1;
+    let b = helper(a);
+    let c = b + a;
+    println!("{}", c);
+}
+"#;
+
+    const CALLS: &str = r#"
+fn helper(x: u32) -> u32 {
+    x + 1
+}
+
+fn main() {
+    let mut sum = 0;
+    for i in 0..3 {
+        sum += helper(i);
+    }
+    assert_eq!(sum, 6);
+}
+"#;
+
+    const TRAIT_CALL: &str = r#"
+trait Greet {
+    fn greet(&self) -> u32;
+}
+
+struct Foo;
+
+impl Greet for Foo {
+    fn greet(&self) -> u32 {
+        1
+    }
+}
+
+fn main() {
+    let a = Foo.greet();
+    assert_eq!(a, 1);
+}
+"#;
+
+    /// The stack depth and the position in the innermost frame.
+    fn position(pcx: &PrirodaContext) -> (usize, mir::BasicBlock, usize) {
+        let frame = pcx.ecx.frame();
+        (pcx.ecx.stack().len(), frame.block, frame.stmt)
+    }
+
+    /// The block the call to a function whose path contains `callee` returns to, if that call is
+    /// the next thing to execute.
+    fn at_call(pcx: &PrirodaContext, callee: &str) -> Option<mir::BasicBlock> {
+        let frame = pcx.ecx.frame();
+        let block = &frame.mir.basic_blocks()[frame.block];
+        if frame.stmt != block.statements.len() {
+            return None;
+        }
+        match block.terminator().kind {
+            mir::TerminatorKind::Call { ref func, destination: Some((_, target)), .. } => match func.ty(frame.mir, pcx.ecx.tcx.tcx).sty {
+                rustc::ty::TyKind::FnDef(def_id, _) if pcx.ecx.tcx.def_path_str(def_id).contains(callee) => Some(target),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Run `next` until the current function returns, returning the position after every command
+    /// before.
+    fn next_to_end(pcx: &mut PrirodaContext) -> Vec<(usize, mir::BasicBlock, usize)> {
+        let depth = pcx.ecx.stack().len();
+        let mut positions = vec![position(pcx)];
+        for _ in 0..1000 {
+            command(pcx, "next");
+            // Returning from the bottom frame finishes the program instead
+            if pcx.ecx.stack().len() < depth || (depth == 1 && is_ret(&pcx.ecx)) {
+                return positions;
+            }
+            positions.push(position(pcx));
+        }
+        panic!("`next` didn't reach the end: {:?}", positions);
+    }
+
+    #[test]
+    fn next_follows_loop_back_edges() {
+        let positions = with_program(LOOP, next_to_end);
+        let depth = positions[0].0;
+        assert!(positions.iter().all(|position| position.0 == depth), "{:?}", positions);
+        assert!(positions.windows(2).all(|pair| pair[0] != pair[1]), "{:?}", positions);
+        // Every iteration stops at the statements of the loop body again
+        let distinct = positions.iter().collect::<HashSet<_>>();
+        assert!(distinct.len() < positions.len(), "{:?}", positions);
+    }
+
+    #[test]
+    fn next_steps_over_call_in_block() {
+        let (depth, target, steps, after, function) = with_program(CALL, |pcx| {
+            let depth = pcx.ecx.stack().len();
+            let mut target = None;
+            for _ in 0..100 {
+                target = at_call(pcx, "helper");
+                if target.is_some() {
+                    break;
+                }
+                command(pcx, "next");
+            }
+            let before = *pcx.step_count;
+            command(pcx, "next");
+            (depth, target.expect("no call to helper"), *pcx.step_count - before, position(pcx), current_fn(pcx))
+        });
+        assert_eq!(function, "main");
+        assert_eq!((after.0, after.1), (depth, target));
+        assert!(steps > 1, "the call to helper took {} steps", steps);
+    }
+
+    #[test]
+    fn until_leaves_simple_loop() {
+        let (depth, outside_at_start, body, after, rest) = with_program(LOOP, |pcx| {
+            let depth = pcx.ecx.stack().len();
+            let outside_at_start = innermost_loop(pcx.ecx.frame().mir, pcx.ecx.frame().block).is_none();
+            let mut body = None;
+            for _ in 0..100 {
+                body = innermost_loop(pcx.ecx.frame().mir, pcx.ecx.frame().block);
+                if body.is_some() {
+                    break;
+                }
+                command(pcx, "next");
+            }
+            let body = body.expect("never entered the loop");
+            command(pcx, "until");
+            let after = position(pcx);
+            (depth, outside_at_start, body, after, next_to_end(pcx))
+        });
+        assert!(outside_at_start);
+        assert!(!body.contains(&mir::START_BLOCK));
+        assert_eq!(after.0, depth);
+        assert!(!body.contains(&after.1), "{:?} is in the loop {:?}", after, body);
+        // The loop ran to completion, so it is never entered again
+        assert!(rest.iter().all(|&(_, block, _)| !body.contains(&block)), "{:?}", rest);
+    }
+
+    #[test]
+    fn until_leaves_innermost_loop() {
+        let (depth, inner, outer, after) = with_program(NESTED_LOOPS, |pcx| {
+            let depth = pcx.ecx.stack().len();
+            let mir = pcx.ecx.frame().mir;
+            let loops = mir.basic_blocks().indices().filter_map(|bb| innermost_loop(mir, bb)).collect::<Vec<_>>();
+            let inner = loops.iter().min_by_key(|body| body.len()).expect("no loops").clone();
+            let outer = loops.iter().max_by_key(|body| body.len()).unwrap().clone();
+            for _ in 0..200 {
+                if inner.contains(&pcx.ecx.frame().block) {
+                    break;
+                }
+                command(pcx, "next");
+            }
+            command(pcx, "until");
+            (depth, inner, outer, position(pcx))
+        });
+        assert!(inner.len() < outer.len() && inner.is_subset(&outer), "{:?} {:?}", inner, outer);
+        assert_eq!(after.0, depth);
+        assert!(!inner.contains(&after.1), "{:?} is in the inner loop {:?}", after, inner);
+        assert!(outer.contains(&after.1), "{:?} left the outer loop {:?}", after, outer);
+    }
+
+    const DANGLING: &str = r#"
+fn main() {
+    let p = 16usize as *const u8;
+    let x = unsafe { *p };
+    assert_eq!(x, 0);
+}
+"#;
+
+    const STATIC_WRITE: &str = r#"
+static X: u8 = 1;
+
+fn main() {
+    let p = &X as *const u8 as *mut u8;
+    unsafe {
+        *p = 2;
+    }
+}
+"#;
+
+    /// Commands which run far enough to stop for any reason except completing their step.
+    const RUNNING: &[&str] = &["continue", "step 100000", "next 100000", "return 100", "continue-to-fn no_such_fn", "continue-to-output"];
+
+    /// Run each of `cmds` from the start of `main` and note the reason it stopped for.
+    fn stops(pcx: &mut PrirodaContext, cmds: &[&str]) -> Vec<(String, Option<&'static str>)> {
+        cmds.iter()
+            .map(|cmd| {
+                restart(pcx);
+                step_command(pcx, cmd);
+                (cmd.to_string(), pcx.last_stop.as_ref().map(StopReason::kind))
+            })
+            .collect()
+    }
+
+    fn assert_stops(stops: &[(String, Option<&str>)], expected: &str) {
+        for (cmd, kind) in stops {
+            assert_eq!(*kind, Some(expected), "`{}`", cmd);
+        }
+    }
+
+    /// Step into `helper`.
+    fn enter_helper(pcx: &mut PrirodaContext) {
+        while current_fn(pcx) != "helper" {
+            command(pcx, "step");
+        }
+    }
+
+    #[test]
+    fn stop_step_complete() {
+        let cmds = &["step", "step 2", "next", "next 2", "step-block", "until", "continue-to-fn helper", "continue-to-output", "goto 1 0"];
+        let (mut completed, returned) = with_program(CALL, move |pcx| {
+            let completed = stops(pcx, cmds);
+            enter_helper(pcx);
+            step_command(pcx, "return");
+            (completed, pcx.last_stop.as_ref().map(StopReason::kind))
+        });
+        completed.push(("return".to_string(), returned));
+        assert_stops(&completed, "step_complete");
+    }
+
+    #[test]
+    fn stop_finished() {
+        let (finished, output) = with_program(CALL, |pcx| {
+            let finishing = RUNNING.iter().cloned().filter(|&cmd| cmd != "continue-to-output").collect::<Vec<_>>();
+            let finished = stops(pcx, &finishing);
+            // The first one stops after the output of `println!`
+            restart(pcx);
+            command(pcx, "continue-to-output");
+            let output = step_command(pcx, "continue-to-output");
+            (finished, (output, pcx.last_stop.as_ref().map(StopReason::kind)))
+        });
+        assert_stops(&finished, "finished");
+        assert_eq!(output, ("The program finished without producing output".to_string(), Some("finished")));
+    }
+
+    #[test]
+    fn stop_breakpoint() {
+        let (bp, at_bp, last) = with_program(CALL, |pcx| {
+            enter_helper(pcx);
+            let bp = Breakpoint(pcx.ecx.frame().instance.def_id(), mir::START_BLOCK, 0);
+            pcx.config.bptree.add_breakpoint(bp);
+            let at_bp = stops(pcx, RUNNING);
+            (bp, at_bp, pcx.last_stop.clone())
+        });
+        assert_stops(&at_bp, "breakpoint");
+        match last {
+            Some(StopReason::Breakpoint(at)) => assert_eq!(at, bp),
+            reason => panic!("stopped for {:?}", reason),
+        }
+    }
+
+    #[test]
+    fn stop_step_limit() {
+        let limited = with_program(CALL, |pcx| {
+            command(pcx, "limit 3");
+            let mut limited = stops(pcx, RUNNING);
+            limited.extend(stops(pcx, &["goto 1 0"]));
+            limited
+        });
+        assert_stops(&limited, "step_limit");
+    }
+
+    #[test]
+    fn stop_error() {
+        let (failed, checked) = with_program(DANGLING, |pcx| {
+            let failed = stops(pcx, RUNNING);
+            restart(pcx);
+            (failed, checked_step_command(pcx, "continue").is_err())
+        });
+        assert_stops(&failed, "error");
+        assert!(checked, "`continue` into an error succeeded");
+    }
+
+    #[test]
+    fn stop_interrupted() {
+        let interrupted = with_program(CALL, |pcx| {
+            RUNNING
+                .iter()
+                .map(|cmd| {
+                    restart(pcx);
+                    pcx.run.cancel.store(true, Ordering::SeqCst);
+                    step_command(pcx, cmd);
+                    (cmd.to_string(), pcx.last_stop.as_ref().map(StopReason::kind))
+                })
+                .collect::<Vec<_>>()
+        });
+        assert_stops(&interrupted, "interrupted");
+    }
+
+    #[test]
+    fn stop_immutable_write() {
+        let written = with_program(STATIC_WRITE, |pcx| {
+            command(pcx, "strict-writes on");
+            stops(pcx, RUNNING)
+        });
+        assert_stops(&written, "immutable_write");
+    }
+
+    #[test]
+    fn continue_to_fn_pattern_with_spaces() {
+        let entered = with_program(TRAIT_CALL, |pcx| {
+            command(pcx, "continue-to-fn Foo as Greet");
+            current_fn(pcx)
+        });
+        assert_eq!(entered, "<Foo as Greet>::greet");
+    }
+
+    #[test]
+    fn replay_counts_breakpoint_hits() {
+        let hits = with_program(CALLS, |pcx| {
+            enter_helper(pcx);
+            pcx.config.bptree.add_breakpoint(Breakpoint(pcx.ecx.frame().instance.def_id(), mir::START_BLOCK, 0));
+            restart(pcx);
+            let hits = |pcx: &PrirodaContext| pcx.config.bptree.iter_info().next().unwrap().1.hits;
+            command(pcx, "continue");
+            command(pcx, "continue");
+            let forward = hits(pcx);
+            // Back to right before the second hit
+            command(pcx, "step_back");
+            let back = hits(pcx);
+            command(pcx, "restart");
+            (forward, back, hits(pcx))
+        });
+        assert_eq!(hits, (2, 1, 0));
+    }
+
+    #[test]
+    fn reverse_continue_evaluates_conditions() {
+        let (first_hit, reversed, position) = with_program(CALLS, |pcx| {
+            enter_helper(pcx);
+            let bp = Breakpoint(pcx.ecx.frame().instance.def_id(), mir::START_BLOCK, 0);
+            pcx.config.bptree.add_breakpoint(bp);
+            let id = pcx.config.bptree.iter_info().next().unwrap().1.id;
+            // Only the first call stops
+            let condition = Condition::parse(&format!("$hits({}) < 1", id)).unwrap();
+            pcx.config.bptree.set_condition(bp, Some(condition));
+            restart(pcx);
+            command(pcx, "continue");
+            let first_hit = *pcx.step_count;
+            command(pcx, "continue");
+            let reversed = command(pcx, "reverse-continue");
+            (first_hit, reversed, *pcx.step_count)
+        });
+        assert_eq!(reversed, format!("Hit a breakpoint at step {}", first_hit));
+        assert_eq!(position, first_hit);
+    }
+}
@@ -0,0 +1,59 @@
+//! Interpreting small programs in the unit tests.
+//!
+//! The programs are interpreted like priroda interprets any program, so the tests need a sysroot
+//! prepared for miri, found the same way as `find_sysroot` does.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::*;
+
+/// Numbers the source files of the programs of one test run.
+static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Interpret `source` and call `f` with the interpreter stopped at the start of `main`.
+pub fn with_program<T, F>(source: &str, f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce(&mut PrirodaContext) -> T + Send + 'static,
+{
+    let path = std::env::temp_dir().join(format!(
+        "priroda-test-{}-{}.rs",
+        std::process::id(),
+        PROGRAMS.fetch_add(1, Ordering::SeqCst),
+    ));
+    std::fs::write(&path, source).unwrap();
+    let (sender, _handle) = spawn_interpreter(vec!["priroda".to_string(), path.display().to_string()]);
+    let result = sender.do_work(move |pcx| {
+        restart(pcx);
+        f(pcx)
+    });
+    let _ = std::fs::remove_file(&path);
+    // Dropping the sender ends the interpreter thread, which restarts it as if it had crashed
+    std::mem::forget(sender);
+    match result {
+        Ok(result) => result,
+        Err(_) => panic!("Miri crashed while interpreting {}", path.display()),
+    }
+}
+
+/// Run `cmd` and fail the test if it fails.
+pub fn command(pcx: &mut PrirodaContext, cmd: &str) -> String {
+    match crate::step::checked_step_command(pcx, cmd) {
+        Ok(msg) => msg,
+        Err(err) => panic!("`{}` failed: {}", cmd, err),
+    }
+}
+
+/// Restart the program and run the startup code of the standard library up to the start of
+/// `main`.
+pub fn restart(pcx: &mut PrirodaContext) {
+    command(pcx, "restart");
+    while current_fn(pcx) != "main" {
+        command(pcx, "step");
+    }
+}
+
+/// The name of the function the innermost frame executes.
+pub fn current_fn(pcx: &PrirodaContext) -> String {
+    pcx.ecx.tcx.def_path_str(pcx.ecx.frame().instance.def_id())
+}
@@ -0,0 +1,210 @@
+//! The live heap allocations and bytes over the execution, to find where memory usage grows.
+//!
+//! With `memory-timeline on`, every step which is a multiple of the sampling interval records a
+//! sample. When there are more than `MAX_SAMPLES`, every other sample is dropped and the interval
+//! doubles, so the series stays bounded and evenly spaced. Replays rebuild the series, as
+//! restarting clears it.
+
+use horrorshow::prelude::*;
+use horrorshow::Raw;
+
+use crate::*;
+
+const MAX_SAMPLES: usize = 1000;
+
+/// The sampling interval of a new timeline.
+const INITIAL_INTERVAL: u128 = 16;
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 300.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub step: u128,
+    pub allocations: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct Timeline {
+    interval: u128,
+    samples: Vec<Sample>,
+    /// The steps which were at a breakpoint, every other one is dropped when there are too many
+    breakpoints: Vec<u128>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline {
+            interval: INITIAL_INTERVAL,
+            samples: Vec::new(),
+            breakpoints: Vec::new(),
+        }
+    }
+}
+
+impl Timeline {
+    pub fn clear(&mut self) {
+        *self = Timeline::default();
+    }
+
+    fn downsample(&mut self) {
+        self.interval *= 2;
+        let interval = self.interval;
+        self.samples.retain(|sample| sample.step % interval == 0);
+    }
+
+    fn thin_breakpoints(&mut self) {
+        let mut i = 0;
+        self.breakpoints.retain(|_| {
+            i += 1;
+            i % 2 == 0
+        });
+    }
+}
+
+/// Record a sample if the current step is due. Called after every step.
+pub fn record(pcx: &mut PrirodaContext) {
+    if !pcx.config.memory_timeline {
+        return;
+    }
+    let step = *pcx.step_count;
+    if !pcx.ecx.stack().is_empty() && pcx.config.bptree.is_at_breakpoint(&pcx.ecx) {
+        pcx.timeline.breakpoints.push(step);
+        if pcx.timeline.breakpoints.len() > MAX_SAMPLES {
+            pcx.timeline.thin_breakpoints();
+        }
+    }
+    // `step_callback` also runs without stepping, e.g. when adding a watch
+    if step % pcx.timeline.interval != 0 || pcx.timeline.samples.last().map(|sample| sample.step) == Some(step) {
+        return;
+    }
+    let heap = pcx.traces.heap();
+    pcx.timeline.samples.push(Sample {
+        step,
+        allocations: heap.live_allocations(),
+        bytes: heap.live_bytes,
+    });
+    if pcx.timeline.samples.len() > MAX_SAMPLES {
+        pcx.timeline.downsample();
+    }
+}
+
+/// The points of the polyline of `value` in a chart of `WIDTH` x `HEIGHT`.
+fn polyline(samples: &[Sample], max_step: u128, max_value: f64, value: impl Fn(&Sample) -> f64) -> String {
+    samples
+        .iter()
+        .map(|sample| {
+            format!(
+                "{:.1},{:.1}",
+                sample.step as f64 / max_step as f64 * WIDTH,
+                HEIGHT - value(sample) / max_value * HEIGHT
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn chart(pcx: &PrirodaContext) -> String {
+    let timeline = &pcx.timeline;
+    let max_step = timeline.samples.last().map_or(0, |sample| sample.step).max(*pcx.step_count).max(1);
+    let max_bytes = timeline.samples.iter().map(|sample| sample.bytes).max().unwrap_or(0).max(1) as f64;
+    let max_allocs = timeline.samples.iter().map(|sample| sample.allocations).max().unwrap_or(0).max(1) as f64;
+    let x = |step: u128| step as f64 / max_step as f64 * WIDTH;
+    let mut svg = format!(
+        "<svg width=\"{w}\" height=\"{h}\" viewBox=\"-50 -10 {vw} {vh}\" style=\"font-family: monospace; font-size: 10px;\">\
+         <rect x=\"0\" y=\"0\" width=\"{w}\" height=\"{h}\" fill=\"none\" stroke=\"gray\"/>\
+         <text x=\"-45\" y=\"0\" fill=\"blue\">{bytes}B</text>\
+         <text x=\"-45\" y=\"12\" fill=\"green\">{allocs}</text>\
+         <text x=\"{w}\" y=\"{below}\" text-anchor=\"end\">step {steps}</text>",
+        w = WIDTH,
+        h = HEIGHT,
+        vw = WIDTH + 60.0,
+        vh = HEIGHT + 30.0,
+        bytes = max_bytes,
+        allocs = max_allocs,
+        below = HEIGHT + 12.0,
+        steps = max_step,
+    );
+    for &step in &timeline.breakpoints {
+        svg.push_str(&format!(
+            "<line x1=\"{x:.1}\" y1=\"0\" x2=\"{x:.1}\" y2=\"{h}\" stroke=\"orange\" stroke-dasharray=\"4\"><title>breakpoint at step {step}</title></line>",
+            x = x(step),
+            h = HEIGHT,
+            step = step,
+        ));
+    }
+    svg.push_str(&format!(
+        "<polyline points=\"{}\" fill=\"none\" stroke=\"blue\"/>",
+        polyline(&timeline.samples, max_step, max_bytes, |sample| sample.bytes as f64)
+    ));
+    svg.push_str(&format!(
+        "<polyline points=\"{}\" fill=\"none\" stroke=\"green\"/>",
+        polyline(&timeline.samples, max_step, max_allocs, |sample| sample.allocations as f64)
+    ));
+    for sample in &timeline.samples {
+        svg.push_str(&format!(
+            "<a href=\"/timeline/goto/{step}\"><circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"2\" fill=\"blue\">\
+             <title>step {step}: {allocs} allocations, {bytes} bytes</title></circle></a>",
+            step = sample.step,
+            x = x(sample.step),
+            y = HEIGHT - sample.bytes as f64 / max_bytes * HEIGHT,
+            allocs = sample.allocations,
+            bytes = sample.bytes,
+        ));
+    }
+    svg.push_str(&format!(
+        "<line x1=\"{x:.1}\" y1=\"0\" x2=\"{x:.1}\" y2=\"{h}\" stroke=\"red\"><title>current step {step}</title></line>",
+        x = x(*pcx.step_count),
+        h = HEIGHT,
+        step = pcx.step_count,
+    ));
+    svg.push_str("</svg>");
+    svg
+}
+
+pub fn render_timeline(pcx: &PrirodaContext) -> Html<String> {
+    let chart = if pcx.timeline.samples.is_empty() { None } else { Some(chart(pcx)) };
+    crate::render::template(
+        pcx,
+        "Memory timeline".to_string(),
+        html! {
+            a(href="/") { : "Back" }
+            : " ";
+            a(href="/timeline/toggle") {
+                : if pcx.config.memory_timeline { "Stop recording" } else { "Record the memory timeline" }
+            }
+            p {
+                : format!(
+                    "{} samples, one every {} steps. Live heap bytes in blue, live heap allocations in green, breakpoint hits in orange and the current step in red.",
+                    pcx.timeline.samples.len(),
+                    pcx.timeline.interval,
+                )
+            }
+            @ if let Some(chart) = chart {
+                : Raw(chart);
+            } else {
+                p { : "No samples yet, record the timeline and step or restart" }
+            }
+        },
+    )
+}
+
+pub fn routes() -> Vec<::rocket::Route> {
+    routes![timeline::show, timeline::toggle, timeline::goto]
+}
+
+view_route!(show: "/", |pcx| {
+    render_timeline(pcx)
+});
+
+action_route!(toggle: "/toggle", |pcx| {
+    crate::step::step_command(pcx, "memory-timeline")
+});
+
+action_route!(goto: "/goto/<step>", |pcx, step: u64| {
+    match crate::step::replay_to(pcx, u128::from(step)) {
+        Ok(()) => format!("Replayed to step {}", step),
+        Err(e) => e,
+    }
+});
@@ -0,0 +1,184 @@
+//! An opt-in record of every executed statement and terminator.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+use rustc::hir::def_id::DefId;
+use rustc_data_structures::indexed_vec::Idx;
+
+use horrorshow::prelude::*;
+
+use crate::*;
+
+/// The number of entries kept in memory, older entries are dropped.
+const CAPACITY: usize = 100_000;
+
+#[derive(Copy, Clone, Debug)]
+pub struct TraceEntry {
+    step: u128,
+    def_id: DefId,
+    block: mir::BasicBlock,
+    stmt: usize,
+    depth: usize,
+}
+
+#[derive(Default)]
+pub struct ExecutionTrace {
+    enabled: bool,
+    entries: VecDeque<TraceEntry>,
+    /// Every entry is also appended to this file
+    file: Option<File>,
+}
+
+impl ExecutionTrace {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Record the position which is about to be executed as step `*pcx.step_count`.
+pub fn record(pcx: &mut PrirodaContext) {
+    push(pcx, true);
+}
+
+/// Like `record`, for steps replayed to restore an earlier state. These were already written to
+/// the trace file when they were first executed.
+pub fn record_replayed(pcx: &mut PrirodaContext) {
+    push(pcx, false);
+}
+
+fn push(pcx: &mut PrirodaContext, write_file: bool) {
+    if !pcx.exec_trace.enabled {
+        return;
+    }
+    let frame = match pcx.ecx.stack().last() {
+        Some(frame) => frame,
+        None => return,
+    };
+    let entry = TraceEntry {
+        step: *pcx.step_count,
+        def_id: frame.instance.def_id(),
+        block: frame.block,
+        stmt: frame.stmt,
+        depth: pcx.ecx.stack().len() - 1,
+    };
+    if write_file && pcx.exec_trace.file.is_some() {
+        let line = entry_json(pcx, &entry).to_string();
+        if writeln!(pcx.exec_trace.file.as_mut().unwrap(), "{}", line).is_err() {
+            pcx.exec_trace.file = None;
+        }
+    }
+    let trace = &mut pcx.exec_trace;
+    if trace.entries.len() == CAPACITY {
+        trace.entries.pop_front();
+    }
+    trace.entries.push_back(entry);
+}
+
+fn entry_json(pcx: &PrirodaContext, entry: &TraceEntry) -> serde_json::Value {
+    json!({
+        "step": entry.step,
+        "function": pcx.ecx.tcx.def_path_str(entry.def_id),
+        "def_id": format!("{:?}", entry.def_id),
+        "block": entry.block.index(),
+        "stmt": entry.stmt,
+        "depth": entry.depth,
+    })
+}
+
+/// Execute a `trace` command: `trace on [<file>]`, `trace off`, `trace clear` or
+/// `trace dump <file>`.
+pub fn trace_command(pcx: &mut PrirodaContext, args: &[&str]) -> Result<String, String> {
+    Ok(match args {
+        ["on"] => {
+            pcx.exec_trace.enabled = true;
+            pcx.exec_trace.file = None;
+            "Recording the execution trace".to_string()
+        }
+        ["on", path] => match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => {
+                pcx.exec_trace.enabled = true;
+                pcx.exec_trace.file = Some(file);
+                format!("Recording the execution trace, also to {}", path)
+            }
+            Err(err) => return Err(format!("Couldn't open {}: {}", path, err)),
+        },
+        ["off"] => {
+            pcx.exec_trace.enabled = false;
+            pcx.exec_trace.file = None;
+            "Stopped recording the execution trace".to_string()
+        }
+        ["clear"] => {
+            pcx.exec_trace.clear();
+            "Cleared the execution trace".to_string()
+        }
+        ["dump", path] => {
+            let lines = pcx
+                .exec_trace
+                .entries
+                .iter()
+                .map(|entry| entry_json(pcx, entry).to_string() + "\n")
+                .collect::<String>();
+            match std::fs::write(path, lines) {
+                Ok(()) => format!("Wrote {} trace entries to {}", pcx.exec_trace.entries.len(), path),
+                Err(err) => return Err(format!("Couldn't write {}: {}", path, err)),
+            }
+        }
+        _ => return Err(format!("Unknown trace command {:?}", args.join(" "))),
+    })
+}
+
+pub fn routes() -> Vec<::rocket::Route> {
+    routes![trace::show, trace::command]
+}
+
+view_route!(show: "/?<n>", |pcx, n: Option<usize>| {
+    let n = n.unwrap_or(200);
+    let trace = &pcx.exec_trace;
+    let stack = pcx.ecx.stack();
+    let entries = trace.entries.iter().rev().take(n).collect::<Vec<_>>();
+    crate::render::template(pcx, "Execution trace".to_string(), html! {
+        a(href="/") { : "Back" }
+        p {
+            : format!(
+                "Recording is {}, {} entries kept (at most {}). ",
+                if trace.enabled { "on" } else { "off" },
+                trace.entries.len(),
+                CAPACITY,
+            );
+            a(href=if trace.enabled { "/trace/command?cmd=off" } else { "/trace/command?cmd=on" }) {
+                : if trace.enabled { "Stop recording" } else { "Start recording" }
+            }
+            : " ";
+            a(href="/trace/command?cmd=clear") { : "Clear" }
+        }
+        form(action="/trace/command") {
+            input(name="cmd", placeholder="dump <file>");
+        }
+        table(border="1") {
+            tr { th { : "step" } th { : "depth" } th { : "function" } th { : "position" } }
+            @ for entry in entries {
+                tr {
+                    td { : entry.step.to_string() }
+                    td { : entry.depth.to_string() }
+                    // Only frames still on the stack can be viewed
+                    @ if stack.get(entry.depth).map(|frame| frame.instance.def_id()) == Some(entry.def_id) {
+                        td { a(href=format!("/frame/{}", entry.depth)) { : pcx.ecx.tcx.def_path_str(entry.def_id) } }
+                    } else {
+                        td { : pcx.ecx.tcx.def_path_str(entry.def_id) }
+                    }
+                    td { : format!("{}:{}", entry.block.index(), entry.stmt) }
+                }
+            }
+        }
+    })
+});
+
+action_route!(command: "/command?<cmd>", |pcx, cmd: String| {
+    crate::step::step_command(pcx, &format!("trace {}", cmd))
+});
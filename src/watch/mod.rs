@@ -1,9 +1,10 @@
 use std::collections::HashMap;
 use std::fmt::Write;
 
-use rustc::mir::interpret::{Allocation, Pointer, PointerArithmetic};
-use rustc::ty::layout::Size;
+use rustc::mir::interpret::{Allocation, PointerArithmetic};
 use rustc::ty::Instance;
+use rustc_data_structures::indexed_vec::Idx;
+use rustc_mir::interpret::MemoryKind;
 
 use crate::*;
 
@@ -14,6 +15,55 @@ pub struct Traces<'tcx> {
     alloc_traces: HashMap<AllocId, AllocTrace>,
     stack_traces_cpu: Vec<(Vec<(Instance<'tcx>,)>, u128)>,
     stack_traces_mem: Vec<(Vec<(Instance<'tcx>,)>, u128)>,
+    /// The number of calls to the heap allocation functions
+    heap_allocations: u64,
+    /// The file descriptor and bytes of the `write` call the next step executes
+    pending_output: Option<(i32, Vec<u8>)>,
+    /// The step, file descriptor and bytes of the last write to stdout or stderr
+    last_output: Option<(u128, i32, Vec<u8>)>,
+    /// The number of writes to stdout or stderr
+    outputs: u64,
+    /// Whether the next step calls a foreign function. Only those, which miri emulates, allocate
+    /// and free heap memory, so the heap is only scanned after them.
+    pending_foreign_call: bool,
+    heap: HeapStats,
+}
+
+/// Counters of the allocations of the program heaps and the environment, maintained by
+/// `step_callback`.
+#[derive(Debug, Default)]
+pub struct HeapStats {
+    /// The size and creation site of every live allocation
+    live: HashMap<AllocId, (u64, String)>,
+    /// The number of allocations made
+    pub made: u64,
+    /// The number of allocations freed
+    pub freed: u64,
+    pub freed_bytes: u64,
+    pub live_bytes: u64,
+    /// The highest `live_bytes` seen after any step
+    pub peak_live_bytes: u64,
+}
+
+impl HeapStats {
+    pub fn live_allocations(&self) -> usize {
+        self.live.len()
+    }
+
+    /// The step and the innermost local function which made the allocation `alloc_id`.
+    pub fn creation_site(&self, alloc_id: AllocId) -> Option<&str> {
+        self.live.get(&alloc_id).map(|(_, site)| &site[..])
+    }
+}
+
+/// Whether allocations of kind `kind` are made by the program and expected to be freed by it.
+pub fn is_heap(kind: &MemoryKind<miri::MiriMemoryKind>) -> bool {
+    match kind {
+        MemoryKind::Machine(miri::MiriMemoryKind::Rust)
+        | MemoryKind::Machine(miri::MiriMemoryKind::C)
+        | MemoryKind::Machine(miri::MiriMemoryKind::Env) => true,
+        _ => false,
+    }
 }
 
 impl<'tcx> Traces<'tcx> {
@@ -26,18 +76,61 @@ impl<'tcx> Traces<'tcx> {
             alloc_traces,
             stack_traces_cpu: Vec::new(),
             stack_traces_mem: Vec::new(),
+            heap_allocations: 0,
+            pending_output: None,
+            last_output: None,
+            outputs: 0,
+            // The environment is allocated before the first step
+            pending_foreign_call: true,
+            heap: HeapStats::default(),
         }
     }
 
     /// Clear the traces. This should be called before restarting the evaluation.
     pub fn clear(&mut self) {
-        // We have to replace all values of alloc_traces by empty AllocTraces,
-        // because stepping back will change the alloc id's
-        self.alloc_traces.clear();
+        // The trace points belong to the old evaluation. The watched ids are kept, so watches
+        // survive a restart, as the evaluation is deterministic and reuses the same alloc ids.
+        for alloc_trace in self.alloc_traces.values_mut() {
+            alloc_trace.trace_points.clear();
+        }
 
         // We can just empty the stack traces, because they will be rebuild during stepping
         self.stack_traces_cpu.clear();
         self.stack_traces_mem.clear();
+
+        self.heap_allocations = 0;
+        self.pending_output = None;
+        self.last_output = None;
+        self.outputs = 0;
+        self.pending_foreign_call = true;
+        self.heap = HeapStats::default();
+    }
+
+    pub fn heap_allocations(&self) -> u64 {
+        self.heap_allocations
+    }
+
+    pub fn last_output(&self) -> Option<&(u128, i32, Vec<u8>)> {
+        self.last_output.as_ref()
+    }
+
+    pub fn outputs(&self) -> u64 {
+        self.outputs
+    }
+
+    pub fn heap(&self) -> &HeapStats {
+        &self.heap
+    }
+
+    /// The number of steps executed in every function, not counting the functions it called.
+    pub fn steps_per_function(&self) -> HashMap<Instance<'tcx>, u128> {
+        let mut steps = HashMap::new();
+        for (stack_trace, count) in &self.stack_traces_cpu {
+            if let Some(&(instance,)) = stack_trace.last() {
+                *steps.entry(instance).or_insert(0) += count;
+            }
+        }
+        steps
     }
 }
 
@@ -122,9 +215,91 @@ pub fn step_callback(pcx: &mut PrirodaContext) {
         }
     }
 
+    // Heap allocations made or freed by this step
+    if pcx.traces.pending_foreign_call {
+        pcx.traces.pending_foreign_call = false;
+        scan_heap(pcx);
+    }
+    pcx.traces.heap.peak_live_bytes = pcx.traces.heap.peak_live_bytes.max(pcx.traces.heap.live_bytes);
+    crate::timeline::record(pcx);
+
+    // The step positioned at a `write` call was followed by the step executing it
+    if let Some((fd, bytes)) = pcx.traces.pending_output.take() {
+        pcx.traces.last_output = Some((*pcx.step_count, fd, bytes));
+        pcx.traces.outputs += 1;
+    }
+
     stack_trace::step_callback(pcx);
 }
 
+/// Update `HeapStats` with the heap allocations made and freed since the last scan.
+fn scan_heap(pcx: &mut PrirodaContext) {
+    let (heap_count, new_heap) = pcx.ecx.memory().alloc_map().iter(|values| {
+        let mut count = 0;
+        let mut new = Vec::new();
+        for (&id, (kind, alloc)) in values {
+            if is_heap(kind) {
+                count += 1;
+                if !pcx.traces.heap.live.contains_key(&id) {
+                    new.push((id, alloc.bytes.len() as u64));
+                }
+            }
+        }
+        (count, new)
+    });
+    if !new_heap.is_empty() {
+        let site = creation_site(pcx);
+        let heap = &mut pcx.traces.heap;
+        for (id, size) in new_heap {
+            heap.made += 1;
+            heap.live_bytes += size;
+            heap.live.insert(id, (size, site.clone()));
+        }
+    }
+    if pcx.traces.heap.live.len() != heap_count {
+        let ecx = &pcx.ecx;
+        let heap = &mut pcx.traces.heap;
+        let freed = heap
+            .live
+            .keys()
+            .filter(|&&id| ecx.memory().get(id).is_err())
+            .cloned()
+            .collect::<Vec<_>>();
+        for id in freed {
+            let (size, _) = heap.live.remove(&id).unwrap();
+            heap.freed += 1;
+            heap.freed_bytes += size;
+            heap.live_bytes -= size;
+        }
+    }
+}
+
+/// The current step and the innermost function of the local crate, where the allocations of the
+/// last step were made. The standard library functions doing the actual allocation are skipped.
+fn creation_site(pcx: &PrirodaContext) -> String {
+    let stack = pcx.ecx.stack();
+    let frame = stack
+        .iter()
+        .rev()
+        .find(|frame| frame.instance.def_id().is_local())
+        .or_else(|| stack.last());
+    match frame {
+        Some(frame) => format!(
+            "step {} in {} bb{}[{}]",
+            pcx.step_count,
+            pcx.ecx.tcx.def_path_str(frame.instance.def_id()),
+            frame.block.index(),
+            frame.stmt
+        ),
+        None => format!("step {}", pcx.step_count),
+    }
+}
+
+/// Whether `def_id` is the foreign `write` function, which miri emulates for stdout and stderr.
+pub fn is_write_shim(tcx: TyCtxt, def_id: rustc::hir::def_id::DefId) -> bool {
+    tcx.is_foreign_item(def_id) && &*tcx.item_name(def_id).as_str() == "write"
+}
+
 pub fn routes() -> Vec<::rocket::Route> {
     routes![watch::show, watch::continue_and_show, watch::add]
 }
@@ -146,12 +321,7 @@ view_route!(show: "/show", |pcx| {
         for (step_count, trace_point) in &alloc_trace.trace_points {
             let content = match trace_point {
                 AllocTracePoint::Changed(alloc) => {
-                    crate::render::locals::print_alloc(
-                        pcx.ecx.memory().pointer_size().bytes(),
-                        Pointer::new(*alloc_id, Size::from_bytes(0)).with_tag(miri::Tag::Untagged),
-                        alloc,
-                        None
-                    )
+                    crate::render::locals::print_alloc(&pcx.ecx, *alloc_id, alloc, 0, alloc.bytes.len() as u64, &Default::default())
                 }
                 AllocTracePoint::Deallocated => "Dealloc".to_string(),
             };
@@ -171,6 +341,7 @@ view_route!(show: "/show", |pcx| {
 #[get("/continue_and_show")]
 pub fn continue_and_show(sender: State<PrirodaSender>) -> RResult<Html<String>> {
     sender.do_work(move |pcx| {
+        crate::dirty::start_command(pcx);
         crate::step::step(pcx, |_ecx| crate::step::ShouldContinue::Continue);
     })?;
     show(sender)
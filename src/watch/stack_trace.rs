@@ -2,8 +2,10 @@ use std::fmt::Write;
 use std::io::{self, Write as IoWrite};
 use std::process::{Command, Stdio};
 
+use rustc::ty::layout::Size;
 use rustc::ty::{self, Instance, InstanceDef, ParamEnv};
 
+use crate::render::escape_html;
 use crate::*;
 
 pub(super) fn step_callback(pcx: &mut PrirodaContext) {
@@ -88,6 +90,9 @@ fn insert_stack_traces_for_instance<'a, 'tcx: 'a>(
 
     stack_trace.push((instance,));
     insert_stack_trace(&mut traces.stack_traces_cpu, stack_trace.clone(), 1);
+    if ecx.tcx.is_foreign_item(instance.def_id()) {
+        traces.pending_foreign_call = true;
+    }
 
     let _: ::miri::InterpResult = try {
         let args = args
@@ -95,8 +100,18 @@ fn insert_stack_traces_for_instance<'a, 'tcx: 'a>(
             .into_iter()
             .map(|op| ecx.eval_operand(op, None))
             .collect::<Result<Vec<_>, _>>()?;
+        if super::is_write_shim(ecx.tcx.tcx, instance.def_id()) {
+            let fd = ecx.read_scalar(args[0])?.to_i32()?;
+            if fd == 1 || fd == 2 {
+                let buf = ecx.read_scalar(args[1])?.not_undef()?;
+                let len = ecx.read_scalar(args[2])?.to_usize(&ecx.tcx.tcx)?;
+                let bytes = ecx.memory().read_bytes(buf, Size::from_bytes(len))?.to_vec();
+                traces.pending_output = Some((fd, bytes));
+            }
+        }
         match &item_path[..] {
             "alloc::alloc::::__rust_alloc" | "alloc::alloc::::__rust_alloc_zeroed" => {
+                traces.heap_allocations += 1;
                 let size = ecx.read_scalar(args[0])?.to_usize(&ecx.tcx.tcx)?;
                 insert_stack_trace(&mut traces.stack_traces_mem, stack_trace, size as u128);
             }
@@ -234,10 +249,7 @@ fn print_stack_traces<'a, 'tcx: 'a>(
     traces: &[(Vec<(Instance<'tcx>,)>, u128)],
 ) -> ::std::fmt::Result {
     let name_for_instance = |i: Instance| {
-        ecx.tcx
-            .def_path_str(i.def_id())
-            .replace("<", "&lt;")
-            .replace(">", "&gt;")
+        escape_html(&ecx.tcx.def_path_str(i.def_id())).into_owned()
     };
     writeln!(buf, "<h1>Stack trace</h1>\n<ul>\n")?;
     for (stack_trace, count) in traces {